@@ -0,0 +1,13 @@
+use std::env;
+
+/// Passes the names of the enabled cargo features on to the interpreter, which
+/// lists them in `*features*`.
+fn main() {
+    let features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+
+    println!("cargo:rustc-env=RUST_LISP_CARGO_FEATURES={}", features.join(","));
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+use super::number::Number;
+use super::sexp::Sexp;
+
+/// Array storage: general arrays hold any object, specialized float arrays
+/// hold unboxed `f64`s.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ArrayData {
+    General(Vec<Sexp>),
+    Float(Vec<f64>),
+}
+
+impl ArrayData {
+    fn len(&self) -> usize {
+        match *self {
+            ArrayData::General(ref v) => v.len(),
+            ArrayData::Float(ref v) => v.len(),
+        }
+    }
+}
+
+/// A row-major array. Vectors (rank one) may have a fill pointer, and
+/// adjustable vectors grow in `vector-push-extend`.
+#[derive(PartialEq, Debug)]
+pub struct Array {
+    dimensions: Vec<usize>,
+    data: ArrayData,
+    fill_pointer: Option<usize>,
+    adjustable: bool,
+}
+
+impl Array {
+    pub fn new(dimensions: Vec<usize>, data: ArrayData) -> Array {
+        Array {
+            dimensions,
+            data,
+            fill_pointer: None,
+            adjustable: false,
+        }
+    }
+
+    pub fn vector(elements: Vec<Sexp>) -> Array {
+        Array::new(vec![elements.len()], ArrayData::General(elements))
+    }
+
+    /// Builds an array of the given rank from nested lists, as in
+    /// `#2A((1 2) (3 4))`.
+    pub fn from_nested(rank: usize, contents: &Sexp) -> Option<Array> {
+        let mut dimensions = vec![];
+        let mut level = contents;
+        for _ in 0..rank {
+            match *level {
+                Sexp::List(ref v) => {
+                    dimensions.push(v.len());
+                    match v.first() {
+                        Some(first) => level = first,
+                        None => break,
+                    }
+                }
+                Sexp::Nil => dimensions.push(0),
+                _ => return None,
+            }
+        }
+        dimensions.resize(rank, 0);
+
+        flatten_contents(contents, &dimensions)
+            .map(|elements| Array::new(dimensions, ArrayData::General(elements)))
+    }
+
+    pub fn set_fill_pointer(&mut self, fill_pointer: Option<usize>) -> Result<(), String> {
+        match fill_pointer {
+            Some(f) if self.dimensions.len() != 1 || f > self.data.len() => {
+                Err(format!("Argument error: {}", f))
+            }
+            f => {
+                self.fill_pointer = f;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_adjustable(&mut self, adjustable: bool) {
+        self.adjustable = adjustable;
+    }
+
+    pub fn dimensions(&self) -> &[usize] {
+        &self.dimensions
+    }
+
+    pub fn fill_pointer(&self) -> Option<usize> {
+        self.fill_pointer
+    }
+
+    pub fn is_float(&self) -> bool {
+        match self.data {
+            ArrayData::Float(_) => true,
+            ArrayData::General(_) => false,
+        }
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The number of active elements: the fill pointer if there is one.
+    pub fn len(&self) -> usize {
+        self.fill_pointer.unwrap_or_else(|| self.data.len())
+    }
+
+    pub fn row_major_index(&self, subscripts: &[usize]) -> Result<usize, String> {
+        if subscripts.len() != self.dimensions.len() {
+            return Err(format!("Wrong number of subscripts: {}", subscripts.len()));
+        }
+
+        let mut index = 0;
+        for (&s, &d) in subscripts.iter().zip(self.dimensions.iter()) {
+            if s >= d {
+                return Err(format!("Index {} out of bounds for dimension {}", s, d));
+            }
+            index = index * d + s;
+        }
+
+        Ok(index)
+    }
+
+    pub fn get(&self, index: usize) -> Sexp {
+        match self.data {
+            ArrayData::General(ref v) => v[index].clone(),
+            ArrayData::Float(ref v) => Sexp::Number(Number::Float(v[index])),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: Sexp) -> Result<(), String> {
+        match self.data {
+            ArrayData::General(ref mut v) => v[index] = value,
+            ArrayData::Float(ref mut v) => v[index] = float_value(&value)?,
+        }
+
+        Ok(())
+    }
+
+    /// Stores `value` at the fill pointer and advances it, growing adjustable
+    /// vectors by `extension` elements when full. Returns the index stored at,
+    /// or `None` if the vector is full and cannot grow.
+    pub fn push(&mut self, value: Sexp, extension: Option<usize>) -> Result<Option<usize>, String> {
+        let fill_pointer = match self.fill_pointer {
+            Some(f) => f,
+            None => return Err("Vector has no fill pointer".to_string()),
+        };
+
+        if fill_pointer == self.data.len() {
+            match extension {
+                Some(extension) if self.adjustable => {
+                    let new_len = self.data.len() + extension.max(self.data.len()).max(1);
+                    if new_len > TOTAL_SIZE_LIMIT {
+                        return Err(format!("Array too large: {}", new_len));
+                    }
+                    match self.data {
+                        ArrayData::General(ref mut v) => v.resize(new_len, Sexp::Nil),
+                        ArrayData::Float(ref mut v) => v.resize(new_len, 0.0),
+                    }
+                    self.dimensions[0] = new_len;
+                }
+                Some(_) => return Err("Vector is not adjustable".to_string()),
+                None => return Ok(None),
+            }
+        }
+
+        self.set(fill_pointer, value)?;
+        self.fill_pointer = Some(fill_pointer + 1);
+        Ok(Some(fill_pointer))
+    }
+
+    pub fn pop(&mut self) -> Result<Sexp, String> {
+        match self.fill_pointer {
+            Some(0) => Err("Vector is empty".to_string()),
+            Some(f) => {
+                self.fill_pointer = Some(f - 1);
+                Ok(self.get(f - 1))
+            }
+            None => Err("Vector has no fill pointer".to_string()),
+        }
+    }
+
+    /// The active elements in row-major order.
+    pub fn elements(&self) -> Vec<Sexp> {
+        (0..self.len()).map(|i| self.get(i)).collect()
+    }
+
+    fn fmt_level(&self, f: &mut fmt::Formatter, axis: usize, offset: usize) -> fmt::Result {
+        let len = if axis == 0 && self.dimensions.len() == 1 {
+            self.len()
+        } else {
+            self.dimensions[axis]
+        };
+        // Only used when every axis so far is non-empty, in which case it
+        // fits as the whole array does.
+        let stride = total_size(&self.dimensions[axis + 1..]).unwrap_or(0);
+
+        write!(f, "(")?;
+        for i in 0..len {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            if axis + 1 == self.dimensions.len() && f.alternate() {
+                write!(f, "{:#}", self.get(offset + i))?;
+            } else if axis + 1 == self.dimensions.len() {
+                write!(f, "{}", self.get(offset + i))?;
+            } else {
+                self.fmt_level(f, axis + 1, offset + i * stride)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+fn float_value(value: &Sexp) -> Result<f64, String> {
+    match *value {
+        Sexp::Number(ref n) if !n.is_complex() => Ok(n.to_f64()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The most elements an array may have, the value of
+/// `array-total-size-limit`, so that making a huge one is an error rather
+/// than running out of memory.
+pub const TOTAL_SIZE_LIMIT: usize = 1 << 24;
+
+/// The number of elements in an array of the given dimensions, or `None` if
+/// it is over `TOTAL_SIZE_LIMIT`.
+pub fn total_size(dimensions: &[usize]) -> Option<usize> {
+    if dimensions.contains(&0) {
+        return Some(0);
+    }
+    dimensions.iter()
+        .try_fold(1usize, |size, &d| size.checked_mul(d))
+        .filter(|&size| size <= TOTAL_SIZE_LIMIT)
+}
+
+/// Flattens nested lists of the given dimensions into row-major order.
+pub fn flatten_contents(contents: &Sexp, dimensions: &[usize]) -> Option<Vec<Sexp>> {
+    let mut elements = vec![];
+
+    if flatten(contents, dimensions, &mut elements) {
+        Some(elements)
+    } else {
+        None
+    }
+}
+
+fn flatten(contents: &Sexp, dimensions: &[usize], elements: &mut Vec<Sexp>) -> bool {
+    if dimensions.is_empty() {
+        elements.push(contents.clone());
+        return true;
+    }
+
+    match *contents {
+        Sexp::List(ref v) if v.len() == dimensions[0] => {
+            v.iter().all(|c| flatten(c, &dimensions[1..], elements))
+        }
+        Sexp::Nil if dimensions[0] == 0 => true,
+        _ => false,
+    }
+}
+
+impl fmt::Display for Array {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.dimensions.len() {
+            0 => write!(f, "#0A{}", self.get(0)),
+            1 => {
+                write!(f, "#")?;
+                self.fmt_level(f, 0, 0)
+            }
+            rank => {
+                write!(f, "#{}A", rank)?;
+                self.fmt_level(f, 0, 0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Array, ArrayData};
+    use super::super::number::Number;
+    use super::super::sexp::Sexp;
+    use super::super::test_util::{int, list};
+
+    #[test]
+    fn test_row_major_index() {
+        let array = Array::new(vec![2, 3], ArrayData::General(vec![Sexp::Nil; 6]));
+
+        assert_eq!(array.row_major_index(&[1, 2]), Ok(5));
+        assert_eq!(array.row_major_index(&[0, 1]), Ok(1));
+        assert_eq!(array.row_major_index(&[2, 0]),
+                   Err("Index 2 out of bounds for dimension 2".to_string()));
+        assert_eq!(array.row_major_index(&[0]),
+                   Err("Wrong number of subscripts: 1".to_string()));
+    }
+
+    #[test]
+    fn test_from_nested() {
+        let array = Array::from_nested(2, &list(vec![list(vec![int(1), int(2)]),
+                                                     list(vec![int(3), int(4)])]))
+            .unwrap();
+
+        assert_eq!(array.dimensions(), &[2, 2]);
+        assert_eq!(array.get(2), int(3));
+        assert_eq!(Array::from_nested(2, &list(vec![list(vec![int(1)]), list(vec![])])), None);
+        assert_eq!(Array::from_nested(0, &int(7)).unwrap().get(0), int(7));
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut array = Array::new(vec![1], ArrayData::General(vec![Sexp::Nil]));
+        array.set_fill_pointer(Some(0)).unwrap();
+
+        assert_eq!(array.push(int(1), None), Ok(Some(0)));
+        assert_eq!(array.push(int(2), None), Ok(None));
+        assert_eq!(array.push(int(2), Some(1)), Err("Vector is not adjustable".to_string()));
+
+        array.set_adjustable(true);
+        assert_eq!(array.push(int(2), Some(1)), Ok(Some(1)));
+        assert_eq!(array.push(int(3), Some(1)), Ok(Some(2)));
+        assert_eq!(array.elements(), vec![int(1), int(2), int(3)]);
+        assert_eq!(array.pop(), Ok(int(3)));
+        assert_eq!(array.len(), 2);
+    }
+
+    #[test]
+    fn test_float_data() {
+        let mut array = Array::new(vec![2], ArrayData::Float(vec![0.0; 2]));
+
+        assert_eq!(array.set(0, int(1)), Ok(()));
+        assert_eq!(array.get(0), Sexp::Number(Number::Float(1.0)));
+        assert_eq!(array.set(1, Sexp::Nil), Err("Argument error: NIL".to_string()));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut vector = Array::vector(vec![int(1), int(2), int(3)]);
+        assert_eq!(format!("{}", vector), "#(1 2 3)");
+
+        vector.set_fill_pointer(Some(2)).unwrap();
+        assert_eq!(format!("{}", vector), "#(1 2)");
+
+        let matrix = Array::new(vec![2, 2], ArrayData::Float(vec![1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(format!("{}", matrix), "#2A((1.0 2.0) (3.0 4.0))");
+        assert_eq!(format!("{}", Array::new(vec![], ArrayData::General(vec![int(5)]))),
+                   "#0A5");
+        assert_eq!(format!("{}", Array::new(vec![2, 0], ArrayData::General(vec![]))),
+                   "#2A(() ())");
+    }
+}
@@ -0,0 +1,331 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::super::array::{self, Array, ArrayData};
+use super::super::env::{self, Env};
+use super::super::number::Number;
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::{expect_args, keyword_name};
+
+fn array_arg(s: &Sexp) -> Result<Rc<RefCell<Array>>, String> {
+    match *s {
+        Sexp::Array(ref a) => Ok(a.clone()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn index(n: &Sexp) -> Result<usize, String> {
+    match *n {
+        Sexp::Number(Number::Fixnum(i)) if i >= 0 => Ok(i as usize),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn indices(s: &[Sexp]) -> Result<Vec<usize>, String> {
+    s.iter().map(index).collect()
+}
+
+fn fixnum(n: usize) -> Sexp {
+    Sexp::Number(Number::Fixnum(n as i64))
+}
+
+fn is_float_type(element_type: &Sexp) -> bool {
+    match *element_type {
+        Sexp::Symbol(ref t) => {
+            matches!(package::cl_name(t),
+                     Some("FLOAT" | "SINGLE-FLOAT" | "DOUBLE-FLOAT" | "SHORT-FLOAT" | "LONG-FLOAT"))
+        }
+        _ => false,
+    }
+}
+
+/// `(make-array dimensions &key element-type initial-element initial-contents
+/// adjustable fill-pointer)`; float element types give a specialized array.
+fn make_array(args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() || args.len() % 2 != 1 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let dimensions = match args[0] {
+        Sexp::List(ref v) => indices(v)?,
+        Sexp::Nil => vec![],
+        ref n => vec![index(n)?],
+    };
+
+    let (mut float, mut initial_element, mut initial_contents) = (false, None, None);
+    let (mut adjustable, mut fill_pointer) = (false, Sexp::Nil);
+    for pair in args[1..].chunks(2) {
+        match keyword_name(&pair[0])? {
+            "ELEMENT-TYPE" => float = is_float_type(&pair[1]),
+            "INITIAL-ELEMENT" => initial_element = Some(pair[1].clone()),
+            "INITIAL-CONTENTS" => initial_contents = Some(pair[1].clone()),
+            "ADJUSTABLE" => adjustable = pair[1] != Sexp::Nil,
+            "FILL-POINTER" => fill_pointer = pair[1].clone(),
+            _ => return Err(format!("Argument error: {}", pair[0])),
+        }
+    }
+
+    let size = array::total_size(&dimensions)
+        .ok_or_else(|| format!("Array too large: {}", args[0]))?;
+    let elements = match initial_contents {
+        Some(Sexp::Array(ref a)) if dimensions.len() == 1 => Some(a.borrow().elements()),
+        Some(ref contents) => {
+            let elements = array::flatten_contents(contents, &dimensions)
+                .ok_or_else(|| format!("Argument error: {}", contents))?;
+            Some(elements)
+        }
+        None => None,
+    };
+    if elements.as_ref().is_some_and(|e| e.len() != size) {
+        return Err(format!("Argument error: {}", args[0]));
+    }
+
+    let (data, default) = if float {
+        (ArrayData::Float(vec![0.0; size]), Sexp::Number(Number::Float(0.0)))
+    } else {
+        (ArrayData::General(vec![Sexp::Nil; size]), Sexp::Nil)
+    };
+    let initial = initial_element.unwrap_or(default);
+
+    let mut array = Array::new(dimensions, data);
+    for (i, e) in elements.unwrap_or_else(|| vec![initial; size]).into_iter().enumerate() {
+        array.set(i, e)?;
+    }
+    array.set_adjustable(adjustable);
+    match fill_pointer {
+        Sexp::Nil => {}
+        Sexp::True => array.set_fill_pointer(Some(size))?,
+        ref n => array.set_fill_pointer(Some(index(n)?))?,
+    }
+
+    Ok(Sexp::Array(Rc::new(RefCell::new(array))))
+}
+
+fn vector(args: Vec<Sexp>) -> SexpResult {
+    Ok(Sexp::Array(Rc::new(RefCell::new(Array::vector(args)))))
+}
+
+fn arrayp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Array(_) => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn vectorp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn aref(args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+    let array = array_arg(&args[0])?;
+    let array = array.borrow();
+    let index = array.row_major_index(&indices(&args[1..])?)?;
+
+    Ok(array.get(index))
+}
+
+/// `(setf (aref array subscripts...) value)`
+fn setf_aref(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+    let array = array_arg(&args[1])?;
+    let mut array = array.borrow_mut();
+    let index = array.row_major_index(&indices(&args[2..])?)?;
+
+    array.set(index, args[0].clone())?;
+    Ok(args[0].clone())
+}
+
+fn array_dimensions(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let array = array_arg(&args[0])?;
+    let dimensions: Vec<Sexp> = array.borrow().dimensions().iter().map(|&d| fixnum(d)).collect();
+
+    Ok(if dimensions.is_empty() { Sexp::Nil } else { Sexp::list(dimensions) })
+}
+
+fn array_dimension(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let array = array_arg(&args[0])?;
+    let axis = index(&args[1])?;
+
+    let dimension = array.borrow().dimensions().get(axis).cloned();
+
+    dimension.map(fixnum).ok_or_else(|| format!("Argument error: {}", axis))
+}
+
+fn array_rank(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(fixnum(array_arg(&args[0])?.borrow().dimensions().len()))
+}
+
+fn array_total_size(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(fixnum(array_arg(&args[0])?.borrow().total_size()))
+}
+
+fn array_element_type(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let float = array_arg(&args[0])?.borrow().is_float();
+
+    Ok(if float { Sexp::Symbol(package::cl_symbol("DOUBLE-FLOAT")) } else { Sexp::True })
+}
+
+fn fill_pointer(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let array = array_arg(&args[0])?;
+    let fill_pointer = array.borrow().fill_pointer();
+
+    fill_pointer.map(fixnum).ok_or_else(|| format!("Argument error: {}", args[0]))
+}
+
+fn vector_push(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let array = array_arg(&args[1])?;
+    let pushed = array.borrow_mut().push(args[0].clone(), None)?;
+
+    Ok(pushed.map_or(Sexp::Nil, fixnum))
+}
+
+/// `(vector-push-extend element vector [extension])` grows an adjustable
+/// vector when it is full.
+fn vector_push_extend(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+    let array = array_arg(&args[1])?;
+    let extension = match args.get(2) {
+        Some(n) => index(n)?,
+        None => 1,
+    };
+    let pushed = array.borrow_mut().push(args[0].clone(), Some(extension))?;
+
+    Ok(pushed.map_or(Sexp::Nil, fixnum))
+}
+
+fn vector_pop(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let array = array_arg(&args[0])?;
+    let popped = array.borrow_mut().pop();
+    popped
+}
+
+pub fn register(env: &Env) {
+    env::env_set(env, "array-total-size-limit", fixnum(array::TOTAL_SIZE_LIMIT));
+    env::env_set_function(env, "make-array".to_string(), Sexp::BuiltInFunc(make_array));
+    env::env_set_function(env, "vector".to_string(), Sexp::BuiltInFunc(vector));
+    env::env_set_function(env, "arrayp".to_string(), Sexp::BuiltInFunc(arrayp));
+    env::env_set_function(env, "vectorp".to_string(), Sexp::BuiltInFunc(vectorp));
+    env::env_set_function(env, "aref".to_string(), Sexp::BuiltInFunc(aref));
+    env::env_set_setf_function(env, "aref", Sexp::BuiltInFunc(setf_aref));
+    env::env_set_function(env, "array-dimensions".to_string(), Sexp::BuiltInFunc(array_dimensions));
+    env::env_set_function(env, "array-dimension".to_string(), Sexp::BuiltInFunc(array_dimension));
+    env::env_set_function(env, "array-rank".to_string(), Sexp::BuiltInFunc(array_rank));
+    env::env_set_function(env, "array-total-size".to_string(), Sexp::BuiltInFunc(array_total_size));
+    env::env_set_function(env,
+                          "array-element-type".to_string(),
+                          Sexp::BuiltInFunc(array_element_type));
+    env::env_set_function(env, "fill-pointer".to_string(), Sexp::BuiltInFunc(fill_pointer));
+    env::env_set_function(env, "vector-push".to_string(), Sexp::BuiltInFunc(vector_push));
+    env::env_set_function(env, "vector-push-extend".to_string(), Sexp::BuiltInFunc(vector_push_extend));
+    env::env_set_function(env, "vector-pop".to_string(), Sexp::BuiltInFunc(vector_pop));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sexp::Sexp;
+    use super::super::super::test_util::{int, symbol, eval_source};
+
+    #[test]
+    fn test_make_array() {
+        let matrix = super::make_array(vec![Sexp::list(vec![int(2), int(3)]),
+                                            symbol(":initial-element"),
+                                            int(0)])
+            .unwrap();
+
+        assert_eq!(format!("{}", matrix), "#2A((0 0 0) (0 0 0))");
+        assert_eq!(super::array_dimensions(vec![matrix.clone()]),
+                   Ok(Sexp::list(vec![int(2), int(3)])));
+        assert_eq!(super::array_rank(vec![matrix.clone()]), Ok(int(2)));
+        assert_eq!(super::array_total_size(vec![matrix.clone()]), Ok(int(6)));
+        assert_eq!(super::vectorp(vec![matrix]), Ok(Sexp::Nil));
+
+        let floats = super::make_array(vec![int(2), symbol(":element-type"), symbol("double-float")])
+            .unwrap();
+        assert_eq!(format!("{}", floats), "#(0.0 0.0)");
+        assert_eq!(super::array_element_type(vec![floats]), Ok(symbol("DOUBLE-FLOAT")));
+        assert_eq!(eval_source("(array-element-type (make-array 2 :element-type '|float|))"),
+                   Ok("T".to_string()));
+
+        let contents = Sexp::list(vec![Sexp::list(vec![int(1), int(2)]),
+                                       Sexp::list(vec![int(3), int(4)])]);
+        let matrix = super::make_array(vec![Sexp::list(vec![int(2), int(2)]),
+                                            symbol(":initial-contents"),
+                                            contents])
+            .unwrap();
+        assert_eq!(super::aref(vec![matrix, int(1), int(0)]), Ok(int(3)));
+        assert_eq!(super::make_array(vec![int(2), symbol(":initial-contents"), Sexp::Nil]),
+                   Err("Argument error: NIL".to_string()));
+
+        assert_eq!(super::make_array(vec![int(2), symbol("initial-element"), int(0)]),
+                   Err("Argument error: INITIAL-ELEMENT".to_string()));
+
+        let huge = int(1 << 32);
+        let empty = super::make_array(vec![Sexp::list(vec![int(0), huge.clone(), huge.clone()])]);
+        assert_eq!(empty.map(|a| format!("{}", a)), Ok("#3A()".to_string()));
+        assert_eq!(super::make_array(vec![Sexp::list(vec![huge.clone(), huge, int(2)])]),
+                   Err("Array too large: (4294967296 4294967296 2)".to_string()));
+    }
+
+    #[test]
+    fn test_aref_and_setf() {
+        let vector = super::vector(vec![int(1), int(2), int(3)]).unwrap();
+
+        assert_eq!(super::aref(vec![vector.clone(), int(2)]), Ok(int(3)));
+        assert_eq!(super::setf_aref(vec![int(9), vector.clone(), int(0)]), Ok(int(9)));
+        assert_eq!(format!("{}", vector), "#(9 2 3)");
+        assert_eq!(super::aref(vec![vector.clone(), int(3)]),
+                   Err("Index 3 out of bounds for dimension 3".to_string()));
+        assert_eq!(super::aref(vec![vector, int(0), int(0)]),
+                   Err("Wrong number of subscripts: 2".to_string()));
+    }
+
+    #[test]
+    fn test_vector_push_extend() {
+        let vector = super::make_array(vec![int(0),
+                                            symbol(":adjustable"),
+                                            Sexp::True,
+                                            symbol(":fill-pointer"),
+                                            int(0)])
+            .unwrap();
+
+        assert_eq!(super::vector_push(vec![int(1), vector.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::vector_push_extend(vec![int(1), vector.clone()]), Ok(int(0)));
+        assert_eq!(super::vector_push_extend(vec![int(2), vector.clone()]), Ok(int(1)));
+        assert_eq!(super::fill_pointer(vec![vector.clone()]), Ok(int(2)));
+        assert_eq!(super::vector_pop(vec![vector.clone()]), Ok(int(2)));
+        assert_eq!(format!("{}", vector), "#(1)");
+        assert_eq!(super::vector_push_extend(vec![int(2), vector.clone()]), Ok(int(1)));
+        assert_eq!(super::vector_push_extend(vec![int(3), vector.clone(), int(1 << 30)]),
+                   Err("Array too large: 1073741826".to_string()));
+    }
+
+    #[test]
+    fn test_total_size_limit() {
+        assert_eq!(eval_source("(make-array 100000000000000)"),
+                   Err("Array too large: 100000000000000".to_string()));
+        assert_eq!(eval_source("(make-array (list array-total-size-limit 2))"),
+                   Err("Array too large: (16777216 2)".to_string()));
+    }
+}
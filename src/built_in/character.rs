@@ -0,0 +1,303 @@
+use std::char;
+use super::super::env::{self, Env};
+use super::super::number::Number;
+use super::super::sexp::{Sexp, SexpResult};
+use super::expect_args;
+
+fn boolean(b: bool) -> Sexp {
+    if b { Sexp::True } else { Sexp::Nil }
+}
+
+fn characterp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Char(_) => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+/// Returns the character at an index of a string.
+fn char_at(args: Vec<Sexp>) -> SexpResult {
+    let (s, index) = unpack_args!(args, 1 Sexp::String, 1 Sexp::Number);
+
+    match index {
+        Number::Fixnum(i) if i >= 0 => {
+            s.chars()
+                .nth(i as usize)
+                .map(Sexp::Char)
+                .ok_or_else(|| format!("Index {} out of bounds for {:?}", i, s))
+        }
+        n => Err(format!("Argument error: {}", n)),
+    }
+}
+
+fn char_code(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(Sexp::Number(Number::Fixnum(c as i64)))
+}
+
+fn code_char(args: Vec<Sexp>) -> SexpResult {
+    let code = unpack_args!(args, 1 Sexp::Number);
+
+    match code {
+        Number::Fixnum(n) if n >= 0 => {
+            Ok(char::from_u32(n as u32).filter(|_| n <= u32::MAX as i64).map_or(Sexp::Nil, Sexp::Char))
+        }
+        n => Err(format!("Argument error: {}", n)),
+    }
+}
+
+/// Maps a character to the single character its case conversion produces,
+/// leaving characters like `ß` that expand to several characters unchanged.
+fn convert_case<I>(c: char, converted: I) -> char
+    where I: Iterator<Item = char>
+{
+    let converted: Vec<char> = converted.collect();
+
+    if converted.len() == 1 { converted[0] } else { c }
+}
+
+pub fn upcase(c: char) -> char {
+    convert_case(c, c.to_uppercase())
+}
+
+pub fn downcase(c: char) -> char {
+    convert_case(c, c.to_lowercase())
+}
+
+fn char_upcase(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(Sexp::Char(upcase(c)))
+}
+
+fn char_downcase(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(Sexp::Char(downcase(c)))
+}
+
+fn alpha_char_p(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(boolean(c.is_alphabetic()))
+}
+
+fn alphanumericp(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(boolean(c.is_alphanumeric()))
+}
+
+fn upper_case_p(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(boolean(c.is_uppercase()))
+}
+
+fn lower_case_p(args: Vec<Sexp>) -> SexpResult {
+    let c = unpack_args!(args, 1 Sexp::Char);
+
+    Ok(boolean(c.is_lowercase()))
+}
+
+/// Returns the weight of a digit in the given radix (10 by default), or nil
+/// if the character is not a digit.
+fn digit_char_p(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let (c, radix) = unpack_args!(args, 1 Sexp::Char, N Sexp::Number);
+
+    let radix = match radix.first() {
+        None => 10,
+        Some(&Number::Fixnum(r)) if (2..=36).contains(&r) => r as u32,
+        Some(n) => return Err(format!("Argument error: {}", n)),
+    };
+
+    Ok(c.to_digit(radix).map_or(Sexp::Nil, |d| Sexp::Number(Number::Fixnum(d as i64))))
+}
+
+fn char_comparison<F>(args: Vec<Sexp>, key: fn(char) -> char, f: F) -> SexpResult
+    where F: Fn(char, char) -> bool
+{
+    let len = args.len();
+    if len == 0 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let cs: Vec<char> = unpack_args!(args, N Sexp::Char).into_iter().map(key).collect();
+
+    Ok(boolean(cs.iter().zip(cs.iter().skip(1)).all(|(&a, &b)| f(a, b))))
+}
+
+fn all_different(args: Vec<Sexp>, key: fn(char) -> char) -> SexpResult {
+    let len = args.len();
+    if len == 0 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let cs: Vec<char> = unpack_args!(args, N Sexp::Char).into_iter().map(key).collect();
+
+    Ok(boolean(cs.iter().enumerate().all(|(i, a)| !cs[i + 1..].contains(a))))
+}
+
+fn identity(c: char) -> char {
+    c
+}
+
+fn char_eq(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, identity, |a, b| a == b)
+}
+
+fn char_ne(args: Vec<Sexp>) -> SexpResult {
+    all_different(args, identity)
+}
+
+fn char_lt(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, identity, |a, b| a < b)
+}
+
+fn char_gt(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, identity, |a, b| a > b)
+}
+
+fn char_lte(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, identity, |a, b| a <= b)
+}
+
+fn char_gte(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, identity, |a, b| a >= b)
+}
+
+fn char_equal(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, downcase, |a, b| a == b)
+}
+
+fn char_not_equal(args: Vec<Sexp>) -> SexpResult {
+    all_different(args, downcase)
+}
+
+fn char_lessp(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, downcase, |a, b| a < b)
+}
+
+fn char_greaterp(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, downcase, |a, b| a > b)
+}
+
+fn char_not_greaterp(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, downcase, |a, b| a <= b)
+}
+
+fn char_not_lessp(args: Vec<Sexp>) -> SexpResult {
+    char_comparison(args, downcase, |a, b| a >= b)
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "characterp".to_string(), Sexp::BuiltInFunc(characterp));
+    env::env_set_function(env, "char".to_string(), Sexp::BuiltInFunc(char_at));
+    env::env_set_function(env, "char-code".to_string(), Sexp::BuiltInFunc(char_code));
+    env::env_set_function(env, "code-char".to_string(), Sexp::BuiltInFunc(code_char));
+    env::env_set_function(env, "char-upcase".to_string(), Sexp::BuiltInFunc(char_upcase));
+    env::env_set_function(env, "char-downcase".to_string(), Sexp::BuiltInFunc(char_downcase));
+    env::env_set_function(env, "alpha-char-p".to_string(), Sexp::BuiltInFunc(alpha_char_p));
+    env::env_set_function(env, "alphanumericp".to_string(), Sexp::BuiltInFunc(alphanumericp));
+    env::env_set_function(env, "upper-case-p".to_string(), Sexp::BuiltInFunc(upper_case_p));
+    env::env_set_function(env, "lower-case-p".to_string(), Sexp::BuiltInFunc(lower_case_p));
+    env::env_set_function(env, "digit-char-p".to_string(), Sexp::BuiltInFunc(digit_char_p));
+    env::env_set_function(env, "char=".to_string(), Sexp::BuiltInFunc(char_eq));
+    env::env_set_function(env, "char/=".to_string(), Sexp::BuiltInFunc(char_ne));
+    env::env_set_function(env, "char<".to_string(), Sexp::BuiltInFunc(char_lt));
+    env::env_set_function(env, "char>".to_string(), Sexp::BuiltInFunc(char_gt));
+    env::env_set_function(env, "char<=".to_string(), Sexp::BuiltInFunc(char_lte));
+    env::env_set_function(env, "char>=".to_string(), Sexp::BuiltInFunc(char_gte));
+    env::env_set_function(env, "char-equal".to_string(), Sexp::BuiltInFunc(char_equal));
+    env::env_set_function(env, "char-not-equal".to_string(), Sexp::BuiltInFunc(char_not_equal));
+    env::env_set_function(env, "char-lessp".to_string(), Sexp::BuiltInFunc(char_lessp));
+    env::env_set_function(env, "char-greaterp".to_string(), Sexp::BuiltInFunc(char_greaterp));
+    env::env_set_function(env, "char-not-greaterp".to_string(), Sexp::BuiltInFunc(char_not_greaterp));
+    env::env_set_function(env, "char-not-lessp".to_string(), Sexp::BuiltInFunc(char_not_lessp));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sexp::Sexp;
+    use super::super::super::test_util::{int, string};
+
+    #[test]
+    fn test_characterp() {
+        assert_eq!(super::characterp(vec![Sexp::Char('a')]), Ok(Sexp::True));
+        assert_eq!(super::characterp(vec![string("a")]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_char_at() {
+        assert_eq!(super::char_at(vec![string("héllo"), int(1)]), Ok(Sexp::Char('é')));
+        assert_eq!(super::char_at(vec![string("abc"), int(3)]),
+                   Err("Index 3 out of bounds for \"abc\"".to_string()));
+        assert_eq!(super::char_at(vec![string("abc"), int(-1)]),
+                   Err("Argument error: -1".to_string()));
+        assert_eq!(super::char_at(vec![string("abc")]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_char_code_and_code_char() {
+        assert_eq!(super::char_code(vec![Sexp::Char('A')]), Ok(int(65)));
+        assert_eq!(super::char_code(vec![Sexp::Char('\u{1F600}')]), Ok(int(0x1F600)));
+        assert_eq!(super::code_char(vec![int(97)]), Ok(Sexp::Char('a')));
+        assert_eq!(super::code_char(vec![int(0xD800)]), Ok(Sexp::Nil));
+        assert_eq!(super::char_code(vec![int(97)]), Err("Argument error: 97".to_string()));
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        assert_eq!(super::char_upcase(vec![Sexp::Char('a')]), Ok(Sexp::Char('A')));
+        assert_eq!(super::char_upcase(vec![Sexp::Char('é')]), Ok(Sexp::Char('É')));
+        assert_eq!(super::char_upcase(vec![Sexp::Char('ß')]), Ok(Sexp::Char('ß')));
+        assert_eq!(super::char_downcase(vec![Sexp::Char('A')]), Ok(Sexp::Char('a')));
+        assert_eq!(super::char_downcase(vec![Sexp::Char('1')]), Ok(Sexp::Char('1')));
+    }
+
+    #[test]
+    fn test_char_predicates() {
+        assert_eq!(super::alpha_char_p(vec![Sexp::Char('λ')]), Ok(Sexp::True));
+        assert_eq!(super::alpha_char_p(vec![Sexp::Char('1')]), Ok(Sexp::Nil));
+        assert_eq!(super::alphanumericp(vec![Sexp::Char('1')]), Ok(Sexp::True));
+        assert_eq!(super::upper_case_p(vec![Sexp::Char('A')]), Ok(Sexp::True));
+        assert_eq!(super::lower_case_p(vec![Sexp::Char('A')]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_digit_char_p() {
+        assert_eq!(super::digit_char_p(vec![Sexp::Char('7')]), Ok(int(7)));
+        assert_eq!(super::digit_char_p(vec![Sexp::Char('a')]), Ok(Sexp::Nil));
+        assert_eq!(super::digit_char_p(vec![Sexp::Char('f'), int(16)]), Ok(int(15)));
+        assert_eq!(super::digit_char_p(vec![Sexp::Char('f'), int(37)]),
+                   Err("Argument error: 37".to_string()));
+    }
+
+    #[test]
+    fn test_char_comparisons() {
+        let (a, b, upper_a) = (Sexp::Char('a'), Sexp::Char('b'), Sexp::Char('A'));
+
+        assert_eq!(super::char_eq(vec![a.clone(), a.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_eq(vec![a.clone(), upper_a.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::char_ne(vec![a.clone(), b.clone(), a.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::char_lt(vec![a.clone(), b.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_gt(vec![a.clone(), b.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::char_lte(vec![a.clone(), a.clone(), b.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_gte(vec![b.clone(), a.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_equal(vec![a.clone(), upper_a.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_not_equal(vec![a.clone(), upper_a.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::char_lessp(vec![upper_a.clone(), b.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_greaterp(vec![b.clone(), upper_a.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_not_greaterp(vec![upper_a.clone(), a.clone()]),
+                   Ok(Sexp::True));
+        assert_eq!(super::char_not_lessp(vec![a.clone(), upper_a.clone()]), Ok(Sexp::True));
+        assert_eq!(super::char_eq(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(super::char_lt(vec![a, int(1)]), Err("Argument error: 1".to_string()));
+    }
+}
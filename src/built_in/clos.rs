@@ -0,0 +1,85 @@
+use super::super::clos;
+use super::super::env::{self, Env};
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::symbol::Symbol;
+use super::expect_args;
+
+fn symbol(s: &Sexp) -> Result<&Symbol, String> {
+    match *s {
+        Sexp::Symbol(ref symbol) => Ok(symbol),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// `(make-instance 'class &rest initargs)`
+fn make_instance(args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let name = symbol(&args[0])?;
+    let class = clos::find_class(name).ok_or_else(|| format!("The class {} is undefined", name))?;
+
+    clos::make_instance(&class, &args[1..])
+}
+
+fn slot_value(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+
+    clos::slot_value(&args[0], symbol(&args[1])?)
+}
+
+/// `(setf (slot-value object slot) value)`
+fn setf_slot_value(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 3, 3)?;
+
+    clos::set_slot_value(&args[1], symbol(&args[2])?, args[0].clone())
+}
+
+fn slot_boundp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let bound = clos::slot_boundp(&args[0], symbol(&args[1])?)?;
+
+    Ok(if bound { Sexp::True } else { Sexp::Nil })
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "make-instance".to_string(), Sexp::BuiltInFunc(make_instance));
+    env::env_set_function(env, "slot-value".to_string(), Sexp::BuiltInFunc(slot_value));
+    env::env_set_setf_function(env, "slot-value", Sexp::BuiltInFunc(setf_slot_value));
+    env::env_set_function(env, "slot-boundp".to_string(), Sexp::BuiltInFunc(slot_boundp));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::clos;
+    use super::super::super::env;
+    use super::super::super::number::Number;
+    use super::super::super::sexp::Sexp;
+    use super::super::super::test_util::symbol;
+
+    #[test]
+    fn test_slot_access() {
+        let env = env::env_new(None);
+        clos::defclass(&[symbol("defclass"),
+                         symbol("box"),
+                         Sexp::Nil,
+                         Sexp::list(vec![Sexp::list(vec![symbol("content"),
+                                                          symbol(":initarg"),
+                                                          symbol(":content")])])],
+                       &env)
+            .unwrap();
+
+        let one = Sexp::Number(Number::Fixnum(1));
+        let b = super::make_instance(vec![symbol("box")]).unwrap();
+        assert_eq!(super::slot_boundp(vec![b.clone(), symbol("content")]), Ok(Sexp::Nil));
+        assert_eq!(super::setf_slot_value(vec![one.clone(), b.clone(), symbol("content")]),
+                   Ok(one.clone()));
+        assert_eq!(super::slot_value(vec![b.clone(), symbol("content")]), Ok(one.clone()));
+        assert_eq!(super::slot_boundp(vec![b, symbol("content")]), Ok(Sexp::True));
+        assert_eq!(super::make_instance(vec![symbol("crate")]),
+                   Err("The class CRATE is undefined".to_string()));
+        assert_eq!(super::slot_value(vec![one, symbol("content")]),
+                   Err("Argument error: 1".to_string()));
+    }
+}
@@ -0,0 +1,964 @@
+use std::iter;
+use num::{BigInt, Signed, ToPrimitive};
+use super::super::character::char_name;
+use super::super::env::{self, Env};
+use super::super::number::Number;
+use super::super::sexp::{self, Sexp, SexpResult};
+use super::super::stream::Stream;
+
+#[derive(PartialEq, Debug, Clone)]
+enum Param {
+    Int(i64),
+    Char(char),
+    /// `V`: the parameter is taken from the arguments.
+    Arg,
+    /// `#`: the number of remaining arguments.
+    Remaining,
+    Absent,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Directive {
+    params: Vec<Param>,
+    colon: bool,
+    at: bool,
+    kind: char,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Item {
+    Text(String),
+    Simple(Directive),
+    /// `~{body~}`
+    Iteration(Directive, Vec<Item>),
+    /// `~[clause~;clause~:;default~]`; the flag marks a default clause.
+    Conditional(Directive, Vec<Vec<Item>>, bool),
+}
+
+fn format_error(message: &str) -> String {
+    format!("Format error: {}", message)
+}
+
+/// Parses a control string into items, stopping at the first directive whose
+/// kind is in `closers` and returning that directive as well.
+fn parse_items(chars: &[char],
+               pos: &mut usize,
+               closers: &[char])
+               -> Result<(Vec<Item>, Option<Directive>), String> {
+    let mut items = vec![];
+    let mut text = String::new();
+
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        *pos += 1;
+
+        if c != '~' {
+            text.push(c);
+            continue;
+        }
+
+        let directive = parse_directive(chars, pos)?;
+        if directive.kind == '\n' {
+            if directive.at {
+                text.push('\n');
+            }
+            if !directive.colon {
+                while *pos < chars.len() && chars[*pos].is_whitespace() && chars[*pos] != '\n' {
+                    *pos += 1;
+                }
+            }
+            continue;
+        }
+
+        if !text.is_empty() {
+            items.push(Item::Text(text));
+            text = String::new();
+        }
+
+        match directive.kind {
+            k if closers.contains(&k) => return Ok((items, Some(directive))),
+            '{' => {
+                match parse_items(chars, pos, &['}'])? {
+                    (body, Some(_)) => items.push(Item::Iteration(directive, body)),
+                    (_, None) => return Err(format_error("~{ without matching ~}")),
+                }
+            }
+            '[' => {
+                let mut clauses = vec![];
+                let mut default = false;
+
+                loop {
+                    match parse_items(chars, pos, &[';', ']'])? {
+                        (clause, Some(end)) => {
+                            clauses.push(clause);
+                            if end.kind == ']' {
+                                break;
+                            }
+                            default = end.colon;
+                        }
+                        (_, None) => return Err(format_error("~[ without matching ~]")),
+                    }
+                }
+
+                items.push(Item::Conditional(directive, clauses, default));
+            }
+            '}' | ']' | ';' => {
+                return Err(format!("Format error: unmatched ~{}", directive.kind));
+            }
+            _ => items.push(Item::Simple(directive)),
+        }
+    }
+
+    if !text.is_empty() {
+        items.push(Item::Text(text));
+    }
+
+    Ok((items, None))
+}
+
+fn parse_directive(chars: &[char], pos: &mut usize) -> Result<Directive, String> {
+    let unterminated = || format_error("unterminated directive");
+    let mut params = vec![];
+
+    loop {
+        let start = *pos;
+        let param = match chars.get(*pos) {
+            Some(&'\'') => {
+                *pos += 2;
+                Param::Char(*chars.get(start + 1).ok_or_else(unterminated)?)
+            }
+            Some(&'v') | Some(&'V') => {
+                *pos += 1;
+                Param::Arg
+            }
+            Some(&'#') => {
+                *pos += 1;
+                Param::Remaining
+            }
+            Some(&c) if c.is_ascii_digit() || c == '-' || c == '+' => {
+                *pos += 1;
+                while chars.get(*pos).is_some_and(|c| c.is_ascii_digit()) {
+                    *pos += 1;
+                }
+                let digits: String = chars[start..*pos].iter().collect();
+                Param::Int(digits.parse().map_err(|_| format_error("invalid parameter"))?)
+            }
+            _ => Param::Absent,
+        };
+
+        if chars.get(*pos) == Some(&',') {
+            *pos += 1;
+            params.push(param);
+        } else {
+            if param != Param::Absent {
+                params.push(param);
+            }
+            break;
+        }
+    }
+
+    let (mut colon, mut at) = (false, false);
+    loop {
+        match chars.get(*pos) {
+            Some(&':') => colon = true,
+            Some(&'@') => at = true,
+            _ => break,
+        }
+        *pos += 1;
+    }
+
+    let kind = chars.get(*pos).ok_or_else(unterminated)?.to_ascii_lowercase();
+    *pos += 1;
+
+    Ok(Directive {
+        params,
+        colon,
+        at,
+        kind,
+    })
+}
+
+struct Args {
+    items: Vec<Sexp>,
+    pos: usize,
+}
+
+impl Args {
+    fn new(items: Vec<Sexp>) -> Args {
+        Args { items, pos: 0 }
+    }
+
+    fn next(&mut self) -> SexpResult {
+        match self.items.get(self.pos) {
+            Some(s) => {
+                self.pos += 1;
+                Ok(s.clone())
+            }
+            None => Err(format_error("not enough arguments")),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.items.len() - self.pos
+    }
+
+    fn goto(&mut self, pos: i64) -> Result<(), String> {
+        if pos < 0 || pos as usize > self.items.len() {
+            return Err(format_error("argument index out of range"));
+        }
+
+        self.pos = pos as usize;
+        Ok(())
+    }
+}
+
+/// Formatted text plus whether the destination was at the start of a line
+/// before any of it was written, for `~&`.
+struct Output {
+    text: String,
+    fresh: bool,
+}
+
+impl Output {
+    fn at_line_start(&self) -> bool {
+        if self.text.is_empty() { self.fresh } else { self.text.ends_with('\n') }
+    }
+
+    fn column(&self) -> usize {
+        match self.text.rfind('\n') {
+            Some(i) => self.text[i + 1..].chars().count(),
+            None => self.text.chars().count(),
+        }
+    }
+}
+
+enum Flow {
+    Continue,
+    /// `~^` ran out of arguments.
+    Escape,
+}
+
+/// Directive parameters with `V` and `#` resolved.
+struct Params(Vec<Param>);
+
+impl Params {
+    fn resolve(params: &[Param], args: &mut Args) -> Result<Params, String> {
+        let mut resolved = vec![];
+
+        for p in params {
+            resolved.push(match *p {
+                Param::Arg => {
+                    match args.next()? {
+                        Sexp::Number(Number::Fixnum(n)) => Param::Int(n),
+                        Sexp::Char(c) => Param::Char(c),
+                        Sexp::Nil => Param::Absent,
+                        v => return Err(format!("Format error: invalid parameter {}", v)),
+                    }
+                }
+                Param::Remaining => Param::Int(args.remaining() as i64),
+                ref p => p.clone(),
+            });
+        }
+
+        Ok(Params(resolved))
+    }
+
+    fn int(&self, i: usize) -> Option<i64> {
+        match self.0.get(i) {
+            Some(&Param::Int(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn count(&self, i: usize, default: i64) -> Result<usize, String> {
+        match self.int(i).unwrap_or(default) {
+            n if n < 0 => Err(format_error("negative parameter")),
+            n => Ok(n as usize),
+        }
+    }
+
+    fn character(&self, i: usize, default: char) -> char {
+        match self.0.get(i) {
+            Some(&Param::Char(c)) => c,
+            _ => default,
+        }
+    }
+}
+
+fn interpret(items: &[Item], args: &mut Args, out: &mut Output) -> Result<Flow, String> {
+    for item in items {
+        let flow = match *item {
+            Item::Text(ref s) => {
+                out.text.push_str(s);
+                Flow::Continue
+            }
+            Item::Simple(ref d) => simple_directive(d, args, out)?,
+            Item::Iteration(ref d, ref body) => iteration(d, body, args, out)?,
+            Item::Conditional(ref d, ref clauses, default) => {
+                conditional(d, clauses, default, args, out)?
+            }
+        };
+
+        if let Flow::Escape = flow {
+            return Ok(Flow::Escape);
+        }
+    }
+
+    Ok(Flow::Continue)
+}
+
+fn simple_directive(d: &Directive, args: &mut Args, out: &mut Output) -> Result<Flow, String> {
+    let params = Params::resolve(&d.params, args)?;
+
+    match d.kind {
+        'a' | 's' => {
+            let arg = args.next()?;
+            sexp::check_printable(&arg)?;
+            let text = match arg {
+                Sexp::Nil if d.colon => "()".to_string(),
+                ref arg if d.kind == 'a' => format!("{:#}", arg),
+                ref arg => format!("{}", arg),
+            };
+            let padded = pad(&text,
+                             params.count(0, 0)?,
+                             params.count(1, 1)?,
+                             params.count(2, 0)?,
+                             params.character(3, ' '),
+                             d.at);
+            out.text.push_str(&padded);
+        }
+        'd' => out.text.push_str(&integer(&args.next()?, 10, d, &params, 0)?),
+        'b' => out.text.push_str(&integer(&args.next()?, 2, d, &params, 0)?),
+        'o' => out.text.push_str(&integer(&args.next()?, 8, d, &params, 0)?),
+        'x' => out.text.push_str(&integer(&args.next()?, 16, d, &params, 0)?),
+        'r' => {
+            let arg = args.next()?;
+            let text = match params.int(0) {
+                Some(radix) if (2..=36).contains(&radix) => {
+                    integer(&arg, radix as u32, d, &params, 1)?
+                }
+                Some(radix) => return Err(format!("Format error: invalid radix {}", radix)),
+                None => english_or_roman(&arg, d)?,
+            };
+            out.text.push_str(&text);
+        }
+        'f' => out.text.push_str(&fixed(&args.next()?, d, &params)?),
+        'e' => out.text.push_str(&exponential(&args.next()?, d, &params)?),
+        'c' => {
+            let c = match args.next()? {
+                Sexp::Char(c) => c,
+                v => return Err(format!("Argument error: {}", v)),
+            };
+            let text = if d.at {
+                format!("{}", Sexp::Char(c))
+            } else if d.colon {
+                char_name(c).unwrap_or_else(|| c.to_string())
+            } else {
+                c.to_string()
+            };
+            out.text.push_str(&text);
+        }
+        'p' => {
+            if d.colon {
+                let back = args.pos as i64 - 1;
+                args.goto(back)?;
+            }
+            let singular = args.next()? == Sexp::Number(Number::Fixnum(1));
+            let suffix = match (d.at, singular) {
+                (true, true) => "y",
+                (true, false) => "ies",
+                (false, true) => "",
+                (false, false) => "s",
+            };
+            out.text.push_str(suffix);
+        }
+        '%' => out.text.extend(iter::repeat_n('\n', params.count(0, 1)?)),
+        '&' => {
+            let n = params.count(0, 1)?;
+            if n > 0 {
+                if !out.at_line_start() {
+                    out.text.push('\n');
+                }
+                out.text.extend(iter::repeat_n('\n', n - 1));
+            }
+        }
+        '~' => out.text.extend(iter::repeat_n('~', params.count(0, 1)?)),
+        't' => {
+            let column = out.column();
+            let spaces = if d.at {
+                let (colrel, colinc) = (params.count(0, 1)?, params.count(1, 1)?);
+                let target = column + colrel;
+                if colinc > 1 && !target.is_multiple_of(colinc) {
+                    colrel + colinc - target % colinc
+                } else {
+                    colrel
+                }
+            } else {
+                let (colnum, colinc) = (params.count(0, 1)?, params.count(1, 1)?);
+                if column < colnum {
+                    colnum - column
+                } else if colinc > 0 {
+                    colinc - (column - colnum) % colinc
+                } else {
+                    0
+                }
+            };
+            out.text.extend(iter::repeat_n(' ', spaces));
+        }
+        '*' => {
+            let n = params.int(0);
+            let target = if d.at {
+                n.unwrap_or(0)
+            } else if d.colon {
+                args.pos as i64 - n.unwrap_or(1)
+            } else {
+                args.pos as i64 + n.unwrap_or(1)
+            };
+            args.goto(target)?;
+        }
+        '^' => {
+            let escape = match (params.int(0), params.int(1)) {
+                (None, _) => args.remaining() == 0,
+                (Some(a), None) => a == 0,
+                (Some(a), Some(b)) => a == b,
+            };
+            if escape {
+                return Ok(Flow::Escape);
+            }
+        }
+        k => return Err(format!("Format error: unknown directive ~{}", k)),
+    }
+
+    Ok(Flow::Continue)
+}
+
+fn iteration(d: &Directive,
+             body: &[Item],
+             args: &mut Args,
+             out: &mut Output)
+             -> Result<Flow, String> {
+    let params = Params::resolve(&d.params, args)?;
+    let max = params.int(0).map(|n| n.max(0) as usize);
+    let list = |s: Sexp| {
+        match s {
+            Sexp::List(v) => Ok(v.to_vec()),
+            Sexp::Nil => Ok(vec![]),
+            v => Err(format!("Argument error: {}", v)),
+        }
+    };
+
+    let mut own_args;
+    let source = if d.at {
+        args
+    } else {
+        own_args = Args::new(list(args.next()?)?);
+        &mut own_args
+    };
+
+    let mut count = 0;
+    while source.remaining() > 0 && max.is_none_or(|m| count < m) {
+        count += 1;
+
+        if d.colon {
+            let mut step = Args::new(list(source.next()?)?);
+            interpret(body, &mut step, out)?;
+        } else if let Flow::Escape = interpret(body, source, out)? {
+            break;
+        }
+    }
+
+    Ok(Flow::Continue)
+}
+
+fn conditional(d: &Directive,
+               clauses: &[Vec<Item>],
+               default: bool,
+               args: &mut Args,
+               out: &mut Output)
+               -> Result<Flow, String> {
+    let params = Params::resolve(&d.params, args)?;
+
+    let clause = if d.colon {
+        if clauses.len() != 2 {
+            return Err(format_error("~:[ requires exactly two clauses"));
+        }
+        match args.next()? {
+            Sexp::Nil => &clauses[0],
+            _ => &clauses[1],
+        }
+    } else if d.at {
+        match args.next()? {
+            Sexp::Nil => return Ok(Flow::Continue),
+            _ => {
+                let back = args.pos as i64 - 1;
+                args.goto(back)?;
+                &clauses[0]
+            }
+        }
+    } else {
+        let index = match params.int(0) {
+            Some(n) => n,
+            None => {
+                match args.next()? {
+                    Sexp::Number(Number::Fixnum(n)) => n,
+                    v => return Err(format!("Argument error: {}", v)),
+                }
+            }
+        };
+        let last = clauses.len() - 1;
+        if index >= 0 && (index as usize) < last || !default && index as usize == last {
+            &clauses[index as usize]
+        } else if default {
+            &clauses[last]
+        } else {
+            return Ok(Flow::Continue);
+        }
+    };
+
+    interpret(clause, args, out)
+}
+
+/// Pads `text` to at least `mincol` characters: `minpad` copies of `padchar`
+/// first, then `colinc` at a time. Padding goes on the left if `left` is set.
+fn pad(text: &str, mincol: usize, colinc: usize, minpad: usize, padchar: char, left: bool) -> String {
+    let len = text.chars().count();
+    let mut padding = minpad;
+    while len + padding < mincol {
+        padding += colinc.max(1);
+    }
+
+    let fill: String = iter::repeat_n(padchar, padding).collect();
+    if left { fill + text } else { text.to_string() + &fill }
+}
+
+fn pad_left(text: &str, mincol: usize, padchar: char) -> String {
+    pad(text, mincol, 1, 0, padchar, true)
+}
+
+/// Formats an integer for `~D`, `~B`, `~O`, `~X` and `~nR`; `first` is the
+/// index of the `mincol` parameter. Non-integers are printed as by `~A`.
+fn integer(arg: &Sexp,
+           radix: u32,
+           d: &Directive,
+           params: &Params,
+           first: usize)
+           -> Result<String, String> {
+    let mincol = params.count(first, 0)?;
+    let padchar = params.character(first + 1, ' ');
+
+    let n = match *arg {
+        Sexp::Number(ref n) => n.to_bigint(),
+        _ => None,
+    };
+    let n = match n {
+        Some(n) => n,
+        None => return Ok(pad_left(&format!("{:#}", arg), mincol, padchar)),
+    };
+
+    let mut digits = n.abs().to_str_radix(radix).to_uppercase();
+    if d.colon {
+        let commachar = params.character(first + 2, ',');
+        let interval = params.count(first + 3, 3)?.max(1);
+        digits = group_digits(&digits, commachar, interval);
+    }
+
+    let sign = if n.is_negative() {
+        "-"
+    } else if d.at {
+        "+"
+    } else {
+        ""
+    };
+
+    Ok(pad_left(&format!("{}{}", sign, digits), mincol, padchar))
+}
+
+fn group_digits(digits: &str, separator: char, interval: usize) -> String {
+    let chars: Vec<char> = digits.chars().collect();
+    let mut grouped = String::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && (chars.len() - i).is_multiple_of(interval) {
+            grouped.push(separator);
+        }
+        grouped.push(*c);
+    }
+
+    grouped
+}
+
+fn real_value(arg: &Sexp) -> Option<f64> {
+    match *arg {
+        Sexp::Number(ref n) if !n.is_complex() => Some(n.to_f64()),
+        _ => None,
+    }
+}
+
+fn sign_prefix(x: f64, at: bool) -> &'static str {
+    if x.is_sign_negative() && x != 0.0 {
+        "-"
+    } else if at {
+        "+"
+    } else {
+        ""
+    }
+}
+
+/// `~w,d,,,padcharF`
+fn fixed(arg: &Sexp, d: &Directive, params: &Params) -> Result<String, String> {
+    let width = params.int(0).map(|w| w.max(0) as usize);
+    let padchar = params.character(4, ' ');
+
+    let x = match real_value(arg) {
+        Some(x) if x.is_finite() => x,
+        _ => return Ok(pad_left(&format!("{:#}", arg), width.unwrap_or(0), padchar)),
+    };
+    let sign = sign_prefix(x, d.at);
+
+    let mut digits = match params.int(1) {
+        Some(decimals) => format!("{:.*}", decimals.max(0) as usize, x.abs()),
+        None => {
+            let shortest = format!("{}", x.abs());
+            match width {
+                Some(w) if sign.len() + shortest.len() > w => {
+                    let int_len = format!("{:.0}", x.abs().trunc()).len();
+                    let decimals = w.saturating_sub(sign.len() + int_len + 1);
+                    format!("{:.*}", decimals, x.abs())
+                }
+                _ => shortest,
+            }
+        }
+    };
+    if !digits.contains('.') {
+        digits.push_str(if params.int(1) == Some(0) || width.is_some() { "." } else { ".0" });
+    }
+
+    Ok(pad_left(&format!("{}{}", sign, digits), width.unwrap_or(0), padchar))
+}
+
+/// `~w,d,e,,,padcharE`
+fn exponential(arg: &Sexp, d: &Directive, params: &Params) -> Result<String, String> {
+    let width = params.count(0, 0)?;
+    let padchar = params.character(5, ' ');
+
+    let x = match real_value(arg) {
+        Some(x) if x.is_finite() => x,
+        _ => return Ok(pad_left(&format!("{:#}", arg), width, padchar)),
+    };
+
+    let formatted = match params.int(1) {
+        Some(decimals) => format!("{:.*e}", decimals.max(0) as usize, x.abs()),
+        None => format!("{:e}", x.abs()),
+    };
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    let exponent: i64 = exponent[1..].parse().unwrap();
+
+    let mut mantissa = mantissa.to_string();
+    if !mantissa.contains('.') {
+        mantissa.push_str(".0");
+    }
+    let exponent_digits = params.count(2, 1)?;
+    let exponent_sign = if exponent < 0 { '-' } else { '+' };
+
+    Ok(pad_left(&format!("{}{}e{}{:0>width$}",
+                         sign_prefix(x, d.at),
+                         mantissa,
+                         exponent_sign,
+                         exponent.abs(),
+                         width = exponent_digits),
+                width,
+                padchar))
+}
+
+const ONES: [&str; 20] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight",
+                          "nine", "ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen",
+                          "sixteen", "seventeen", "eighteen", "nineteen"];
+
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy",
+                          "eighty", "ninety"];
+
+const SCALES: [&str; 7] = ["", " thousand", " million", " billion", " trillion",
+                           " quadrillion", " quintillion"];
+
+fn below_thousand(n: u64) -> String {
+    let mut words = vec![];
+
+    if n >= 100 {
+        words.push(format!("{} hundred", ONES[(n / 100) as usize]));
+    }
+    match n % 100 {
+        0 => {}
+        r if r < 20 => words.push(ONES[r as usize].to_string()),
+        r if r % 10 == 0 => words.push(TENS[(r / 10) as usize].to_string()),
+        r => words.push(format!("{}-{}", TENS[(r / 10) as usize], ONES[(r % 10) as usize])),
+    }
+
+    words.join(" ")
+}
+
+fn cardinal(n: i64) -> String {
+    if n == 0 {
+        return "zero".to_string();
+    }
+
+    let mut remaining = n.unsigned_abs();
+    let mut groups = vec![];
+    let mut scale = 0;
+    while remaining > 0 {
+        let group = remaining % 1000;
+        if group > 0 {
+            groups.push(format!("{}{}", below_thousand(group), SCALES[scale]));
+        }
+        remaining /= 1000;
+        scale += 1;
+    }
+    groups.reverse();
+
+    let words = groups.join(" ");
+    if n < 0 { format!("negative {}", words) } else { words }
+}
+
+fn ordinal(n: i64) -> String {
+    let words = cardinal(n);
+    let split = words.rfind([' ', '-']).map_or(0, |i| i + 1);
+    let (init, last) = words.split_at(split);
+
+    let last = match last {
+        "zero" => "zeroth".to_string(),
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        w if w.ends_with('y') => format!("{}ieth", &w[..w.len() - 1]),
+        w => format!("{}th", w),
+    };
+
+    format!("{}{}", init, last)
+}
+
+fn roman(n: i64, old_style: bool) -> String {
+    let numerals: &[(i64, &str)] = if old_style {
+        &[(1000, "M"), (500, "D"), (100, "C"), (50, "L"), (10, "X"), (5, "V"), (1, "I")]
+    } else {
+        &[(1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+          (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")]
+    };
+
+    let mut remaining = n;
+    let mut result = String::new();
+    for &(value, numeral) in numerals {
+        while remaining >= value {
+            result.push_str(numeral);
+            remaining -= value;
+        }
+    }
+
+    result
+}
+
+/// `~R` without a radix: English cardinals, `~:R` ordinals, `~@R` Roman
+/// numerals and `~:@R` old-style Roman numerals.
+fn english_or_roman(arg: &Sexp, d: &Directive) -> Result<String, String> {
+    let n = match *arg {
+        Sexp::Number(ref n) => n.to_bigint().as_ref().and_then(BigInt::to_i64),
+        _ => None,
+    };
+    let n = n.ok_or_else(|| format!("Argument error: {}", arg))?;
+
+    if d.at {
+        if !(1..=3999).contains(&n) {
+            return Err(format!("Argument error: {}", arg));
+        }
+        Ok(roman(n, d.colon))
+    } else if d.colon {
+        Ok(ordinal(n))
+    } else {
+        Ok(cardinal(n))
+    }
+}
+
+/// Formats `args` according to the control string `control`.
+fn format_to_string(control: &str, args: Vec<Sexp>, fresh: bool) -> Result<String, String> {
+    let chars: Vec<char> = control.chars().collect();
+    let mut pos = 0;
+    let (items, _) = parse_items(&chars, &mut pos, &[])?;
+
+    let mut out = Output {
+        text: String::new(),
+        fresh,
+    };
+    interpret(&items, &mut Args::new(args), &mut out)?;
+
+    Ok(out.text)
+}
+
+/// `(format destination control args...)`: a destination of `t` writes to
+/// standard output, `nil` returns a string, and a stream is written to.
+fn format(args: Vec<Sexp>) -> SexpResult {
+    let len = args.len();
+    if len < 2 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let mut args = args.into_iter();
+    let destination = args.next().unwrap();
+    let control = match args.next().unwrap() {
+        Sexp::String(s) => s,
+        v => return Err(format!("Argument error: {}", v)),
+    };
+    let args: Vec<Sexp> = args.collect();
+
+    let stream = match destination {
+        Sexp::Nil => return format_to_string(&control, args, true).map(Sexp::string),
+        Sexp::True => Stream::Stdout,
+        Sexp::Stream(s) => s,
+        v => return Err(format!("Argument error: {}", v)),
+    };
+
+    let text = format_to_string(&control, args, stream.at_line_start())?;
+    stream.write_str(&text);
+    Ok(Sexp::Nil)
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "format".to_string(), Sexp::BuiltInFunc(format));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sexp::Sexp;
+    use super::super::super::stream::Stream;
+    use super::super::super::test_util::{int, float, string};
+
+    fn format(control: &str, args: Vec<Sexp>) -> Result<String, String> {
+        let mut all = vec![Sexp::Nil, string(control)];
+        all.extend(args);
+
+        match super::format(all) {
+            Ok(Sexp::String(s)) => Ok(s.to_string()),
+            Ok(v) => panic!("format returned {}", v),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn test_aesthetic_and_standard() {
+        assert_eq!(format("~a and ~s", vec![string("a"), string("b")]),
+                   Ok("a and \"b\"".to_string()));
+        assert_eq!(format("~a ~:a", vec![Sexp::Nil, Sexp::Nil]), Ok("NIL ()".to_string()));
+        assert_eq!(format("[~5a]", vec![string("ab")]), Ok("[ab   ]".to_string()));
+        assert_eq!(format("[~5@a]", vec![string("ab")]), Ok("[   ab]".to_string()));
+        assert_eq!(format("[~5,,,'.a]", vec![string("ab")]), Ok("[ab...]".to_string()));
+        assert_eq!(format("[~6a]", vec![string("日本語")]), Ok("[日本語   ]".to_string()));
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(format("~d", vec![int(42)]), Ok("42".to_string()));
+        assert_eq!(format("~@d", vec![int(42)]), Ok("+42".to_string()));
+        assert_eq!(format("~:d", vec![int(-1234567)]), Ok("-1,234,567".to_string()));
+        assert_eq!(format("~5,'0d", vec![int(42)]), Ok("00042".to_string()));
+        assert_eq!(format("~x ~b ~o", vec![int(255), int(5), int(8)]),
+                   Ok("FF 101 10".to_string()));
+        assert_eq!(format("~d", vec![string("x")]), Ok("x".to_string()));
+        assert_eq!(format("~vd", vec![int(4), int(7)]), Ok("   7".to_string()));
+    }
+
+    #[test]
+    fn test_radix() {
+        assert_eq!(format("~r", vec![int(123)]),
+                   Ok("one hundred twenty-three".to_string()));
+        assert_eq!(format("~r", vec![int(-2000001)]),
+                   Ok("negative two million one".to_string()));
+        assert_eq!(format("~:r", vec![int(42)]), Ok("forty-second".to_string()));
+        assert_eq!(format("~:r", vec![int(20)]), Ok("twentieth".to_string()));
+        assert_eq!(format("~@r", vec![int(1994)]), Ok("MCMXCIV".to_string()));
+        assert_eq!(format("~:@r", vec![int(4)]), Ok("IIII".to_string()));
+        assert_eq!(format("~3r", vec![int(10)]), Ok("101".to_string()));
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(format("~f", vec![float(1.5)]), Ok("1.5".to_string()));
+        assert_eq!(format("~f", vec![int(2)]), Ok("2.0".to_string()));
+        assert_eq!(format("~,2f", vec![float(1.23456)]), Ok("1.23".to_string()));
+        assert_eq!(format("~8,3f", vec![float(-1.23456)]), Ok("  -1.235".to_string()));
+        assert_eq!(format("~@f", vec![float(1.5)]), Ok("+1.5".to_string()));
+        assert_eq!(format("~4f", vec![float(1.23456)]), Ok("1.23".to_string()));
+        assert_eq!(format("~e", vec![float(12345.0)]), Ok("1.2345e+4".to_string()));
+        assert_eq!(format("~,2e", vec![float(0.000123)]), Ok("1.23e-4".to_string()));
+        assert_eq!(format("~e", vec![float(1.0)]), Ok("1.0e+0".to_string()));
+    }
+
+    #[test]
+    fn test_newlines_and_tildes() {
+        assert_eq!(format("a~%b~2%c~~", vec![]), Ok("a\nb\n\nc~".to_string()));
+        assert_eq!(format("~&a~&~&b", vec![]), Ok("a\nb".to_string()));
+        assert_eq!(format("a~\n    b", vec![]), Ok("ab".to_string()));
+    }
+
+    #[test]
+    fn test_iteration() {
+        let list = Sexp::list(vec![int(1), int(2), int(3)]);
+
+        assert_eq!(format("~{~a~^, ~}", vec![list.clone()]), Ok("1, 2, 3".to_string()));
+        assert_eq!(format("~{~a~}", vec![Sexp::Nil]), Ok("".to_string()));
+        assert_eq!(format("~2{~a~}", vec![list.clone()]), Ok("12".to_string()));
+        assert_eq!(format("~@{~a~^-~}", vec![int(1), int(2)]), Ok("1-2".to_string()));
+        assert_eq!(format("~:{~a=~a;~}",
+                          vec![Sexp::list(vec![Sexp::list(vec![int(1), int(2)]),
+                                               Sexp::list(vec![int(3), int(4)])])]),
+                   Ok("1=2;3=4;".to_string()));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        assert_eq!(format("~[zero~;one~;two~]", vec![int(1)]), Ok("one".to_string()));
+        assert_eq!(format("~[zero~;one~]", vec![int(5)]), Ok("".to_string()));
+        assert_eq!(format("~[zero~;one~:;many~]", vec![int(5)]), Ok("many".to_string()));
+        assert_eq!(format("~:[no~;yes~]", vec![Sexp::Nil]), Ok("no".to_string()));
+        assert_eq!(format("~:[no~;yes~]", vec![Sexp::True]), Ok("yes".to_string()));
+        assert_eq!(format("~@[x=~a~]", vec![int(1)]), Ok("x=1".to_string()));
+        assert_eq!(format("~@[x=~a~]", vec![Sexp::Nil]), Ok("".to_string()));
+    }
+
+    #[test]
+    fn test_plurals_and_characters() {
+        assert_eq!(format("~d item~:p", vec![int(1)]), Ok("1 item".to_string()));
+        assert_eq!(format("~d item~:p", vec![int(2)]), Ok("2 items".to_string()));
+        assert_eq!(format("~d pupp~:@p", vec![int(3)]), Ok("3 puppies".to_string()));
+        assert_eq!(format("~c~:c~@c", vec![Sexp::Char('a'), Sexp::Char(' '), Sexp::Char('b')]),
+                   Ok("aSpace#\\b".to_string()));
+    }
+
+    #[test]
+    fn test_tabulation() {
+        assert_eq!(format("ab~6tc", vec![]), Ok("ab    c".to_string()));
+        assert_eq!(format("abcdefg~4,4tc", vec![]), Ok("abcdefg c".to_string()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(format("~a", vec![]), Err("Format error: not enough arguments".to_string()));
+        assert_eq!(format("~{~a", vec![Sexp::Nil]),
+                   Err("Format error: ~{ without matching ~}".to_string()));
+        assert_eq!(format("~q", vec![]), Err("Format error: unknown directive ~q".to_string()));
+        assert_eq!(format("~", vec![]), Err("Format error: unterminated directive".to_string()));
+        assert_eq!(super::format(vec![Sexp::Nil]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_stream_destination() {
+        let stream = Stream::string_output();
+
+        assert_eq!(super::format(vec![Sexp::Stream(stream.clone()), string("~a~&"), int(1)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::format(vec![Sexp::Stream(stream.clone()), string("~&x")]),
+                   Ok(Sexp::Nil));
+        assert_eq!(stream.take_string(), Some("1\nx".to_string()));
+    }
+}
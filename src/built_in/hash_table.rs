@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::super::env::{self, Env};
+use super::super::hash_table::{HashTable, HashTest};
+use super::super::number::Number;
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::{expect_args, keyword_name, predicate};
+use super::symbol::global_function;
+
+fn table(s: &Sexp) -> Result<Rc<RefCell<HashTable>>, String> {
+    match *s {
+        Sexp::HashTable(ref t) => Ok(t.clone()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The test a `:test` argument designates, by name or as one of the
+/// equality predicates themselves, as in `#'equal`.
+fn hash_test(test: &Sexp) -> Result<HashTest, String> {
+    let predicates = [(predicate::eq as fn(Vec<Sexp>) -> SexpResult, HashTest::Eq),
+                      (predicate::eql, HashTest::Eql),
+                      (predicate::equal, HashTest::Equal),
+                      (predicate::equalp, HashTest::Equalp)];
+    let found = match *test {
+        Sexp::Symbol(ref t) => HashTest::from_name(t),
+        Sexp::BuiltInFunc(f) => {
+            predicates.iter().find(|&&(p, _)| std::ptr::fn_addr_eq(p, f)).map(|&(_, t)| t)
+        }
+        _ => None,
+    };
+
+    found.ok_or_else(|| format!("Argument error: {}", test))
+}
+
+/// `(make-hash-table &key test size)`
+fn make_hash_table(args: Vec<Sexp>) -> SexpResult {
+    if !args.len().is_multiple_of(2) {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let mut test = HashTest::Eql;
+    for pair in args.chunks(2) {
+        match (keyword_name(&pair[0])?, &pair[1]) {
+            ("TEST", t) => test = hash_test(t)?,
+            ("SIZE", &Sexp::Number(_)) => {}
+            (_, v) => return Err(format!("Argument error: {}", v)),
+        }
+    }
+
+    Ok(Sexp::HashTable(Rc::new(RefCell::new(HashTable::new(test)))))
+}
+
+fn hash_table_p(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::HashTable(_) => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+/// `(gethash key table [default])` returns the value, or the default, and
+/// whether the key was present.
+fn gethash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+    let t = table(&args[1])?;
+    let t = t.borrow();
+
+    match t.get(&args[0]) {
+        Some(v) => Ok(Sexp::Values(vec![v.clone(), Sexp::True])),
+        None => Ok(Sexp::Values(vec![args.get(2).cloned().unwrap_or(Sexp::Nil), Sexp::Nil])),
+    }
+}
+
+/// `(setf (gethash key table [default]) value)`
+fn setf_gethash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 3, 4)?;
+    let t = table(&args[2])?;
+
+    t.borrow_mut().insert(args[1].clone(), args[0].clone());
+    Ok(args[0].clone())
+}
+
+fn remhash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let t = table(&args[1])?;
+    let removed = t.borrow_mut().remove(&args[0]);
+
+    Ok(if removed { Sexp::True } else { Sexp::Nil })
+}
+
+fn clrhash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    table(&args[0])?.borrow_mut().clear();
+    Ok(args[0].clone())
+}
+
+/// Calls the function with each key and value. The entries are collected
+/// first, so the function may modify the table.
+fn maphash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let f = global_function(&args[0])?;
+    let entries = table(&args[1])?.borrow().entries();
+
+    for (k, v) in entries {
+        f.apply(vec![k, v])?;
+    }
+
+    Ok(Sexp::Nil)
+}
+
+fn hash_table_count(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let count = table(&args[0])?.borrow().len();
+
+    Ok(Sexp::Number(Number::Fixnum(count as i64)))
+}
+
+fn hash_table_test(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let test = table(&args[0])?.borrow().test();
+
+    Ok(Sexp::Symbol(package::cl_symbol(test.name())))
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "make-hash-table".to_string(), Sexp::BuiltInFunc(make_hash_table));
+    env::env_set_function(env, "hash-table-p".to_string(), Sexp::BuiltInFunc(hash_table_p));
+    env::env_set_function(env, "gethash".to_string(), Sexp::BuiltInFunc(gethash));
+    env::env_set_setf_function(env, "gethash", Sexp::BuiltInFunc(setf_gethash));
+    env::env_set_function(env, "remhash".to_string(), Sexp::BuiltInFunc(remhash));
+    env::env_set_function(env, "clrhash".to_string(), Sexp::BuiltInFunc(clrhash));
+    env::env_set_function(env, "maphash".to_string(), Sexp::BuiltInFunc(maphash));
+    env::env_set_function(env, "hash-table-count".to_string(), Sexp::BuiltInFunc(hash_table_count));
+    env::env_set_function(env, "hash-table-test".to_string(), Sexp::BuiltInFunc(hash_table_test));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::super::super::hash_table::{HashTable, HashTest};
+    use super::super::super::number::Number;
+    use super::super::super::package;
+    use super::super::super::sexp::{Sexp, SexpResult};
+    use super::super::super::test_util::{int, string, symbol, eval_source};
+
+    fn cl(name: &str) -> Sexp {
+        Sexp::Symbol(package::cl_symbol(&name.to_uppercase()))
+    }
+
+    fn new_table(test: &str) -> Sexp {
+        super::make_hash_table(vec![symbol(":test"), cl(test)]).unwrap()
+    }
+
+    #[test]
+    fn test_make_hash_table() {
+        assert_eq!(super::make_hash_table(vec![]),
+                   Ok(Sexp::HashTable(Rc::new(RefCell::new(HashTable::new(HashTest::Eql))))));
+        assert_eq!(super::hash_table_test(vec![new_table("EQUALP")]), Ok(cl("EQUALP")));
+        assert_eq!(super::make_hash_table(vec![symbol(":test"), symbol("same")]),
+                   Err("Argument error: SAME".to_string()));
+        assert_eq!(super::make_hash_table(vec![symbol(":test")]),
+                   Err("Invalid number of arguments: 1".to_string()));
+        assert_eq!(super::hash_table_p(vec![new_table("eq")]), Ok(Sexp::True));
+        let equal = super::make_hash_table(vec![symbol(":test"), Sexp::BuiltInFunc(super::predicate::equal)]);
+        assert_eq!(super::hash_table_test(vec![equal.unwrap()]), Ok(cl("EQUAL")));
+        assert_eq!(eval_source("(make-hash-table :test '|equal|)"),
+                   Err("Argument error: |equal|".to_string()));
+        assert_eq!(super::make_hash_table(vec![symbol("test"), symbol("eq")]),
+                   Err("Argument error: TEST".to_string()));
+        assert_eq!(super::hash_table_p(vec![int(1)]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_gethash_and_setf() {
+        let table = new_table("equal");
+
+        assert_eq!(super::gethash(vec![string("a"), table.clone()]),
+                   Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])));
+        assert_eq!(super::gethash(vec![string("a"), table.clone(), int(0)]),
+                   Ok(Sexp::Values(vec![int(0), Sexp::Nil])));
+        assert_eq!(super::setf_gethash(vec![int(1), string("a"), table.clone()]), Ok(int(1)));
+        assert_eq!(super::gethash(vec![string("a"), table.clone()]),
+                   Ok(Sexp::Values(vec![int(1), Sexp::True])));
+        assert_eq!(super::hash_table_count(vec![table.clone()]), Ok(int(1)));
+        assert_eq!(super::gethash(vec![string("A"), table]),
+                   Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])));
+    }
+
+    #[test]
+    fn test_equalp_keys() {
+        let table = new_table("equalp");
+
+        super::setf_gethash(vec![int(1), string("Key"), table.clone()]).unwrap();
+        super::setf_gethash(vec![int(2), int(1), table.clone()]).unwrap();
+        assert_eq!(super::gethash(vec![string("KEY"), table.clone()]),
+                   Ok(Sexp::Values(vec![int(1), Sexp::True])));
+        assert_eq!(super::gethash(vec![Sexp::Number(Number::Float(1.0)), table]),
+                   Ok(Sexp::Values(vec![int(2), Sexp::True])));
+    }
+
+    #[test]
+    fn test_remhash_and_clrhash() {
+        let table = new_table("eql");
+        super::setf_gethash(vec![int(1), int(1), table.clone()]).unwrap();
+        super::setf_gethash(vec![int(2), int(2), table.clone()]).unwrap();
+
+        assert_eq!(super::remhash(vec![int(1), table.clone()]), Ok(Sexp::True));
+        assert_eq!(super::remhash(vec![int(1), table.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::hash_table_count(vec![table.clone()]), Ok(int(1)));
+        assert_eq!(super::clrhash(vec![table.clone()]), Ok(table.clone()));
+        assert_eq!(super::hash_table_count(vec![table]), Ok(int(0)));
+    }
+
+    fn list(args: Vec<Sexp>) -> SexpResult {
+        Ok(Sexp::list(args))
+    }
+
+    #[test]
+    fn test_maphash() {
+        let table = new_table("eql");
+        super::setf_gethash(vec![int(10), int(1), table.clone()]).unwrap();
+
+        assert_eq!(super::maphash(vec![Sexp::BuiltInFunc(list), table.clone()]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::maphash(vec![Sexp::BuiltInFunc(super::remhash), table.clone()]),
+                   Err("Argument error: 10".to_string()));
+        assert_eq!(super::maphash(vec![int(1), table]),
+                   Err("Illegal function call".to_string()));
+    }
+}
@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Weak;
+use super::super::env::{self, Env, EnvData};
+use super::super::package;
+use super::super::parser;
+use super::super::readtable;
+use super::super::sexp::{self, Sexp, SexpResult};
+use super::super::stream::Stream;
+use super::{define_global, global, keyword_name};
+
+/// The variables `load` binds for the file it loads, and restores after.
+const LOAD_VARIABLES: &[&str] = &["*LOAD-PATHNAME*", "*LOAD-TRUENAME*", "*PACKAGE*", "*READTABLE*"];
+
+/// Reads and evaluates the forms of the file at `path` in `env`, with
+/// `*load-pathname*` and `*load-truename*` naming the file, and `*package*`
+/// and `*readtable*` restored afterwards so that an `in-package` in the file
+/// doesn't leak out. A first line starting with `#!` is skipped, so that
+/// scripts can be made executable. With `print`, the value of each form is
+/// printed.
+pub fn load_file(env: &Env, path: &str, verbose: bool, print: bool) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Can't load {}: {}", path, e))?;
+    let source = match source.strip_prefix("#!") {
+        Some(rest) => rest.find('\n').map_or("", |i| &rest[i..]),
+        None => &source[..],
+    };
+    let truename = fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string());
+
+    let saved: Vec<Option<Sexp>> = LOAD_VARIABLES.iter()
+        .map(|&name| env::env_get(env, package::cl_symbol(name)))
+        .collect();
+    let package = package::current();
+    env::env_set(env, package::cl_symbol("*LOAD-PATHNAME*"), Sexp::string(path));
+    env::env_set(env, package::cl_symbol("*LOAD-TRUENAME*"), Sexp::string(truename));
+    if verbose {
+        Stream::Stdout.fresh_line();
+        Stream::Stdout.write_str(&format!("; Loading {}\n", path));
+    }
+
+    let result = load_forms(env, &Stream::string_input(source), print);
+
+    for (&name, value) in LOAD_VARIABLES.iter().zip(saved) {
+        env::env_set(env, package::cl_symbol(name), value.unwrap_or(Sexp::Nil));
+    }
+    package::set_current(package);
+    readtable::use_env(env);
+    result
+}
+
+/// Reads and evaluates the forms of `stream` in `env`, printing each value
+/// with `print`.
+pub fn load_forms(env: &Env, stream: &Stream, print: bool) -> Result<(), String> {
+    loop {
+        readtable::use_env(env);
+        let value = match parser::read(stream)? {
+            Some(form) => form.eval(env)?,
+            None => return Ok(()),
+        };
+
+        if print {
+            sexp::check_printable(&value)?;
+            Stream::Stdout.fresh_line();
+            Stream::Stdout.write_str(&format!("{}\n", value));
+        }
+    }
+}
+
+/// `(load filespec &key verbose print if-does-not-exist)` returns t, or nil
+/// if the file doesn't exist and `if-does-not-exist` is nil.
+fn load(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() || args.len().is_multiple_of(2) {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+    let path = match args[0] {
+        Sexp::String(ref s) => s.to_string(),
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    let (mut verbose, mut print, mut must_exist) = (false, false, true);
+    for pair in args[1..].chunks(2) {
+        match keyword_name(&pair[0])? {
+            "VERBOSE" => verbose = pair[1] != Sexp::Nil,
+            "PRINT" => print = pair[1] != Sexp::Nil,
+            "IF-DOES-NOT-EXIST" => must_exist = pair[1] != Sexp::Nil,
+            _ => return Err(format!("Argument error: {}", pair[0])),
+        }
+    }
+
+    if !must_exist && !Path::new(&path).exists() {
+        return Ok(Sexp::Nil);
+    }
+    load_file(&global(env)?, &path, verbose, print)?;
+    Ok(Sexp::True)
+}
+
+pub fn register(env: &Env) {
+    env::env_set(env, "*load-pathname*".to_string(), Sexp::Nil);
+    env::env_set(env, "*load-truename*".to_string(), Sexp::Nil);
+    define_global(env, "load", load);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::rc::Rc;
+    use super::super::super::env;
+    use super::super::super::number::Number;
+    use super::super::super::sexp::Sexp;
+    use super::super::default_env;
+
+    #[test]
+    fn test_load() {
+        let path = temp_dir().join(format!("rust-lisp-load-{}.lisp", std::process::id()));
+        let path_name = path.to_string_lossy().into_owned();
+        fs::write(&path, "#!/usr/bin/env rust-lisp\n(defparameter x 1)\n(defparameter from *load-pathname*)\n")
+            .unwrap();
+        let env = default_env();
+        let global = Rc::downgrade(&env);
+
+        assert_eq!(super::load(&global, vec![Sexp::string(path_name.clone())]), Ok(Sexp::True));
+        assert_eq!(env::env_get(&env, "x"), Some(Sexp::Number(Number::Fixnum(1))));
+        assert_eq!(env::env_get(&env, "from"), Some(Sexp::string(path_name.clone())));
+        assert_eq!(env::env_get(&env, "*load-pathname*"), Some(Sexp::Nil));
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(super::load(&global,
+                               vec![Sexp::string(path_name.clone()),
+                                    Sexp::symbol(":if-does-not-exist"),
+                                    Sexp::Nil]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::load(&global,
+                               vec![Sexp::string(path_name.clone()),
+                                    Sexp::symbol("if-does-not-exist"),
+                                    Sexp::Nil]),
+                   Err("Argument error: IF-DOES-NOT-EXIST".to_string()));
+        assert!(super::load(&global, vec![Sexp::string(path_name)])
+            .unwrap_err()
+            .starts_with("Can't load"));
+    }
+}
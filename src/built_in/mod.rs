@@ -0,0 +1,1188 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::{Rc, Weak};
+use num::{BigInt, BigRational, Integer, One, Signed, ToPrimitive, Zero};
+use super::env::{self, Env, EnvData};
+use super::number::{self, Number, Rounding};
+use super::random::{RandomState, RandomStateData};
+use super::sexp::{Closure, Sexp, SexpResult};
+use super::symbol::Symbol;
+
+macro_rules! unpack_args {
+    ($src:expr, N $rest:path) => {{
+        unpack_arg!($src, 0usize, N $rest)
+    }};
+
+    ($src:expr, $(1 $t:path),+, N $rest:path) => {{
+        let mut index = 0usize;
+
+        (
+            $({
+                index += 1;
+                unpack_arg!($src, index - 1, 1 $t)
+            }),*,
+            unpack_arg!($src, index, N $rest)
+        )
+    }};
+
+    ($src:expr, 1 $t:path) => {{
+        unpack_arity!($src, 1usize);
+        unpack_arg!($src, 0usize, 1 $t)
+    }};
+
+    ($src:expr, $(1 $t:path),+) => {{
+        unpack_arity!($src, [$(stringify!($t)),+].len());
+        let mut index = 0usize;
+
+        (
+            $({
+                index += 1;
+                unpack_arg!($src, index - 1, 1 $t)
+            }),*
+        )
+    }};
+}
+
+macro_rules! unpack_arity {
+    ($src:expr, $count:expr) => {{
+        let len = $src.iter().len();
+
+        if len != $count {
+            return Err(format!("Invalid number of arguments: {}", len))
+        }
+    }};
+}
+
+macro_rules! unpack_arg {
+    ($src:expr, $index:expr, N $rest:path) => {{
+        let tmp: Result<Vec<_>, String> = $src.iter()
+            .skip($index)
+            .map(|i| {
+                match *i {
+                    $rest(ref n) => Ok(n.clone()),
+                    ref v => Err(format!("Argument error: {}", v)),
+                }
+            })
+            .collect();
+
+        if let Err(m) = tmp { return Err(m) }
+
+        tmp.unwrap()
+    }};
+
+    ($src:expr, $index:expr, 1 $t:path) => {{
+        let tmp = $src.iter().nth($index);
+
+        if tmp.is_none() {
+            return Err(format!("Invalid number of arguments: {}", $src.iter().len()))
+        }
+
+        match *tmp.unwrap() {
+            $t(ref n) => n.clone(),
+            ref v => return Err(format!("Argument error: {}", v)),
+        }
+    }};
+}
+
+mod array;
+mod character;
+mod clos;
+mod format;
+mod hash_table;
+mod load;
+mod package;
+mod predicate;
+mod readtable;
+mod sequence;
+mod stream;
+mod string;
+mod symbol;
+
+pub use self::load::{load_file, load_forms};
+
+fn expect_args(args: &[Sexp], min: usize, max: usize) -> Result<(), String> {
+    let len = args.len();
+
+    if len < min || len > max {
+        Err(format!("Invalid number of arguments: {}", len))
+    } else {
+        Ok(())
+    }
+}
+
+/// The name of the keyword `key`, to match keyword arguments against.
+fn keyword_name(key: &Sexp) -> Result<&str, String> {
+    match *key {
+        Sexp::Symbol(ref k) if k.is_keyword() => Ok(k.name()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The global environment, which built-ins such as `symbol-value`
+/// see rather than any lexical bindings.
+fn global(env: &Weak<RefCell<EnvData>>) -> Result<Env, String> {
+    env.upgrade().ok_or_else(|| "The global environment is gone".to_string())
+}
+
+/// Defines a built-in that is passed the global environment along with its
+/// arguments.
+fn define_global<F>(env: &Env, name: &str, f: F)
+    where F: Fn(&Weak<RefCell<EnvData>>, Vec<Sexp>) -> SexpResult + 'static
+{
+    env::env_set_function(env, name, global_closure(env, f));
+}
+
+/// A function that calls `f` with the global environment `env`.
+fn global_closure<F>(env: &Env, f: F) -> Sexp
+    where F: Fn(&Weak<RefCell<EnvData>>, Vec<Sexp>) -> SexpResult + 'static
+{
+    let global = Rc::downgrade(env);
+
+    Sexp::Closure(Closure::new(move |args| f(&global, args)))
+}
+
+fn real(n: Number) -> Result<Number, String> {
+    if n.is_complex() {
+        Err(format!("Argument error: {}", n))
+    } else {
+        Ok(n)
+    }
+}
+
+fn reals(ns: Vec<Number>) -> Result<Vec<Number>, String> {
+    ns.into_iter().map(real).collect()
+}
+
+fn unpack_integers(args: &[Sexp]) -> Result<Vec<BigInt>, String> {
+    args.iter()
+        .map(|a| {
+            match *a {
+                Sexp::Number(ref n) => n.to_bigint(),
+                _ => None,
+            }
+            .ok_or_else(|| format!("Argument error: {}", a))
+        })
+        .collect()
+}
+
+fn add(args: Vec<Sexp>) -> SexpResult {
+    let ns = unpack_args!(args, N Sexp::Number);
+
+    Ok(Sexp::Number(ns.iter().fold(Number::Fixnum(0), |sum, n| &sum + n)))
+}
+
+fn subtract(args: Vec<Sexp>) -> SexpResult {
+    let (first, rest) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+
+    if rest.is_empty() {
+        Ok(Sexp::Number(-&first))
+    } else {
+        Ok(Sexp::Number(rest.iter().fold(first, |result, n| &result - n)))
+    }
+}
+
+fn multiply(args: Vec<Sexp>) -> SexpResult {
+    let (first, rest) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+
+    Ok(Sexp::Number(rest.iter().fold(first, |result, n| &result * n)))
+}
+
+fn divide(args: Vec<Sexp>) -> SexpResult {
+    let (first, rest) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+
+    if rest.is_empty() {
+        return Number::Fixnum(1).checked_div(&first).map(Sexp::Number);
+    }
+
+    rest.iter().try_fold(first, |result, n| result.checked_div(n)).map(Sexp::Number)
+}
+
+fn rounding_division(args: Vec<Sexp>, rounding: Rounding) -> Result<(Number, Number), String> {
+    expect_args(&args, 1, 2)?;
+    let (n, rest) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+    let divisor = rest.into_iter().next().unwrap_or(Number::Fixnum(1));
+
+    n.divide_rounding(&divisor, rounding)
+}
+
+/// The quotient and the remainder of a rounding division, as two values.
+fn quotient_and_remainder(args: Vec<Sexp>, rounding: Rounding) -> SexpResult {
+    let (q, r) = rounding_division(args, rounding)?;
+
+    Ok(Sexp::Values(vec![Sexp::Number(q), Sexp::Number(r)]))
+}
+
+fn floor(args: Vec<Sexp>) -> SexpResult {
+    quotient_and_remainder(args, Rounding::Floor)
+}
+
+fn truncate(args: Vec<Sexp>) -> SexpResult {
+    quotient_and_remainder(args, Rounding::Truncate)
+}
+
+fn modulo(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    rounding_division(args, Rounding::Floor).map(|(_, r)| Sexp::Number(r))
+}
+
+fn rem(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    rounding_division(args, Rounding::Truncate).map(|(_, r)| Sexp::Number(r))
+}
+
+fn gcd(args: Vec<Sexp>) -> SexpResult {
+    let ns = unpack_integers(&args)?;
+
+    Ok(Sexp::Number(Number::from_bigint(ns.iter().fold(BigInt::zero(), |a, b| a.gcd(b)))))
+}
+
+fn lcm(args: Vec<Sexp>) -> SexpResult {
+    let ns = unpack_integers(&args)?;
+
+    Ok(Sexp::Number(Number::from_bigint(ns.iter().fold(BigInt::one(), |a, b| a.lcm(b)))))
+}
+
+fn expt(args: Vec<Sexp>) -> SexpResult {
+    let (base, power) = unpack_args!(args, 1 Sexp::Number, 1 Sexp::Number);
+
+    base.expt(&power).map(Sexp::Number)
+}
+
+fn rational_part<F>(args: Vec<Sexp>, f: F) -> SexpResult
+    where F: Fn(&BigRational) -> &BigInt
+{
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    match n.to_ratio() {
+        Some(r) => Ok(Sexp::Number(Number::from_bigint(f(&r).clone()))),
+        None => Err(format!("Argument error: {}", n)),
+    }
+}
+
+fn numerator(args: Vec<Sexp>) -> SexpResult {
+    rational_part(args, BigRational::numer)
+}
+
+fn denominator(args: Vec<Sexp>) -> SexpResult {
+    rational_part(args, BigRational::denom)
+}
+
+fn float(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let (n, _) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+
+    Ok(Sexp::Number(Number::Float(real(n)?.to_f64())))
+}
+
+fn ceiling(args: Vec<Sexp>) -> SexpResult {
+    quotient_and_remainder(args, Rounding::Ceiling)
+}
+
+fn round(args: Vec<Sexp>) -> SexpResult {
+    quotient_and_remainder(args, Rounding::Round)
+}
+
+fn abs(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.abs()))
+}
+
+fn signum(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.signum()))
+}
+
+fn extremum(args: Vec<Sexp>, wanted: Ordering) -> SexpResult {
+    let (first, rest) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+    let (first, rest) = (real(first)?, reals(rest)?);
+
+    Ok(Sexp::Number(rest.into_iter().fold(first, |best, n| {
+        if n.num_cmp(&best) == Some(wanted) { n } else { best }
+    })))
+}
+
+fn min(args: Vec<Sexp>) -> SexpResult {
+    extremum(args, Ordering::Less)
+}
+
+fn max(args: Vec<Sexp>) -> SexpResult {
+    extremum(args, Ordering::Greater)
+}
+
+fn one_plus(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(&n + &Number::Fixnum(1)))
+}
+
+fn one_minus(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(&n - &Number::Fixnum(1)))
+}
+
+fn sqrt(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    n.sqrt().map(Sexp::Number)
+}
+
+fn float_function(args: Vec<Sexp>, f: fn(f64) -> f64) -> SexpResult {
+    let n = real(unpack_args!(args, 1 Sexp::Number))?;
+
+    Ok(Sexp::Number(Number::Float(f(n.to_f64()))))
+}
+
+fn exp(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.exp()))
+}
+
+fn log(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let (n, base) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+
+    if n.is_zero() {
+        return Err("Division by zero".to_string());
+    }
+
+    match base.first() {
+        Some(b) => n.ln().checked_div(&b.ln()).map(Sexp::Number),
+        None => Ok(Sexp::Number(n.ln())),
+    }
+}
+
+fn realpart(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.parts().0))
+}
+
+fn imagpart(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.parts().1))
+}
+
+fn conjugate(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(n.conjugate()))
+}
+
+fn phase(args: Vec<Sexp>) -> SexpResult {
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    Ok(Sexp::Number(Number::Float(n.phase())))
+}
+
+fn complex(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let (r, i) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+    let i = i.into_iter().next().unwrap_or(Number::Fixnum(0));
+
+    Ok(Sexp::Number(Number::complex(real(r)?, real(i)?)))
+}
+
+fn complexp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Number(ref n) if n.is_complex() => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn sin(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::sin)
+}
+
+fn cos(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::cos)
+}
+
+fn tan(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::tan)
+}
+
+fn asin(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::asin)
+}
+
+fn acos(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::acos)
+}
+
+fn atan(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let (y, x) = unpack_args!(args, 1 Sexp::Number, N Sexp::Number);
+    let (y, x) = (real(y)?, reals(x)?);
+
+    match x.first() {
+        Some(x) => Ok(Sexp::Number(Number::Float(y.to_f64().atan2(x.to_f64())))),
+        None => Ok(Sexp::Number(Number::Float(y.to_f64().atan()))),
+    }
+}
+
+fn sinh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::sinh)
+}
+
+fn cosh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::cosh)
+}
+
+fn tanh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::tanh)
+}
+
+fn asinh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::asinh)
+}
+
+fn acosh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::acosh)
+}
+
+fn atanh(args: Vec<Sexp>) -> SexpResult {
+    float_function(args, f64::atanh)
+}
+
+fn numeric_predicate<F>(args: Vec<Sexp>, f: F) -> SexpResult
+    where F: Fn(&Number) -> bool
+{
+    let n = unpack_args!(args, 1 Sexp::Number);
+
+    if f(&n) { Ok(Sexp::True) } else { Ok(Sexp::Nil) }
+}
+
+fn zerop(args: Vec<Sexp>) -> SexpResult {
+    numeric_predicate(args, Number::is_zero)
+}
+
+fn plusp(args: Vec<Sexp>) -> SexpResult {
+    numeric_predicate(args, |n| n.num_cmp(&Number::Fixnum(0)) == Some(Ordering::Greater))
+}
+
+fn minusp(args: Vec<Sexp>) -> SexpResult {
+    numeric_predicate(args, Number::is_negative)
+}
+
+fn parity(args: Vec<Sexp>, even: bool) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let n = unpack_integers(&args)?;
+
+    if n[0].is_even() == even { Ok(Sexp::True) } else { Ok(Sexp::Nil) }
+}
+
+fn evenp(args: Vec<Sexp>) -> SexpResult {
+    parity(args, true)
+}
+
+fn oddp(args: Vec<Sexp>) -> SexpResult {
+    parity(args, false)
+}
+
+fn logical_operation<F>(args: Vec<Sexp>, identity: i64, f: F) -> SexpResult
+    where F: Fn(BigInt, &BigInt) -> BigInt
+{
+    let ns = unpack_integers(&args)?;
+
+    Ok(Sexp::Number(Number::from_bigint(ns.iter().fold(BigInt::from(identity), f))))
+}
+
+fn logand(args: Vec<Sexp>) -> SexpResult {
+    logical_operation(args, -1, |a, b| a & b)
+}
+
+fn logior(args: Vec<Sexp>) -> SexpResult {
+    logical_operation(args, 0, |a, b| a | b)
+}
+
+fn logxor(args: Vec<Sexp>) -> SexpResult {
+    logical_operation(args, 0, |a, b| a ^ b)
+}
+
+fn ash(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let ns = unpack_integers(&args)?;
+
+    let (n, count) = (&ns[0], &ns[1]);
+
+    let shifted = if count.is_negative() {
+        // Shifting right past every bit leaves only the sign.
+        let count = count.magnitude().to_u64().unwrap_or(u64::MAX).min(n.bits() + 1);
+        n >> count as usize
+    } else if n.is_zero() {
+        BigInt::zero()
+    } else {
+        match count.to_u64().and_then(|c| c.checked_add(n.bits())) {
+            Some(bits) if bits <= number::MAX_INTEGER_BITS => n << (bits - n.bits()) as usize,
+            _ => return Err(format!("The result of (ASH {} {}) is too large", n, count)),
+        }
+    };
+
+    Ok(Sexp::Number(Number::from_bigint(shifted)))
+}
+
+/// The optional random state argument at `index`, or the value of `*random-state*`.
+fn random_state_arg(env: &Weak<RefCell<EnvData>>, args: &[Sexp], index: usize) -> Result<RandomState, String> {
+    match args.get(index) {
+        Some(Sexp::RandomState(state)) => Ok(state.clone()),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => {
+            match env::env_get(&global(env)?, super::package::cl_symbol("*RANDOM-STATE*")) {
+                Some(Sexp::RandomState(state)) => Ok(state),
+                Some(v) => Err(format!("Argument error: {}", v)),
+                None => Err("The variable *RANDOM-STATE* is unbound".to_string()),
+            }
+        }
+    }
+}
+
+fn random(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let limit = match args[0] {
+        Sexp::Number(ref n) => real(n.clone())?,
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+    let state = random_state_arg(env, &args, 1)?;
+
+    if limit.is_negative() || limit.is_zero() {
+        return Err(format!("Argument error: {}", limit));
+    }
+
+    match limit.to_bigint() {
+        Some(n) => {
+            let words = (n.bits() / 64 + 1) as usize;
+            let random = (0..words).fold(BigInt::zero(), |r, _| (r << 64) + state.next_u64());
+            Ok(Sexp::Number(Number::from_bigint(random.mod_floor(&n))))
+        }
+        None => {
+            let unit = (state.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+            Ok(Sexp::Number(Number::Float(unit * limit.to_f64())))
+        }
+    }
+}
+
+/// Copies the given random state or, given `nil`, `*random-state*`; given
+/// `t`, makes a new one seeded from the clock.
+fn make_random_state(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 1)?;
+
+    let state = match args.first() {
+        Some(Sexp::True) => RandomStateData::fresh(),
+        Some(Sexp::Nil) | None => random_state_arg(env, &[], 0)?.copy(),
+        Some(_) => random_state_arg(env, &args, 0)?.copy(),
+    };
+
+    Ok(Sexp::RandomState(state))
+}
+
+fn random_state_p(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(if let Sexp::RandomState(_) = args[0] { Sexp::True } else { Sexp::Nil })
+}
+
+fn numberp(args: Vec<Sexp>) -> SexpResult {
+    let len = args.len();
+    if len != 1 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    match args[0] {
+        Sexp::Number(_) => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn numeric_comparison<F>(args: Vec<Sexp>, f: F) -> SexpResult
+    where F: Fn(Ordering) -> bool
+{
+    let len = args.len();
+    if len == 0 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let ns = reals(unpack_args!(args, N Sexp::Number))?;
+
+    if ns.iter().zip(ns.iter().skip(1)).all(|(a, b)| a.num_cmp(b).is_some_and(&f)) {
+        Ok(Sexp::True)
+    } else {
+        Ok(Sexp::Nil)
+    }
+}
+
+fn num_eq(args: Vec<Sexp>) -> SexpResult {
+    let len = args.len();
+    if len == 0 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let ns = unpack_args!(args, N Sexp::Number);
+
+    if ns.iter().zip(ns.iter().skip(1)).all(|(a, b)| a.num_eq(b)) {
+        Ok(Sexp::True)
+    } else {
+        Ok(Sexp::Nil)
+    }
+}
+fn num_ne(args: Vec<Sexp>) -> SexpResult {
+    let len = args.len();
+    if len == 0 {
+        return Err(format!("Invalid number of arguments: {}", len));
+    }
+
+    let ns = unpack_args!(args, N Sexp::Number);
+
+    let distinct = ns.iter()
+        .enumerate()
+        .all(|(i, a)| ns[i + 1..].iter().all(|b| !a.num_eq(b)));
+    if distinct { Ok(Sexp::True) } else { Ok(Sexp::Nil) }
+}
+fn lt(args: Vec<Sexp>) -> SexpResult {
+    numeric_comparison(args, |o| o == Ordering::Less)
+}
+fn lte(args: Vec<Sexp>) -> SexpResult {
+    numeric_comparison(args, |o| o != Ordering::Greater)
+}
+fn gt(args: Vec<Sexp>) -> SexpResult {
+    numeric_comparison(args, |o| o == Ordering::Greater)
+}
+fn gte(args: Vec<Sexp>) -> SexpResult {
+    numeric_comparison(args, |o| o != Ordering::Less)
+}
+
+fn values(args: Vec<Sexp>) -> SexpResult {
+    if args.len() == 1 {
+        Ok(args.into_iter().next().unwrap())
+    } else {
+        Ok(Sexp::Values(args))
+    }
+}
+
+fn list(args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() {
+        Ok(Sexp::Nil)
+    } else {
+        Ok(Sexp::list(args))
+    }
+}
+
+/// The keywords `*features*` starts with: the interpreter's name, the
+/// platform it was built for and the enabled cargo features.
+fn features() -> Sexp {
+    let mut names = vec!["rust-lisp".to_string(),
+                         std::env::consts::OS.to_string(),
+                         std::env::consts::ARCH.replace('_', "-")];
+    if cfg!(unix) {
+        names.push("unix".to_string());
+    }
+    let width = if cfg!(target_pointer_width = "64") { "64-bit" } else { "32-bit" };
+    let endian = if cfg!(target_endian = "little") { "little-endian" } else { "big-endian" };
+    names.push(width.to_string());
+    names.push(endian.to_string());
+    let cargo_features = env!("RUST_LISP_CARGO_FEATURES").split(',').filter(|f| !f.is_empty());
+    names.extend(cargo_features.map(String::from));
+
+    Sexp::list(names.iter()
+        .map(|name| Sexp::Symbol(Symbol::keyword(&name.to_uppercase())))
+        .collect())
+}
+
+/// The global environment, with the built-ins as the external symbols of the
+/// locked `common-lisp` package.
+pub fn default_env() -> Env {
+    let env = env::env_new(None);
+    let user = super::package::current();
+    let cl = super::package::find_package("COMMON-LISP").unwrap();
+    super::package::set_current(cl.clone());
+    env::env_set(&env, "t".to_string(), Sexp::True);
+    env::env_set(&env, "nil".to_string(), Sexp::Nil);
+    env::env_set_function(&env, "values".to_string(), Sexp::BuiltInFunc(values));
+    env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+
+    env::env_set_function(&env, "+".to_string(), Sexp::BuiltInFunc(add));
+    env::env_set_function(&env, "-".to_string(), Sexp::BuiltInFunc(subtract));
+    env::env_set_function(&env, "*".to_string(), Sexp::BuiltInFunc(multiply));
+    env::env_set_function(&env, "/".to_string(), Sexp::BuiltInFunc(divide));
+    env::env_set_function(&env, "numberp".to_string(), Sexp::BuiltInFunc(numberp));
+    env::env_set_function(&env, "<".to_string(), Sexp::BuiltInFunc(lt));
+    env::env_set_function(&env, "<=".to_string(), Sexp::BuiltInFunc(lte));
+    env::env_set_function(&env, ">".to_string(), Sexp::BuiltInFunc(gt));
+    env::env_set_function(&env, ">=".to_string(), Sexp::BuiltInFunc(gte));
+    env::env_set_function(&env, "floor".to_string(), Sexp::BuiltInFunc(floor));
+    env::env_set_function(&env, "truncate".to_string(), Sexp::BuiltInFunc(truncate));
+    env::env_set_function(&env, "mod".to_string(), Sexp::BuiltInFunc(modulo));
+    env::env_set_function(&env, "rem".to_string(), Sexp::BuiltInFunc(rem));
+    env::env_set_function(&env, "gcd".to_string(), Sexp::BuiltInFunc(gcd));
+    env::env_set_function(&env, "lcm".to_string(), Sexp::BuiltInFunc(lcm));
+    env::env_set_function(&env, "expt".to_string(), Sexp::BuiltInFunc(expt));
+    env::env_set_function(&env, "numerator".to_string(), Sexp::BuiltInFunc(numerator));
+    env::env_set_function(&env, "denominator".to_string(), Sexp::BuiltInFunc(denominator));
+    env::env_set_function(&env, "float".to_string(), Sexp::BuiltInFunc(float));
+    env::env_set_function(&env, "=".to_string(), Sexp::BuiltInFunc(num_eq));
+    env::env_set_function(&env, "/=".to_string(), Sexp::BuiltInFunc(num_ne));
+    env::env_set_function(&env, "ceiling".to_string(), Sexp::BuiltInFunc(ceiling));
+    env::env_set_function(&env, "round".to_string(), Sexp::BuiltInFunc(round));
+    env::env_set_function(&env, "abs".to_string(), Sexp::BuiltInFunc(abs));
+    env::env_set_function(&env, "signum".to_string(), Sexp::BuiltInFunc(signum));
+    env::env_set_function(&env, "min".to_string(), Sexp::BuiltInFunc(min));
+    env::env_set_function(&env, "max".to_string(), Sexp::BuiltInFunc(max));
+    env::env_set_function(&env, "1+".to_string(), Sexp::BuiltInFunc(one_plus));
+    env::env_set_function(&env, "1-".to_string(), Sexp::BuiltInFunc(one_minus));
+    env::env_set_function(&env, "sqrt".to_string(), Sexp::BuiltInFunc(sqrt));
+    env::env_set_function(&env, "exp".to_string(), Sexp::BuiltInFunc(exp));
+    env::env_set_function(&env, "log".to_string(), Sexp::BuiltInFunc(log));
+    env::env_set_function(&env, "sin".to_string(), Sexp::BuiltInFunc(sin));
+    env::env_set_function(&env, "cos".to_string(), Sexp::BuiltInFunc(cos));
+    env::env_set_function(&env, "tan".to_string(), Sexp::BuiltInFunc(tan));
+    env::env_set_function(&env, "asin".to_string(), Sexp::BuiltInFunc(asin));
+    env::env_set_function(&env, "acos".to_string(), Sexp::BuiltInFunc(acos));
+    env::env_set_function(&env, "atan".to_string(), Sexp::BuiltInFunc(atan));
+    env::env_set_function(&env, "sinh".to_string(), Sexp::BuiltInFunc(sinh));
+    env::env_set_function(&env, "cosh".to_string(), Sexp::BuiltInFunc(cosh));
+    env::env_set_function(&env, "tanh".to_string(), Sexp::BuiltInFunc(tanh));
+    env::env_set_function(&env, "asinh".to_string(), Sexp::BuiltInFunc(asinh));
+    env::env_set_function(&env, "acosh".to_string(), Sexp::BuiltInFunc(acosh));
+    env::env_set_function(&env, "atanh".to_string(), Sexp::BuiltInFunc(atanh));
+    env::env_set_function(&env, "zerop".to_string(), Sexp::BuiltInFunc(zerop));
+    env::env_set_function(&env, "plusp".to_string(), Sexp::BuiltInFunc(plusp));
+    env::env_set_function(&env, "minusp".to_string(), Sexp::BuiltInFunc(minusp));
+    env::env_set_function(&env, "evenp".to_string(), Sexp::BuiltInFunc(evenp));
+    env::env_set_function(&env, "oddp".to_string(), Sexp::BuiltInFunc(oddp));
+    env::env_set_function(&env, "logand".to_string(), Sexp::BuiltInFunc(logand));
+    env::env_set_function(&env, "logior".to_string(), Sexp::BuiltInFunc(logior));
+    env::env_set_function(&env, "logxor".to_string(), Sexp::BuiltInFunc(logxor));
+    env::env_set_function(&env, "ash".to_string(), Sexp::BuiltInFunc(ash));
+    env::env_set(&env, "*random-state*".to_string(), Sexp::RandomState(RandomStateData::fresh()));
+    define_global(&env, "random", random);
+    define_global(&env, "make-random-state", make_random_state);
+    env::env_set_function(&env, "random-state-p".to_string(), Sexp::BuiltInFunc(random_state_p));
+    env::env_set_function(&env, "complex".to_string(), Sexp::BuiltInFunc(complex));
+    env::env_set_function(&env, "complexp".to_string(), Sexp::BuiltInFunc(complexp));
+    env::env_set_function(&env, "realpart".to_string(), Sexp::BuiltInFunc(realpart));
+    env::env_set_function(&env, "imagpart".to_string(), Sexp::BuiltInFunc(imagpart));
+    env::env_set_function(&env, "conjugate".to_string(), Sexp::BuiltInFunc(conjugate));
+    env::env_set_function(&env, "phase".to_string(), Sexp::BuiltInFunc(phase));
+
+    character::register(&env);
+    string::register(&env);
+    format::register(&env);
+    hash_table::register(&env);
+    stream::register(&env);
+    array::register(&env);
+    sequence::register(&env);
+    clos::register(&env);
+    predicate::register(&env);
+    symbol::register(&env);
+    package::register(&env);
+    readtable::register(&env);
+    load::register(&env);
+
+    env::env_set(&env, "*features*".to_string(), features());
+    env::env_set(&env, "*package*".to_string(), Sexp::Package(user.clone()));
+    cl.export_all();
+    super::package::set_current(user);
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sexp::Sexp;
+    use super::super::number::Number;
+    use super::super::test_util::{int, ratio, float, eval_source};
+    use num::BigInt;
+
+    #[test]
+    fn test_add() {
+        assert_eq!(super::add(vec![int(1)]), Ok(int(1)));
+        assert_eq!(super::add(vec![int(1), int(2), int(3)]),
+                   Ok(int(6)));
+        assert_eq!(super::add(vec![Sexp::string("3")]),
+                   Err("Argument error: \"3\"".to_string()));
+    }
+
+    #[test]
+    fn test_subtract() {
+        assert_eq!(super::subtract(vec![int(1)]),
+                   Ok(int(-1)));
+        assert_eq!(super::subtract(vec![int(1), int(2), int(3)]),
+                   Ok(int(-4)));
+        assert_eq!(super::subtract(vec![Sexp::string("3")]),
+                   Err("Argument error: \"3\"".to_string()));
+        assert_eq!(super::subtract(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_multiply() {
+        assert_eq!(super::multiply(vec![int(1)]),
+                   Ok(int(1)));
+        assert_eq!(super::multiply(vec![int(1), int(2), int(3)]),
+                   Ok(int(6)));
+        assert_eq!(super::multiply(vec![Sexp::string("3")]),
+                   Err("Argument error: \"3\"".to_string()));
+    }
+
+    #[test]
+    fn test_divide() {
+        assert_eq!(super::divide(vec![int(1)]), Ok(int(1)));
+        assert_eq!(super::divide(vec![int(1), int(2), int(3)]),
+                   Ok(ratio(1, 6)));
+        assert_eq!(super::divide(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(super::divide(vec![Sexp::string("3")]),
+                   Err("Argument error: \"3\"".to_string()));
+        assert_eq!(super::divide(vec![int(1), int(0)]),
+                   Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_numberp() {
+        assert_eq!(super::numberp(vec![int(1)]), Ok(Sexp::True));
+        assert_eq!(super::numberp(vec![Sexp::True]), Ok(Sexp::Nil));
+        assert_eq!(super::numberp(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(super::numberp(vec![Sexp::True, Sexp::True]),
+                   Err("Invalid number of arguments: 2".to_string()));
+    }
+
+    #[test]
+    fn test_lt() {
+        assert_eq!(super::lt(vec![int(1)]), Ok(Sexp::True));
+        assert_eq!(super::lt(vec![int(1), int(2)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::lt(vec![int(1), int(2), int(3)]),
+                   Ok(Sexp::True));
+
+        assert_eq!(super::lt(vec![int(2), int(1)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::lt(vec![int(2), int(2)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::lt(vec![int(1), int(3), int(2)]),
+                   Ok(Sexp::Nil));
+
+        assert_eq!(super::lt(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_lte() {
+        assert_eq!(super::lte(vec![int(1)]), Ok(Sexp::True));
+        assert_eq!(super::lte(vec![int(1), int(2)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::lte(vec![int(2), int(2)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::lte(vec![int(1), int(2), int(3)]),
+                   Ok(Sexp::True));
+
+        assert_eq!(super::lte(vec![int(2), int(1)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::lte(vec![int(1), int(3), int(2)]),
+                   Ok(Sexp::Nil));
+
+        assert_eq!(super::lte(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_gt() {
+        assert_eq!(super::gt(vec![int(1)]), Ok(Sexp::True));
+        assert_eq!(super::gt(vec![int(2), int(1)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::gt(vec![int(3), int(2), int(1)]),
+                   Ok(Sexp::True));
+
+        assert_eq!(super::gt(vec![int(1), int(2)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::gt(vec![int(2), int(2)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::gt(vec![int(2), int(1), int(3)]),
+                   Ok(Sexp::Nil));
+
+        assert_eq!(super::gt(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_gte() {
+        assert_eq!(super::gte(vec![int(1)]), Ok(Sexp::True));
+        assert_eq!(super::gte(vec![int(2), int(1)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::gte(vec![int(2), int(2)]),
+                   Ok(Sexp::True));
+        assert_eq!(super::gte(vec![int(3), int(2), int(1)]),
+                   Ok(Sexp::True));
+
+        assert_eq!(super::gte(vec![int(1), int(2)]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::gte(vec![int(2), int(1), int(3)]),
+                   Ok(Sexp::Nil));
+
+        assert_eq!(super::gte(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_arithmetic_stays_exact() {
+        assert_eq!(super::multiply(vec![int(99999999999), int(99999999999)]),
+                   Ok(Sexp::Number(Number::Bignum("9999999999800000000001".parse().unwrap()))));
+        assert_eq!(super::divide(vec![int(1), int(3)]), Ok(ratio(1, 3)));
+        assert_eq!(super::divide(vec![int(2)]), Ok(ratio(1, 2)));
+        assert_eq!(super::add(vec![ratio(1, 3), ratio(2, 3)]), Ok(int(1)));
+        assert_eq!(super::add(vec![ratio(1, 2), float(0.25)]), Ok(float(0.75)));
+        assert_eq!(super::subtract(vec![float(1.5)]), Ok(float(-1.5)));
+    }
+
+    #[test]
+    fn test_comparison_across_types() {
+        assert_eq!(super::lt(vec![ratio(1, 3), float(0.5), int(1)]), Ok(Sexp::True));
+        assert_eq!(super::gte(vec![int(1), float(1.)]), Ok(Sexp::True));
+        assert_eq!(super::gt(vec![int(1), ratio(3, 2)]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_floor_and_truncate() {
+        let values = |q, r| Ok(Sexp::Values(vec![q, r]));
+
+        assert_eq!(super::floor(vec![int(7), int(2)]), values(int(3), int(1)));
+        assert_eq!(super::floor(vec![int(-7), int(2)]), values(int(-4), int(1)));
+        assert_eq!(super::floor(vec![float(2.5)]), values(int(2), float(0.5)));
+        assert_eq!(super::floor(vec![ratio(-1, 2)]), values(int(-1), ratio(1, 2)));
+        assert_eq!(super::truncate(vec![int(-7), int(2)]), values(int(-3), int(-1)));
+        assert_eq!(super::truncate(vec![float(-2.5)]), values(int(-2), float(-0.5)));
+        assert_eq!(super::floor(vec![int(1), int(0)]),
+                   Err("Division by zero".to_string()));
+        assert_eq!(super::floor(vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(eval_source("(multiple-value-list (floor 7 2))"), Ok("(3 1)".to_string()));
+        assert_eq!(eval_source("(+ (floor 7 2) 1)"), Ok("4".to_string()));
+    }
+
+    #[test]
+    fn test_mod_and_rem() {
+        assert_eq!(super::modulo(vec![int(-7), int(2)]), Ok(int(1)));
+        assert_eq!(super::rem(vec![int(-7), int(2)]), Ok(int(-1)));
+        assert_eq!(super::modulo(vec![float(5.5), int(2)]), Ok(float(1.5)));
+        assert_eq!(super::modulo(vec![int(5)]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_gcd_and_lcm() {
+        assert_eq!(super::gcd(vec![]), Ok(int(0)));
+        assert_eq!(super::gcd(vec![int(12), int(-18)]), Ok(int(6)));
+        assert_eq!(super::lcm(vec![]), Ok(int(1)));
+        assert_eq!(super::lcm(vec![int(4), int(-6)]), Ok(int(12)));
+        assert_eq!(super::gcd(vec![float(1.)]), Err("Argument error: 1.0".to_string()));
+    }
+
+    #[test]
+    fn test_expt() {
+        assert_eq!(super::expt(vec![int(2), int(100)]),
+                   Ok(Sexp::Number(Number::Bignum("1267650600228229401496703205376"
+                       .parse()
+                       .unwrap()))));
+        assert_eq!(super::expt(vec![ratio(2, 3), int(2)]), Ok(ratio(4, 9)));
+        assert_eq!(super::expt(vec![float(2.), int(3)]), Ok(float(8.)));
+        assert_eq!(super::expt(vec![int(2)]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_numerator_and_denominator() {
+        assert_eq!(super::numerator(vec![ratio(-2, 6)]), Ok(int(-1)));
+        assert_eq!(super::denominator(vec![ratio(-2, 6)]), Ok(int(3)));
+        assert_eq!(super::denominator(vec![int(5)]), Ok(int(1)));
+        assert_eq!(super::numerator(vec![float(0.5)]), Err("Argument error: 0.5".to_string()));
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(super::float(vec![ratio(1, 4)]), Ok(float(0.25)));
+        assert_eq!(super::float(vec![int(2), float(1.)]), Ok(float(2.)));
+        assert_eq!(super::float(vec![Sexp::True]), Err("Argument error: T".to_string()));
+    }
+
+    #[test]
+    fn test_unpack_args_checks_exact_arity() {
+        assert_eq!(super::abs(vec![]), Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(super::abs(vec![int(1), int(2)]),
+                   Err("Invalid number of arguments: 2".to_string()));
+        assert_eq!(super::abs(vec![Sexp::True]), Err("Argument error: T".to_string()));
+    }
+
+    #[test]
+    fn test_abs_signum_min_max() {
+        assert_eq!(super::abs(vec![int(-3)]), Ok(int(3)));
+        assert_eq!(super::abs(vec![float(-0.5)]), Ok(float(0.5)));
+        assert_eq!(super::signum(vec![ratio(-1, 3)]), Ok(int(-1)));
+        assert_eq!(super::min(vec![int(3), ratio(1, 2), int(2)]), Ok(ratio(1, 2)));
+        assert_eq!(super::max(vec![int(3), float(4.), int(2)]), Ok(float(4.)));
+        assert_eq!(super::max(vec![]), Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let values = |q, r| Ok(Sexp::Values(vec![q, r]));
+
+        assert_eq!(super::round(vec![float(2.5)]), values(int(2), float(0.5)));
+        assert_eq!(super::round(vec![int(7), int(2)]), values(int(4), int(-1)));
+        assert_eq!(super::ceiling(vec![ratio(1, 3)]), values(int(1), ratio(-2, 3)));
+        assert_eq!(super::ceiling(vec![float(-1.5)]), values(int(-1), float(-0.5)));
+    }
+
+    #[test]
+    fn test_transcendental_functions() {
+        assert_eq!(super::sqrt(vec![int(9)]), Ok(int(3)));
+        assert_eq!(super::sqrt(vec![float(2.25)]), Ok(float(1.5)));
+        assert_eq!(super::exp(vec![int(0)]), Ok(float(1.)));
+        assert_eq!(super::log(vec![int(1)]), Ok(float(0.)));
+        assert_eq!(super::log(vec![int(8), int(2)]), Ok(float(3.)));
+        assert_eq!(super::log(vec![int(0)]), Err("Division by zero".to_string()));
+        assert_eq!(super::sin(vec![int(0)]), Ok(float(0.)));
+        assert_eq!(super::cos(vec![int(0)]), Ok(float(1.)));
+        assert_eq!(super::atan(vec![int(1), int(1)]), Ok(float(std::f64::consts::FRAC_PI_4)));
+        assert_eq!(super::tanh(vec![int(0)]), Ok(float(0.)));
+        assert_eq!(super::log(vec![]), Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_numeric_predicates() {
+        assert_eq!(super::zerop(vec![float(0.)]), Ok(Sexp::True));
+        assert_eq!(super::plusp(vec![ratio(1, 2)]), Ok(Sexp::True));
+        assert_eq!(super::minusp(vec![int(0)]), Ok(Sexp::Nil));
+        assert_eq!(super::evenp(vec![int(-4)]), Ok(Sexp::True));
+        assert_eq!(super::oddp(vec![int(4)]), Ok(Sexp::Nil));
+        assert_eq!(super::evenp(vec![float(2.)]), Err("Argument error: 2.0".to_string()));
+    }
+
+    #[test]
+    fn test_numeric_equality() {
+        assert_eq!(super::num_eq(vec![int(1), float(1.), ratio(2, 2)]), Ok(Sexp::True));
+        assert_eq!(super::num_eq(vec![int(1), int(2)]), Ok(Sexp::Nil));
+        assert_eq!(super::num_ne(vec![int(1), int(2), int(3)]), Ok(Sexp::True));
+        assert_eq!(super::num_ne(vec![int(1), int(2), int(1)]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_increment_and_decrement() {
+        assert_eq!(super::one_plus(vec![int(1)]), Ok(int(2)));
+        assert_eq!(super::one_minus(vec![ratio(1, 2)]), Ok(ratio(-1, 2)));
+    }
+
+    #[test]
+    fn test_bitwise_operations() {
+        assert_eq!(super::logand(vec![]), Ok(int(-1)));
+        assert_eq!(super::logand(vec![int(12), int(10)]), Ok(int(8)));
+        assert_eq!(super::logior(vec![int(12), int(10)]), Ok(int(14)));
+        assert_eq!(super::logxor(vec![int(12), int(10)]), Ok(int(6)));
+        assert_eq!(super::logand(vec![int(-1), int(5)]), Ok(int(5)));
+        assert_eq!(super::ash(vec![int(1), int(70)]),
+                   Ok(Sexp::Number(Number::from_bigint(BigInt::from(1) << 70))));
+        assert_eq!(super::ash(vec![int(-5), int(-1)]), Ok(int(-3)));
+        assert_eq!(super::ash(vec![int(-5), int(-1000)]), Ok(int(-1)));
+        assert_eq!(super::ash(vec![int(0), int(100000000000)]), Ok(int(0)));
+        assert_eq!(super::ash(vec![int(1), int(100000000000)]),
+                   Err("The result of (ASH 1 100000000000) is too large".to_string()));
+    }
+
+    #[test]
+    fn test_random() {
+        let env = super::default_env();
+        let global = ::std::rc::Rc::downgrade(&env);
+
+        let state = super::make_random_state(&global, vec![Sexp::True]).unwrap();
+        let copy = super::make_random_state(&global, vec![state.clone()]).unwrap();
+        let first = super::random(&global, vec![int(1000), state]);
+        assert_eq!(super::random(&global, vec![int(1000), copy]), first);
+
+        for _ in 0..100 {
+            match super::random(&global, vec![int(10)]) {
+                Ok(Sexp::Number(Number::Fixnum(n))) => assert!((0..10).contains(&n)),
+                r => panic!("unexpected {:?}", r),
+            }
+            match super::random(&global, vec![float(1.)]) {
+                Ok(Sexp::Number(Number::Float(n))) => assert!((0. ..1.).contains(&n)),
+                r => panic!("unexpected {:?}", r),
+            }
+        }
+
+        assert_eq!(super::random(&global, vec![int(0)]), Err("Argument error: 0".to_string()));
+        assert_eq!(super::make_random_state(&global, vec![int(5)]),
+                   Err("Argument error: 5".to_string()));
+        assert_eq!(eval_source("(random-state-p (make-random-state))"), Ok("T".to_string()));
+        assert_eq!(eval_source("(typep *random-state* 'random-state)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(defparameter s (make-random-state nil)) \
+                                (= (random 1000000) (random 1000000 s))"),
+                   Ok("T".to_string()));
+    }
+
+    fn complex(r: i64, i: i64) -> Sexp {
+        Sexp::Number(Number::complex(Number::Fixnum(r), Number::Fixnum(i)))
+    }
+
+    #[test]
+    fn test_complex_arithmetic() {
+        assert_eq!(super::add(vec![complex(1, 2), complex(3, -2)]), Ok(int(4)));
+        assert_eq!(super::multiply(vec![complex(0, 1), complex(0, 1)]), Ok(int(-1)));
+        assert_eq!(super::sqrt(vec![int(-1)]), Ok(complex(0, 1)));
+        assert_eq!(super::abs(vec![complex(3, -4)]), Ok(float(5.)));
+        assert_eq!(super::num_eq(vec![complex(1, 2), complex(1, 2)]), Ok(Sexp::True));
+        assert_eq!(super::lt(vec![complex(1, 2), int(3)]),
+                   Err("Argument error: #C(1 2)".to_string()));
+        assert_eq!(super::floor(vec![complex(1, 2)]),
+                   Err("Argument error: #C(1 2)".to_string()));
+        assert_eq!(super::sin(vec![complex(1, 2)]),
+                   Err("Argument error: #C(1 2)".to_string()));
+    }
+
+    #[test]
+    fn test_complex_accessors() {
+        assert_eq!(super::complex(vec![int(1), int(2)]), Ok(complex(1, 2)));
+        assert_eq!(super::complex(vec![int(1)]), Ok(int(1)));
+        assert_eq!(super::complexp(vec![complex(1, 2)]), Ok(Sexp::True));
+        assert_eq!(super::complexp(vec![int(1)]), Ok(Sexp::Nil));
+        assert_eq!(super::realpart(vec![complex(1, 2)]), Ok(int(1)));
+        assert_eq!(super::imagpart(vec![complex(1, 2)]), Ok(int(2)));
+        assert_eq!(super::imagpart(vec![float(1.5)]), Ok(float(0.)));
+        assert_eq!(super::conjugate(vec![complex(1, 2)]), Ok(complex(1, -2)));
+        assert_eq!(super::phase(vec![complex(0, 1)]), Ok(float(std::f64::consts::FRAC_PI_2)));
+        assert_eq!(super::exp(vec![int(0)]), Ok(float(1.)));
+        assert_eq!(super::log(vec![int(-1)]),
+                   Ok(Sexp::Number(Number::complex(Number::Float(0.),
+                                                   Number::Float(std::f64::consts::PI)))));
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(super::values(vec![int(1)]), Ok(int(1)));
+        assert_eq!(super::values(vec![]), Ok(Sexp::Values(vec![])));
+        assert_eq!(super::values(vec![int(1), int(2)]),
+                   Ok(Sexp::Values(vec![int(1), int(2)])));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(super::list(vec![]), Ok(Sexp::Nil));
+        assert_eq!(super::list(vec![int(1), int(2)]), Ok(Sexp::list(vec![int(1), int(2)])));
+    }
+
+    #[test]
+    fn test_features() {
+        let features = match super::features() {
+            Sexp::List(features) => features.to_vec(),
+            v => panic!("unexpected {}", v),
+        };
+
+        assert!(features.contains(&Sexp::symbol(":rust-lisp")));
+        assert!(features.contains(&Sexp::symbol(":64-bit")) ||
+                features.contains(&Sexp::symbol(":32-bit")));
+        assert!(features.iter().all(|f| matches!(*f, Sexp::Symbol(ref s) if s.is_keyword())));
+    }
+}
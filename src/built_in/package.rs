@@ -0,0 +1,70 @@
+use super::super::env::{self, Env};
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::symbol::Symbol;
+use super::expect_args;
+
+/// The package named by the optional argument at `index`, or the current one.
+fn package_arg(args: &[Sexp], index: usize) -> Result<package::Package, String> {
+    match args.get(index) {
+        Some(p) => package::designated(p),
+        None => Ok(package::current()),
+    }
+}
+
+/// `(find-symbol name [package])` returns the symbol and how it is
+/// accessible, or nil twice.
+fn find_symbol(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let name = match args[0] {
+        Sexp::String(ref s) => s.to_string(),
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    match package_arg(&args, 1)?.find_symbol(&name) {
+        Some((symbol, status)) => {
+            let status = Sexp::Symbol(Symbol::keyword(status.name()));
+            Ok(Sexp::Values(vec![Sexp::from_symbol(symbol), status]))
+        }
+        None => Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])),
+    }
+}
+
+fn find_package(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(package::designated(&args[0]).map(Sexp::Package).unwrap_or(Sexp::Nil))
+}
+
+fn package_name(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(Sexp::string(package::designated(&args[0])?.name()))
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "find-symbol".to_string(), Sexp::BuiltInFunc(find_symbol));
+    env::env_set_function(env, "find-package".to_string(), Sexp::BuiltInFunc(find_package));
+    env::env_set_function(env, "package-name".to_string(), Sexp::BuiltInFunc(package_name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::package;
+    use super::super::super::sexp::Sexp;
+
+    #[test]
+    fn test_find_symbol() {
+        let user = package::find_package("CL-USER").unwrap();
+        let x = Sexp::symbol("x");
+
+        assert_eq!(super::find_symbol(vec![Sexp::string("X")]),
+                   Ok(Sexp::Values(vec![x, Sexp::symbol(":internal")])));
+        assert_eq!(super::find_symbol(vec![Sexp::string("NOT-INTERNED"), Sexp::symbol(":cl")]),
+                   Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])));
+        assert_eq!(super::find_package(vec![Sexp::string("CL-USER")]), Ok(Sexp::Package(user)));
+        assert_eq!(super::find_package(vec![Sexp::symbol("nope")]), Ok(Sexp::Nil));
+        assert_eq!(super::package_name(vec![Sexp::symbol(":cl")]),
+                   Ok(Sexp::string("COMMON-LISP")));
+    }
+}
@@ -0,0 +1,126 @@
+use super::super::env::{self, Env};
+use super::super::hash_table::HashTest;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::types;
+use super::expect_args;
+
+fn boolean(b: bool) -> Sexp {
+    if b { Sexp::True } else { Sexp::Nil }
+}
+
+fn compare(args: &[Sexp], test: HashTest) -> SexpResult {
+    expect_args(args, 2, 2)?;
+
+    Ok(boolean(test.test(&args[0], &args[1])))
+}
+
+pub fn eq(args: Vec<Sexp>) -> SexpResult {
+    compare(&args, HashTest::Eq)
+}
+
+pub fn eql(args: Vec<Sexp>) -> SexpResult {
+    compare(&args, HashTest::Eql)
+}
+
+pub fn equal(args: Vec<Sexp>) -> SexpResult {
+    compare(&args, HashTest::Equal)
+}
+
+pub fn equalp(args: Vec<Sexp>) -> SexpResult {
+    compare(&args, HashTest::Equalp)
+}
+
+fn type_of(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(types::type_of(&args[0]))
+}
+
+/// `(typep object type-specifier)`
+fn typep(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+
+    Ok(boolean(types::typep(&args[0], &args[1])?))
+}
+
+/// Defines a one-argument predicate from a test on its argument.
+macro_rules! predicate {
+    ($name:ident, $test:expr) => {
+        fn $name(args: Vec<Sexp>) -> SexpResult {
+            expect_args(&args, 1, 1)?;
+            let test: fn(&Sexp) -> bool = $test;
+
+            Ok(boolean(test(&args[0])))
+        }
+    };
+}
+
+predicate!(null, types::is_null);
+predicate!(consp, types::is_cons);
+predicate!(atom, |x| !types::is_cons(x));
+predicate!(listp, |x| matches!(*x, Sexp::List(_) | Sexp::Cons(_) | Sexp::Nil));
+predicate!(symbolp, |x| matches!(*x, Sexp::Symbol(_) | Sexp::Nil | Sexp::True));
+predicate!(stringp, |x| matches!(*x, Sexp::String(_)));
+predicate!(functionp,
+           |x| matches!(*x, Sexp::BuiltInFunc(_) | Sexp::UserFunc(_) | Sexp::Closure(_)));
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "eq".to_string(), Sexp::BuiltInFunc(eq));
+    env::env_set_function(env, "eql".to_string(), Sexp::BuiltInFunc(eql));
+    env::env_set_function(env, "equal".to_string(), Sexp::BuiltInFunc(equal));
+    env::env_set_function(env, "equalp".to_string(), Sexp::BuiltInFunc(equalp));
+    env::env_set_function(env, "type-of".to_string(), Sexp::BuiltInFunc(type_of));
+    env::env_set_function(env, "typep".to_string(), Sexp::BuiltInFunc(typep));
+    env::env_set_function(env, "null".to_string(), Sexp::BuiltInFunc(null));
+    env::env_set_function(env, "consp".to_string(), Sexp::BuiltInFunc(consp));
+    env::env_set_function(env, "atom".to_string(), Sexp::BuiltInFunc(atom));
+    env::env_set_function(env, "listp".to_string(), Sexp::BuiltInFunc(listp));
+    env::env_set_function(env, "symbolp".to_string(), Sexp::BuiltInFunc(symbolp));
+    env::env_set_function(env, "stringp".to_string(), Sexp::BuiltInFunc(stringp));
+    env::env_set_function(env, "functionp".to_string(), Sexp::BuiltInFunc(functionp));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::number::Number;
+    use super::super::super::sexp::Sexp;
+    use super::super::super::test_util::{int, eval_source};
+
+    #[test]
+    fn test_equality() {
+        let s = Sexp::string("a");
+        let l = Sexp::list(vec![int(1), int(2)]);
+
+        assert_eq!(super::eq(vec![Sexp::string("a"), Sexp::string("a")]), Ok(Sexp::Nil));
+        assert_eq!(super::eq(vec![s.clone(), s.clone()]), Ok(Sexp::True));
+        assert_eq!(super::eql(vec![int(1), int(1)]), Ok(Sexp::True));
+        assert_eq!(super::eql(vec![Sexp::Number(Number::Float(1.0)), int(1)]), Ok(Sexp::Nil));
+        assert_eq!(super::eql(vec![l.clone(), Sexp::list(vec![int(1), int(2)])]), Ok(Sexp::Nil));
+        assert_eq!(super::equal(vec![l.clone(), Sexp::list(vec![int(1), int(2)])]), Ok(Sexp::True));
+        assert_eq!(super::equal(vec![Sexp::string("A"), Sexp::string("a")]), Ok(Sexp::Nil));
+        assert_eq!(super::equalp(vec![Sexp::string("A"), Sexp::string("a")]), Ok(Sexp::True));
+        assert_eq!(super::eq(vec![int(1)]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_predicates() {
+        let empty = Sexp::list(vec![]);
+
+        assert_eq!(super::null(vec![empty.clone()]), Ok(Sexp::True));
+        assert_eq!(super::consp(vec![empty.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::atom(vec![Sexp::Nil]), Ok(Sexp::True));
+        assert_eq!(super::listp(vec![Sexp::Nil]), Ok(Sexp::True));
+        assert_eq!(super::listp(vec![int(1)]), Ok(Sexp::Nil));
+        assert_eq!(super::symbolp(vec![Sexp::Nil]), Ok(Sexp::True));
+        assert_eq!(super::stringp(vec![Sexp::string("a")]), Ok(Sexp::True));
+        assert_eq!(super::functionp(vec![Sexp::BuiltInFunc(super::eq)]), Ok(Sexp::True));
+        assert_eq!(super::type_of(vec![int(1)]), Ok(Sexp::symbol("FIXNUM")));
+        assert_eq!(super::typep(vec![int(1), Sexp::symbol("integer")]),
+                   Ok(Sexp::True));
+        assert_eq!(eval_source("(null 'nil)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(typep 'nil 'null)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(typep '() 'list)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(type-of 'nil)"), Ok("NULL".to_string()));
+    }
+}
@@ -0,0 +1,317 @@
+use std::cell::RefCell;
+use std::rc::Weak;
+use super::super::env::{self, Env, EnvData};
+use super::super::package;
+use super::super::parser;
+use super::super::readtable::{self, Case, Macro, Readtable};
+use super::super::number::Number;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::stream::Stream;
+use super::super::symbol::Symbol;
+use super::stream::{input_stream, or_eof};
+use super::string::index;
+use super::symbol::designated_function;
+use super::{define_global, expect_args, global, keyword_name};
+
+fn boolean(b: bool) -> Sexp {
+    if b { Sexp::True } else { Sexp::Nil }
+}
+
+/// The value of `*readtable*`.
+fn current(env: &Weak<RefCell<EnvData>>) -> Result<Readtable, String> {
+    match env::env_get(&global(env)?, package::cl_symbol("*READTABLE*")) {
+        Some(Sexp::Readtable(readtable)) => Ok(readtable),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => Ok(readtable::current()),
+    }
+}
+
+/// The optional readtable argument at `index`, or the value of `*readtable*`.
+fn readtable_arg(env: &Weak<RefCell<EnvData>>, args: &[Sexp], index: usize) -> Result<Readtable, String> {
+    match args.get(index) {
+        Some(Sexp::Readtable(readtable)) => Ok(readtable.clone()),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => current(env),
+    }
+}
+
+fn char_arg(args: &[Sexp], index: usize) -> Result<char, String> {
+    match args.get(index) {
+        Some(&Sexp::Char(c)) => Ok(c),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => Err(format!("Invalid number of arguments: {}", args.len())),
+    }
+}
+
+fn readtable_case(args: Vec<Sexp>) -> SexpResult {
+    let readtable = unpack_args!(args, 1 Sexp::Readtable);
+
+    Ok(Sexp::Symbol(Symbol::keyword(readtable.case().name())))
+}
+
+/// `(setf (readtable-case readtable) mode)` with one of `:upcase`,
+/// `:downcase`, `:preserve` or `:invert`.
+fn setf_readtable_case(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let case = match args[0] {
+        Sexp::Symbol(ref s) if s.is_keyword() => Case::from_name(s.name()),
+        _ => None,
+    };
+
+    match (case, &args[1]) {
+        (Some(case), Sexp::Readtable(readtable)) => {
+            readtable.set_case(case);
+            Ok(args[0].clone())
+        }
+        (None, _) => Err(format!("Argument error: {}", args[0])),
+        (_, v) => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn readtablep(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(boolean(matches!(args[0], Sexp::Readtable(_))))
+}
+
+/// `(copy-readtable [from [to]])` copies `from`, the current readtable by
+/// default or the standard one if nil, into `to`, or into a new readtable if
+/// that is nil or not given.
+fn copy_readtable(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 2)?;
+    let from = match args.first() {
+        Some(Sexp::Nil) => readtable::standard(),
+        _ => readtable_arg(env, &args, 0)?,
+    };
+
+    match args.get(1) {
+        None | Some(Sexp::Nil) => Ok(Sexp::Readtable(from.copy())),
+        Some(Sexp::Readtable(to)) => {
+            from.copy_into(to);
+            Ok(args[1].clone())
+        }
+        Some(v) => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// `(set-macro-character char function [non-terminating-p [readtable]])`
+fn set_macro_character(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 4)?;
+    let c = char_arg(&args, 0)?;
+    let function = designated_function(env, &args[1])?;
+    let non_terminating = args.get(2).is_some_and(|v| *v != Sexp::Nil);
+
+    readtable_arg(env, &args, 3)?.set_macro_character(c, function, non_terminating);
+    Ok(Sexp::True)
+}
+
+/// `(get-macro-character char [readtable])` returns the function and whether
+/// the character is non-terminating, or nil twice if it isn't a macro
+/// character.
+fn get_macro_character(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let c = char_arg(&args, 0)?;
+
+    match readtable_arg(env, &args, 1)?.macro_character(c) {
+        Some((Macro::Function(f), non_terminating)) => {
+            Ok(Sexp::Values(vec![f, boolean(non_terminating)]))
+        }
+        Some((Macro::Dispatch(_), non_terminating)) => {
+            Ok(Sexp::Values(vec![Sexp::BuiltInFunc(parser::read_dispatch), boolean(non_terminating)]))
+        }
+        None => Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])),
+    }
+}
+
+/// `(make-dispatch-macro-character char [non-terminating-p [readtable]])`
+fn make_dispatch_macro_character(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 3)?;
+    let c = char_arg(&args, 0)?;
+    let non_terminating = args.get(1).is_some_and(|v| *v != Sexp::Nil);
+
+    readtable_arg(env, &args, 2)?.make_dispatch_macro_character(c, non_terminating);
+    Ok(Sexp::True)
+}
+
+/// `(set-dispatch-macro-character char sub-char function [readtable])`
+fn set_dispatch_macro_character(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 3, 4)?;
+    let c = char_arg(&args, 0)?;
+    let sub = char_arg(&args, 1)?;
+    let function = designated_function(env, &args[2])?;
+
+    readtable_arg(env, &args, 3)?.set_dispatch_macro_character(c, sub, function)?;
+    Ok(Sexp::True)
+}
+
+/// `(get-dispatch-macro-character char sub-char [readtable])`
+fn get_dispatch_macro_character(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+    let c = char_arg(&args, 0)?;
+    let sub = char_arg(&args, 1)?;
+    let readtable = readtable_arg(env, &args, 2)?;
+
+    match readtable.macro_character(c) {
+        Some((Macro::Dispatch(_), _)) => Ok(readtable.dispatch_macro_character(c, sub).unwrap_or(Sexp::Nil)),
+        _ => Err(format!("{} is not a dispatching macro character", Sexp::Char(c))),
+    }
+}
+
+/// `(read [stream [eof-error-p [eof-value [recursive-p]]]])` reads an object
+/// with the value of `*readtable*`.
+fn read(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 4)?;
+    let stream = input_stream(&args, 0)?;
+
+    readtable::use_env(&global(env)?);
+    or_eof(parser::read(&stream)?, &args, 0)
+}
+
+/// `(read-from-string string [eof-error-p [eof-value]] &key start end)`
+/// returns the object read from the string and the index of the first
+/// character not read.
+fn read_from_string(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() || (args.len() > 3 && args.len().is_multiple_of(2)) {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+    let chars = match args[0] {
+        Sexp::String(ref s) => s.chars().collect::<Vec<_>>(),
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    let (mut start, mut end) = (0, chars.len());
+    for pair in args.get(3..).unwrap_or(&[]).chunks(2) {
+        match keyword_name(&pair[0])? {
+            "START" => start = index(&pair[1])?,
+            "END" if pair[1] == Sexp::Nil => {}
+            "END" => end = index(&pair[1])?,
+            _ => return Err(format!("Argument error: {}", pair[0])),
+        }
+    }
+    if start > end || end > chars.len() {
+        return Err(format!("Argument error: {}", args[0]));
+    }
+
+    let stream = Stream::string_input(&chars[start..end].iter().collect::<String>());
+    readtable::use_env(&global(env)?);
+    let object = or_eof(parser::read(&stream)?, &args, 0)?;
+    let position = start + stream.position().unwrap_or(0);
+
+    Ok(Sexp::Values(vec![object, Sexp::Number(Number::Fixnum(position as i64))]))
+}
+
+pub fn register(env: &Env) {
+    env::env_set(env, "*readtable*".to_string(), Sexp::Readtable(readtable::current()));
+    env::env_set(env, "*read-eval*".to_string(), Sexp::True);
+    env::env_set_function(env, "readtable-case".to_string(), Sexp::BuiltInFunc(readtable_case));
+    env::env_set_setf_function(env, "readtable-case", Sexp::BuiltInFunc(setf_readtable_case));
+    env::env_set_function(env, "readtablep".to_string(), Sexp::BuiltInFunc(readtablep));
+    define_global(env, "copy-readtable", copy_readtable);
+    define_global(env, "set-macro-character", set_macro_character);
+    define_global(env, "get-macro-character", get_macro_character);
+    define_global(env, "make-dispatch-macro-character", make_dispatch_macro_character);
+    define_global(env, "set-dispatch-macro-character", set_dispatch_macro_character);
+    define_global(env, "get-dispatch-macro-character", get_dispatch_macro_character);
+    define_global(env, "read", read);
+    define_global(env, "read-from-string", read_from_string);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::super::super::env;
+    use super::super::super::package;
+    use super::super::super::readtable;
+    use super::super::super::sexp::Sexp;
+    use super::super::super::stream::Stream;
+    use super::super::super::test_util::int;
+
+    #[test]
+    fn test_readtable_case() {
+        let readtable = Sexp::Readtable(readtable::current());
+
+        assert_eq!(super::readtable_case(vec![readtable.clone()]), Ok(Sexp::symbol(":upcase")));
+        assert_eq!(super::setf_readtable_case(vec![Sexp::symbol(":invert"), readtable.clone()]),
+                   Ok(Sexp::symbol(":invert")));
+        assert_eq!(super::readtable_case(vec![readtable.clone()]), Ok(Sexp::symbol(":invert")));
+        super::setf_readtable_case(vec![Sexp::symbol(":upcase"), readtable.clone()]).unwrap();
+        assert_eq!(super::setf_readtable_case(vec![Sexp::symbol(":sideways"), readtable]),
+                   Err("Argument error: :SIDEWAYS".to_string()));
+    }
+
+    #[test]
+    fn test_macro_characters() {
+        let env = env::env_new(None);
+        let global = Rc::downgrade(&env);
+        let copy = match super::copy_readtable(&global, vec![Sexp::Nil]) {
+            Ok(Sexp::Readtable(r)) => r,
+            other => panic!("unexpected {:?}", other),
+        };
+        env::env_set(&env, package::cl_symbol("*READTABLE*"), Sexp::Readtable(copy.clone()));
+        let f = Sexp::BuiltInFunc(super::readtablep);
+
+        assert_eq!(super::set_macro_character(&global, vec![Sexp::Char('!'), f.clone()]),
+                   Ok(Sexp::True));
+        assert_eq!(super::get_macro_character(&global, vec![Sexp::Char('!')]),
+                   Ok(Sexp::Values(vec![f.clone(), Sexp::Nil])));
+        assert_eq!(super::get_macro_character(&global, vec![Sexp::Char('!'),
+                                                            Sexp::Readtable(readtable::standard())]),
+                   Ok(Sexp::Values(vec![Sexp::Nil, Sexp::Nil])));
+        assert_eq!(super::make_dispatch_macro_character(&global, vec![Sexp::Char('$'), Sexp::True]),
+                   Ok(Sexp::True));
+        assert_eq!(super::set_dispatch_macro_character(&global,
+                                                       vec![Sexp::Char('$'), Sexp::Char('x'), f.clone()]),
+                   Ok(Sexp::True));
+        assert_eq!(super::get_dispatch_macro_character(&global, vec![Sexp::Char('$'), Sexp::Char('X')]),
+                   Ok(f));
+        assert_eq!(super::get_dispatch_macro_character(&global, vec![Sexp::Char('!'), Sexp::Char('x')]),
+                   Err("#\\! is not a dispatching macro character".to_string()));
+        assert!(copy.macro_character('!').is_some());
+        assert!(readtable::current().macro_character('!').is_none());
+        assert_eq!(super::readtablep(vec![Sexp::Readtable(copy)]), Ok(Sexp::True));
+    }
+
+    #[test]
+    fn test_read() {
+        let env = env::env_new(None);
+        super::register(&env);
+        let global = Rc::downgrade(&env);
+        let stream = Sexp::Stream(Stream::string_input("(a 1) #.(+ 1 1)"));
+        let eof = Sexp::symbol(":eof");
+        env::env_set_function(&env, "+", Sexp::BuiltInFunc(super::super::add));
+
+        assert_eq!(super::read(&global, vec![stream.clone()]),
+                   Ok(Sexp::list(vec![Sexp::symbol("a"), int(1)])));
+        assert_eq!(super::read(&global, vec![stream.clone()]), Ok(int(2)));
+        assert_eq!(super::read(&global, vec![stream.clone(), Sexp::Nil, eof.clone()]),
+                   Ok(eof.clone()));
+        assert_eq!(super::read(&global, vec![stream]), Err("End of file".to_string()));
+        env::env_set(&env,
+                     package::cl_symbol("*STANDARD-INPUT*"),
+                     Sexp::Stream(Stream::string_input("b")));
+        readtable::use_env(&env);
+        assert_eq!(super::read(&global, vec![]), Ok(Sexp::symbol("b")));
+        assert_eq!(super::read_from_string(&global, vec![Sexp::string("a b")]),
+                   Ok(Sexp::Values(vec![Sexp::symbol("a"), int(2)])));
+        assert_eq!(super::read_from_string(&global,
+                                           vec![Sexp::string("a b"),
+                                                Sexp::True,
+                                                Sexp::Nil,
+                                                Sexp::symbol(":start"),
+                                                int(2)]),
+                   Ok(Sexp::Values(vec![Sexp::symbol("b"), int(3)])));
+        assert_eq!(super::read_from_string(&global,
+                                           vec![Sexp::string("a b"),
+                                                Sexp::True,
+                                                Sexp::Nil,
+                                                Sexp::symbol("start"),
+                                                int(2)]),
+                   Err("Argument error: START".to_string()));
+        assert_eq!(super::read_from_string(&global, vec![Sexp::string(" "), Sexp::Nil, eof.clone()]),
+                   Ok(Sexp::Values(vec![eof, int(1)])));
+
+        env::env_set(&env, package::cl_symbol("*READ-EVAL*"), Sexp::Nil);
+        assert_eq!(super::read_from_string(&global, vec![Sexp::string("#.1")]),
+                   Err("Can't read #.1 while *read-eval* is nil".to_string()));
+    }
+}
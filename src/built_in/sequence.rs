@@ -0,0 +1,769 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::super::array::Array;
+use super::super::env::{self, Env};
+use super::super::hash_table::HashTest;
+use super::super::number::Number;
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::{expect_args, keyword_name};
+use super::symbol::global_function;
+
+fn fixnum(n: usize) -> Sexp {
+    Sexp::Number(Number::Fixnum(n as i64))
+}
+
+fn index(n: &Sexp) -> Result<usize, String> {
+    match *n {
+        Sexp::Number(Number::Fixnum(i)) if i >= 0 => Ok(i as usize),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The elements of a list, string or vector.
+fn elements(seq: &Sexp) -> Result<Vec<Sexp>, String> {
+    match *seq {
+        Sexp::Nil | Sexp::List(_) | Sexp::Cons(_) => list_elements(seq),
+        Sexp::String(ref s) => Ok(s.chars().map(Sexp::Char).collect()),
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 => Ok(a.borrow().elements()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The elements of a proper list.
+fn list_elements(list: &Sexp) -> Result<Vec<Sexp>, String> {
+    match list.list_parts()? {
+        (elements, Sexp::Nil) => Ok(elements),
+        _ => Err(format!("{} is not a proper list", list)),
+    }
+}
+
+fn list(elements: Vec<Sexp>) -> Sexp {
+    if elements.is_empty() {
+        Sexp::Nil
+    } else {
+        Sexp::list(elements)
+    }
+}
+
+fn string(elements: Vec<Sexp>) -> SexpResult {
+    elements.into_iter()
+        .map(|e| {
+            match e {
+                Sexp::Char(c) => Ok(c),
+                v => Err(format!("Argument error: {}", v)),
+            }
+        })
+        .collect::<Result<String, String>>()
+        .map(Sexp::string)
+}
+
+fn vector(elements: Vec<Sexp>) -> Sexp {
+    Sexp::Array(Rc::new(RefCell::new(Array::vector(elements))))
+}
+
+/// A fresh sequence of the same type as `seq` holding `elements`.
+fn like(seq: &Sexp, elements: Vec<Sexp>) -> SexpResult {
+    match *seq {
+        Sexp::String(_) => string(elements),
+        Sexp::Array(_) => Ok(vector(elements)),
+        _ => Ok(list(elements)),
+    }
+}
+
+/// A fresh sequence of the type named by `result_type`, `list`, `vector` or
+/// `string`, holding `elements`.
+fn of_type(result_type: &Sexp, elements: Vec<Sexp>) -> SexpResult {
+    match *result_type {
+        Sexp::Symbol(ref t) => {
+            match package::cl_name(t) {
+                Some("LIST") => Ok(list(elements)),
+                Some("VECTOR") => Ok(vector(elements)),
+                Some("STRING") => string(elements),
+                _ => Err(format!("Argument error: {}", t)),
+            }
+        }
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// Replaces the active elements of a vector in place.
+fn replace_elements(array: &Rc<RefCell<Array>>, elements: Vec<Sexp>) -> Result<(), String> {
+    let mut array = array.borrow_mut();
+    for (i, e) in elements.into_iter().enumerate() {
+        array.set(i, e)?;
+    }
+
+    Ok(())
+}
+
+fn truthy(result: SexpResult) -> Result<bool, String> {
+    Ok(result?.primary() != Sexp::Nil)
+}
+
+/// The keyword arguments shared by the sequence functions.
+#[derive(Default)]
+struct Options {
+    key: Option<Sexp>,
+    test: Option<Sexp>,
+    from_end: bool,
+    initial_value: Option<Sexp>,
+}
+
+impl Options {
+    fn parse(args: &[Sexp]) -> Result<Options, String> {
+        if !args.len().is_multiple_of(2) {
+            return Err(format!("Invalid number of arguments: {}", args.len()));
+        }
+
+        let mut options = Options::default();
+        for pair in args.chunks(2) {
+            match keyword_name(&pair[0])? {
+                "KEY" if pair[1] != Sexp::Nil => options.key = Some(global_function(&pair[1])?),
+                "KEY" => {}
+                "TEST" => options.test = Some(global_function(&pair[1])?),
+                "FROM-END" => options.from_end = pair[1] != Sexp::Nil,
+                "INITIAL-VALUE" => options.initial_value = Some(pair[1].clone()),
+                _ => return Err(format!("Argument error: {}", pair[0])),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn key(&self, element: &Sexp) -> SexpResult {
+        match self.key {
+            Some(ref f) => f.apply(vec![element.clone()]).map(Sexp::primary),
+            None => Ok(element.clone()),
+        }
+    }
+
+    /// Whether `element` matches `item` under `:test`, which defaults to `eql`.
+    fn matches(&self, item: &Sexp, element: &Sexp) -> Result<bool, String> {
+        let key = self.key(element)?;
+
+        match self.test {
+            Some(ref f) => truthy(f.apply(vec![item.clone(), key])),
+            None => Ok(HashTest::Eql.test(item, &key)),
+        }
+    }
+
+    fn satisfies(&self, predicate: &Sexp, element: &Sexp) -> Result<bool, String> {
+        truthy(global_function(predicate)?.apply(vec![self.key(element)?]))
+    }
+}
+
+/// Calls `f` on successive elements of the sequences, stopping at the shortest.
+fn map_elements(f: &Sexp, seqs: &[Sexp]) -> Result<Vec<Sexp>, String> {
+    let f = global_function(f)?;
+    let seqs = seqs.iter().map(elements).collect::<Result<Vec<_>, _>>()?;
+    let len = seqs.iter().map(Vec::len).min().unwrap_or(0);
+
+    (0..len)
+        .map(|i| f.apply(seqs.iter().map(|s| s[i].clone()).collect()).map(Sexp::primary))
+        .collect()
+}
+
+fn mapcar(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    map_elements(&args[0], &args[1..]).map(list)
+}
+
+/// Like `mapcar` but for side effects; returns the first sequence.
+fn mapc(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    map_elements(&args[0], &args[1..])?;
+    Ok(args[1].clone())
+}
+
+/// `(map result-type function sequences...)`, where the result type is
+/// `list`, `vector`, `string` or `nil`.
+fn map(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 3 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let results = map_elements(&args[1], &args[2..])?;
+    match args[0] {
+        Sexp::Nil => Ok(Sexp::Nil),
+        ref t => of_type(t, results),
+    }
+}
+
+/// `(reduce function sequence &key key from-end initial-value)`
+fn reduce(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let f = global_function(&args[0])?;
+    let options = Options::parse(&args[2..])?;
+    let mut elements = elements(&args[1])?
+        .iter()
+        .map(|e| options.key(e))
+        .collect::<Result<Vec<_>, _>>()?;
+    if options.from_end {
+        elements.reverse();
+    }
+
+    let mut elements = elements.into_iter();
+    let mut result = match options.initial_value.clone().or_else(|| elements.next()) {
+        Some(v) => v,
+        None => return f.apply(vec![]),
+    };
+    for e in elements {
+        let call_args = if options.from_end { vec![e, result] } else { vec![result, e] };
+        result = f.apply(call_args)?.primary();
+    }
+
+    Ok(result)
+}
+
+fn remove_matching(args: &[Sexp], keep: bool) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let options = Options::parse(&args[2..])?;
+    let mut kept = vec![];
+    for e in elements(&args[1])? {
+        if options.satisfies(&args[0], &e)? == keep {
+            kept.push(e);
+        }
+    }
+
+    like(&args[1], kept)
+}
+
+fn remove_if(args: Vec<Sexp>) -> SexpResult {
+    remove_matching(&args, false)
+}
+
+fn remove_if_not(args: Vec<Sexp>) -> SexpResult {
+    remove_matching(&args, true)
+}
+
+/// The indices of the elements matching `item`, or satisfying the predicate
+/// when `predicate` is set.
+fn matching_indices(args: &[Sexp], predicate: bool) -> Result<Vec<(usize, Sexp)>, String> {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let options = Options::parse(&args[2..])?;
+    let mut found = vec![];
+    for (i, e) in elements(&args[1])?.into_iter().enumerate() {
+        let matched = if predicate {
+            options.satisfies(&args[0], &e)?
+        } else {
+            options.matches(&args[0], &e)?
+        };
+        if matched {
+            found.push((i, e));
+        }
+    }
+    if options.from_end {
+        found.reverse();
+    }
+
+    Ok(found)
+}
+
+fn find(args: Vec<Sexp>) -> SexpResult {
+    Ok(matching_indices(&args, false)?.into_iter().next().map_or(Sexp::Nil, |(_, e)| e))
+}
+
+fn find_if(args: Vec<Sexp>) -> SexpResult {
+    Ok(matching_indices(&args, true)?.into_iter().next().map_or(Sexp::Nil, |(_, e)| e))
+}
+
+fn position(args: Vec<Sexp>) -> SexpResult {
+    Ok(matching_indices(&args, false)?.first().map_or(Sexp::Nil, |&(i, _)| fixnum(i)))
+}
+
+fn position_if(args: Vec<Sexp>) -> SexpResult {
+    Ok(matching_indices(&args, true)?.first().map_or(Sexp::Nil, |&(i, _)| fixnum(i)))
+}
+
+fn count(args: Vec<Sexp>) -> SexpResult {
+    Ok(fixnum(matching_indices(&args, false)?.len()))
+}
+
+fn count_if(args: Vec<Sexp>) -> SexpResult {
+    Ok(fixnum(matching_indices(&args, true)?.len()))
+}
+
+/// `(member item list &key key test)` returns the tail of the list starting
+/// at the first match.
+fn member(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let options = Options::parse(&args[2..])?;
+    let elements = list_elements(&args[1])?;
+    for (i, e) in elements.iter().enumerate() {
+        if options.matches(&args[0], e)? {
+            return Ok(list(elements[i..].to_vec()));
+        }
+    }
+
+    Ok(Sexp::Nil)
+}
+
+/// `(assoc item alist &key key test)` finds the first entry whose head
+/// matches; `nil` entries are skipped.
+fn assoc(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let options = Options::parse(&args[2..])?;
+    for entry in list_elements(&args[1])? {
+        match entry {
+            Sexp::Nil => {}
+            Sexp::List(ref v) if !v.is_empty() => {
+                if options.matches(&args[0], &v[0])? {
+                    return Ok(entry.clone());
+                }
+            }
+            Sexp::Cons(ref c) => {
+                if options.matches(&args[0], c.borrow().car())? {
+                    return Ok(entry.clone());
+                }
+            }
+            ref v => return Err(format!("Argument error: {}", v)),
+        }
+    }
+
+    Ok(Sexp::Nil)
+}
+
+/// `(append lists... tail)` copies the lists onto the last argument, which
+/// need not be a list: `(append '(1) 2)` is `(1 . 2)`.
+fn append(args: Vec<Sexp>) -> SexpResult {
+    let (tail, lists) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Sexp::Nil),
+    };
+    let mut result = vec![];
+    for arg in lists {
+        result.extend(list_elements(arg)?);
+    }
+
+    Ok(if result.is_empty() { tail.clone() } else { Sexp::dotted_list(result, tail.clone()) })
+}
+
+/// `(concatenate result-type sequences...)`; without a result type the
+/// sequences are joined into a string.
+fn concatenate(args: Vec<Sexp>) -> SexpResult {
+    let (result_type, sequences) = match args.first() {
+        Some(&Sexp::Symbol(_)) => (Some(&args[0]), &args[1..]),
+        _ => (None, &args[..]),
+    };
+
+    let mut result = vec![];
+    for s in sequences {
+        result.extend(elements(s)?);
+    }
+
+    match result_type {
+        Some(t) => of_type(t, result),
+        None => string(result),
+    }
+}
+
+/// Returns the `[start, end)` slice of a sequence as a fresh sequence of the
+/// same type; an omitted or nil end means the end of the sequence.
+fn subseq(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+    let elements = elements(&args[0])?;
+    let start = index(&args[1])?;
+    let end = match args.get(2) {
+        None | Some(&Sexp::Nil) => elements.len(),
+        Some(n) => index(n)?,
+    };
+
+    if start > end || end > elements.len() {
+        return Err(format!("Bounds {} {} out of range for {}", start, end, args[0]));
+    }
+    like(&args[0], elements[start..end].to_vec())
+}
+
+fn reverse(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let mut elements = elements(&args[0])?;
+
+    elements.reverse();
+    like(&args[0], elements)
+}
+
+/// Reverses vectors in place; other sequences are reversed as by `reverse`.
+fn nreverse(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Array(ref a) => {
+            let mut elements = elements(&args[0])?;
+            elements.reverse();
+            replace_elements(a, elements)?;
+            Ok(args[0].clone())
+        }
+        _ => reverse(args),
+    }
+}
+
+fn length(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::String(ref s) => Ok(fixnum(s.chars().count())),
+        Sexp::List(ref v) => Ok(fixnum(v.len())),
+        Sexp::Nil => Ok(fixnum(0)),
+        Sexp::Cons(_) => Ok(fixnum(list_elements(&args[0])?.len())),
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 => Ok(fixnum(a.borrow().len())),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn elt(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let i = index(&args[1])?;
+    let elements = elements(&args[0])?;
+
+    elements.get(i)
+        .cloned()
+        .ok_or_else(|| format!("Index {} out of bounds for {}", i, args[0]))
+}
+
+/// A stable merge sort whose comparison may fail.
+fn merge_sort<F>(mut v: Vec<Sexp>, less: &F) -> Result<Vec<Sexp>, String>
+    where F: Fn(&Sexp, &Sexp) -> Result<bool, String>
+{
+    if v.len() <= 1 {
+        return Ok(v);
+    }
+
+    let right = v.split_off(v.len() / 2);
+    let (left, right) = (merge_sort(v, less)?, merge_sort(right, less)?);
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = if less(r, l)? { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+/// `(sort sequence predicate &key key)`. Vectors are sorted in place; the
+/// sort is always stable, so `stable-sort` is the same function.
+fn sort(args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let predicate = global_function(&args[1])?;
+    let options = Options::parse(&args[2..])?;
+    let less = |a: &Sexp, b: &Sexp| truthy(predicate.apply(vec![options.key(a)?, options.key(b)?]));
+    let sorted = merge_sort(elements(&args[0])?, &less)?;
+
+    match args[0] {
+        Sexp::Array(ref a) => {
+            replace_elements(a, sorted)?;
+            Ok(args[0].clone())
+        }
+        ref seq => like(seq, sorted),
+    }
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "mapcar".to_string(), Sexp::BuiltInFunc(mapcar));
+    env::env_set_function(env, "mapc".to_string(), Sexp::BuiltInFunc(mapc));
+    env::env_set_function(env, "map".to_string(), Sexp::BuiltInFunc(map));
+    env::env_set_function(env, "reduce".to_string(), Sexp::BuiltInFunc(reduce));
+    env::env_set_function(env, "remove-if".to_string(), Sexp::BuiltInFunc(remove_if));
+    env::env_set_function(env, "remove-if-not".to_string(), Sexp::BuiltInFunc(remove_if_not));
+    env::env_set_function(env, "find".to_string(), Sexp::BuiltInFunc(find));
+    env::env_set_function(env, "find-if".to_string(), Sexp::BuiltInFunc(find_if));
+    env::env_set_function(env, "position".to_string(), Sexp::BuiltInFunc(position));
+    env::env_set_function(env, "position-if".to_string(), Sexp::BuiltInFunc(position_if));
+    env::env_set_function(env, "count".to_string(), Sexp::BuiltInFunc(count));
+    env::env_set_function(env, "count-if".to_string(), Sexp::BuiltInFunc(count_if));
+    env::env_set_function(env, "member".to_string(), Sexp::BuiltInFunc(member));
+    env::env_set_function(env, "assoc".to_string(), Sexp::BuiltInFunc(assoc));
+    env::env_set_function(env, "append".to_string(), Sexp::BuiltInFunc(append));
+    env::env_set_function(env, "concatenate".to_string(), Sexp::BuiltInFunc(concatenate));
+    env::env_set_function(env, "subseq".to_string(), Sexp::BuiltInFunc(subseq));
+    env::env_set_function(env, "reverse".to_string(), Sexp::BuiltInFunc(reverse));
+    env::env_set_function(env, "nreverse".to_string(), Sexp::BuiltInFunc(nreverse));
+    env::env_set_function(env, "length".to_string(), Sexp::BuiltInFunc(length));
+    env::env_set_function(env, "elt".to_string(), Sexp::BuiltInFunc(elt));
+    env::env_set_function(env, "sort".to_string(), Sexp::BuiltInFunc(sort));
+    env::env_set_function(env, "stable-sort".to_string(), Sexp::BuiltInFunc(sort));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::super::super::array::Array;
+    use super::super::super::number::Number;
+    use super::super::super::sexp::{Sexp, SexpResult};
+    use super::super::super::test_util::{int, string, symbol, eval_source};
+
+    fn ints(v: &[i64]) -> Sexp {
+        Sexp::list(v.iter().map(|&n| int(n)).collect())
+    }
+
+    fn vector(v: &[i64]) -> Sexp {
+        Sexp::Array(Rc::new(RefCell::new(Array::vector(v.iter().map(|&n| int(n)).collect()))))
+    }
+
+    fn add(args: Vec<Sexp>) -> SexpResult {
+        let sum = args.iter()
+            .map(|a| {
+                match *a {
+                    Sexp::Number(Number::Fixnum(n)) => n,
+                    _ => 0,
+                }
+            })
+            .sum();
+        Ok(int(sum))
+    }
+
+    fn list(args: Vec<Sexp>) -> SexpResult {
+        Ok(Sexp::list(args))
+    }
+
+    fn less(args: Vec<Sexp>) -> SexpResult {
+        match (&args[0], &args[1]) {
+            (Sexp::Number(Number::Fixnum(a)), Sexp::Number(Number::Fixnum(b))) if a < b => {
+                Ok(Sexp::True)
+            }
+            (Sexp::Char(a), Sexp::Char(b)) if a < b => Ok(Sexp::True),
+            _ => Ok(Sexp::Nil),
+        }
+    }
+
+    fn oddp(args: Vec<Sexp>) -> SexpResult {
+        match args[0] {
+            Sexp::Number(Number::Fixnum(n)) if n % 2 != 0 => Ok(Sexp::True),
+            _ => Ok(Sexp::Nil),
+        }
+    }
+
+    fn first(args: Vec<Sexp>) -> SexpResult {
+        match args[0] {
+            Sexp::List(ref v) => Ok(v[0].clone()),
+            ref v => Err(format!("Argument error: {}", v)),
+        }
+    }
+
+    #[test]
+    fn test_mapcar_and_map() {
+        assert_eq!(super::mapcar(vec![Sexp::BuiltInFunc(add), ints(&[1, 2, 3]), vector(&[10, 20])]),
+                   Ok(ints(&[11, 22])));
+        assert_eq!(super::mapcar(vec![Sexp::BuiltInFunc(add), Sexp::Nil]), Ok(Sexp::Nil));
+        assert_eq!(super::mapc(vec![Sexp::BuiltInFunc(add), ints(&[1])]), Ok(ints(&[1])));
+        assert_eq!(format!("{}",
+                           super::map(vec![symbol("vector"),
+                                           Sexp::BuiltInFunc(add),
+                                           ints(&[1, 2])])
+                               .unwrap()),
+                   "#(1 2)");
+        assert_eq!(super::map(vec![symbol("string"), Sexp::BuiltInFunc(add), ints(&[1])]),
+                   Err("Argument error: 1".to_string()));
+        assert_eq!(super::mapcar(vec![int(1), ints(&[1])]),
+                   Err("Illegal function call".to_string()));
+        assert_eq!(eval_source("(map '|list| #'evenp '(1))"),
+                   Err("Argument error: |list|".to_string()));
+    }
+
+    #[test]
+    fn test_reduce() {
+        assert_eq!(super::reduce(vec![Sexp::BuiltInFunc(add), ints(&[1, 2, 3])]), Ok(int(6)));
+        assert_eq!(super::reduce(vec![Sexp::BuiltInFunc(add), Sexp::Nil]), Ok(int(0)));
+        assert_eq!(super::reduce(vec![Sexp::BuiltInFunc(list),
+                                      ints(&[1, 2]),
+                                      symbol(":initial-value"),
+                                      int(0)]),
+                   Ok(Sexp::list(vec![ints(&[0, 1]), int(2)])));
+        assert_eq!(super::reduce(vec![Sexp::BuiltInFunc(list),
+                                      ints(&[1, 2, 3]),
+                                      symbol(":from-end"),
+                                      Sexp::True]),
+                   Ok(Sexp::list(vec![int(1), ints(&[2, 3])])));
+    }
+
+    #[test]
+    fn test_remove_if() {
+        assert_eq!(super::remove_if(vec![Sexp::BuiltInFunc(oddp), ints(&[1, 2, 3, 4])]),
+                   Ok(ints(&[2, 4])));
+        assert_eq!(super::remove_if_not(vec![Sexp::BuiltInFunc(oddp), vector(&[1, 2, 3])]),
+                   Ok(vector(&[1, 3])));
+        assert_eq!(super::remove_if(vec![Sexp::BuiltInFunc(oddp), ints(&[1])]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_find_position_count() {
+        let pairs = Sexp::list(vec![ints(&[1, 10]), ints(&[2, 20]), ints(&[1, 30])]);
+
+        assert_eq!(super::find(vec![int(2), ints(&[1, 2, 3])]), Ok(int(2)));
+        assert_eq!(super::find(vec![int(1), pairs.clone(), symbol(":key"), Sexp::BuiltInFunc(first)]),
+                   Ok(ints(&[1, 10])));
+        assert_eq!(super::find(vec![int(1),
+                                    pairs.clone(),
+                                    symbol(":key"),
+                                    Sexp::BuiltInFunc(first),
+                                    symbol(":from-end"),
+                                    Sexp::True]),
+                   Ok(ints(&[1, 30])));
+        assert_eq!(super::position(vec![Sexp::Char('l'), string("hello")]), Ok(int(2)));
+        assert_eq!(super::position(vec![int(9), vector(&[1])]), Ok(Sexp::Nil));
+        assert_eq!(super::position_if(vec![Sexp::BuiltInFunc(oddp), ints(&[2, 3])]), Ok(int(1)));
+        assert_eq!(super::count(vec![int(1), pairs, symbol(":key"), Sexp::BuiltInFunc(first)]),
+                   Ok(int(2)));
+        assert_eq!(super::count(vec![int(1),
+                                     ints(&[1, 2, 3]),
+                                     symbol(":test"),
+                                     Sexp::BuiltInFunc(less)]),
+                   Ok(int(2)));
+        assert_eq!(super::count_if(vec![Sexp::BuiltInFunc(oddp), vector(&[1, 3])]), Ok(int(2)));
+        assert_eq!(super::find(vec![int(1), ints(&[1]), symbol(":bogus"), Sexp::True]),
+                   Err("Argument error: :BOGUS".to_string()));
+        assert_eq!(super::find(vec![int(1), ints(&[1]), symbol("key"), Sexp::Nil]),
+                   Err("Argument error: KEY".to_string()));
+    }
+
+    #[test]
+    fn test_member_and_assoc() {
+        let alist = Sexp::list(vec![Sexp::list(vec![Sexp::Char('a'), int(1)]),
+                                    Sexp::Nil,
+                                    Sexp::list(vec![Sexp::Char('b'), int(2)])]);
+
+        assert_eq!(super::member(vec![int(2), ints(&[1, 2, 3])]), Ok(ints(&[2, 3])));
+        assert_eq!(super::member(vec![int(4), ints(&[1, 2, 3])]), Ok(Sexp::Nil));
+        assert_eq!(super::assoc(vec![Sexp::Char('b'), alist.clone()]),
+                   Ok(Sexp::list(vec![Sexp::Char('b'), int(2)])));
+        assert_eq!(super::assoc(vec![Sexp::Char('c'), alist]), Ok(Sexp::Nil));
+        assert_eq!(super::assoc(vec![int(2),
+                                     Sexp::list(vec![ints(&[1, 10]), ints(&[2, 20])])]),
+                   Ok(ints(&[2, 20])));
+        assert_eq!(super::assoc(vec![int(5), ints(&[1])]), Err("Argument error: 1".to_string()));
+
+        let dotted = Sexp::list(vec![Sexp::cons(int(1), symbol("a")),
+                                     Sexp::cons(int(2), symbol("b"))]);
+        assert_eq!(super::assoc(vec![int(2), dotted]), Ok(Sexp::cons(int(2), symbol("b"))));
+        assert_eq!(super::member(vec![int(2), Sexp::cons(int(1), int(2))]),
+                   Err("(1 . 2) is not a proper list".to_string()));
+    }
+
+    #[test]
+    fn test_append_and_reverse() {
+        assert_eq!(super::append(vec![ints(&[1]), Sexp::Nil, ints(&[2, 3])]),
+                   Ok(ints(&[1, 2, 3])));
+        assert_eq!(super::append(vec![]), Ok(Sexp::Nil));
+        assert_eq!(super::append(vec![ints(&[1]), ints(&[2]), Sexp::cons(int(3), int(4))]),
+                   Ok(Sexp::dotted_list(vec![int(1), int(2), int(3)], int(4))));
+        assert_eq!(super::append(vec![ints(&[1]), int(2)]), Ok(Sexp::cons(int(1), int(2))));
+        assert_eq!(super::append(vec![Sexp::Nil, int(2)]), Ok(int(2)));
+        assert_eq!(super::append(vec![Sexp::cons(int(1), int(2)), Sexp::Nil]),
+                   Err("(1 . 2) is not a proper list".to_string()));
+        assert_eq!(super::reverse(vec![string("abc")]), Ok(string("cba")));
+        assert_eq!(super::reverse(vec![ints(&[1, 2])]), Ok(ints(&[2, 1])));
+
+        let v = vector(&[1, 2, 3]);
+        assert_eq!(super::nreverse(vec![v.clone()]), Ok(v.clone()));
+        assert_eq!(v, vector(&[3, 2, 1]));
+    }
+
+    #[test]
+    fn test_concatenate() {
+        assert_eq!(super::concatenate(vec![symbol("string"), string("foo"), string("bär")]),
+                   Ok(string("foobär")));
+        assert_eq!(super::concatenate(vec![string("a"), string("b")]), Ok(string("ab")));
+        assert_eq!(super::concatenate(vec![]), Ok(string("")));
+        assert_eq!(super::concatenate(vec![symbol("list"), ints(&[1]), vector(&[2]), string("c")]),
+                   Ok(Sexp::list(vec![int(1), int(2), Sexp::Char('c')])));
+        assert_eq!(super::concatenate(vec![symbol("vector"), vector(&[1]), ints(&[2])]),
+                   Ok(vector(&[1, 2])));
+        assert_eq!(super::concatenate(vec![symbol("string"), string("a"), ints(&[1])]),
+                   Err("Argument error: 1".to_string()));
+        assert_eq!(super::concatenate(vec![string("a"), int(1)]),
+                   Err("Argument error: 1".to_string()));
+    }
+
+    #[test]
+    fn test_subseq() {
+        assert_eq!(super::subseq(vec![string("日本語です"), int(1), int(3)]), Ok(string("本語")));
+        assert_eq!(super::subseq(vec![string("hello"), int(2)]), Ok(string("llo")));
+        assert_eq!(super::subseq(vec![string("hello"), int(2), Sexp::Nil]), Ok(string("llo")));
+        assert_eq!(super::subseq(vec![ints(&[1, 2, 3]), int(1)]), Ok(ints(&[2, 3])));
+        assert_eq!(super::subseq(vec![ints(&[1, 2, 3]), int(3)]), Ok(Sexp::Nil));
+
+        let v = vector(&[1, 2, 3]);
+        let tail = super::subseq(vec![v.clone(), int(1)]).unwrap();
+        assert_eq!(tail, vector(&[2, 3]));
+        assert_eq!(v, vector(&[1, 2, 3]));
+        assert_eq!(super::subseq(vec![string("abc"), int(2), int(4)]),
+                   Err("Bounds 2 4 out of range for \"abc\"".to_string()));
+    }
+
+    #[test]
+    fn test_length_and_elt() {
+        assert_eq!(super::length(vec![string("héllo")]), Ok(int(5)));
+        assert_eq!(super::length(vec![string("")]), Ok(int(0)));
+        assert_eq!(super::length(vec![Sexp::list(vec![int(1), int(2)])]), Ok(int(2)));
+        assert_eq!(super::length(vec![vector(&[1, 2, 3])]), Ok(int(3)));
+        assert_eq!(super::length(vec![int(1)]), Err("Argument error: 1".to_string()));
+        assert_eq!(super::length(vec![Sexp::dotted_list(vec![int(1)], ints(&[2, 3]))]), Ok(int(3)));
+        assert_eq!(super::length(vec![Sexp::cons(int(1), int(2))]),
+                   Err("(1 . 2) is not a proper list".to_string()));
+        assert_eq!(super::elt(vec![string("abc"), int(1)]), Ok(Sexp::Char('b')));
+        assert_eq!(super::elt(vec![ints(&[1]), int(1)]),
+                   Err("Index 1 out of bounds for (1)".to_string()));
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(super::sort(vec![ints(&[3, 1, 2]), Sexp::BuiltInFunc(less)]),
+                   Ok(ints(&[1, 2, 3])));
+        assert_eq!(super::sort(vec![string("cab"), Sexp::BuiltInFunc(less)]), Ok(string("abc")));
+
+        let pairs = Sexp::list(vec![ints(&[2, 1]), ints(&[1, 2]), ints(&[2, 3]), ints(&[1, 4])]);
+        assert_eq!(super::sort(vec![pairs, Sexp::BuiltInFunc(less), symbol(":key"), Sexp::BuiltInFunc(first)]),
+                   Ok(Sexp::list(vec![ints(&[1, 2]), ints(&[1, 4]), ints(&[2, 1]), ints(&[2, 3])])));
+
+        let v = vector(&[2, 1]);
+        assert_eq!(super::sort(vec![v.clone(), Sexp::BuiltInFunc(less)]), Ok(v.clone()));
+        assert_eq!(v, vector(&[1, 2]));
+        assert_eq!(super::sort(vec![ints(&[2, 1]), Sexp::BuiltInFunc(first)]),
+                   Err("Argument error: 1".to_string()));
+    }
+
+    #[test]
+    fn test_function_designators() {
+        let define = "(defun double (x) (* x 2)) (defun negate (x) (- x)) ";
+        let results = "(list (mapcar 'double '(1 2)) \
+                              (map 'list 'double '(1)) \
+                              (reduce '+ '(1 2 3)) \
+                              (sort (list 3 1 2) '<) \
+                              (remove-if 'evenp '(1 2 3)) \
+                              (find -2 '(1 2) :key 'negate) \
+                              (member 2.0 '(1 2) :test 'equalp))";
+
+        assert_eq!(eval_source(&format!("{}{}", define, results)),
+                   Ok("((2 4) (2) 6 (1 2 3) (1 3) 2 (2))".to_string()));
+        assert_eq!(eval_source("(mapcar 'nope '(1))"),
+                   Err("The function NOPE is undefined".to_string()));
+    }
+}
@@ -0,0 +1,202 @@
+use super::super::env::{self, Env};
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::stream::Stream;
+use super::expect_args;
+
+/// Optional stream arguments default to standard output; `t` also means
+/// standard output.
+fn output_stream(args: &[Sexp], at: usize) -> Result<Stream, String> {
+    match args.get(at) {
+        None | Some(&Sexp::Nil) | Some(&Sexp::True) => Ok(Stream::Stdout),
+        Some(Sexp::Stream(s)) if !s.is_input() => Ok(s.clone()),
+        Some(v) => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// Optional stream arguments default to the value of `*standard-input*`, as
+/// do nil and `t`.
+pub fn input_stream(args: &[Sexp], at: usize) -> Result<Stream, String> {
+    match args.get(at) {
+        Some(Sexp::Stream(s)) if s.is_input() => Ok(s.clone()),
+        None | Some(&Sexp::Nil) | Some(&Sexp::True) => standard_input(),
+        Some(v) => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn standard_input() -> Result<Stream, String> {
+    let name = package::cl_symbol("*STANDARD-INPUT*");
+    match env::global().and_then(|env| env::env_get(&env, name)) {
+        Some(Sexp::Stream(s)) if s.is_input() => Ok(s),
+        Some(v) => Err(format!("The value of *STANDARD-INPUT* is {}, not an input stream", v)),
+        None => Ok(Stream::Stdin),
+    }
+}
+
+/// What reading `object` from the stream at `args[at]` gives: the object, or
+/// at end of file an error unless `eof-error-p` is nil, in which case
+/// `eof-value`.
+pub fn or_eof(object: Option<Sexp>, args: &[Sexp], at: usize) -> SexpResult {
+    match object {
+        Some(object) => Ok(object),
+        None if args.get(at + 1) == Some(&Sexp::Nil) => Ok(args.get(at + 2).cloned().unwrap_or(Sexp::Nil)),
+        None => Err("End of file".to_string()),
+    }
+}
+
+fn make_string_output_stream(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 0)?;
+
+    Ok(Sexp::Stream(Stream::string_output()))
+}
+
+fn make_string_input_stream(args: Vec<Sexp>) -> SexpResult {
+    let s = unpack_args!(args, 1 Sexp::String);
+
+    Ok(Sexp::Stream(Stream::string_input(&s)))
+}
+
+/// `(read-char [stream [eof-error-p [eof-value]]])`
+fn read_char(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 3)?;
+    let stream = input_stream(&args, 0)?;
+
+    or_eof(stream.read_char().map(Sexp::Char), &args, 0)
+}
+
+/// `(peek-char [peek-type [stream [eof-error-p [eof-value]]]])`, where a peek
+/// type of `t` skips whitespace first.
+fn peek_char(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 4)?;
+    let stream = input_stream(&args, 1)?;
+
+    match *args.first().unwrap_or(&Sexp::Nil) {
+        Sexp::Nil => (),
+        Sexp::True => {
+            while stream.peek_char().is_some_and(char::is_whitespace) {
+                stream.read_char();
+            }
+        }
+        ref v => return Err(format!("Argument error: {}", v)),
+    }
+    or_eof(stream.peek_char().map(Sexp::Char), &args, 1)
+}
+
+fn unread_char(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let stream = input_stream(&args, 1)?;
+
+    match args[0] {
+        Sexp::Char(_) => {
+            stream.unread_char();
+            Ok(Sexp::Nil)
+        }
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn get_output_stream_string(args: Vec<Sexp>) -> SexpResult {
+    let stream = unpack_args!(args, 1 Sexp::Stream);
+
+    stream.take_string().map(Sexp::string).ok_or_else(|| format!("Argument error: {}", stream))
+}
+
+fn write_string(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let stream = output_stream(&args, 1)?;
+
+    match args[0] {
+        Sexp::String(ref s) => {
+            stream.write_str(s);
+            Ok(args[0].clone())
+        }
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn terpri(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 1)?;
+
+    output_stream(&args, 0)?.write_str("\n");
+    Ok(Sexp::Nil)
+}
+
+fn fresh_line(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 1)?;
+    let stream = output_stream(&args, 0)?;
+
+    if stream.at_line_start() {
+        Ok(Sexp::Nil)
+    } else {
+        stream.write_str("\n");
+        Ok(Sexp::True)
+    }
+}
+
+pub fn register(env: &Env) {
+    env::env_set(env, "*standard-output*".to_string(), Sexp::Stream(Stream::Stdout));
+    env::env_set(env, "*standard-input*".to_string(), Sexp::Stream(Stream::Stdin));
+    env::env_set(env, "*print-circle*".to_string(), Sexp::Nil);
+    env::env_set_function(env,
+                          "make-string-output-stream".to_string(),
+                          Sexp::BuiltInFunc(make_string_output_stream));
+    env::env_set_function(env,
+                          "get-output-stream-string".to_string(),
+                          Sexp::BuiltInFunc(get_output_stream_string));
+    env::env_set_function(env,
+                          "make-string-input-stream".to_string(),
+                          Sexp::BuiltInFunc(make_string_input_stream));
+    env::env_set_function(env, "read-char".to_string(), Sexp::BuiltInFunc(read_char));
+    env::env_set_function(env, "peek-char".to_string(), Sexp::BuiltInFunc(peek_char));
+    env::env_set_function(env, "unread-char".to_string(), Sexp::BuiltInFunc(unread_char));
+    env::env_set_function(env, "write-string".to_string(), Sexp::BuiltInFunc(write_string));
+    env::env_set_function(env, "terpri".to_string(), Sexp::BuiltInFunc(terpri));
+    env::env_set_function(env, "fresh-line".to_string(), Sexp::BuiltInFunc(fresh_line));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::sexp::Sexp;
+    use super::super::super::stream::Stream;
+
+    #[test]
+    fn test_string_output_stream() {
+        let stream = match super::make_string_output_stream(vec![]) {
+            Ok(Sexp::Stream(s)) => s,
+            other => panic!("unexpected {:?}", other),
+        };
+        let hello = Sexp::string("hello");
+
+        assert_eq!(super::write_string(vec![hello.clone(), Sexp::Stream(stream.clone())]),
+                   Ok(hello));
+        assert_eq!(super::fresh_line(vec![Sexp::Stream(stream.clone())]), Ok(Sexp::True));
+        assert_eq!(super::fresh_line(vec![Sexp::Stream(stream.clone())]), Ok(Sexp::Nil));
+        assert_eq!(super::terpri(vec![Sexp::Stream(stream.clone())]), Ok(Sexp::Nil));
+        assert_eq!(super::get_output_stream_string(vec![Sexp::Stream(stream.clone())]),
+                   Ok(Sexp::string("hello\n\n")));
+        assert_eq!(super::get_output_stream_string(vec![Sexp::Stream(stream)]),
+                   Ok(Sexp::string("")));
+    }
+
+    #[test]
+    fn test_string_input_stream() {
+        let stream = super::make_string_input_stream(vec![Sexp::string(" ab")]).unwrap();
+        let eof = Sexp::symbol(":eof");
+
+        assert_eq!(super::peek_char(vec![Sexp::True, stream.clone()]), Ok(Sexp::Char('a')));
+        assert_eq!(super::read_char(vec![stream.clone()]), Ok(Sexp::Char('a')));
+        assert_eq!(super::unread_char(vec![Sexp::Char('a'), stream.clone()]), Ok(Sexp::Nil));
+        assert_eq!(super::read_char(vec![stream.clone()]), Ok(Sexp::Char('a')));
+        assert_eq!(super::read_char(vec![stream.clone()]), Ok(Sexp::Char('b')));
+        assert_eq!(super::read_char(vec![stream.clone(), Sexp::Nil, eof.clone()]), Ok(eof));
+        assert_eq!(super::read_char(vec![stream.clone()]), Err("End of file".to_string()));
+        assert_eq!(super::write_string(vec![Sexp::string("x"), stream]),
+                   Err("Argument error: #<STRING-INPUT-STREAM>".to_string()));
+    }
+
+    #[test]
+    fn test_get_output_stream_string_requires_string_stream() {
+        assert_eq!(super::get_output_stream_string(vec![Sexp::Stream(Stream::Stdout)]),
+                   Err("Argument error: #<STANDARD-OUTPUT-STREAM>".to_string()));
+    }
+}
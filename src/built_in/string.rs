@@ -0,0 +1,501 @@
+use std::cmp::Ordering;
+use super::super::env::{self, Env};
+use super::super::number::Number;
+use super::super::parser;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::stream::Stream;
+use super::character::downcase;
+use super::{expect_args, keyword_name};
+
+/// Accepts the string designators: strings, symbols (their name) and characters.
+fn string_designator(s: &Sexp) -> Result<String, String> {
+    match *s {
+        Sexp::String(ref s) => Ok(s.to_string()),
+        Sexp::Symbol(ref s) => Ok(s.name().to_string()),
+        Sexp::Char(c) => Ok(c.to_string()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+pub fn index(n: &Sexp) -> Result<usize, String> {
+    match *n {
+        Sexp::Number(Number::Fixnum(i)) if i >= 0 => Ok(i as usize),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn fixnum(n: usize) -> Sexp {
+    Sexp::Number(Number::Fixnum(n as i64))
+}
+
+/// Converts a byte offset into `s` to the index of the character starting there.
+fn char_index(s: &str, byte_offset: usize) -> usize {
+    s[..byte_offset].chars().count()
+}
+
+fn string(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    string_designator(&args[0]).map(Sexp::string)
+}
+
+fn string_upcase(args: Vec<Sexp>) -> SexpResult {
+    let s = unpack_args!(args, 1 Sexp::String);
+
+    Ok(Sexp::string(s.to_uppercase()))
+}
+
+fn string_downcase(args: Vec<Sexp>) -> SexpResult {
+    let s = unpack_args!(args, 1 Sexp::String);
+
+    Ok(Sexp::string(s.to_lowercase()))
+}
+
+/// The character bag of `string-trim` may be a string or a list of characters.
+fn char_bag(bag: &Sexp) -> Result<Vec<char>, String> {
+    match *bag {
+        Sexp::String(ref s) => Ok(s.chars().collect()),
+        Sexp::List(ref v) => {
+            v.iter()
+                .map(|c| {
+                    match *c {
+                        Sexp::Char(c) => Ok(c),
+                        ref v => Err(format!("Argument error: {}", v)),
+                    }
+                })
+                .collect()
+        }
+        Sexp::Nil => Ok(vec![]),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn trim(args: Vec<Sexp>, left: bool, right: bool) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let bag = char_bag(&args[0])?;
+    let s = string_designator(&args[1])?;
+
+    let mut trimmed = &s[..];
+    if left {
+        trimmed = trimmed.trim_start_matches(&bag[..]);
+    }
+    if right {
+        trimmed = trimmed.trim_end_matches(&bag[..]);
+    }
+
+    Ok(Sexp::string(trimmed))
+}
+
+fn string_trim(args: Vec<Sexp>) -> SexpResult {
+    trim(args, true, true)
+}
+
+fn string_left_trim(args: Vec<Sexp>) -> SexpResult {
+    trim(args, true, false)
+}
+
+fn string_right_trim(args: Vec<Sexp>) -> SexpResult {
+    trim(args, false, true)
+}
+
+/// Returns the character index of the first occurrence of the first string
+/// in the second, or nil.
+fn search(args: Vec<Sexp>) -> SexpResult {
+    let (needle, haystack) = unpack_args!(args, 1 Sexp::String, 1 Sexp::String);
+
+    Ok(haystack.find(&needle[..]).map_or(Sexp::Nil, |i| fixnum(char_index(&haystack, i))))
+}
+
+/// Compares two string designators character by character, returning the
+/// ordering and the index of the first mismatching character.
+fn compare_strings(args: Vec<Sexp>, fold: bool) -> Result<(Ordering, usize), String> {
+    expect_args(&args, 2, 2)?;
+    let key = |c: char| if fold { downcase(c) } else { c };
+    let a: Vec<char> = string_designator(&args[0])?.chars().map(key).collect();
+    let b: Vec<char> = string_designator(&args[1])?.chars().map(key).collect();
+
+    let mismatch = a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count();
+    let ordering = match (a.get(mismatch), b.get(mismatch)) {
+        (Some(x), Some(y)) => x.cmp(y),
+        _ => a.len().cmp(&b.len()),
+    };
+
+    Ok((ordering, mismatch))
+}
+
+fn string_equality(args: Vec<Sexp>, fold: bool) -> SexpResult {
+    let (ordering, _) = compare_strings(args, fold)?;
+
+    Ok(if ordering == Ordering::Equal { Sexp::True } else { Sexp::Nil })
+}
+
+/// Ordering comparisons return the mismatch index when true, like Common Lisp.
+fn string_comparison<F>(args: Vec<Sexp>, fold: bool, accept: F) -> SexpResult
+    where F: Fn(Ordering) -> bool
+{
+    let (ordering, mismatch) = compare_strings(args, fold)?;
+
+    Ok(if accept(ordering) { fixnum(mismatch) } else { Sexp::Nil })
+}
+
+fn string_eq(args: Vec<Sexp>) -> SexpResult {
+    string_equality(args, false)
+}
+
+fn string_ne(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, false, |o| o != Ordering::Equal)
+}
+
+fn string_lt(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, false, |o| o == Ordering::Less)
+}
+
+fn string_gt(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, false, |o| o == Ordering::Greater)
+}
+
+fn string_lte(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, false, |o| o != Ordering::Greater)
+}
+
+fn string_gte(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, false, |o| o != Ordering::Less)
+}
+
+fn string_equal(args: Vec<Sexp>) -> SexpResult {
+    string_equality(args, true)
+}
+
+fn string_not_equal(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, true, |o| o != Ordering::Equal)
+}
+
+fn string_lessp(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, true, |o| o == Ordering::Less)
+}
+
+fn string_greaterp(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, true, |o| o == Ordering::Greater)
+}
+
+fn string_not_greaterp(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, true, |o| o != Ordering::Greater)
+}
+
+fn string_not_lessp(args: Vec<Sexp>) -> SexpResult {
+    string_comparison(args, true, |o| o != Ordering::Less)
+}
+
+fn radix_arg(args: &[Sexp], at: usize) -> Result<u32, String> {
+    match args.get(at) {
+        None => Ok(10),
+        Some(&Sexp::Number(Number::Fixnum(r))) if (2..=36).contains(&r) => Ok(r as u32),
+        Some(v) => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// `(parse-integer string &key start end radix junk-allowed)` reads an
+/// optionally signed integer surrounded by whitespace, returning it and the
+/// index where reading stopped. With `junk-allowed` it stops at the first
+/// character that isn't a digit, and returns nil if there were none.
+fn parse_integer(args: Vec<Sexp>) -> SexpResult {
+    if args.is_empty() || args.len().is_multiple_of(2) {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+    let chars: Vec<char> = string_designator(&args[0])?.chars().collect();
+
+    let (mut start, mut end, mut radix, mut junk_allowed) = (0, chars.len(), 10, false);
+    for pair in args[1..].chunks(2) {
+        match keyword_name(&pair[0])? {
+            "START" => start = index(&pair[1])?,
+            "END" if pair[1] == Sexp::Nil => {}
+            "END" => end = index(&pair[1])?,
+            "RADIX" => radix = radix_arg(pair, 1)?,
+            "JUNK-ALLOWED" => junk_allowed = pair[1] != Sexp::Nil,
+            _ => return Err(format!("Argument error: {}", pair[0])),
+        }
+    }
+    if start > end || end > chars.len() {
+        return Err(format!("Bounds {} {} out of range for {}", start, end, args[0]));
+    }
+
+    let skip_whitespace = |mut i: usize| {
+        while i < end && chars[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    };
+    let mut i = skip_whitespace(start);
+    let negative = i < end && chars[i] == '-';
+    if i < end && (chars[i] == '-' || chars[i] == '+') {
+        i += 1;
+    }
+    let digits_start = i;
+    while i < end && chars[i].is_digit(radix) {
+        i += 1;
+    }
+    let digits: String = chars[digits_start..i].iter().collect();
+    let value = parser::parse_unsigned(&digits, radix)
+        .map(|n| Sexp::Number(Number::from_bigint(if negative { -n } else { n })));
+
+    if junk_allowed {
+        return Ok(Sexp::Values(vec![value.unwrap_or(Sexp::Nil), fixnum(i)]));
+    }
+    match value {
+        Some(n) if skip_whitespace(i) == end => Ok(Sexp::Values(vec![n, fixnum(end)])),
+        _ => Err(format!("Junk in string {}", args[0])),
+    }
+}
+
+/// Reads a number using the reader's numeric syntax, returning nil if the
+/// string is not exactly one number.
+fn string_to_number(args: Vec<Sexp>) -> SexpResult {
+    let s = unpack_args!(args, 1 Sexp::String);
+
+    let stream = Stream::string_input(s.trim());
+    match parser::read_object(&stream) {
+        Ok(n @ Sexp::Number(_)) if stream.peek_char().is_none() => Ok(n),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn integer_to_string(n: &Number, radix: u32) -> Option<String> {
+    n.to_bigint().map(|i| i.to_str_radix(radix).to_uppercase())
+}
+
+fn number_to_string(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let radix = radix_arg(&args, 1)?;
+    let n = match args[0] {
+        Sexp::Number(ref n) => n,
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    if radix == 10 {
+        return Ok(Sexp::string(n.to_string()));
+    }
+
+    let printed = match n.to_ratio() {
+        Some(ref r) if n.to_bigint().is_none() => {
+            let numer = Number::from_bigint(r.numer().clone());
+            let denom = Number::from_bigint(r.denom().clone());
+            integer_to_string(&numer, radix)
+                .and_then(|n| integer_to_string(&denom, radix).map(|d| format!("{}/{}", n, d)))
+        }
+        _ => integer_to_string(n, radix),
+    };
+
+    printed.map(Sexp::string).ok_or_else(|| format!("Argument error: {}", n))
+}
+
+fn separator(s: &Sexp) -> Result<String, String> {
+    match string_designator(s)? {
+        ref sep if sep.is_empty() => Err(format!("Argument error: {}", s)),
+        sep => Ok(sep),
+    }
+}
+
+/// `(split separator string)` splits on every occurrence of the separator.
+fn split(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let sep = separator(&args[0])?;
+    let s = match args[1] {
+        Sexp::String(ref s) => s,
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    Ok(Sexp::list(s.split(&sep[..]).map(Sexp::string).collect()))
+}
+
+/// `(join separator strings)` is the inverse of `split`.
+fn join(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let sep = string_designator(&args[0])?;
+    let parts: Vec<String> = match args[1] {
+        Sexp::List(ref v) => v.iter().map(string_designator).collect::<Result<_, _>>()?,
+        Sexp::Nil => vec![],
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    Ok(Sexp::string(parts.join(&sep)))
+}
+
+/// `(replace-all old new string)` replaces every occurrence of `old`.
+fn replace_all(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 3, 3)?;
+    let old = separator(&args[0])?;
+    let new = string_designator(&args[1])?;
+    let s = match args[2] {
+        Sexp::String(ref s) => s,
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    Ok(Sexp::string(s.replace(&old[..], &new)))
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "string".to_string(), Sexp::BuiltInFunc(string));
+    env::env_set_function(env, "string-upcase".to_string(), Sexp::BuiltInFunc(string_upcase));
+    env::env_set_function(env, "string-downcase".to_string(), Sexp::BuiltInFunc(string_downcase));
+    env::env_set_function(env, "string-trim".to_string(), Sexp::BuiltInFunc(string_trim));
+    env::env_set_function(env, "string-left-trim".to_string(), Sexp::BuiltInFunc(string_left_trim));
+    env::env_set_function(env, "string-right-trim".to_string(), Sexp::BuiltInFunc(string_right_trim));
+    env::env_set_function(env, "search".to_string(), Sexp::BuiltInFunc(search));
+    env::env_set_function(env, "string=".to_string(), Sexp::BuiltInFunc(string_eq));
+    env::env_set_function(env, "string/=".to_string(), Sexp::BuiltInFunc(string_ne));
+    env::env_set_function(env, "string<".to_string(), Sexp::BuiltInFunc(string_lt));
+    env::env_set_function(env, "string>".to_string(), Sexp::BuiltInFunc(string_gt));
+    env::env_set_function(env, "string<=".to_string(), Sexp::BuiltInFunc(string_lte));
+    env::env_set_function(env, "string>=".to_string(), Sexp::BuiltInFunc(string_gte));
+    env::env_set_function(env, "string-equal".to_string(), Sexp::BuiltInFunc(string_equal));
+    env::env_set_function(env, "string-not-equal".to_string(), Sexp::BuiltInFunc(string_not_equal));
+    env::env_set_function(env, "string-lessp".to_string(), Sexp::BuiltInFunc(string_lessp));
+    env::env_set_function(env, "string-greaterp".to_string(), Sexp::BuiltInFunc(string_greaterp));
+    env::env_set_function(env, "string-not-greaterp".to_string(), Sexp::BuiltInFunc(string_not_greaterp));
+    env::env_set_function(env, "string-not-lessp".to_string(), Sexp::BuiltInFunc(string_not_lessp));
+    env::env_set_function(env, "parse-integer".to_string(), Sexp::BuiltInFunc(parse_integer));
+    env::env_set_function(env, "string-to-number".to_string(), Sexp::BuiltInFunc(string_to_number));
+    env::env_set_function(env, "number-to-string".to_string(), Sexp::BuiltInFunc(number_to_string));
+    env::env_set_function(env, "split".to_string(), Sexp::BuiltInFunc(split));
+    env::env_set_function(env, "join".to_string(), Sexp::BuiltInFunc(join));
+    env::env_set_function(env, "replace-all".to_string(), Sexp::BuiltInFunc(replace_all));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::number::Number;
+    use super::super::super::sexp::Sexp;
+    use super::super::super::test_util::{int, string};
+
+    fn strings(ss: &[&str]) -> Sexp {
+        Sexp::list(ss.iter().map(|s| string(s)).collect())
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        assert_eq!(super::string_upcase(vec![string("straße")]), Ok(string("STRASSE")));
+        assert_eq!(super::string_downcase(vec![string("ÉCOLE")]), Ok(string("école")));
+    }
+
+    #[test]
+    fn test_string_trim() {
+        assert_eq!(super::string_trim(vec![string(" \t"), string("  hi \t")]),
+                   Ok(string("hi")));
+        assert_eq!(super::string_left_trim(vec![Sexp::list(vec![Sexp::Char('é')]),
+                                                string("ééaé")]),
+                   Ok(string("aé")));
+        assert_eq!(super::string_right_trim(vec![string("é"), string("ééaé")]),
+                   Ok(string("ééa")));
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(super::search(vec![string("lo"), string("héllo")]), Ok(int(3)));
+        assert_eq!(super::search(vec![string("x"), string("hello")]), Ok(Sexp::Nil));
+        assert_eq!(super::search(vec![string(""), string("hello")]), Ok(int(0)));
+    }
+
+    #[test]
+    fn test_string_comparisons() {
+        assert_eq!(super::string_eq(vec![string("abc"), string("abc")]), Ok(Sexp::True));
+        assert_eq!(super::string_eq(vec![string("abc"), string("ABC")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_eq(vec![Sexp::symbol("a"), Sexp::Char('A')]),
+                   Ok(Sexp::True));
+        assert_eq!(super::string_ne(vec![string("abc"), string("abd")]), Ok(int(2)));
+        assert_eq!(super::string_lt(vec![string("abc"), string("abd")]), Ok(int(2)));
+        assert_eq!(super::string_lt(vec![string("ab"), string("abc")]), Ok(int(2)));
+        assert_eq!(super::string_lt(vec![string("abc"), string("ab")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_gt(vec![string("b"), string("a")]), Ok(int(0)));
+        assert_eq!(super::string_lte(vec![string("abc"), string("abc")]), Ok(int(3)));
+        assert_eq!(super::string_gte(vec![string("a"), string("b")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_eq(vec![string("a")]),
+                   Err("Invalid number of arguments: 1".to_string()));
+    }
+
+    #[test]
+    fn test_case_insensitive_string_comparisons() {
+        assert_eq!(super::string_equal(vec![string("Straße"), string("STRAßE")]),
+                   Ok(Sexp::True));
+        assert_eq!(super::string_not_equal(vec![string("abc"), string("ABC")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_lessp(vec![string("apple"), string("Banana")]), Ok(int(0)));
+        assert_eq!(super::string_greaterp(vec![string("apple"), string("Banana")]),
+                   Ok(Sexp::Nil));
+        assert_eq!(super::string_not_greaterp(vec![string("ABC"), string("abc")]), Ok(int(3)));
+        assert_eq!(super::string_not_lessp(vec![string("b"), string("A")]), Ok(int(0)));
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        let parsed = |n: i64, end: i64| Ok(Sexp::Values(vec![int(n), int(end)]));
+
+        assert_eq!(super::parse_integer(vec![string(" 42 ")]), parsed(42, 4));
+        assert_eq!(super::parse_integer(vec![string("-17")]), parsed(-17, 3));
+        assert_eq!(super::parse_integer(vec![string("ff"), Sexp::symbol(":radix"), int(16)]),
+                   parsed(255, 2));
+        assert_eq!(super::parse_integer(vec![string("a12b"),
+                                             Sexp::symbol(":start"),
+                                             int(1),
+                                             Sexp::symbol(":end"),
+                                             int(3)]),
+                   parsed(12, 3));
+        let junk_allowed = |s: &str| {
+            super::parse_integer(vec![string(s), Sexp::symbol(":junk-allowed"), Sexp::True])
+        };
+        assert_eq!(junk_allowed(" 12a"), parsed(12, 3));
+        assert_eq!(junk_allowed("x"), Ok(Sexp::Values(vec![Sexp::Nil, int(0)])));
+        assert_eq!(super::parse_integer(vec![string("12a")]),
+                   Err("Junk in string \"12a\"".to_string()));
+        assert_eq!(super::parse_integer(vec![string("1 2")]),
+                   Err("Junk in string \"1 2\"".to_string()));
+        assert_eq!(super::parse_integer(vec![string("")]),
+                   Err("Junk in string \"\"".to_string()));
+        assert_eq!(super::parse_integer(vec![string("1"), Sexp::symbol(":radix"), int(1)]),
+                   Err("Argument error: 1".to_string()));
+        assert_eq!(super::parse_integer(vec![string("1"), int(16)]),
+                   Err("Invalid number of arguments: 2".to_string()));
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        assert_eq!(super::string_to_number(vec![string("42")]), Ok(int(42)));
+        assert_eq!(super::string_to_number(vec![string(" 1.5 ")]),
+                   Ok(Sexp::Number(Number::Float(1.5))));
+        assert_eq!(super::string_to_number(vec![string("#xff")]), Ok(int(255)));
+        assert_eq!(super::string_to_number(vec![string("12abc")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_to_number(vec![string("1 2")]), Ok(Sexp::Nil));
+        assert_eq!(super::string_to_number(vec![string("")]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_number_to_string() {
+        assert_eq!(super::number_to_string(vec![int(42)]), Ok(string("42")));
+        assert_eq!(super::number_to_string(vec![Sexp::Number(Number::Float(0.5))]),
+                   Ok(string("0.5")));
+        assert_eq!(super::number_to_string(vec![int(255), int(16)]), Ok(string("FF")));
+        assert_eq!(super::number_to_string(vec![int(-5), int(2)]), Ok(string("-101")));
+        assert_eq!(super::number_to_string(vec![Sexp::Number(Number::Float(0.5)), int(2)]),
+                   Err("Argument error: 0.5".to_string()));
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        assert_eq!(super::split(vec![string(", "), string("a, b, c")]),
+                   Ok(strings(&["a", "b", "c"])));
+        assert_eq!(super::split(vec![Sexp::Char('é'), string("aéb")]),
+                   Ok(strings(&["a", "b"])));
+        assert_eq!(super::split(vec![string(","), string("")]), Ok(strings(&[""])));
+        assert_eq!(super::split(vec![string(""), string("abc")]),
+                   Err("Argument error: \"\"".to_string()));
+        assert_eq!(super::join(vec![string("-"), strings(&["a", "b", "c"])]),
+                   Ok(string("a-b-c")));
+        assert_eq!(super::join(vec![string("-"), Sexp::Nil]), Ok(string("")));
+    }
+
+    #[test]
+    fn test_replace_all() {
+        assert_eq!(super::replace_all(vec![string("ö"), string("oe"), string("schön schön")]),
+                   Ok(string("schoen schoen")));
+        assert_eq!(super::replace_all(vec![string(""), string("x"), string("abc")]),
+                   Err("Argument error: \"\"".to_string()));
+    }
+}
@@ -0,0 +1,338 @@
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use super::super::env::{self, Env, EnvData};
+use super::super::hash_table::HashTest;
+use super::super::package;
+use super::super::sexp::{Sexp, SexpResult};
+use super::super::symbol::Symbol;
+use super::{define_global, expect_args, global, global_closure};
+
+/// The symbol `s` is, treating `nil` and `t` as the symbols they print as.
+fn symbol(s: &Sexp) -> Result<Symbol, String> {
+    match *s {
+        Sexp::Symbol(ref symbol) => Ok(symbol.clone()),
+        Sexp::Nil => Ok(package::cl_symbol("NIL")),
+        Sexp::True => Ok(package::cl_symbol("T")),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn string(s: &Sexp) -> Result<String, String> {
+    match *s {
+        Sexp::String(ref s) => Ok(s.to_string()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// `(get symbol indicator [default])`
+fn get(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+
+    Ok(symbol(&args[0])?
+        .get(&args[1])
+        .unwrap_or_else(|| args.get(2).cloned().unwrap_or(Sexp::Nil)))
+}
+
+/// `(setf (get symbol indicator [default]) value)`
+fn setf_get(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 3, 4)?;
+
+    symbol(&args[1])?.put(args[2].clone(), args[0].clone());
+    Ok(args[0].clone())
+}
+
+fn symbol_plist(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let plist = symbol(&args[0])?.plist();
+
+    Ok(if plist.is_empty() { Sexp::Nil } else { Sexp::list(plist) })
+}
+
+fn remprop(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+    let removed = symbol(&args[0])?.remprop(&args[1]);
+
+    Ok(if removed { Sexp::True } else { Sexp::Nil })
+}
+
+/// `(getf plist indicator [default])`
+fn getf(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 3)?;
+    let plist: &[Sexp] = match args[0] {
+        Sexp::List(ref v) if v.len().is_multiple_of(2) => v,
+        Sexp::Nil => &[],
+        ref v => return Err(format!("Malformed property list: {}", v)),
+    };
+
+    Ok(plist.chunks(2)
+        .find(|pair| HashTest::Eq.test(&pair[0], &args[1]))
+        .map(|pair| pair[1].clone())
+        .unwrap_or_else(|| args.get(2).cloned().unwrap_or(Sexp::Nil)))
+}
+
+fn keywordp(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match args[0] {
+        Sexp::Symbol(ref s) if s.is_keyword() => Ok(Sexp::True),
+        _ => Ok(Sexp::Nil),
+    }
+}
+
+fn symbol_name(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(Sexp::string(symbol(&args[0])?.name()))
+}
+
+/// `(intern name [package])` returns the symbol and, if it was already
+/// accessible, how.
+fn intern(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 2)?;
+    let name = string(&args[0])?;
+    let package = match args.get(1) {
+        Some(p) => package::designated(p)?,
+        None => package::current(),
+    };
+    let status = match package.find_symbol(&name) {
+        Some((_, status)) => Sexp::Symbol(Symbol::keyword(status.name())),
+        None => Sexp::Nil,
+    };
+
+    Ok(Sexp::Values(vec![Sexp::from_symbol(package.intern(&name)), status]))
+}
+
+fn make_symbol(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    Ok(Sexp::Symbol(Symbol::uninterned(&string(&args[0])?)))
+}
+
+/// `(gensym [prefix])`
+fn gensym(args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 0, 1)?;
+    let prefix = match args.first() {
+        Some(prefix) => string(prefix)?,
+        None => "G".to_string(),
+    };
+
+    Ok(Sexp::Symbol(Symbol::gensym(&prefix)))
+}
+
+fn symbol_value(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let s = symbol(&args[0])?;
+
+    match args[0] {
+        Sexp::Nil | Sexp::True => return Ok(args[0].clone()),
+        _ if s.is_keyword() => return Ok(args[0].clone()),
+        _ => {}
+    }
+    env::env_get(&global(env)?, &s).ok_or_else(|| format!("The variable {} is unbound", s))
+}
+
+/// `(setf (symbol-value symbol) value)`
+fn setf_symbol_value(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 2, 2)?;
+
+    env::env_set(&global(env)?, symbol(&args[1])?, args[0].clone());
+    Ok(args[0].clone())
+}
+
+fn symbol_function(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let s = symbol(&args[0])?;
+
+    match env::env_get_function(&global(env)?, &s) {
+        Some(f @ Sexp::BuiltInFunc(_)) |
+        Some(f @ Sexp::UserFunc(_)) |
+        Some(f @ Sexp::Closure(_)) => Ok(f),
+        _ => Err(format!("The function {} is undefined", s)),
+    }
+}
+
+fn fboundp(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    match env::env_get_function(&global(env)?, symbol(&args[0])?) {
+        Some(_) => Ok(Sexp::True),
+        None => Ok(Sexp::Nil),
+    }
+}
+
+fn fmakunbound(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+    let s = symbol(&args[0])?;
+    package::check_lock(&s)?;
+
+    env::env_remove_function(&global(env)?, s);
+    Ok(args[0].clone())
+}
+
+/// The function `designator` names: itself, or a symbol's global function.
+pub fn designated_function(env: &Weak<RefCell<EnvData>>, designator: &Sexp) -> SexpResult {
+    match *designator {
+        Sexp::Symbol(_) => symbol_function(env, vec![designator.clone()]),
+        ref f => Ok(f.clone()),
+    }
+}
+
+/// The function `designator` names in the global environment, for the
+/// built-ins such as `mapcar` that take functions but no environment.
+pub fn global_function(designator: &Sexp) -> SexpResult {
+    let global = env::global().map(|g| Rc::downgrade(&g)).unwrap_or_default();
+
+    designated_function(&global, designator)
+}
+
+fn funcall(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    match args.split_first() {
+        Some((f, rest)) => designated_function(env, f)?.apply(rest.to_vec()),
+        None => Err("Invalid number of arguments: 0".to_string()),
+    }
+}
+
+/// `(apply function arg... list)` calls with the args followed by the
+/// elements of the list.
+fn apply(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    if args.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let mut spread = args[1..args.len() - 1].to_vec();
+    match args[args.len() - 1] {
+        Sexp::List(ref v) => spread.extend(v.iter().cloned()),
+        Sexp::Nil => {}
+        ref v => return Err(format!("Argument error: {}", v)),
+    }
+    designated_function(env, &args[0])?.apply(spread)
+}
+
+/// `(eval form)` evaluates `form` in the global environment.
+fn eval(env: &Weak<RefCell<EnvData>>, args: Vec<Sexp>) -> SexpResult {
+    expect_args(&args, 1, 1)?;
+
+    args[0].eval(&global(env)?)
+}
+
+pub fn register(env: &Env) {
+    env::env_set_function(env, "get".to_string(), Sexp::BuiltInFunc(get));
+    env::env_set_setf_function(env, "get", Sexp::BuiltInFunc(setf_get));
+    env::env_set_function(env, "symbol-plist".to_string(), Sexp::BuiltInFunc(symbol_plist));
+    env::env_set_function(env, "remprop".to_string(), Sexp::BuiltInFunc(remprop));
+    env::env_set_function(env, "getf".to_string(), Sexp::BuiltInFunc(getf));
+    env::env_set_function(env, "keywordp".to_string(), Sexp::BuiltInFunc(keywordp));
+    env::env_set_function(env, "symbol-name".to_string(), Sexp::BuiltInFunc(symbol_name));
+    env::env_set_function(env, "intern".to_string(), Sexp::BuiltInFunc(intern));
+    env::env_set_function(env, "make-symbol".to_string(), Sexp::BuiltInFunc(make_symbol));
+    env::env_set_function(env, "gensym".to_string(), Sexp::BuiltInFunc(gensym));
+    define_global(env, "symbol-value", symbol_value);
+    env::env_set_setf_function(env, "symbol-value", global_closure(env, setf_symbol_value));
+    define_global(env, "symbol-function", symbol_function);
+    define_global(env, "fboundp", fboundp);
+    define_global(env, "fmakunbound", fmakunbound);
+    define_global(env, "funcall", funcall);
+    define_global(env, "apply", apply);
+    define_global(env, "eval", eval);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::env;
+    use super::super::super::sexp::Sexp;
+    use super::super::super::symbol::Symbol;
+    use super::super::super::test_util::int;
+
+    fn call(env: &env::Env, name: &str, args: Vec<Sexp>) -> Result<Sexp, String> {
+        env::env_get_function(env, name).unwrap().apply(args)
+    }
+
+    #[test]
+    fn test_plist() {
+        let s = Sexp::Symbol(Symbol::uninterned("S"));
+        let k = Sexp::symbol("k");
+
+        assert_eq!(super::get(vec![s.clone(), k.clone(), int(0)]), Ok(int(0)));
+        assert_eq!(super::setf_get(vec![int(1), s.clone(), k.clone()]), Ok(int(1)));
+        assert_eq!(super::get(vec![s.clone(), k.clone()]), Ok(int(1)));
+        assert_eq!(super::symbol_plist(vec![s.clone()]), Ok(Sexp::list(vec![k.clone(), int(1)])));
+        assert_eq!(super::remprop(vec![s.clone(), k.clone()]), Ok(Sexp::True));
+        assert_eq!(super::symbol_plist(vec![s]), Ok(Sexp::Nil));
+    }
+
+    #[test]
+    fn test_keywords() {
+        let plist = Sexp::list(vec![Sexp::symbol(":a"), int(1), Sexp::symbol(":b"), int(2)]);
+
+        assert_eq!(super::keywordp(vec![Sexp::symbol(":a")]), Ok(Sexp::True));
+        assert_eq!(super::keywordp(vec![Sexp::symbol("a")]), Ok(Sexp::Nil));
+        assert_eq!(super::symbol_name(vec![Sexp::symbol(":a")]), Ok(Sexp::string("A")));
+        assert_eq!(super::getf(vec![plist.clone(), Sexp::symbol(":b")]), Ok(int(2)));
+        assert_eq!(super::getf(vec![plist, Sexp::symbol(":c"), int(0)]), Ok(int(0)));
+        assert_eq!(super::getf(vec![Sexp::list(vec![Sexp::symbol(":a")]), Sexp::symbol(":a")]),
+                   Err("Malformed property list: (:A)".to_string()));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(super::symbol_name(vec![Sexp::symbol("abc")]), Ok(Sexp::string("ABC")));
+        assert_eq!(super::symbol_name(vec![Sexp::Nil]), Ok(Sexp::string("NIL")));
+        assert_eq!(super::intern(vec![Sexp::string("ABC")]),
+                   Ok(Sexp::Values(vec![Sexp::symbol("abc"), Sexp::symbol(":internal")])));
+
+        let made = super::make_symbol(vec![Sexp::string("ABC")]).unwrap();
+        assert!(made != Sexp::symbol("abc"));
+        assert_eq!(format!("{}", made), "#:ABC");
+        assert!(super::gensym(vec![]).unwrap() != super::gensym(vec![]).unwrap());
+    }
+
+    #[test]
+    fn test_symbol_value_and_function() {
+        let global = env::env_new(None);
+        super::register(&global);
+        env::env_set(&global, "x", int(1));
+
+        assert_eq!(call(&global, "symbol-value", vec![Sexp::symbol("x")]), Ok(int(1)));
+        let setf_symbol_value = env::env_get_setf_function(&global, "symbol-value").unwrap();
+        assert_eq!(setf_symbol_value.apply(vec![int(2), Sexp::symbol("x")]), Ok(int(2)));
+        assert_eq!(env::env_get(&global, "x"), Some(int(2)));
+        assert_eq!(call(&global, "symbol-value", vec![Sexp::symbol("y")]),
+                   Err("The variable Y is unbound".to_string()));
+        assert_eq!(call(&global, "symbol-function", vec![Sexp::symbol("get")]),
+                   Ok(Sexp::BuiltInFunc(super::get)));
+        assert_eq!(call(&global, "symbol-function", vec![Sexp::symbol("x")]),
+                   Err("The function X is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_function_namespace() {
+        let global = env::env_new(None);
+        super::register(&global);
+        env::env_set(&global, "get", int(1));
+        let args = Sexp::list(vec![Sexp::symbol("s"), Sexp::symbol("k"), int(2)]);
+
+        assert_eq!(call(&global, "fboundp", vec![Sexp::symbol("get")]), Ok(Sexp::True));
+        assert_eq!(call(&global,
+                        "funcall",
+                        vec![Sexp::symbol("get"), Sexp::symbol("s"), Sexp::symbol("k"), int(2)]),
+                   Ok(int(2)));
+        assert_eq!(call(&global, "apply", vec![Sexp::symbol("get"), args]), Ok(int(2)));
+        assert_eq!(call(&global, "fmakunbound", vec![Sexp::symbol("get")]),
+                   Ok(Sexp::symbol("get")));
+        assert_eq!(call(&global, "fboundp", vec![Sexp::symbol("get")]), Ok(Sexp::Nil));
+        assert_eq!(env::env_get(&global, "get"), Some(int(1)));
+    }
+
+    #[test]
+    fn test_eval() {
+        let global = env::env_new(None);
+        super::register(&global);
+        env::env_set(&global, "x", int(1));
+
+        assert_eq!(call(&global, "eval", vec![Sexp::symbol("x")]), Ok(int(1)));
+        assert_eq!(call(&global,
+                        "eval",
+                        vec![Sexp::list(vec![Sexp::symbol("quote"), Sexp::symbol("y")])]),
+                   Ok(Sexp::symbol("y")));
+    }
+}
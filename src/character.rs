@@ -0,0 +1,66 @@
+/// Names accepted by the `#\` reader syntax. When a character has several
+/// names the first one is used for printing.
+const CHAR_NAMES: &[(&str, char)] = &[
+    ("Space", ' '),
+    ("Newline", '\n'),
+    ("Tab", '\t'),
+    ("Return", '\r'),
+    ("Linefeed", '\n'),
+    ("Page", '\x0c'),
+    ("Backspace", '\x08'),
+    ("Rubout", '\x7f'),
+    ("Nul", '\0'),
+    ("Escape", '\x1b'),
+    ("Bell", '\x07'),
+];
+
+/// Looks up a character by name, ignoring case. Names of the form `U+XXXX`
+/// give the character's code point in hexadecimal.
+pub fn char_from_name(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c);
+    }
+
+    if let Some(code) = name.strip_prefix("U+").or_else(|| name.strip_prefix("u+")) {
+        return u32::from_str_radix(code, 16).ok().and_then(::std::char::from_u32);
+    }
+
+    CHAR_NAMES.iter()
+        .find(|&&(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, c)| c)
+}
+
+/// Returns the name `#\` syntax should use to print a character, or `None`
+/// when the character can be printed as itself.
+pub fn char_name(c: char) -> Option<String> {
+    match CHAR_NAMES.iter().find(|&&(_, named)| named == c) {
+        Some(&(name, _)) => Some(name.to_string()),
+        None if c.is_control() => Some(format!("U+{:04X}", c as u32)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{char_from_name, char_name};
+
+    #[test]
+    fn test_char_from_name() {
+        assert_eq!(char_from_name("a"), Some('a'));
+        assert_eq!(char_from_name("é"), Some('é'));
+        assert_eq!(char_from_name("Space"), Some(' '));
+        assert_eq!(char_from_name("NEWLINE"), Some('\n'));
+        assert_eq!(char_from_name("U+1F600"), Some('😀'));
+        assert_eq!(char_from_name("U+D800"), None);
+        assert_eq!(char_from_name("ab"), None);
+    }
+
+    #[test]
+    fn test_char_name() {
+        assert_eq!(char_name('a'), None);
+        assert_eq!(char_name(' '), Some("Space".to_string()));
+        assert_eq!(char_name('\n'), Some("Newline".to_string()));
+        assert_eq!(char_name('\u{1}'), Some("U+0001".to_string()));
+    }
+}
@@ -0,0 +1,160 @@
+/// What to do before the REPL or script, in the order given.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Eval(String),
+    Load(String),
+}
+
+/// The command line: `rust-lisp [options] [script [args...]]`.
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub actions: Vec<Action>,
+    pub script: Option<String>,
+    /// The arguments after the script, or after `--`.
+    pub args: Vec<String>,
+    /// Where the REPL keeps its history, if anywhere.
+    pub history_file: Option<String>,
+    pub init: bool,
+    pub quiet: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+pub const USAGE: &str = "Usage: rust-lisp [options] [script [args...]]
+
+Runs the script if one is given, then exits; otherwise evaluates the -e and -l
+options in order and, unless there was an -e, reads forms from standard input
+if it isn't a terminal or starts the REPL if it is.
+
+Options:
+  -e, --eval EXPR        evaluate EXPR (may be repeated)
+  -l, --load FILE        load FILE (may be repeated)
+      --script FILE      run FILE as a script with the remaining arguments
+      --no-history       don't read or write the REPL history
+      --history-file PATH
+                         keep the REPL history in PATH (default history.txt)
+      --no-init          don't load ~/.rust-lisprc
+  -q, --quiet            don't print the banner and goodbye message
+  -h, --help             print this help
+  -v, --version          print the version
+      --                 pass the remaining arguments to *command-line-args*
+";
+
+/// Splits `--name=value` into its name and value.
+fn split_value(arg: &str) -> (&str, Option<&str>) {
+    match arg.find('=') {
+        Some(i) if arg.starts_with("--") => (&arg[..i], Some(&arg[i + 1..])),
+        _ => (arg, None),
+    }
+}
+
+/// Parses the arguments after the program name.
+pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        actions: vec![],
+        script: None,
+        args: vec![],
+        history_file: Some("history.txt".to_string()),
+        init: true,
+        quiet: false,
+        help: false,
+        version: false,
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline) = split_value(arg);
+        let mut value = || {
+            inline.map(String::from)
+                .or_else(|| iter.next().cloned())
+                .ok_or_else(|| format!("Option {} needs an argument", name))
+        };
+
+        match name {
+            "-e" | "--eval" => options.actions.push(Action::Eval(value()?)),
+            "-l" | "--load" => options.actions.push(Action::Load(value()?)),
+            "--history-file" => options.history_file = Some(value()?),
+            "--script" => {
+                options.script = Some(value()?);
+                options.init = false;
+                break;
+            }
+            _ if inline.is_some() => return Err(format!("Option {} doesn't take an argument", name)),
+            "--no-history" => options.history_file = None,
+            "--no-init" => options.init = false,
+            "-q" | "--quiet" => options.quiet = true,
+            "-h" | "--help" => options.help = true,
+            "-v" | "--version" => options.version = true,
+            "--" => break,
+            _ if name.starts_with('-') && name.len() > 1 => return Err(format!("Unknown option {}", name)),
+            _ => {
+                options.script = Some(arg.clone());
+                options.init = false;
+                break;
+            }
+        }
+    }
+    options.args = iter.cloned().collect();
+
+    Ok(options)
+}
+
+/// `~/.rust-lisprc`, which the REPL loads at startup if it exists.
+pub fn init_file() -> Option<String> {
+    std::env::var("HOME").ok().map(|home| format!("{}/.rust-lisprc", home))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Action};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_actions() {
+        let options = parse(&args(&["-e", "(+ 1 2)", "--load=a.lisp", "--eval", "x", "-l", "b.lisp"]))
+            .unwrap();
+
+        assert_eq!(options.actions,
+                   vec![Action::Eval("(+ 1 2)".to_string()),
+                        Action::Load("a.lisp".to_string()),
+                        Action::Eval("x".to_string()),
+                        Action::Load("b.lisp".to_string())]);
+        assert_eq!(options.script, None);
+        assert!(options.init);
+        assert_eq!(options.history_file, Some("history.txt".to_string()));
+    }
+
+    #[test]
+    fn test_script() {
+        let options = parse(&args(&["-q", "run.lisp", "-e", "x"])).unwrap();
+        assert_eq!(options.script, Some("run.lisp".to_string()));
+        assert_eq!(options.args, args(&["-e", "x"]));
+        assert!(options.quiet);
+        assert!(!options.init);
+
+        let options = parse(&args(&["--script", "run.lisp", "a"])).unwrap();
+        assert_eq!(options.script, Some("run.lisp".to_string()));
+        assert_eq!(options.args, args(&["a"]));
+
+        let options = parse(&args(&["--", "-a", "b"])).unwrap();
+        assert_eq!(options.script, None);
+        assert_eq!(options.args, args(&["-a", "b"]));
+    }
+
+    #[test]
+    fn test_flags() {
+        let options = parse(&args(&["--no-history", "--no-init", "--help", "--version"])).unwrap();
+        assert_eq!(options.history_file, None);
+        assert!(!options.init && options.help && options.version);
+        assert_eq!(parse(&args(&["--history-file", "h.txt"])).unwrap().history_file,
+                   Some("h.txt".to_string()));
+
+        assert_eq!(parse(&args(&["-e"])), Err("Option -e needs an argument".to_string()));
+        assert_eq!(parse(&args(&["--frobnicate"])), Err("Unknown option --frobnicate".to_string()));
+        assert_eq!(parse(&args(&["--quiet=yes"])),
+                   Err("Option --quiet doesn't take an argument".to_string()));
+    }
+}
@@ -0,0 +1,891 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use super::env::{self, Env};
+use super::number::Number;
+use super::package;
+use super::sexp::{self, Closure, Sexp, SexpResult};
+use super::stream::Stream;
+use super::symbol::Symbol;
+
+thread_local! {
+    static CLASSES: RefCell<HashMap<Symbol, Rc<Class>>> =
+        RefCell::new(HashMap::from([standard_object()]));
+    static GENERICS: RefCell<HashMap<Symbol, Rc<RefCell<Generic>>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The class `standard-object` with its name, the superclass of the classes
+/// defined without any.
+fn standard_object() -> (Symbol, Rc<Class>) {
+    let name = package::cl_symbol("STANDARD-OBJECT");
+    let class = Class {
+        name: name.clone(),
+        precedence: names(&["STANDARD-OBJECT", "T"]),
+        direct_slots: vec![],
+        slots: vec![],
+        env: env::env_new(None),
+    };
+
+    (name, Rc::new(class))
+}
+
+pub fn find_class(name: &Symbol) -> Option<Rc<Class>> {
+    CLASSES.with(|c| c.borrow().get(name).cloned())
+}
+
+fn find_generic(name: &Symbol) -> Option<Rc<RefCell<Generic>>> {
+    GENERICS.with(|g| g.borrow().get(name).cloned())
+}
+
+/// The symbols of `common-lisp` named in `list`, such as standard class names.
+fn names(list: &[&str]) -> Vec<Symbol> {
+    list.iter().map(|s| package::cl_symbol(s)).collect()
+}
+
+/// The class precedence list of any object, most specific first. Built-in
+/// values get the usual classes, such as `(integer rational real number t)`.
+pub fn class_precedence(object: &Sexp) -> Vec<Symbol> {
+    match *object {
+        Sexp::Number(Number::Fixnum(_)) |
+        Sexp::Number(Number::Bignum(_)) => names(&["INTEGER", "RATIONAL", "REAL", "NUMBER", "T"]),
+        Sexp::Number(Number::Ratio(_)) => names(&["RATIO", "RATIONAL", "REAL", "NUMBER", "T"]),
+        Sexp::Number(Number::Float(_)) => names(&["FLOAT", "REAL", "NUMBER", "T"]),
+        Sexp::Number(Number::Complex(..)) => names(&["COMPLEX", "NUMBER", "T"]),
+        Sexp::Char(_) => names(&["CHARACTER", "T"]),
+        Sexp::String(_) => names(&["STRING", "VECTOR", "ARRAY", "SEQUENCE", "T"]),
+        Sexp::Symbol(ref s) if s.is_keyword() => names(&["KEYWORD", "SYMBOL", "T"]),
+        Sexp::Symbol(_) | Sexp::True => names(&["SYMBOL", "T"]),
+        Sexp::List(ref v) if !v.is_empty() => names(&["CONS", "LIST", "SEQUENCE", "T"]),
+        Sexp::Cons(_) => names(&["CONS", "LIST", "SEQUENCE", "T"]),
+        Sexp::List(_) |
+        Sexp::Nil => names(&["NULL", "SYMBOL", "LIST", "SEQUENCE", "T"]),
+        Sexp::Stream(_) => names(&["STREAM", "T"]),
+        Sexp::HashTable(_) => names(&["HASH-TABLE", "T"]),
+        Sexp::Package(_) => names(&["PACKAGE", "T"]),
+        Sexp::Readtable(_) => names(&["READTABLE", "T"]),
+        Sexp::RandomState(_) => names(&["RANDOM-STATE", "T"]),
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 => {
+            names(&["VECTOR", "ARRAY", "SEQUENCE", "T"])
+        }
+        Sexp::Array(_) => names(&["ARRAY", "T"]),
+        Sexp::Struct(ref s) => {
+            let mut precedence = vec![];
+            let mut struct_type = Some(s.borrow().struct_type().clone());
+            while let Some(t) = struct_type {
+                precedence.push(t.name().clone());
+                struct_type = t.include().cloned();
+            }
+            precedence.extend(names(&["STRUCTURE-OBJECT", "T"]));
+            precedence
+        }
+        Sexp::Instance(ref i) => i.borrow().class.precedence.clone(),
+        Sexp::BuiltInFunc(_) |
+        Sexp::UserFunc(_) |
+        Sexp::Closure(_) |
+        Sexp::Macro(_) => names(&["FUNCTION", "T"]),
+        Sexp::Values(_) => names(&["T"]),
+    }
+}
+
+struct SlotDefinition {
+    name: Symbol,
+    initargs: Vec<Symbol>,
+    initform: Option<Sexp>,
+}
+
+/// A class defined by `defclass`. `precedence` is its C3 linearization and
+/// `slots` its effective slots, inherited ones first.
+pub struct Class {
+    name: Symbol,
+    precedence: Vec<Symbol>,
+    direct_slots: Vec<SlotDefinition>,
+    slots: Vec<SlotDefinition>,
+    env: Env,
+}
+
+impl Class {
+    fn slot_index(&self, name: &Symbol) -> Option<usize> {
+        self.slots.iter().position(|s| s.name == *name)
+    }
+}
+
+impl PartialEq for Class {
+    fn eq(&self, other: &Class) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Class({})", self.name)
+    }
+}
+
+/// An instance of a standard class; unbound slots are `None`.
+#[derive(PartialEq, Debug)]
+pub struct Instance {
+    class: Rc<Class>,
+    slots: Vec<Option<Sexp>>,
+}
+
+impl Instance {
+    /// The values of the bound slots.
+    pub fn slot_values(&self) -> Vec<Sexp> {
+        self.slots.iter().flatten().cloned().collect()
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<{}>", self.class.name)
+    }
+}
+
+/// Merges the precedence lists of the superclasses, keeping each list's order
+/// and the order of the superclasses themselves.
+fn linearize(name: &Symbol, supers: &[Rc<Class>]) -> Result<Vec<Symbol>, String> {
+    let mut sequences: Vec<Vec<Symbol>> = supers.iter().map(|s| s.precedence.clone()).collect();
+    sequences.push(supers.iter().map(|s| s.name.clone()).collect());
+
+    let mut precedence = vec![name.clone()];
+    loop {
+        sequences.retain(|s| !s.is_empty());
+        if sequences.is_empty() {
+            return Ok(precedence);
+        }
+
+        let candidate = sequences.iter()
+            .map(|s| s[0].clone())
+            .find(|c| sequences.iter().all(|s| !s[1..].contains(c)))
+            .ok_or_else(|| format!("Inconsistent precedence graph for {}", name))?;
+
+        for s in &mut sequences {
+            if s[0] == candidate {
+                s.remove(0);
+            }
+        }
+        precedence.push(candidate);
+    }
+}
+
+fn effective_slots(precedence: &[Symbol], direct_slots: &[SlotDefinition]) -> Vec<SlotDefinition> {
+    let mut slots: Vec<SlotDefinition> = vec![];
+    let mut add = |direct: &SlotDefinition| {
+        match slots.iter().position(|s| s.name == direct.name) {
+            Some(i) => {
+                slots[i].initargs.extend(direct.initargs.iter().cloned());
+                if direct.initform.is_some() {
+                    slots[i].initform = direct.initform.clone();
+                }
+            }
+            None => {
+                slots.push(SlotDefinition {
+                    name: direct.name.clone(),
+                    initargs: direct.initargs.clone(),
+                    initform: direct.initform.clone(),
+                })
+            }
+        }
+    };
+
+    for name in precedence[1..].iter().rev() {
+        if let Some(class) = find_class(name) {
+            class.direct_slots.iter().for_each(&mut add);
+        }
+    }
+    direct_slots.iter().for_each(&mut add);
+
+    slots
+}
+
+fn symbol(s: &Sexp) -> Result<Symbol, String> {
+    match *s {
+        Sexp::Symbol(ref symbol) => Ok(symbol.clone()),
+        Sexp::True => Ok(package::cl_symbol("T")),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn keyword_name(s: &Sexp) -> Result<&str, String> {
+    match *s {
+        Sexp::Symbol(ref k) if k.is_keyword() => Ok(k.name()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn list_of(s: &Sexp) -> Result<&[Sexp], String> {
+    match *s {
+        Sexp::List(ref v) => Ok(v),
+        Sexp::Nil => Ok(&[]),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// Parses a slot specifier, defining its readers, writers and accessors.
+fn parse_slot(spec: &Sexp, env: &Env) -> Result<SlotDefinition, String> {
+    let (name, options) = match *spec {
+        Sexp::Symbol(ref name) => (name.clone(), &[][..]),
+        Sexp::List(ref v) if !v.is_empty() && v.len() % 2 == 1 => (symbol(&v[0])?, &v[1..]),
+        ref v => return Err(format!("Argument error: {}", v)),
+    };
+
+    let mut slot = SlotDefinition { name, initargs: vec![], initform: None };
+    for pair in options.chunks(2) {
+        match keyword_name(&pair[0])? {
+            "INITARG" => slot.initargs.push(symbol(&pair[1])?),
+            "INITFORM" => slot.initform = Some(pair[1].clone()),
+            "READER" => define_reader(env, symbol(&pair[1])?, &slot.name),
+            "WRITER" => env::env_set_function(env, symbol(&pair[1])?, slot_writer(&slot.name)),
+            "ACCESSOR" => {
+                let accessor = symbol(&pair[1])?;
+                define_reader(env, accessor.clone(), &slot.name);
+                env::env_set_setf_function(env, accessor, slot_writer(&slot.name));
+            }
+            "TYPE" | "DOCUMENTATION" | "ALLOCATION" => {}
+            _ => return Err(format!("Argument error: {}", pair[0])),
+        }
+    }
+
+    Ok(slot)
+}
+
+fn define_reader(env: &Env, name: Symbol, slot: &Symbol) {
+    let slot = slot.clone();
+    env::env_set_function(env,
+                          name,
+                          Sexp::Closure(Closure::new(move |args| {
+                              match args.first() {
+                                  Some(object) if args.len() == 1 => slot_value(object, &slot),
+                                  _ => Err(format!("Invalid number of arguments: {}", args.len())),
+                              }
+                          })));
+}
+
+/// A function of the new value and an object that sets the slot `slot`.
+fn slot_writer(slot: &Symbol) -> Sexp {
+    let slot = slot.clone();
+    Sexp::Closure(Closure::new(move |args| {
+        if args.len() != 2 {
+            return Err(format!("Invalid number of arguments: {}", args.len()));
+        }
+        set_slot_value(&args[1], &slot, args[0].clone())
+    }))
+}
+
+/// `(defclass name (superclasses...) (slot-specifiers...) options...)`
+pub fn defclass(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 4 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let name = symbol(&v[1])?;
+    let mut supers = vec![];
+    for s in list_of(&v[2])? {
+        let super_name = symbol(s)?;
+        supers.push(find_class(&super_name)
+            .ok_or_else(|| format!("The class {} is undefined", super_name))?);
+    }
+    if supers.is_empty() {
+        supers.push(find_class(&package::cl_symbol("STANDARD-OBJECT")).unwrap());
+    }
+
+    let precedence = linearize(&name, &supers)?;
+    let mut direct_slots = vec![];
+    for spec in list_of(&v[3])? {
+        direct_slots.push(parse_slot(spec, env)?);
+    }
+    let slots = effective_slots(&precedence, &direct_slots);
+
+    let class = Class {
+        name: name.clone(),
+        precedence,
+        direct_slots,
+        slots,
+        env: env.clone(),
+    };
+    CLASSES.with(|c| c.borrow_mut().insert(name.clone(), Rc::new(class)));
+
+    Ok(Sexp::Symbol(name))
+}
+
+/// Makes an instance from `:initarg value` pairs; slots without an initarg
+/// get their initform, evaluated in the environment of the `defclass`.
+pub fn make_instance(class: &Rc<Class>, initargs: &[Sexp]) -> SexpResult {
+    if !initargs.len().is_multiple_of(2) {
+        return Err(format!("Invalid number of arguments: {}", initargs.len() + 1));
+    }
+
+    let mut slots = vec![None; class.slots.len()];
+    for pair in initargs.chunks(2) {
+        let initarg = symbol(&pair[0])?;
+        let mut found = false;
+        for (i, slot) in class.slots.iter().enumerate() {
+            if slot.initargs.contains(&initarg) {
+                found = true;
+                if slots[i].is_none() {
+                    slots[i] = Some(pair[1].clone());
+                }
+            }
+        }
+        if !found {
+            return Err(format!("Invalid initialization argument {} for {}", initarg, class.name));
+        }
+    }
+
+    for (i, slot) in class.slots.iter().enumerate() {
+        if let (None, Some(initform)) = (&slots[i], &slot.initform) {
+            slots[i] = Some(initform.eval(&class.env)?.primary());
+        }
+    }
+
+    Ok(Sexp::Instance(Rc::new(RefCell::new(Instance {
+        class: class.clone(),
+        slots,
+    }))))
+}
+
+fn instance_slot(object: &Sexp, slot: &Symbol) -> Result<(Rc<RefCell<Instance>>, usize), String> {
+    match *object {
+        Sexp::Instance(ref i) => {
+            let index = i.borrow().class.slot_index(slot);
+            index.map(|index| (i.clone(), index))
+                .ok_or_else(|| format!("The slot {} is missing from {}", slot, object))
+        }
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+pub fn slot_value(object: &Sexp, slot: &Symbol) -> SexpResult {
+    let (instance, index) = instance_slot(object, slot)?;
+    let value = instance.borrow().slots[index].clone();
+
+    value.ok_or_else(|| format!("The slot {} is unbound in {}", slot, object))
+}
+
+pub fn set_slot_value(object: &Sexp, slot: &Symbol, value: Sexp) -> SexpResult {
+    let (instance, index) = instance_slot(object, slot)?;
+
+    instance.borrow_mut().slots[index] = Some(value.clone());
+    Ok(value)
+}
+
+pub fn slot_boundp(object: &Sexp, slot: &Symbol) -> Result<bool, String> {
+    let (instance, index) = instance_slot(object, slot)?;
+    let bound = instance.borrow().slots[index].is_some();
+
+    Ok(bound)
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Qualifier {
+    Primary,
+    Before,
+    After,
+    Around,
+}
+
+struct Method {
+    qualifier: Qualifier,
+    specializers: Vec<Symbol>,
+    params: Vec<Symbol>,
+    body: Vec<Sexp>,
+    env: Env,
+}
+
+/// A generic function: its methods, and the number of required arguments
+/// they all specialize.
+struct Generic {
+    name: Symbol,
+    arity: Option<usize>,
+    methods: Vec<Rc<Method>>,
+}
+
+type Next = Rc<dyn Fn(Vec<Sexp>) -> SexpResult>;
+
+impl Generic {
+    fn add_method(&mut self, method: Method) -> Result<(), String> {
+        match self.arity {
+            Some(n) if n != method.params.len() => {
+                return Err(format!("The method has {} required arguments but {} takes {}",
+                                   method.params.len(),
+                                   self.name,
+                                   n));
+            }
+            _ => self.arity = Some(method.params.len()),
+        }
+
+        self.methods.retain(|m| {
+            m.qualifier != method.qualifier || m.specializers != method.specializers
+        });
+        self.methods.push(Rc::new(method));
+        Ok(())
+    }
+
+    /// The applicable methods, most specific first: methods are compared by
+    /// the positions of their specializers in each argument's precedence list.
+    fn applicable_methods(&self, args: &[Sexp]) -> Vec<Rc<Method>> {
+        let precedences: Vec<Vec<Symbol>> = args.iter().map(class_precedence).collect();
+        let mut applicable: Vec<(Vec<usize>, Rc<Method>)> = self.methods
+            .iter()
+            .filter_map(|m| {
+                let ranks: Option<Vec<usize>> = m.specializers
+                    .iter()
+                    .zip(precedences.iter())
+                    .map(|(s, p)| p.iter().position(|c| c == s))
+                    .collect();
+                ranks.map(|r| (r, m.clone()))
+            })
+            .collect();
+
+        applicable.sort_by(|a, b| a.0.cmp(&b.0));
+        applicable.into_iter().map(|(_, m)| m).collect()
+    }
+
+    fn has_primary_method(&self, args: &[Sexp]) -> bool {
+        self.applicable_methods(args).iter().any(|m| m.qualifier == Qualifier::Primary)
+    }
+}
+
+/// Calls a method with `call-next-method` and `next-method-p` bound to invoke
+/// `next`, by default with the same arguments.
+fn call_method(method: &Rc<Method>, args: Vec<Sexp>, next: Option<Next>) -> SexpResult {
+    let env = env::env_new(Some(method.env.clone()));
+    for (name, arg) in method.params.iter().zip(args.iter()) {
+        env::env_set(&env, name.clone(), arg.clone());
+    }
+
+    let has_next = next.is_some();
+    env::env_set_function(&env,
+                          "call-next-method".to_string(),
+                          Sexp::Closure(Closure::new(move |next_args| {
+                              let next_args = if next_args.is_empty() { args.clone() } else { next_args };
+                              match next {
+                                  Some(ref next) => next(next_args),
+                                  None => Err("There is no next method".to_string()),
+                              }
+                          })));
+    env::env_set_function(&env,
+                          "next-method-p".to_string(),
+                          Sexp::Closure(Closure::new(move |_| {
+                              Ok(if has_next { Sexp::True } else { Sexp::Nil })
+                          })));
+
+    sexp::eval_body(&method.body, &env)
+}
+
+/// Chains `methods[i..]` so that each one's next method is the one after
+/// it, and the last one's is `last`.
+fn chain(methods: Rc<Vec<Rc<Method>>>, i: usize, last: Option<Next>) -> Option<Next> {
+    if i == methods.len() {
+        return last;
+    }
+
+    Some(Rc::new(move |args| {
+        call_method(&methods[i], args, chain(methods.clone(), i + 1, last.clone()))
+    }))
+}
+
+/// Runs the standard method combination: around methods wrap the before
+/// methods, the primary methods and the after methods (least specific first).
+fn call_generic(generic: &Rc<RefCell<Generic>>, args: Vec<Sexp>) -> SexpResult {
+    let (name, arity, methods) = {
+        let g = generic.borrow();
+        (g.name.clone(), g.arity, g.applicable_methods(&args))
+    };
+    if arity.is_some_and(|n| n != args.len()) {
+        return Err(format!("Invalid number of arguments: {}", args.len()));
+    }
+
+    let with = |q: Qualifier| -> Vec<Rc<Method>> {
+        methods.iter().filter(|m| m.qualifier == q).cloned().collect()
+    };
+    let (arounds, befores, primaries) = (with(Qualifier::Around),
+                                         with(Qualifier::Before),
+                                         Rc::new(with(Qualifier::Primary)));
+    let mut afters = with(Qualifier::After);
+    afters.reverse();
+
+    if primaries.is_empty() {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        return Err(format!("No applicable method for {} with arguments ({})",
+                           name,
+                           args.join(" ")));
+    }
+
+    let standard: Next = Rc::new(move |args: Vec<Sexp>| {
+        for method in &befores {
+            call_method(method, args.clone(), None)?;
+        }
+        let result = chain(primaries.clone(), 0, None).unwrap()(args.clone())?;
+        for method in &afters {
+            call_method(method, args.clone(), None)?;
+        }
+        Ok(result)
+    });
+
+    chain(Rc::new(arounds), 0, Some(standard)).unwrap()(args)
+}
+
+fn ensure_generic(name: &Symbol, arity: Option<usize>, env: &Env) -> Rc<RefCell<Generic>> {
+    let generic = find_generic(name).unwrap_or_else(|| {
+        let generic = Rc::new(RefCell::new(Generic {
+            name: name.clone(),
+            arity,
+            methods: vec![],
+        }));
+        GENERICS.with(|g| g.borrow_mut().insert(name.clone(), generic.clone()));
+        generic
+    });
+
+    let dispatch = generic.clone();
+    env::env_set_function(env,
+                          name.clone(),
+                          Sexp::Closure(Closure::new(move |args| call_generic(&dispatch, args))));
+    generic
+}
+
+/// `(defgeneric name (params...) options...)`
+pub fn defgeneric(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 3 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let name = symbol(&v[1])?;
+    let arity = list_of(&v[2])?.len();
+    let generic = ensure_generic(&name, Some(arity), env);
+    let mut g = generic.borrow_mut();
+    if g.methods.is_empty() {
+        g.arity = Some(arity);
+    } else if g.arity != Some(arity) {
+        return Err(format!("The lambda list of {} does not match its methods", name));
+    }
+
+    Ok(Sexp::Symbol(name))
+}
+
+/// `(defmethod name [qualifier] (params...) body...)`, where a parameter is
+/// `name` or `(name class)`.
+pub fn defmethod(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 3 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let name = symbol(&v[1])?;
+    let (qualifier, rest) = match v[2] {
+        Sexp::Symbol(_) => {
+            let qualifier = match keyword_name(&v[2])? {
+                "BEFORE" => Qualifier::Before,
+                "AFTER" => Qualifier::After,
+                "AROUND" => Qualifier::Around,
+                _ => return Err(format!("Argument error: {}", v[2])),
+            };
+            (qualifier, &v[3..])
+        }
+        _ => (Qualifier::Primary, &v[2..]),
+    };
+    if rest.is_empty() {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let (mut params, mut specializers) = (vec![], vec![]);
+    for param in list_of(&rest[0])? {
+        if let Some(k) = sexp::lambda_list_keyword(param) {
+            return Err(format!("Misplaced or unsupported lambda list keyword {}", k));
+        }
+        match *param {
+            Sexp::Symbol(ref p) => {
+                params.push(p.clone());
+                specializers.push(package::cl_symbol("T"));
+            }
+            Sexp::List(ref p) if p.len() == 2 => {
+                params.push(symbol(&p[0])?);
+                specializers.push(symbol(&p[1])?);
+            }
+            ref p => return Err(format!("Argument error: {}", p)),
+        }
+    }
+
+    let generic = ensure_generic(&name, None, env);
+    generic.borrow_mut()
+        .add_method(Method {
+            qualifier,
+            specializers,
+            params,
+            body: rest[1..].to_vec(),
+            env: env.clone(),
+        })?;
+
+    Ok(Sexp::Symbol(name))
+}
+
+/// Whether a user's `print-object` method applies to `object`.
+pub fn has_print_object(object: &Sexp) -> bool {
+    find_generic(&package::cl_symbol("PRINT-OBJECT")).is_some_and(|generic| {
+        let args = vec![object.clone(), Sexp::Stream(Stream::string_output())];
+        generic.borrow().has_primary_method(&args)
+    })
+}
+
+/// What a user's `print-object` method prints for `object`, if there is an
+/// applicable one.
+pub fn print_object(object: &Sexp) -> Option<String> {
+    if !has_print_object(object) {
+        return None;
+    }
+    let generic = find_generic(&package::cl_symbol("PRINT-OBJECT"))?;
+    let stream = Stream::string_output();
+    call_generic(&generic, vec![object.clone(), Sexp::Stream(stream.clone())]).ok()?;
+    stream.take_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::super::env::{self, Env};
+    use super::super::number::Number;
+    use super::super::package;
+    use super::super::readtable::{self, Case};
+    use super::super::sexp::{Sexp, SexpResult};
+    use super::super::symbol::Symbol;
+    use super::Class;
+    use super::super::test_util::{int, symbol, list};
+
+    fn symbols(names: &[&str]) -> Sexp {
+        list(names.iter().map(|n| symbol(n)).collect())
+    }
+
+    fn find_class(name: &str) -> Rc<Class> {
+        super::find_class(&Symbol::from(name)).unwrap()
+    }
+
+    fn define_class(env: &Env, name: &str, supers: &[&str], slots: Vec<Sexp>) -> SexpResult {
+        super::defclass(&[symbol("defclass"), symbol(name), symbols(supers), list(slots)], env)
+    }
+
+    fn string_list(args: Vec<Sexp>) -> SexpResult {
+        Ok(Sexp::list(args))
+    }
+
+    fn new_env() -> Env {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(string_list));
+        env
+    }
+
+    #[test]
+    fn test_linearization() {
+        let env = new_env();
+        for (name, supers) in [("o1", vec![]),
+                               ("a1", vec!["o1"]),
+                               ("b1", vec!["o1"]),
+                               ("c1", vec!["a1", "b1"]),
+                               ("d1", vec!["b1", "a1"])] {
+            define_class(&env, name, &supers, vec![]).unwrap();
+        }
+
+        let c1 = find_class("c1");
+        let precedence: Vec<&str> = c1.precedence.iter().map(|c| c.name()).collect();
+        assert_eq!(precedence, vec!["C1", "A1", "B1", "O1", "STANDARD-OBJECT", "T"]);
+        assert_eq!(define_class(&env, "e1", &["c1", "d1"], vec![]),
+                   Err("Inconsistent precedence graph for E1".to_string()));
+        assert_eq!(define_class(&env, "f1", &["nope"], vec![]),
+                   Err("The class NOPE is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_make_instance_and_slots() {
+        let env = new_env();
+        define_class(&env,
+                     "pt",
+                     &[],
+                     vec![list(vec![symbol("x"),
+                                    symbol(":initarg"),
+                                    symbol(":x"),
+                                    symbol(":initform"),
+                                    int(0),
+                                    symbol(":accessor"),
+                                    symbol("pt-x")]),
+                          symbol("y")])
+            .unwrap();
+        define_class(&env,
+                     "pt3",
+                     &["pt"],
+                     vec![list(vec![symbol("z"), symbol(":initarg"), symbol(":z")])])
+            .unwrap();
+
+        let class = find_class("pt3");
+        let p = super::make_instance(&class, &[symbol(":z"), int(3)]).unwrap();
+        assert_eq!(format!("{}", p), "#<PT3>");
+        assert_eq!(super::slot_value(&p, &Symbol::from("x")), Ok(int(0)));
+        assert_eq!(super::slot_value(&p, &Symbol::from("z")), Ok(int(3)));
+        assert_eq!(super::slot_boundp(&p, &Symbol::from("y")), Ok(false));
+        assert_eq!(super::slot_value(&p, &Symbol::from("y")),
+                   Err("The slot Y is unbound in #<PT3>".to_string()));
+        assert_eq!(super::slot_value(&p, &Symbol::from("w")),
+                   Err("The slot W is missing from #<PT3>".to_string()));
+
+        let accessor = env::env_get_function(&env, "pt-x").unwrap();
+        let setter = env::env_get_setf_function(&env, "pt-x").unwrap();
+        assert_eq!(setter.apply(vec![int(5), p.clone()]), Ok(int(5)));
+        assert_eq!(accessor.apply(vec![p]), Ok(int(5)));
+        assert_eq!(super::make_instance(&class, &[symbol(":w"), int(1)]),
+                   Err("Invalid initialization argument :W for PT3".to_string()));
+        let spec = list(vec![symbol("x"), symbol("initarg"), symbol(":x")]);
+        assert_eq!(define_class(&env, "pt4", &[], vec![spec]),
+                   Err("Argument error: INITARG".to_string()));
+    }
+
+    fn method(env: &Env, name: &str, qualifier: Option<&str>, params: Sexp, body: Sexp) {
+        let mut form = vec![symbol("defmethod"), symbol(name)];
+        form.extend(qualifier.map(symbol));
+        form.push(params);
+        form.push(body);
+        super::defmethod(&form, env).unwrap();
+    }
+
+    fn call(env: &Env, name: &str, args: Vec<Sexp>) -> SexpResult {
+        env::env_get_function(env, name).unwrap().apply(args)
+    }
+
+    #[test]
+    fn test_dispatch_on_built_in_classes() {
+        let env = new_env();
+        method(&env, "describe-it", None, symbols(&["x"]), symbol(":thing"));
+        method(&env,
+               "describe-it",
+               None,
+               list(vec![symbols(&["x", "number"])]),
+               symbol(":number"));
+        method(&env,
+               "describe-it",
+               None,
+               list(vec![symbols(&["x", "integer"])]),
+               list(vec![symbol("list"), symbol(":integer"), list(vec![symbol("call-next-method")])]));
+
+        assert_eq!(call(&env, "describe-it", vec![int(1)]),
+                   Ok(list(vec![symbol(":integer"), symbol(":number")])));
+        assert_eq!(call(&env, "describe-it", vec![Sexp::Number(Number::Float(0.5))]),
+                   Ok(symbol(":number")));
+        assert_eq!(call(&env, "describe-it", vec![Sexp::string("s")]),
+                   Ok(symbol(":thing")));
+        assert_eq!(call(&env, "describe-it", vec![]),
+                   Err("Invalid number of arguments: 0".to_string()));
+    }
+
+    #[test]
+    fn test_multiple_dispatch() {
+        let env = new_env();
+        method(&env,
+               "collide",
+               None,
+               list(vec![symbols(&["a", "number"]), symbols(&["b", "string"])]),
+               symbol(":number-string"));
+        method(&env,
+               "collide",
+               None,
+               list(vec![symbols(&["a", "string"]), symbols(&["b", "number"])]),
+               symbol(":string-number"));
+
+        assert_eq!(call(&env, "collide", vec![int(1), Sexp::string("s")]),
+                   Ok(symbol(":number-string")));
+        assert_eq!(call(&env, "collide", vec![Sexp::string("s"), int(1)]),
+                   Ok(symbol(":string-number")));
+        assert_eq!(call(&env, "collide", vec![int(1), int(1)]),
+                   Err("No applicable method for COLLIDE with arguments (1 1)".to_string()));
+    }
+
+    #[test]
+    fn test_qualifiers() {
+        let env = new_env();
+        env::env_set(&env, "log".to_string(), Sexp::Nil);
+        let push_log = |entry: &str| {
+            list(vec![symbol("setf"),
+                      symbol("log"),
+                      list(vec![symbol("list"), symbol(entry), symbol("log")])])
+        };
+
+        method(&env, "run", None, symbols(&["x"]), push_log(":primary"));
+        method(&env, "run", Some(":before"), symbols(&["x"]), push_log(":before"));
+        method(&env, "run", Some(":after"), symbols(&["x"]), push_log(":after"));
+        method(&env,
+               "run",
+               Some(":around"),
+               symbols(&["x"]),
+               list(vec![symbol("list"), symbol(":around"), list(vec![symbol("call-next-method")])]));
+
+        let log = |entries: &[&str]| {
+            entries.iter().fold(Sexp::Nil, |acc, e| list(vec![symbol(e), acc]))
+        };
+        assert_eq!(call(&env, "run", vec![int(1)]),
+                   Ok(list(vec![symbol(":around"), log(&[":before", ":primary"])])));
+        assert_eq!(env::env_get(&env, "log"), Some(log(&[":before", ":primary", ":after"])));
+    }
+
+    #[test]
+    fn test_print_object() {
+        let env = new_env();
+        define_class(&env, "printed", &[], vec![]).unwrap();
+        define_class(&env, "plain", &[], vec![]).unwrap();
+        env::env_set_function(&env, "write-string".to_string(), Sexp::BuiltInFunc(write_string));
+        method(&env,
+               "print-object",
+               None,
+               list(vec![symbols(&["p", "printed"]), symbol("stream")]),
+               list(vec![symbol("write-string"),
+                         Sexp::string("<printed>"),
+                         symbol("stream")]));
+
+        let printed = super::make_instance(&find_class("printed"), &[]).unwrap();
+        let plain = super::make_instance(&find_class("plain"), &[]).unwrap();
+        assert_eq!(format!("{}", printed), "<printed>");
+        assert_eq!(format!("{}", list(vec![plain, printed])), "(#<PLAIN> <printed>)");
+        assert!(Rc::ptr_eq(&find_class("plain"), &find_class("PLAIN")));
+    }
+
+    #[test]
+    fn test_classes_in_packages() {
+        let env = new_env();
+        for name in ["A", "B"] {
+            package::defpackage(&[symbol("defpackage"), Sexp::string(name)], &env).unwrap();
+            package::set_current(package::find_package(name).unwrap());
+            define_class(&env, "widget", &[], vec![]).unwrap();
+            method(&env, "kind", None, list(vec![symbols(&["w", "widget"])]), symbol(":widget"));
+        }
+
+        let widget = super::make_instance(&find_class("a::widget"), &[]).unwrap();
+        assert!(!Rc::ptr_eq(&find_class("a::widget"), &find_class("b::widget")));
+        assert_eq!(call(&env, "a::kind", vec![widget.clone()]), Ok(symbol(":widget")));
+        assert_eq!(call(&env, "b::kind", vec![widget]),
+                   Err("No applicable method for KIND with arguments (#<A::WIDGET>)".to_string()));
+    }
+
+    #[test]
+    fn test_preserved_case() {
+        let env = new_env();
+        readtable::current().set_case(Case::Preserve);
+        let name = |s: &str| Sexp::Symbol(Symbol::intern(s));
+        let key = |s: &str| Sexp::Symbol(Symbol::keyword(s));
+        let slot = |s: &str| list(vec![name(s), symbol(":initarg"), key(s)]);
+        let slots = list(vec![slot("w"), slot("W")]);
+        super::defclass(&[symbol("defclass"), name("Box"), Sexp::Nil, slots], &env).unwrap();
+
+        let class = super::find_class(&Symbol::intern("Box")).unwrap();
+        let b = super::make_instance(&class, &[key("w"), int(1), key("W"), int(2)]).unwrap();
+        assert_eq!(format!("{}", b), "#<Box>");
+        assert_eq!(super::slot_value(&b, &Symbol::intern("w")), Ok(int(1)));
+        assert_eq!(super::slot_value(&b, &Symbol::intern("W")), Ok(int(2)));
+        readtable::current().set_case(Case::Upcase);
+    }
+
+        fn write_string(args: Vec<Sexp>) -> SexpResult {
+        match (&args[0], &args[1]) {
+            (Sexp::String(s), Sexp::Stream(stream)) => {
+                stream.write_str(s);
+                Ok(args[0].clone())
+            }
+            _ => Err("Argument error".to_string()),
+        }
+    }
+}
@@ -1,68 +1,156 @@
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use super::sexp::Sexp;
+use super::symbol::Symbol;
 
+/// Variable and function bindings, which are separate namespaces: a symbol
+/// can name both a variable and a function. The functions named `(setf
+/// name)`, which `setf` calls for the place `(name args...)`, are a third
+/// namespace keyed by `name`.
 #[derive(PartialEq, Debug)]
 pub struct EnvData {
-    data: HashMap<String, Sexp>,
+    data: HashMap<Symbol, Sexp>,
+    functions: HashMap<Symbol, Sexp>,
+    setf_functions: HashMap<Symbol, Sexp>,
     enclosing: Option<Env>,
 }
 
+#[derive(Clone, Copy)]
+enum Namespace {
+    Variables,
+    Functions,
+    SetfFunctions,
+}
+
 impl EnvData {
     fn new(enclosing: Option<Env>) -> EnvData {
         EnvData {
             data: HashMap::new(),
-            enclosing: enclosing,
+            functions: HashMap::new(),
+            setf_functions: HashMap::new(),
+            enclosing,
         }
     }
 }
 
 pub type Env = Rc<RefCell<EnvData>>;
 
+thread_local! {
+    static GLOBAL: RefCell<Weak<RefCell<EnvData>>> = const { RefCell::new(Weak::new()) };
+}
+
 pub fn env_new(enclosing: Option<Env>) -> Env {
     Rc::new(RefCell::new(EnvData::new(enclosing)))
 }
 
-pub fn env_set(env: &Env, k: String, v: Sexp) {
-    env.borrow_mut().data.insert(k.to_uppercase(), v);
+/// Makes `env` the global environment, whose variables such as `*read-eval*`
+/// and `*print-circle*` the reader and printer consult.
+pub fn set_global(env: &Env) {
+    GLOBAL.with(|g| *g.borrow_mut() = Rc::downgrade(env));
+}
+
+/// The global environment, if it is still around.
+pub fn global() -> Option<Env> {
+    GLOBAL.with(|g| g.borrow().upgrade())
+}
+
+/// Binds `k`, a symbol or a name to read as one.
+pub fn env_set<K: Into<Symbol>>(env: &Env, k: K, v: Sexp) {
+    env.borrow_mut().data.insert(k.into(), v);
+}
+
+pub fn env_get<K: Into<Symbol>>(env: &Env, k: K) -> Option<Sexp> {
+    lookup(env, &k.into(), Namespace::Variables)
+}
+
+pub fn env_set_function<K: Into<Symbol>>(env: &Env, k: K, f: Sexp) {
+    env.borrow_mut().functions.insert(k.into(), f);
+}
+
+pub fn env_get_function<K: Into<Symbol>>(env: &Env, k: K) -> Option<Sexp> {
+    lookup(env, &k.into(), Namespace::Functions)
+}
+
+/// Binds the function `(setf k)`.
+pub fn env_set_setf_function<K: Into<Symbol>>(env: &Env, k: K, f: Sexp) {
+    env.borrow_mut().setf_functions.insert(k.into(), f);
 }
 
-pub fn env_get(env: &Env, k: &str) -> Option<Sexp> {
+pub fn env_get_setf_function<K: Into<Symbol>>(env: &Env, k: K) -> Option<Sexp> {
+    lookup(env, &k.into(), Namespace::SetfFunctions)
+}
+
+/// Removes the function binding of `k` from this env only, returning
+/// whether there was one.
+pub fn env_remove_function<K: Into<Symbol>>(env: &Env, k: K) -> bool {
+    env.borrow_mut().functions.remove(&k.into()).is_some()
+}
+
+fn lookup(env: &Env, k: &Symbol, namespace: Namespace) -> Option<Sexp> {
     let e = env.borrow();
+    let found = match namespace {
+        Namespace::Variables => e.data.get(k),
+        Namespace::Functions => e.functions.get(k),
+        Namespace::SetfFunctions => e.setf_functions.get(k),
+    };
 
-    match e.data.get(&k.to_uppercase()) {
+    match found {
         Some(v) => Some((*v).clone()),
         None => {
             match e.enclosing {
-                Some(ref enc) => env_get(enc, k),
+                Some(ref enc) => lookup(enc, k, namespace),
                 None => None,
             }
         }
     }
 }
 
+/// Sets `k` in the innermost env that binds it, or in the outermost env if
+/// it is unbound.
+pub fn env_assign<K: Into<Symbol>>(env: &Env, k: K, v: Sexp) {
+    let key = k.into();
+    let mut current = env.clone();
+
+    loop {
+        let enclosing = {
+            let e = current.borrow();
+            if e.data.contains_key(&key) { None } else { e.enclosing.clone() }
+        };
+
+        match enclosing {
+            Some(enc) => current = enc,
+            None => break,
+        }
+    }
+
+    env_set(&current, key, v);
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::sexp::Sexp;
-    use super::{env_new, env_get, env_set};
+    use super::super::number::Number;
+    use super::{env_new, env_get, env_set, env_assign};
+    use super::{env_get_function, env_set_function, env_remove_function};
+    use super::{env_get_setf_function, env_set_setf_function};
 
     #[test]
     fn test_get_is_case_insensitive() {
         let env = env_new(None);
 
-        env_set(&env, "k".to_string(), Sexp::Number(5.0));
-        assert_eq!(env_get(&env, &"K".to_string()), Some(Sexp::Number(5.0)));
+        env_set(&env, "k".to_string(), Sexp::Number(Number::Fixnum(5)));
+        assert_eq!(env_get(&env, "K"), Some(Sexp::Number(Number::Fixnum(5))));
     }
 
     #[test]
     fn test_get_and_set_with_no_enclosing_env() {
         let env = env_new(None);
 
-        assert_eq!(env_get(&env, &"k".to_string()), None);
+        assert_eq!(env_get(&env, "k"), None);
 
-        env_set(&env, "k".to_string(), Sexp::Number(5.0));
-        assert_eq!(env_get(&env, &"k".to_string()), Some(Sexp::Number(5.0)));
+        env_set(&env, "k".to_string(), Sexp::Number(Number::Fixnum(5)));
+        assert_eq!(env_get(&env, "k"), Some(Sexp::Number(Number::Fixnum(5))));
     }
 
     #[test]
@@ -70,9 +158,44 @@ mod tests {
         let enclosing = env_new(None);
         let env = env_new(Some(enclosing.clone()));
 
-        assert_eq!(env_get(&env, &"k".to_string()), None);
+        assert_eq!(env_get(&env, "k"), None);
+
+        env_set(&enclosing, "k".to_string(), Sexp::Number(Number::Fixnum(5)));
+        assert_eq!(env_get(&env, "k"), Some(Sexp::Number(Number::Fixnum(5))));
+    }
 
-        env_set(&enclosing, "k".to_string(), Sexp::Number(5.0));
-        assert_eq!(env_get(&env, &"k".to_string()), Some(Sexp::Number(5.0)));
+    #[test]
+    fn test_assign() {
+        let global = env_new(None);
+        let outer = env_new(Some(global.clone()));
+        let inner = env_new(Some(outer.clone()));
+
+        env_set(&outer, "k".to_string(), Sexp::Number(Number::Fixnum(1)));
+        env_assign(&inner, "K".to_string(), Sexp::Number(Number::Fixnum(2)));
+        assert_eq!(env_get(&outer, "k"), Some(Sexp::Number(Number::Fixnum(2))));
+        assert_eq!(env_get(&global, "k"), None);
+
+        env_assign(&inner, "j".to_string(), Sexp::Number(Number::Fixnum(3)));
+        assert_eq!(env_get(&global, "j"), Some(Sexp::Number(Number::Fixnum(3))));
+    }
+
+    #[test]
+    fn test_separate_namespaces() {
+        let global = env_new(None);
+        let env = env_new(Some(global.clone()));
+
+        env_set(&global, "k", Sexp::Number(Number::Fixnum(1)));
+        env_set_function(&global, "k", Sexp::Nil);
+        assert_eq!(env_get(&env, "k"), Some(Sexp::Number(Number::Fixnum(1))));
+        assert_eq!(env_get_function(&env, "k"), Some(Sexp::Nil));
+
+        assert!(!env_remove_function(&env, "k"));
+        assert!(env_remove_function(&global, "k"));
+        assert_eq!(env_get_function(&env, "k"), None);
+        assert_eq!(env_get(&env, "k"), Some(Sexp::Number(Number::Fixnum(1))));
+
+        env_set_setf_function(&global, "k", Sexp::True);
+        assert_eq!(env_get_setf_function(&env, "k"), Some(Sexp::True));
+        assert_eq!(env_get_function(&env, "k"), None);
     }
 }
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use num::{BigRational, FromPrimitive};
+use super::number::Number;
+use super::package;
+use super::sexp::Sexp;
+use super::stream::Stream;
+use super::symbol::Symbol;
+
+/// The equivalence used to compare keys, and by the equality predicates.
+/// `eq` and `eql` compare strings and lists by identity, `equal` by contents
+/// and `equalp` also ignores case and numeric type.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum HashTest {
+    Eq,
+    Eql,
+    Equal,
+    Equalp,
+}
+
+impl HashTest {
+    /// The test named by one of the equality predicates in `common-lisp`.
+    pub fn from_name(name: &Symbol) -> Option<HashTest> {
+        match package::cl_name(name)? {
+            "EQ" => Some(HashTest::Eq),
+            "EQL" => Some(HashTest::Eql),
+            "EQUAL" => Some(HashTest::Equal),
+            "EQUALP" => Some(HashTest::Equalp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HashTest::Eq => "EQ",
+            HashTest::Eql => "EQL",
+            HashTest::Equal => "EQUAL",
+            HashTest::Equalp => "EQUALP",
+        }
+    }
+
+    /// Whether the test compares strings and lists by contents.
+    fn by_contents(&self) -> bool {
+        *self == HashTest::Equal || *self == HashTest::Equalp
+    }
+
+    pub fn test(&self, a: &Sexp, b: &Sexp) -> bool {
+        match (a, b) {
+            (Sexp::Number(x), Sexp::Number(y)) => self.numbers_match(x, y),
+            (Sexp::Char(x), Sexp::Char(y)) => self.fold(*x) == self.fold(*y),
+            (Sexp::List(x), Sexp::Nil) |
+            (Sexp::Nil, Sexp::List(x)) => x.is_empty(),
+            (Sexp::String(x), Sexp::String(y)) if !self.by_contents() => Rc::ptr_eq(x, y),
+            (Sexp::List(x), Sexp::List(y)) if !self.by_contents() => {
+                Rc::ptr_eq(x, y) || x.is_empty() && y.is_empty()
+            }
+            (Sexp::String(x), Sexp::String(y)) => {
+                x.chars().map(|c| self.fold(c)).eq(y.chars().map(|c| self.fold(c)))
+            }
+            (Sexp::Symbol(x), Sexp::Symbol(y)) => x == y,
+            (Sexp::List(x), Sexp::List(y)) => {
+                x.len() == y.len() && x.iter().zip(y.iter()).all(|(a, b)| self.test(a, b))
+            }
+            (Sexp::Cons(x), Sexp::Cons(y)) => {
+                Rc::ptr_eq(x, y) ||
+                self.by_contents() && {
+                    let (x, y) = (x.borrow(), y.borrow());
+                    self.test(x.car(), y.car()) && self.test(x.cdr(), y.cdr())
+                }
+            }
+            // A table being changed, as when it is made a key in itself, can't
+            // be looked into, and is only the same as itself.
+            (Sexp::HashTable(x), Sexp::HashTable(y)) => {
+                Rc::ptr_eq(x, y) ||
+                *self == HashTest::Equalp &&
+                match (x.try_borrow(), y.try_borrow()) {
+                    (Ok(x), Ok(y)) => x.equalp(&y),
+                    _ => false,
+                }
+            }
+            (Sexp::Array(x), Sexp::Array(y)) => {
+                Rc::ptr_eq(x, y) ||
+                *self == HashTest::Equalp && {
+                    let (x, y) = (x.borrow(), y.borrow());
+                    x.dimensions() == y.dimensions() && x.len() == y.len() &&
+                    x.elements().iter().zip(y.elements().iter()).all(|(a, b)| self.test(a, b))
+                }
+            }
+            (Sexp::Struct(x), Sexp::Struct(y)) => {
+                Rc::ptr_eq(x, y) ||
+                *self == HashTest::Equalp && {
+                    let (x, y) = (x.borrow(), y.borrow());
+                    x.struct_type() == y.struct_type() &&
+                    x.slots().iter().zip(y.slots().iter()).all(|(a, b)| self.test(a, b))
+                }
+            }
+            (Sexp::Instance(x), Sexp::Instance(y)) => Rc::ptr_eq(x, y),
+            (Sexp::Stream(Stream::StringOutput(x)), Sexp::Stream(Stream::StringOutput(y))) => {
+                Rc::ptr_eq(x, y)
+            }
+            (Sexp::BuiltInFunc(f), Sexp::BuiltInFunc(g)) => *f as usize == *g as usize,
+            (Sexp::Values(_), _) |
+            (_, Sexp::Values(_)) => false,
+            (a, b) => a == b,
+        }
+    }
+
+    fn fold(&self, c: char) -> char {
+        if *self == HashTest::Equalp {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    }
+
+    fn numbers_match(&self, a: &Number, b: &Number) -> bool {
+        match (a, b) {
+            (Number::Complex(ar, ai), Number::Complex(br, bi)) => {
+                self.numbers_match(ar, br) && self.numbers_match(ai, bi)
+            }
+            _ if *self == HashTest::Equalp => {
+                match (exact(a), exact(b)) {
+                    (Some(x), Some(y)) => x == y,
+                    _ => a.num_eq(b),
+                }
+            }
+            (Number::Float(x), Number::Float(y)) => x.to_bits() == y.to_bits(),
+            _ => a == b,
+        }
+    }
+
+    pub fn hash(&self, key: &Sexp) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_into(key, &mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_into<H: Hasher>(&self, key: &Sexp, state: &mut H) {
+        match *key {
+            Sexp::Number(ref n) => {
+                0u8.hash(state);
+                self.hash_number(n, state);
+            }
+            Sexp::Char(c) => {
+                1u8.hash(state);
+                self.fold(c).hash(state);
+            }
+            Sexp::String(ref s) if !self.by_contents() => {
+                2u8.hash(state);
+                (Rc::as_ptr(s) as *const u8 as usize).hash(state);
+            }
+            Sexp::String(ref s) => {
+                2u8.hash(state);
+                for c in s.chars() {
+                    self.fold(c).hash(state);
+                }
+            }
+            Sexp::Symbol(ref s) => {
+                3u8.hash(state);
+                s.hash(state);
+            }
+            Sexp::List(ref v) if v.is_empty() => 6u8.hash(state),
+            Sexp::List(ref v) if !self.by_contents() => {
+                4u8.hash(state);
+                (Rc::as_ptr(v) as usize).hash(state);
+            }
+            Sexp::List(ref v) => {
+                4u8.hash(state);
+                v.len().hash(state);
+                for s in v.iter() {
+                    self.hash_into(s, state);
+                }
+            }
+            Sexp::Cons(ref c) if !self.by_contents() => {
+                4u8.hash(state);
+                (Rc::as_ptr(c) as usize).hash(state);
+            }
+            Sexp::Cons(_) => 4u8.hash(state),
+            Sexp::HashTable(_) if *self == HashTest::Equalp => 5u8.hash(state),
+            Sexp::HashTable(ref t) => {
+                5u8.hash(state);
+                (Rc::as_ptr(t) as usize).hash(state);
+            }
+            Sexp::Array(ref a) if *self == HashTest::Equalp => {
+                9u8.hash(state);
+                a.borrow().dimensions().hash(state);
+            }
+            Sexp::Array(ref a) => {
+                9u8.hash(state);
+                (Rc::as_ptr(a) as usize).hash(state);
+            }
+            Sexp::Struct(ref s) if *self == HashTest::Equalp => {
+                10u8.hash(state);
+                s.borrow().struct_type().name().hash(state);
+            }
+            Sexp::Struct(ref s) => {
+                10u8.hash(state);
+                (Rc::as_ptr(s) as usize).hash(state);
+            }
+            Sexp::Instance(ref i) => {
+                11u8.hash(state);
+                (Rc::as_ptr(i) as usize).hash(state);
+            }
+            Sexp::Nil => 6u8.hash(state),
+            Sexp::True => 7u8.hash(state),
+            _ => 8u8.hash(state),
+        }
+    }
+
+    fn hash_number<H: Hasher>(&self, n: &Number, state: &mut H) {
+        match *n {
+            Number::Complex(ref r, ref i) => {
+                self.hash_number(r, state);
+                self.hash_number(i, state);
+            }
+            _ if *self == HashTest::Equalp => {
+                match exact(n) {
+                    Some(r) => r.hash(state),
+                    None => n.to_f64().to_bits().hash(state),
+                }
+            }
+            Number::Fixnum(i) => i.hash(state),
+            Number::Bignum(ref i) => i.hash(state),
+            Number::Ratio(ref r) => r.hash(state),
+            Number::Float(f) => f.to_bits().hash(state),
+        }
+    }
+}
+
+/// The exact value of a finite real number, which is how `equalp` compares
+/// floats with rationals.
+fn exact(n: &Number) -> Option<BigRational> {
+    match *n {
+        Number::Float(f) => BigRational::from_f64(f),
+        _ => n.to_ratio(),
+    }
+}
+
+/// A hash table that iterates in insertion order. Removed entries leave a
+/// hole in `entries` until enough of them accumulate to compact the table.
+#[derive(PartialEq, Debug)]
+pub struct HashTable {
+    test: HashTest,
+    index: HashMap<u64, Vec<usize>>,
+    entries: Vec<Option<(Sexp, Sexp)>>,
+    count: usize,
+}
+
+impl HashTable {
+    pub fn new(test: HashTest) -> HashTable {
+        HashTable {
+            test,
+            index: HashMap::new(),
+            entries: vec![],
+            count: 0,
+        }
+    }
+
+    pub fn test(&self) -> HashTest {
+        self.test
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    fn position(&self, key: &Sexp, hash: u64) -> Option<usize> {
+        self.index.get(&hash).and_then(|positions| {
+            positions.iter().cloned().find(|&i| {
+                match self.entries[i] {
+                    Some((ref k, _)) => self.test.test(k, key),
+                    None => false,
+                }
+            })
+        })
+    }
+
+    pub fn get(&self, key: &Sexp) -> Option<&Sexp> {
+        self.position(key, self.test.hash(key))
+            .and_then(|i| self.entries[i].as_ref().map(|(_, v)| v))
+    }
+
+    pub fn insert(&mut self, key: Sexp, value: Sexp) {
+        let hash = self.test.hash(&key);
+
+        match self.position(&key, hash) {
+            Some(i) => self.entries[i] = Some((key, value)),
+            None => {
+                self.index.entry(hash).or_default().push(self.entries.len());
+                self.entries.push(Some((key, value)));
+                self.count += 1;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Sexp) -> bool {
+        let hash = self.test.hash(key);
+
+        match self.position(key, hash) {
+            Some(i) => {
+                self.entries[i] = None;
+                self.index.get_mut(&hash).unwrap().retain(|&j| j != i);
+                self.count -= 1;
+
+                if self.count * 2 < self.entries.len() {
+                    self.compact();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.entries.clear();
+        self.count = 0;
+    }
+
+    /// Returns the live entries in insertion order.
+    pub fn entries(&self) -> Vec<(Sexp, Sexp)> {
+        self.entries.iter().filter_map(|e| e.clone()).collect()
+    }
+
+    fn compact(&mut self) {
+        let entries = self.entries();
+        self.clear();
+
+        for (k, v) in entries {
+            self.insert(k, v);
+        }
+    }
+
+    fn equalp(&self, other: &HashTable) -> bool {
+        self.test == other.test && self.count == other.count &&
+        self.entries().iter().all(|(k, v)| {
+            other.get(k).is_some_and(|w| HashTest::Equalp.test(v, w))
+        })
+    }
+}
+
+impl fmt::Display for HashTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<HASH-TABLE :TEST {} :COUNT {}", self.test.name(), self.count)?;
+        for (k, v) in self.entries() {
+            write!(f, " ({} . {})", k, v)?;
+        }
+        write!(f, ">")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::{HashTable, HashTest};
+    use super::super::array::Array;
+    use super::super::number::Number;
+    use super::super::sexp::Sexp;
+    use super::super::test_util::{int, float, string, eval_source};
+    use num::{BigInt, BigRational};
+
+    #[test]
+    fn test_tests() {
+        let half = Sexp::Number(Number::from_ratio(BigRational::new(BigInt::from(1),
+                                                                    BigInt::from(2))));
+
+        assert!(HashTest::Eql.test(&int(1), &int(1)));
+        assert!(!HashTest::Eql.test(&int(1), &float(1.0)));
+        assert!(!HashTest::Eql.test(&float(0.0), &float(-0.0)));
+        assert!(HashTest::Equal.test(&string("a"), &string("a")));
+        assert!(!HashTest::Equal.test(&string("a"), &string("A")));
+        assert!(HashTest::Equalp.test(&string("a"), &string("A")));
+        assert!(HashTest::Equalp.test(&int(1), &float(1.0)));
+        assert!(HashTest::Equalp.test(&half, &float(0.5)));
+        assert!(HashTest::Equal.test(&Sexp::symbol("a"),
+                                     &Sexp::symbol("A")));
+        assert!(HashTest::Equal.test(&Sexp::list(vec![int(1), string("a")]),
+                                     &Sexp::list(vec![int(1), string("a")])));
+    }
+
+    #[test]
+    fn test_identity() {
+        let a = string("a");
+        let list = Sexp::list(vec![int(1)]);
+
+        assert!(HashTest::Eq.test(&a, &a.clone()));
+        assert!(!HashTest::Eq.test(&a, &string("a")));
+        assert!(!HashTest::Eql.test(&list, &Sexp::list(vec![int(1)])));
+        assert!(HashTest::Eql.test(&list, &list.clone()));
+        assert!(HashTest::Eq.test(&Sexp::list(vec![]), &Sexp::Nil));
+        assert_eq!(HashTest::Eq.hash(&Sexp::list(vec![])), HashTest::Eq.hash(&Sexp::Nil));
+        assert_eq!(HashTest::Eq.hash(&a), HashTest::Eq.hash(&a.clone()));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let array = |v| Sexp::Array(Rc::new(RefCell::new(Array::vector(v))));
+        let a = array(vec![int(1), string("x")]);
+        let b = array(vec![float(1.0), string("X")]);
+
+        assert!(HashTest::Eql.test(&a, &a.clone()));
+        assert!(!HashTest::Equal.test(&a, &array(vec![int(1), string("x")])));
+        assert!(HashTest::Equalp.test(&a, &b));
+        assert_eq!(HashTest::Equalp.hash(&a), HashTest::Equalp.hash(&b));
+    }
+
+    #[test]
+    fn test_hashes_agree_with_tests() {
+        assert_eq!(HashTest::Equalp.hash(&int(1)), HashTest::Equalp.hash(&float(1.0)));
+        assert_eq!(HashTest::Equalp.hash(&string("Ab")), HashTest::Equalp.hash(&string("aB")));
+        assert_eq!(HashTest::Equal.hash(&Sexp::symbol("a")),
+                   HashTest::Equal.hash(&Sexp::symbol("A")));
+    }
+
+    #[test]
+    fn test_equalp_table_as_key_in_itself() {
+        let source = "(defparameter h (make-hash-table :test 'equalp)) \
+                      (setf (gethash h h) 1) \
+                      (setf (gethash h h) 2) \
+                      (list (gethash h h) (hash-table-count h))";
+
+        assert_eq!(eval_source(source), Ok("(2 1)".to_string()));
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut table = HashTable::new(HashTest::Equal);
+
+        table.insert(string("a"), int(1));
+        table.insert(string("b"), int(2));
+        table.insert(string("a"), int(3));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&string("a")), Some(&int(3)));
+        assert_eq!(table.get(&string("c")), None);
+
+        assert!(table.remove(&string("a")));
+        assert!(!table.remove(&string("a")));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(&string("b")), Some(&int(2)));
+        assert_eq!(table.entries(), vec![(string("b"), int(2))]);
+
+        table.clear();
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.get(&string("b")), None);
+    }
+
+    #[test]
+    fn test_entries_keep_insertion_order() {
+        let mut table = HashTable::new(HashTest::Eql);
+
+        for i in 0..10 {
+            table.insert(int(i), int(i * i));
+        }
+        for i in 0..8 {
+            table.remove(&int(i));
+        }
+        table.insert(int(0), int(0));
+
+        assert_eq!(table.entries(),
+                   vec![(int(8), int(64)), (int(9), int(81)), (int(0), int(0))]);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut table = HashTable::new(HashTest::Equal);
+        table.insert(string("a"), int(1));
+
+        assert_eq!(format!("{}", table), "#<HASH-TABLE :TEST EQUAL :COUNT 1 (\"a\" . 1)>");
+    }
+}
@@ -1,42 +1,187 @@
-#[macro_use]
-extern crate nom;
 extern crate rustyline;
+extern crate num;
 
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::process;
 use rustyline::Editor;
-use nom::IResult::Done;
+use rustyline::error::ReadlineError;
+use env::Env;
+use sexp::Sexp;
+use stream::Stream;
 
 mod sexp;
+mod number;
+mod character;
+mod stream;
+mod hash_table;
+mod array;
+mod structure;
+mod clos;
+mod symbol;
+mod readtable;
+mod random;
+mod package;
+mod types;
 mod parser;
 mod env;
 mod built_in;
+mod cli;
+#[cfg(test)]
+mod test_util;
 
 fn main() {
-    let mut rl = Editor::new();
-    let _ = rl.load_history("history.txt");
+    let args: Vec<String> = std::env::args().collect();
+    let options = match cli::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("rust-lisp: {}", e);
+            eprintln!("Try 'rust-lisp --help' for more information.");
+            process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    if options.version {
+        println!("rust-lisp {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
     let root = built_in::default_env();
+    let strings = |args: &[String]| {
+        if args.is_empty() {
+            Sexp::Nil
+        } else {
+            Sexp::list(args.iter().map(|a| Sexp::string(&a[..])).collect())
+        }
+    };
+    env::env_set(&root, "*posix-argv*", strings(&args));
+    env::env_set(&root, "*command-line-args*", strings(&options.args));
+
+    if options.init {
+        if let Some(path) = cli::init_file().filter(|path| Path::new(path).exists()) {
+            if let Err(e) = built_in::load_file(&root, &path, false, false) {
+                report_error(&e);
+            }
+        }
+    }
+    for action in &options.actions {
+        let result = match *action {
+            cli::Action::Eval(ref expression) => eval_string(&root, expression),
+            cli::Action::Load(ref path) => built_in::load_file(&root, path, false, false),
+        };
+        exit_on_error(result);
+    }
+
+    let evaluated = options.actions.iter().any(|a| matches!(*a, cli::Action::Eval(_)));
+    if let Some(ref script) = options.script {
+        exit_on_error(built_in::load_file(&root, script, false, false));
+    } else if !evaluated && !io::stdin().is_terminal() {
+        exit_on_error(run_stdin(&root));
+    } else if !evaluated {
+        repl(&root, &options);
+    }
+}
+
+fn report_error(e: &str) {
+    Stream::Stdout.fresh_line();
+    eprintln!("ERROR: {}", e);
+}
 
+/// Exits with a non-zero status if running a script, `-e` or `-l` failed.
+fn exit_on_error(result: Result<(), String>) {
+    if let Err(e) = result {
+        report_error(&e);
+        process::exit(1);
+    }
+}
+
+fn eval_string(root: &Env, expression: &str) -> Result<(), String> {
+    built_in::load_forms(root, &Stream::string_input(expression), false)
+}
+
+/// Evaluates the forms piped to standard input, printing their values.
+fn run_stdin(root: &Env) -> Result<(), String> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).map_err(|e| e.to_string())?;
+
+    built_in::load_forms(root, &Stream::string_input(&input), true)
+}
+
+/// Evaluates the forms in `input`, printing the value of each, up to the
+/// first error.
+fn eval_input(root: &Env, input: &str) {
+    let stream = Stream::string_input(input);
     loop {
-        let readline = rl.readline("rl> ");
-        match readline {
+        readtable::use_env(root);
+        let result = match parser::read(&stream) {
+            Ok(Some(s)) => {
+                s.eval(root).and_then(|s| sexp::check_printable(&s).map(|_| format!("{}\n", s)))
+            }
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+
+        Stream::Stdout.fresh_line();
+        match result {
+            Ok(printed) => Stream::Stdout.write_str(&printed),
+            Err(e) => {
+                Stream::Stdout.write_str(&format!("ERROR: {}\n", e));
+                return;
+            }
+        }
+    }
+}
+
+/// Reads lines until reading them with the current readtable doesn't run out
+/// of input, prompting for the rest of an unfinished form with `...>` indented
+/// by how many lists are open, and then evaluates them all.
+fn repl(root: &Env, options: &cli::Options) {
+    let mut rl = Editor::new();
+    if let Some(ref path) = options.history_file {
+        let _ = rl.load_history(path);
+    }
+    if !options.quiet {
+        println!("rust-lisp {}", env!("CARGO_PKG_VERSION"));
+    }
+
+    let mut input = String::new();
+    let mut prompt = "rl> ".to_string();
+    loop {
+        match rl.readline(&prompt) {
             Ok(line) => {
                 rl.add_history_entry(&line);
+                input.push_str(&line);
+                input.push('\n');
 
-                match parser::sexp(line.as_bytes()) {
-                    Done(_, s) => {
-                        match s.eval(&root) {
-                            Ok(s) => println!("{}", s),
-                            Err(e) => println!("ERROR: {}", e),
-                        };
+                readtable::use_env(root);
+                match parser::unfinished_depth(&input) {
+                    Some(depth) => prompt = format!("...> {}", "  ".repeat(depth)),
+                    None => {
+                        eval_input(root, &input);
+                        input.clear();
+                        prompt = "rl> ".to_string();
                     }
-                    _ => println!("ERROR: Parse error"),
-                };
+                }
+            }
+            Err(ReadlineError::Interrupted) if !input.is_empty() => {
+                input.clear();
+                prompt = "rl> ".to_string();
             }
             _ => {
-                println!("exiting...");
+                if !options.quiet {
+                    println!("exiting...");
+                }
                 break;
             }
         }
     }
 
-    rl.save_history("history.txt").unwrap();
+    if let Some(ref path) = options.history_file {
+        if let Err(e) = rl.save_history(path) {
+            report_error(&format!("Couldn't save the history to {}: {}", path, e));
+        }
+    }
 }
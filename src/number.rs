@@ -0,0 +1,678 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use num::{BigInt, BigRational, FromPrimitive, Integer, One, Signed, ToPrimitive, Zero};
+use num::bigint::Sign;
+
+/// A Lisp number. Integers are stored as fixnums until they overflow, at
+/// which point they are promoted to bignums; ratios are always kept in lowest
+/// terms with a denominator greater than one. Operations on exact numbers stay
+/// exact, and any operation involving a float produces a float. Complex
+/// numbers hold a real and an imaginary part, which are never themselves
+/// complex.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Number {
+    Fixnum(i64),
+    Bignum(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+    Complex(Box<Number>, Box<Number>),
+}
+
+/// The most bits an integer that `expt` or `ash` makes may have; bigger
+/// ones would take more memory than there is.
+pub const MAX_INTEGER_BITS: u64 = 1 << 24;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Rounding {
+    Floor,
+    Ceiling,
+    Truncate,
+    Round,
+}
+
+impl Number {
+    pub fn from_bigint(n: BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Fixnum(n),
+            None => Number::Bignum(n),
+        }
+    }
+
+    pub fn from_ratio(r: BigRational) -> Number {
+        if r.is_integer() {
+            Number::from_bigint(r.to_integer())
+        } else {
+            Number::Ratio(r)
+        }
+    }
+
+    /// Builds a complex number from two reals. If either part is a float
+    /// both become floats, and a complex with an exact zero imaginary part is
+    /// just its real part.
+    pub fn complex(real: Number, imag: Number) -> Number {
+        match (real, imag) {
+            (Number::Float(r), i) => Number::Complex(Box::new(Number::Float(r)),
+                                                     Box::new(Number::Float(i.to_f64()))),
+            (r, Number::Float(i)) => Number::Complex(Box::new(Number::Float(r.to_f64())),
+                                                     Box::new(Number::Float(i))),
+            (r, ref i) if i.is_zero() => r,
+            (r, i) => Number::Complex(Box::new(r), Box::new(i)),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(*self, Number::Complex(..))
+    }
+
+    /// Returns the real and imaginary parts. The imaginary part of a real is
+    /// zero of the same kind.
+    pub fn parts(&self) -> (Number, Number) {
+        match *self {
+            Number::Complex(ref r, ref i) => ((**r).clone(), (**i).clone()),
+            Number::Float(_) => (self.clone(), Number::Float(0.)),
+            _ => (self.clone(), Number::Fixnum(0)),
+        }
+    }
+
+    pub fn conjugate(&self) -> Number {
+        match *self {
+            Number::Complex(ref r, ref i) => Number::complex((**r).clone(), -&**i),
+            _ => self.clone(),
+        }
+    }
+
+    /// The angle of the number in the complex plane.
+    pub fn phase(&self) -> f64 {
+        let (r, i) = self.parts();
+
+        i.to_f64().atan2(r.to_f64())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match *self {
+            Number::Fixnum(n) => n == 0,
+            Number::Bignum(ref n) => n.is_zero(),
+            Number::Ratio(ref r) => r.is_zero(),
+            Number::Float(n) => n == 0.,
+            Number::Complex(ref r, ref i) => r.is_zero() && i.is_zero(),
+        }
+    }
+
+    /// Converts a real number to a float. Complex numbers have no float
+    /// value, so they become NaN.
+    pub fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Fixnum(n) => n as f64,
+            Number::Bignum(ref n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Ratio(ref r) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => n,
+            Number::Complex(..) => f64::NAN,
+        }
+    }
+
+    /// Returns the value as a bignum when it is an integer.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match *self {
+            Number::Fixnum(n) => Some(BigInt::from(n)),
+            Number::Bignum(ref n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an exact ratio when it is rational.
+    pub fn to_ratio(&self) -> Option<BigRational> {
+        match *self {
+            Number::Ratio(ref r) => Some(r.clone()),
+            Number::Float(_) | Number::Complex(..) => None,
+            ref n => n.to_bigint().map(BigRational::from_integer),
+        }
+    }
+
+    fn float_op<F, R>(&self, other: &Number, f: F, r: R) -> Number
+        where F: Fn(f64, f64) -> f64,
+              R: Fn(BigRational, BigRational) -> BigRational
+    {
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(a), Some(b)) => Number::from_ratio(r(a, b)),
+            _ => Number::Float(f(self.to_f64(), other.to_f64())),
+        }
+    }
+
+    /// Applies an arithmetic operation, using the checked fixnum operation
+    /// when both arguments are fixnums and falling back to exact ratios or
+    /// floats according to the usual contagion rules.
+    fn arithmetic<C, F, R>(&self, other: &Number, checked: C, f: F, r: R) -> Number
+        where C: Fn(i64, i64) -> Option<i64>,
+              F: Fn(f64, f64) -> f64,
+              R: Fn(BigRational, BigRational) -> BigRational
+    {
+        if let (&Number::Fixnum(a), &Number::Fixnum(b)) = (self, other) {
+            if let Some(n) = checked(a, b) {
+                return Number::Fixnum(n);
+            }
+        }
+
+        self.float_op(other, f, r)
+    }
+
+    pub fn checked_div(&self, other: &Number) -> Result<Number, String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+
+        if self.is_complex() || other.is_complex() {
+            let ((a, b), (c, d)) = (self.parts(), other.parts());
+            let denominator = &(&c * &c) + &(&d * &d);
+            let real = &(&a * &c) + &(&b * &d);
+            let imag = &(&b * &c) - &(&a * &d);
+
+            return Ok(Number::complex(real.checked_div(&denominator)?,
+                                      imag.checked_div(&denominator)?));
+        }
+
+        Ok(self.float_op(other, |a, b| a / b, |a, b| a / b))
+    }
+
+    /// Divides and rounds the quotient to an integer, returning the quotient
+    /// and the remainder.
+    pub fn divide_rounding(&self,
+                           other: &Number,
+                           rounding: Rounding)
+                           -> Result<(Number, Number), String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+
+        if let Some(n) = [self, other].iter().find(|n| n.is_complex()) {
+            return Err(format!("Argument error: {}", n));
+        }
+
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(a), Some(b)) => {
+                let q = (a.clone() / b.clone()).to_integer_rounding(rounding);
+                let r = a - b * BigRational::from_integer(q.clone());
+                Ok((Number::from_bigint(q), Number::from_ratio(r)))
+            }
+            _ => {
+                let (a, b) = (self.to_f64(), other.to_f64());
+                let q = match rounding {
+                    Rounding::Floor => (a / b).floor(),
+                    Rounding::Ceiling => (a / b).ceil(),
+                    Rounding::Truncate => (a / b).trunc(),
+                    Rounding::Round => (a / b).round_ties_even(),
+                };
+
+                match BigInt::from_f64(q) {
+                    Some(n) => Ok((Number::from_bigint(n), Number::Float(a - q * b))),
+                    None => Err(format!("Cannot convert {} to an integer", q)),
+                }
+            }
+        }
+    }
+
+    /// Numeric equality, which unlike `num_cmp` is defined for complex
+    /// numbers.
+    pub fn num_eq(&self, other: &Number) -> bool {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+
+        a.num_cmp(&c) == Some(Ordering::Equal) && b.num_cmp(&d) == Some(Ordering::Equal)
+    }
+
+    /// Orders two reals. Complex numbers are unordered.
+    pub fn num_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (&Number::Complex(..), _) | (_, &Number::Complex(..)) => None,
+            (&Number::Fixnum(a), &Number::Fixnum(b)) => Some(a.cmp(&b)),
+            _ => {
+                match (self.to_ratio(), other.to_ratio()) {
+                    (Some(a), Some(b)) => Some(a.cmp(&b)),
+                    _ => self.to_f64().partial_cmp(&other.to_f64()),
+                }
+            }
+        }
+    }
+
+    pub fn abs(&self) -> Number {
+        match *self {
+            Number::Float(n) => Number::Float(n.abs()),
+            Number::Complex(ref r, ref i) => Number::Float(r.to_f64().hypot(i.to_f64())),
+            ref n if n.is_negative() => -n,
+            ref n => n.clone(),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num_cmp(&Number::Fixnum(0)) == Some(Ordering::Less)
+    }
+
+    pub fn signum(&self) -> Number {
+        match *self {
+            ref z @ Number::Complex(..) => {
+                z.checked_div(&z.abs()).unwrap_or_else(|_| z.clone())
+            }
+            Number::Float(n) if n == 0. || n.is_nan() => Number::Float(n),
+            Number::Float(n) => Number::Float(n.signum()),
+            ref n => {
+                match n.num_cmp(&Number::Fixnum(0)) {
+                    Some(Ordering::Less) => Number::Fixnum(-1),
+                    Some(Ordering::Greater) => Number::Fixnum(1),
+                    _ => Number::Fixnum(0),
+                }
+            }
+        }
+    }
+
+    /// Takes the principal square root, returning an exact result when the
+    /// argument is the square of a rational or Gaussian rational.
+    pub fn sqrt(&self) -> Result<Number, String> {
+        if self.is_complex() || self.is_negative() {
+            let (a, b) = self.parts();
+            let magnitude = (&(&a * &a) + &(&b * &b)).sqrt()?;
+            let two = Number::Fixnum(2);
+            let real = (&magnitude + &a).checked_div(&two)?.sqrt()?;
+            let imag = (&magnitude - &a).checked_div(&two)?.sqrt()?;
+            let imag = if b.is_negative() { -&imag } else { imag };
+
+            return Ok(Number::complex(real, imag));
+        }
+
+        if let Some(r) = self.to_ratio() {
+            let (n, d) = (r.numer().sqrt(), r.denom().sqrt());
+            let root = BigRational::new(n, d);
+            if &root * &root == r {
+                return Ok(Number::from_ratio(root));
+            }
+        }
+
+        Ok(Number::Float(self.to_f64().sqrt()))
+    }
+
+    pub fn exp(&self) -> Number {
+        let (a, b) = self.parts();
+        let magnitude = a.to_f64().exp();
+
+        if self.is_complex() {
+            let b = b.to_f64();
+            Number::complex(Number::Float(magnitude * b.cos()), Number::Float(magnitude * b.sin()))
+        } else {
+            Number::Float(magnitude)
+        }
+    }
+
+    /// The natural logarithm, which is complex for negative reals.
+    pub fn ln(&self) -> Number {
+        if self.is_complex() || self.is_negative() {
+            Number::complex(Number::Float(self.abs().to_f64().ln()), Number::Float(self.phase()))
+        } else {
+            Number::Float(self.to_f64().ln())
+        }
+    }
+
+    pub fn expt(&self, power: &Number) -> Result<Number, String> {
+        if let (true, Some(p)) = (self.is_exact(), power.to_bigint()) {
+            return self.exact_expt(&p);
+        }
+        if let (true, Some(p)) = (self.is_complex(), power.to_bigint().and_then(|p| p.to_i32())) {
+            return self.expt_by_squaring(p);
+        }
+
+        if self.is_complex() || power.is_complex() || (self.is_negative() && !power.is_integer()) {
+            if self.is_zero() {
+                return Ok(Number::Fixnum(0));
+            }
+            return Ok((power * &self.ln()).exp());
+        }
+
+        let result = match (self, power.to_bigint().and_then(|p| p.to_i32())) {
+            (&Number::Float(b), Some(p)) => b.powi(p),
+            _ => self.to_f64().powf(power.to_f64()),
+        };
+        if result.is_infinite() && self.to_f64().is_finite() && power.to_f64().is_finite() {
+            return Err(format!("Floating point overflow in (EXPT {} {})", self, power));
+        }
+        Ok(Number::Float(result))
+    }
+
+    /// Raises an exact number to an integer power, refusing to make an
+    /// integer of more than `MAX_INTEGER_BITS` bits.
+    fn exact_expt(&self, power: &BigInt) -> Result<Number, String> {
+        if self.is_zero() {
+            return match power.sign() {
+                Sign::Minus => Err("Division by zero".to_string()),
+                Sign::NoSign => Ok(Number::Fixnum(1)),
+                Sign::Plus => Ok(Number::Fixnum(0)),
+            };
+        }
+
+        // The units 1, -1, i and -i repeat every fourth power.
+        let square = self * self;
+        let power = if (&square * &square).num_eq(&Number::Fixnum(1)) {
+            power.mod_floor(&BigInt::from(4))
+        } else {
+            power.clone()
+        };
+        let bits = power.abs().to_u64().and_then(|p| p.checked_mul(self.exact_bits()));
+        let p = match (bits, power.to_i32()) {
+            (Some(bits), Some(p)) if bits <= MAX_INTEGER_BITS => p,
+            _ => return Err(format!("The result of (EXPT {} {}) is too large", self, power)),
+        };
+
+        match self.to_ratio() {
+            Some(base) => Ok(Number::from_ratio(base.pow(p))),
+            None => self.expt_by_squaring(p),
+        }
+    }
+
+    fn expt_by_squaring(&self, p: i32) -> Result<Number, String> {
+        let (mut result, mut square) = (Number::Fixnum(1), self.clone());
+        let mut remaining = p.unsigned_abs();
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = &result * &square;
+            }
+            square = &square * &square;
+            remaining >>= 1;
+        }
+
+        if p < 0 { Number::Fixnum(1).checked_div(&result) } else { Ok(result) }
+    }
+
+    /// Whether the number is rational, or complex with rational parts.
+    pub fn is_exact(&self) -> bool {
+        match *self {
+            Number::Float(_) => false,
+            Number::Complex(ref r, _) => r.is_exact(),
+            _ => true,
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        self.to_bigint().is_some()
+    }
+
+    /// The bits needed for the numerator or denominator of an exact number,
+    /// whichever is longer, or of either part of a complex one.
+    fn exact_bits(&self) -> u64 {
+        match *self {
+            Number::Complex(ref r, ref i) => r.exact_bits().max(i.exact_bits()),
+            ref n => n.to_ratio().map_or(64, |r| r.numer().bits().max(r.denom().bits())),
+        }
+    }
+}
+
+trait ToIntegerRounding {
+    fn to_integer_rounding(&self, rounding: Rounding) -> BigInt;
+}
+
+impl ToIntegerRounding for BigRational {
+    fn to_integer_rounding(&self, rounding: Rounding) -> BigInt {
+        match rounding {
+            Rounding::Floor => self.floor().to_integer(),
+            Rounding::Ceiling => self.ceil().to_integer(),
+            Rounding::Truncate => self.trunc().to_integer(),
+            Rounding::Round => {
+                let floor = self.floor();
+                let half = BigRational::new(BigInt::one(), BigInt::from(2));
+
+                match (self - &floor).cmp(&half) {
+                    Ordering::Less => floor.to_integer(),
+                    Ordering::Greater => floor.to_integer() + 1,
+                    Ordering::Equal if floor.to_integer().is_even() => floor.to_integer(),
+                    Ordering::Equal => floor.to_integer() + 1,
+                }
+            }
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Number {
+        Number::Fixnum(n)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(n: f64) -> Number {
+        Number::Float(n)
+    }
+}
+
+impl Add for &Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        if self.is_complex() || other.is_complex() {
+            let ((a, b), (c, d)) = (self.parts(), other.parts());
+            return Number::complex(&a + &c, &b + &d);
+        }
+
+        self.arithmetic(other, i64::checked_add, |a, b| a + b, |a, b| a + b)
+    }
+}
+
+impl Sub for &Number {
+    type Output = Number;
+
+    fn sub(self, other: &Number) -> Number {
+        if self.is_complex() || other.is_complex() {
+            let ((a, b), (c, d)) = (self.parts(), other.parts());
+            return Number::complex(&a - &c, &b - &d);
+        }
+
+        self.arithmetic(other, i64::checked_sub, |a, b| a - b, |a, b| a - b)
+    }
+}
+
+impl Mul for &Number {
+    type Output = Number;
+
+    fn mul(self, other: &Number) -> Number {
+        if self.is_complex() || other.is_complex() {
+            let ((a, b), (c, d)) = (self.parts(), other.parts());
+            return Number::complex(&(&a * &c) - &(&b * &d), &(&a * &d) + &(&b * &c));
+        }
+
+        self.arithmetic(other, i64::checked_mul, |a, b| a * b, |a, b| a * b)
+    }
+}
+
+impl Neg for &Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        &Number::Fixnum(0) - self
+    }
+}
+
+fn fmt_float(n: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    let abs = n.abs();
+
+    if !n.is_finite() || abs == 0. || (1e-3..1e7).contains(&abs) {
+        return write!(f, "{:?}", n);
+    }
+
+    let s = format!("{:e}", n);
+    let (mantissa, exponent) = s.split_at(s.find('e').unwrap());
+    if mantissa.contains('.') {
+        write!(f, "{}{}", mantissa, exponent)
+    } else {
+        write!(f, "{}.0{}", mantissa, exponent)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Fixnum(n) => write!(f, "{}", n),
+            Number::Bignum(ref n) => write!(f, "{}", n),
+            Number::Ratio(ref r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Number::Float(n) => fmt_float(n, f),
+            Number::Complex(ref r, ref i) => write!(f, "#C({} {})", r, i),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Number, Rounding};
+    use num::{BigInt, BigRational};
+
+    fn ratio(n: i64, d: i64) -> Number {
+        Number::from_ratio(BigRational::new(BigInt::from(n), BigInt::from(d)))
+    }
+
+    #[test]
+    fn test_fixnum_overflow_promotes_to_bignum() {
+        let big = &Number::Fixnum(i64::MAX) + &Number::Fixnum(1);
+
+        assert_eq!(big, Number::Bignum(BigInt::from(i64::MAX) + BigInt::from(1)));
+        assert_eq!(&big - &Number::Fixnum(1), Number::Fixnum(i64::MAX));
+    }
+
+    #[test]
+    fn test_ratios_are_normalized() {
+        assert_eq!(ratio(2, 4), ratio(1, 2));
+        assert_eq!(ratio(4, 2), Number::Fixnum(2));
+        assert_eq!(&ratio(1, 2) + &ratio(1, 2), Number::Fixnum(1));
+    }
+
+    #[test]
+    fn test_float_contagion() {
+        assert_eq!(&Number::Fixnum(1) + &Number::Float(0.5), Number::Float(1.5));
+        assert_eq!(&ratio(1, 2) * &Number::Float(3.), Number::Float(1.5));
+    }
+
+    #[test]
+    fn test_checked_div() {
+        assert_eq!(Number::Fixnum(1).checked_div(&Number::Fixnum(3)), Ok(ratio(1, 3)));
+        assert_eq!(Number::Fixnum(6).checked_div(&Number::Fixnum(3)), Ok(Number::Fixnum(2)));
+        assert_eq!(Number::Fixnum(1).checked_div(&Number::Float(0.)),
+                   Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_divide_rounding() {
+        assert_eq!(Number::Fixnum(-7).divide_rounding(&Number::Fixnum(2), Rounding::Floor),
+                   Ok((Number::Fixnum(-4), Number::Fixnum(1))));
+        assert_eq!(Number::Fixnum(-7).divide_rounding(&Number::Fixnum(2), Rounding::Truncate),
+                   Ok((Number::Fixnum(-3), Number::Fixnum(-1))));
+        assert_eq!(Number::Float(2.5).divide_rounding(&Number::Fixnum(1), Rounding::Floor),
+                   Ok((Number::Fixnum(2), Number::Float(0.5))));
+        assert_eq!(Number::Fixnum(7).divide_rounding(&Number::Fixnum(2), Rounding::Ceiling),
+                   Ok((Number::Fixnum(4), Number::Fixnum(-1))));
+    }
+
+    #[test]
+    fn test_round_to_even() {
+        let round = |n: Number| n.divide_rounding(&Number::Fixnum(1), Rounding::Round).unwrap().0;
+
+        assert_eq!(round(ratio(5, 2)), Number::Fixnum(2));
+        assert_eq!(round(ratio(7, 2)), Number::Fixnum(4));
+        assert_eq!(round(ratio(-5, 2)), Number::Fixnum(-2));
+        assert_eq!(round(ratio(8, 3)), Number::Fixnum(3));
+        assert_eq!(round(Number::Float(2.5)), Number::Fixnum(2));
+        assert_eq!(round(Number::Float(3.5)), Number::Fixnum(4));
+    }
+
+    #[test]
+    fn test_abs_and_signum() {
+        assert_eq!(Number::Fixnum(i64::MIN).abs(),
+                   Number::Bignum(-BigInt::from(i64::MIN)));
+        assert_eq!(ratio(-1, 2).abs(), ratio(1, 2));
+        assert_eq!(ratio(-1, 2).signum(), Number::Fixnum(-1));
+        assert_eq!(Number::Float(-0.5).signum(), Number::Float(-1.));
+        assert_eq!(Number::Float(0.).signum(), Number::Float(0.));
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(Number::Fixnum(16).sqrt(), Ok(Number::Fixnum(4)));
+        assert_eq!(ratio(4, 9).sqrt(), Ok(ratio(2, 3)));
+        assert_eq!(Number::Fixnum(2).sqrt(), Ok(Number::Float(2f64.sqrt())));
+        assert_eq!(Number::Float(2.25).sqrt(), Ok(Number::Float(1.5)));
+    }
+
+    fn complex(r: Number, i: Number) -> Number {
+        Number::complex(r, i)
+    }
+
+    #[test]
+    fn test_complex_construction() {
+        assert_eq!(complex(Number::Fixnum(1), Number::Fixnum(0)), Number::Fixnum(1));
+        assert_eq!(complex(Number::Fixnum(1), Number::Float(0.)),
+                   Number::Complex(Box::new(Number::Float(1.)), Box::new(Number::Float(0.))));
+        assert_eq!(format!("{}", complex(Number::Fixnum(1), ratio(-1, 2))), "#C(1 -1/2)");
+    }
+
+    #[test]
+    fn test_complex_arithmetic() {
+        let i = complex(Number::Fixnum(0), Number::Fixnum(1));
+        let z = complex(Number::Fixnum(1), Number::Fixnum(2));
+
+        assert_eq!(&i * &i, Number::Fixnum(-1));
+        assert_eq!(&z + &Number::Fixnum(1), complex(Number::Fixnum(2), Number::Fixnum(2)));
+        assert_eq!(&z - &z, Number::Fixnum(0));
+        assert_eq!(z.checked_div(&i), Ok(complex(Number::Fixnum(2), Number::Fixnum(-1))));
+        assert_eq!(Number::Fixnum(1).checked_div(&z),
+                   Ok(complex(ratio(1, 5), ratio(-2, 5))));
+        assert_eq!(z.expt(&Number::Fixnum(2)), Ok(complex(Number::Fixnum(-3), Number::Fixnum(4))));
+        assert!(z.num_eq(&complex(Number::Fixnum(1), Number::Fixnum(2))));
+        assert_eq!(z.num_cmp(&z), None);
+    }
+
+    #[test]
+    fn test_complex_functions() {
+        let z = complex(Number::Fixnum(3), Number::Fixnum(4));
+
+        assert_eq!(Number::Fixnum(-1).sqrt(), Ok(complex(Number::Fixnum(0), Number::Fixnum(1))));
+        assert_eq!(Number::Fixnum(-4).sqrt(), Ok(complex(Number::Fixnum(0), Number::Fixnum(2))));
+        assert_eq!(z.sqrt(), Ok(complex(Number::Fixnum(2), Number::Fixnum(1))));
+        assert_eq!(z.conjugate().sqrt(), Ok(complex(Number::Fixnum(2), Number::Fixnum(-1))));
+        assert_eq!(z.abs(), Number::Float(5.));
+        assert_eq!(z.conjugate(), complex(Number::Fixnum(3), Number::Fixnum(-4)));
+        assert_eq!(Number::Fixnum(-1).phase(), ::std::f64::consts::PI);
+        assert_eq!(Number::Fixnum(-1).ln(),
+                   complex(Number::Float(0.), Number::Float(::std::f64::consts::PI)));
+
+        match complex(Number::Fixnum(0), Number::Float(::std::f64::consts::PI)).exp() {
+            Number::Complex(r, i) => {
+                assert!((r.to_f64() + 1.).abs() < 1e-12);
+                assert!(i.to_f64().abs() < 1e-12);
+            }
+            n => panic!("unexpected {}", n),
+        }
+    }
+
+    #[test]
+    fn test_expt() {
+        assert_eq!(Number::Fixnum(2).expt(&Number::Fixnum(10)), Ok(Number::Fixnum(1024)));
+        assert_eq!(Number::Fixnum(2).expt(&Number::Fixnum(-1)), Ok(ratio(1, 2)));
+        assert_eq!(Number::Fixnum(4).expt(&ratio(1, 2)), Ok(Number::Float(2.)));
+        assert_eq!(Number::Fixnum(0).expt(&Number::Fixnum(-1)),
+                   Err("Division by zero".to_string()));
+
+        let huge = Number::Fixnum(1_000_000_000_001);
+        assert_eq!(Number::Fixnum(2).expt(&huge),
+                   Err("The result of (EXPT 2 1000000000001) is too large".to_string()));
+        assert_eq!(Number::Float(2.).expt(&huge),
+                   Err("Floating point overflow in (EXPT 2.0 1000000000001)".to_string()));
+        assert_eq!(Number::Fixnum(-1).expt(&huge), Ok(Number::Fixnum(-1)));
+        assert_eq!(complex(Number::Fixnum(0), Number::Fixnum(1)).expt(&huge),
+                   Ok(complex(Number::Fixnum(0), Number::Fixnum(1))));
+        assert_eq!(Number::Fixnum(0).expt(&huge), Ok(Number::Fixnum(0)));
+        assert_eq!(complex(Number::Float(1.), Number::Float(1.)).expt(&Number::Fixnum(2)),
+                   Ok(complex(Number::Float(0.), Number::Float(2.))));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", Number::Fixnum(-12)), "-12");
+        assert_eq!(format!("{}", ratio(-1, 3)), "-1/3");
+        assert_eq!(format!("{}", Number::Float(1.)), "1.0");
+        assert_eq!(format!("{}", Number::Float(0.25)), "0.25");
+        assert_eq!(format!("{}", Number::Float(1e10)), "1.0e10");
+        assert_eq!(format!("{}", Number::Float(6.02e23)), "6.02e23");
+        assert_eq!(format!("{}", Number::Float(1.5e-7)), "1.5e-7");
+    }
+}
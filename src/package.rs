@@ -0,0 +1,469 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use super::env::{self, Env};
+use super::sexp::{self, Sexp, SexpResult};
+use super::symbol::Symbol;
+use super::types;
+
+/// How a symbol is accessible in a package, as `find-symbol` reports it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Internal,
+    External,
+    Inherited,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Status::Internal => "INTERNAL",
+            Status::External => "EXTERNAL",
+            Status::Inherited => "INHERITED",
+        }
+    }
+}
+
+/// A namespace of symbols. Symbols are present in a package when interned or
+/// imported into it, and a package also inherits the external symbols of the
+/// packages it uses.
+pub struct PackageData {
+    name: String,
+    symbols: RefCell<HashMap<String, Symbol>>,
+    external: RefCell<HashSet<String>>,
+    shadowing: RefCell<HashSet<String>>,
+    uses: RefCell<Vec<Package>>,
+    locked: bool,
+}
+
+pub type Package = Rc<PackageData>;
+
+thread_local! {
+    static PACKAGES: RefCell<HashMap<String, Package>> = RefCell::new(standard_packages());
+    static CURRENT: RefCell<Package> = RefCell::new(find_package("COMMON-LISP-USER").unwrap());
+}
+
+fn standard_packages() -> HashMap<String, Package> {
+    let cl = PackageData::new("COMMON-LISP", true);
+    // Type and class names are external too, so that every package using
+    // `common-lisp` names the same built-in classes.
+    let standard_names = sexp::SPECIAL_FORMS
+        .iter()
+        .chain(types::BUILT_IN_CLASSES)
+        .chain(types::OTHER_TYPES)
+        .chain(types::COMPOUND_TYPES)
+        .chain(sexp::LAMBDA_LIST_KEYWORDS)
+        .chain(&["PRINT-OBJECT"]);
+    for name in standard_names {
+        let symbol = cl.intern(name);
+        cl.export(&symbol);
+    }
+    let keyword = PackageData::new("KEYWORD", false);
+    let user = PackageData::new("COMMON-LISP-USER", false);
+    user.use_package(&cl).unwrap();
+
+    let mut packages = HashMap::new();
+    packages.insert("CL".to_string(), cl.clone());
+    packages.insert("CL-USER".to_string(), user.clone());
+    for p in [cl, keyword, user] {
+        packages.insert(p.name.clone(), p);
+    }
+    packages
+}
+
+impl PackageData {
+    fn new(name: &str, locked: bool) -> Package {
+        Rc::new(PackageData {
+            name: name.to_string(),
+            symbols: RefCell::new(HashMap::new()),
+            external: RefCell::new(HashSet::new()),
+            shadowing: RefCell::new(HashSet::new()),
+            uses: RefCell::new(vec![]),
+            locked,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_external(&self, name: &str) -> bool {
+        self.external.borrow().contains(name)
+    }
+
+    /// The symbol named `name` accessible in this package, preferring a
+    /// present symbol to an inherited one.
+    pub fn find_symbol(&self, name: &str) -> Option<(Symbol, Status)> {
+        if let Some(s) = self.symbols.borrow().get(name) {
+            let status = if self.is_external(name) { Status::External } else { Status::Internal };
+            return Some((s.clone(), status));
+        }
+
+        self.uses
+            .borrow()
+            .iter()
+            .filter(|p| p.is_external(name))
+            .filter_map(|p| p.symbols.borrow().get(name).cloned())
+            .map(|s| (s, Status::Inherited))
+            .next()
+    }
+
+    /// The symbol named `name` accessible in this package, creating one homed
+    /// here if there is none. Keywords are always external.
+    pub fn intern(&self, name: &str) -> Symbol {
+        if let Some((s, _)) = self.find_symbol(name) {
+            return s;
+        }
+
+        let symbol = Symbol::new_in(name, Some(&self.name));
+        self.symbols.borrow_mut().insert(name.to_string(), symbol.clone());
+        if self.name == "KEYWORD" {
+            self.external.borrow_mut().insert(name.to_string());
+        }
+        symbol
+    }
+
+    /// Makes `symbol` present in this package.
+    pub fn import(&self, symbol: &Symbol) {
+        self.symbols.borrow_mut().insert(symbol.name().to_string(), symbol.clone());
+    }
+
+    pub fn export(&self, symbol: &Symbol) {
+        self.import(symbol);
+        self.external.borrow_mut().insert(symbol.name().to_string());
+    }
+
+    /// Interns a new symbol named `name` here even if one is inherited, so
+    /// the inherited one is hidden.
+    pub fn shadow(&self, name: &str) {
+        if !self.symbols.borrow().contains_key(name) {
+            let symbol = Symbol::new_in(name, Some(&self.name));
+            self.symbols.borrow_mut().insert(name.to_string(), symbol);
+        }
+        self.shadowing.borrow_mut().insert(name.to_string());
+    }
+
+    /// Inherits the external symbols of `other`, unless one of them has the
+    /// name of a different symbol already accessible here and not shadowing.
+    pub fn use_package(&self, other: &Package) -> Result<(), String> {
+        if self.uses.borrow().iter().any(|p| Rc::ptr_eq(p, other)) {
+            return Ok(());
+        }
+
+        for name in other.external.borrow().iter() {
+            if self.shadowing.borrow().contains(name) {
+                continue;
+            }
+            let symbol = other.symbols.borrow().get(name).cloned();
+            match self.find_symbol(name) {
+                Some((s, _)) if Some(&s) != symbol.as_ref() => {
+                    return Err(format!("Using {} in {} makes {} conflict with {}:{}",
+                                       other.name, self.name, s, other.name, name));
+                }
+                _ => (),
+            }
+        }
+        self.uses.borrow_mut().push(other.clone());
+        Ok(())
+    }
+
+    /// Every symbol accessible in this package: those present, then those
+    /// inherited and not hidden by a present symbol of the same name.
+    pub fn accessible_symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.symbols.borrow().values().cloned().collect();
+        let mut names: HashSet<String> = self.symbols.borrow().keys().cloned().collect();
+
+        for p in self.uses.borrow().iter() {
+            for name in p.external.borrow().iter() {
+                if names.insert(name.clone()) {
+                    symbols.extend(p.symbols.borrow().get(name).cloned());
+                }
+            }
+        }
+        symbols
+    }
+
+    /// Exports every symbol present, as the built-ins in `common-lisp` are.
+    pub fn export_all(&self) {
+        let names = self.symbols.borrow().keys().cloned().collect::<Vec<_>>();
+        self.external.borrow_mut().extend(names);
+    }
+}
+
+impl PartialEq for PackageData {
+    fn eq(&self, other: &PackageData) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for PackageData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Package({})", self.name)
+    }
+}
+
+impl fmt::Display for PackageData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<PACKAGE \"{}\">", self.name)
+    }
+}
+
+pub fn find_package(name: &str) -> Option<Package> {
+    PACKAGES.with(|p| p.borrow().get(name).cloned())
+}
+
+/// The symbol named `name` in `common-lisp`, such as the name of a standard
+/// class.
+pub fn cl_symbol(name: &str) -> Symbol {
+    find_package("COMMON-LISP").unwrap().intern(name)
+}
+
+/// The name of `s` if it is in `common-lisp`, so that a standard name is only
+/// recognized as the standard symbol.
+pub fn cl_name(s: &Symbol) -> Option<&str> {
+    if s.home().is_some_and(|p| p.name() == "COMMON-LISP") { Some(s.name()) } else { None }
+}
+
+pub fn current() -> Package {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+pub fn set_current(package: Package) {
+    CURRENT.with(|c| *c.borrow_mut() = package);
+}
+
+/// Errors if `symbol` belongs to a locked package, such as `common-lisp`.
+pub fn check_lock(symbol: &Symbol) -> Result<(), String> {
+    match symbol.home() {
+        Some(ref p) if p.locked => {
+            Err(format!("Lock on package {} violated when defining {}", p.name, symbol))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The name a string designator stands for.
+fn designator_name(s: &Sexp) -> Result<String, String> {
+    match *s {
+        Sexp::Symbol(ref s) => Ok(s.name().to_string()),
+        Sexp::String(ref s) => Ok(s.to_string()),
+        Sexp::Char(c) => Ok(c.to_string()),
+        Sexp::Nil => Ok("NIL".to_string()),
+        Sexp::True => Ok("T".to_string()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// The package a package designator names.
+pub fn designated(s: &Sexp) -> Result<Package, String> {
+    if let Sexp::Package(ref p) = *s {
+        return Ok(p.clone());
+    }
+
+    let name = designator_name(s)?;
+    find_package(&name).ok_or_else(|| format!("Package {} does not exist", name))
+}
+
+fn keyword_name(s: &Sexp) -> Result<&str, String> {
+    match *s {
+        Sexp::Symbol(ref k) if k.is_keyword() => Ok(k.name()),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+fn option_names(option: &[Sexp]) -> Result<Vec<String>, String> {
+    option.iter().map(designator_name).collect()
+}
+
+/// `(defpackage name options...)` with the options `(:use packages...)`,
+/// `(:export names...)`, `(:import-from package names...)`, `(:shadow
+/// names...)` and `(:nicknames names...)`. Redefining a package adds to it.
+pub fn defpackage(v: &[Sexp], _env: &Env) -> SexpResult {
+    if v.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let name = designator_name(&v[1])?;
+    let package = find_package(&name).unwrap_or_else(|| {
+        let p = PackageData::new(&name, false);
+        PACKAGES.with(|packages| packages.borrow_mut().insert(name.clone(), p.clone()));
+        p
+    });
+
+    for option in &v[2..] {
+        let option = match *option {
+            Sexp::List(ref o) if !o.is_empty() => o,
+            ref o => return Err(format!("Argument error: {}", o)),
+        };
+        let args = &option[1..];
+
+        match keyword_name(&option[0])? {
+            "USE" => {
+                for p in args {
+                    package.use_package(&designated(p)?)?;
+                }
+            }
+            "EXPORT" => {
+                for name in option_names(args)? {
+                    let symbol = package.intern(&name);
+                    package.export(&symbol);
+                }
+            }
+            "IMPORT-FROM" if !args.is_empty() => {
+                let from = designated(&args[0])?;
+                for name in option_names(&args[1..])? {
+                    let symbol = from.find_symbol(&name)
+                        .ok_or_else(|| format!("Symbol {} not found in the {} package", name, from.name))?
+                        .0;
+                    package.import(&symbol);
+                }
+            }
+            "SHADOW" => {
+                for name in option_names(args)? {
+                    package.shadow(&name);
+                }
+            }
+            "NICKNAMES" => {
+                for nickname in option_names(args)? {
+                    PACKAGES.with(|p| p.borrow_mut().insert(nickname, package.clone()));
+                }
+            }
+            _ => return Err(format!("Argument error: {}", option[0])),
+        }
+    }
+
+    Ok(Sexp::Package(package))
+}
+
+/// `(in-package name)` makes the package current, both for the reader and as
+/// the value of `*package*`.
+pub fn in_package(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() != 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let package = designated(&v[1])?;
+    set_current(package.clone());
+    env::env_assign(env, cl_symbol("*PACKAGE*"), Sexp::Package(package.clone()));
+    Ok(Sexp::Package(package))
+}
+
+/// `(do-symbols (var [package [result]]) body...)` evaluates the body with
+/// `var` bound to each symbol accessible in the package.
+pub fn do_symbols(v: &[Sexp], env: &Env) -> SexpResult {
+    let spec = match v.get(1) {
+        Some(Sexp::List(spec)) if !spec.is_empty() && spec.len() <= 3 => spec,
+        Some(s) => return Err(format!("Argument error: {}", s)),
+        None => return Err("Invalid number of arguments: 0".to_string()),
+    };
+    let var = match spec[0] {
+        Sexp::Symbol(ref s) => s.clone(),
+        ref s => return Err(format!("Argument error: {}", s)),
+    };
+    let package = match spec.get(1) {
+        Some(p) => designated(&p.eval(env)?.primary())?,
+        None => current(),
+    };
+
+    let body_env = env::env_new(Some(env.clone()));
+    for symbol in package.accessible_symbols() {
+        env::env_set(&body_env, &var, Sexp::from_symbol(symbol));
+        sexp::eval_body(&v[2..], &body_env)?;
+    }
+
+    env::env_set(&body_env, &var, Sexp::Nil);
+    match spec.get(2) {
+        Some(result) => result.eval(&body_env),
+        None => Ok(Sexp::Nil),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::env;
+    use super::super::sexp::Sexp;
+    use super::super::symbol::Symbol;
+    use super::Status;
+    use super::cl_symbol;
+    use super::super::test_util::{symbol, eval_source};
+
+    #[test]
+    fn test_intern_and_inherit() {
+        let cl = super::find_package("CL").unwrap();
+        let user = super::find_package("COMMON-LISP-USER").unwrap();
+        let car = cl.intern("CAR");
+        cl.export(&car);
+
+        assert_eq!(user.find_symbol("CAR"), Some((car.clone(), Status::Inherited)));
+        assert_eq!(cl.find_symbol("CAR"), Some((car.clone(), Status::External)));
+        assert_eq!(user.intern("CAR"), car);
+        assert_eq!(super::check_lock(&car),
+                   Err("Lock on package COMMON-LISP violated when defining CAR".to_string()));
+        assert_eq!(user.find_symbol("NOT-THERE-YET"), None);
+        assert_eq!(user.intern("MINE").home(), Some(user));
+    }
+
+    #[test]
+    fn test_defpackage() {
+        let env = env::env_new(None);
+        let lib = Symbol::from("parse");
+        let result = super::defpackage(&[symbol("defpackage"),
+                                         symbol(":lib"),
+                                         Sexp::list(vec![symbol(":use"), symbol(":cl")]),
+                                         Sexp::list(vec![symbol(":export"), Sexp::string("PARSE")]),
+                                         Sexp::list(vec![symbol(":shadow"), symbol(":car")])],
+                                       &env);
+        let package = super::find_package("LIB").unwrap();
+
+        assert_eq!(result, Ok(Sexp::Package(package.clone())));
+        let parse = package.find_symbol("PARSE").unwrap();
+        assert_eq!(parse.1, Status::External);
+        assert!(parse.0 != lib);
+        assert_eq!(format!("{}", parse.0), "LIB:PARSE");
+        assert_eq!(package.find_symbol("CAR").unwrap().1, Status::Internal);
+        assert_eq!(super::defpackage(&[symbol("defpackage"),
+                                       symbol(":other"),
+                                       Sexp::list(vec![symbol(":use"), symbol(":nope")])],
+                                     &env),
+                   Err("Package NOPE does not exist".to_string()));
+    }
+
+    #[test]
+    fn test_defpackage_conflicts() {
+        let inverted = "(setf (readtable-case *readtable*) :invert) (defpackage :p (:use :cl))";
+        assert_eq!(eval_source(inverted), Ok("#<PACKAGE \"P\">".to_string()));
+
+        let exporters = "(defpackage :a (:use) (:export x)) (defpackage :b (:use) (:export x)) ";
+        assert_eq!(eval_source(&format!("{} (defpackage :c (:use :a :b))", exporters)),
+                   Err("Using B in C makes A:X conflict with B:X".to_string()));
+        assert_eq!(eval_source(&format!("{} (defpackage :c (:shadow x) (:use :a :b))", exporters)),
+                   Ok("#<PACKAGE \"C\">".to_string()));
+        assert_eq!(eval_source("(defpackage :c (:use :cl) (:use :cl))"),
+                   Ok("#<PACKAGE \"C\">".to_string()));
+    }
+
+    #[test]
+    fn test_keyword_package_prefix() {
+        assert_eq!(eval_source("'keyword:foo"), Ok(":FOO".to_string()));
+        assert_eq!(eval_source("(eq 'keyword::foo :foo)"), Ok("T".to_string()));
+    }
+
+    #[test]
+    fn test_in_package() {
+        let env = env::env_new(None);
+        super::defpackage(&[symbol("defpackage"), symbol(":app")], &env).unwrap();
+        let app = super::find_package("APP").unwrap();
+        let user_x = Symbol::from("x");
+
+        super::in_package(&[symbol("in-package"), symbol(":app")], &env).unwrap();
+        let app_x = Symbol::from("x");
+        assert_eq!(env::env_get(&env, cl_symbol("*PACKAGE*")), Some(Sexp::Package(app.clone())));
+        assert!(user_x != app_x);
+        assert_eq!(format!("{}", user_x), "COMMON-LISP-USER::X");
+        assert_eq!(format!("{}", app_x), "X");
+
+        super::in_package(&[symbol("in-package"), Sexp::string("CL-USER")], &env).unwrap();
+        assert_eq!(format!("{}", app_x), "APP::X");
+    }
+}
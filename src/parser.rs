@@ -1,167 +1,1328 @@
-use std::str::{self, FromStr};
-use super::sexp::Sexp;
-use nom::{is_alphanumeric, is_digit, multispace};
-
-named!(pub sexp<Sexp>, alt_complete!(number | string | symbol | list));
-
-named!(sign, alt!(tag!("-") | tag!("+")));
-
-named!(digits, take_while1!(is_digit));
-
-named!(integer_with_fractional,
-    recognize!(chain!(
-        opt!(sign) ~
-        digits ~
-        tag!(".") ~
-        opt!(digits),
-        || ()
-    )
-));
-
-named!(fractional,
-    recognize!(chain!(
-        opt!(sign) ~
-        tag!(".") ~
-        digits,
-        || ()
-    )
-));
-
-named!(integer,
-    recognize!(chain!(
-        opt!(sign) ~
-        digits,
-        || ()
-    )
-));
-
-named!(float<f64>, map_res!(
-    map_res!(
-        alt_complete!(
-            integer_with_fractional |
-            fractional |
-            integer
-        ),
-        str::from_utf8
-    ),
-    FromStr::from_str
-));
-
-named!(number<Sexp>, map!(
-    float,
-    Sexp::Number
-));
-
-named!(quote<char>, preceded!(char!('\\'), char!('"')));
-
-named!(non_quote<char>, none_of!("\""));
-
-named!(string<Sexp>, map!(
-    map_res!(
-        delimited!(
-            char!('"'),
-            recognize!(many0!(alt!(quote | non_quote))),
-            char!('"')
-        ),
-        str::from_utf8
-    ),
-    |s| Sexp::String(String::from_str(s).unwrap())
-));
-
-fn is_extended(chr: u8) -> bool {
-    "!$%&*+-./:<=>?@^_~".contains(chr as char)
-}
-
-fn is_extended_alphanumeric(chr: u8) -> bool {
-    is_alphanumeric(chr) || is_extended(chr)
-}
-
-named!(extended_alphanumeric, take_while1!(is_extended_alphanumeric));
-
-named!(symbol<Sexp>, map!(
-    map_res!(
-        extended_alphanumeric,
-        str::from_utf8
-    ),
-    |s| Sexp::Symbol(String::from_str(s).unwrap())
-));
-
-named!(list<Sexp>, map!(
-    delimited!(
-        preceded!(char!('('), opt!(multispace)),
-        separated_list!(multispace, sexp),
-        preceded!(opt!(multispace), char!(')'))
-    ),
-    |sexps| Sexp::List(sexps)
-));
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::str::FromStr;
+use super::array::Array;
+use super::env;
+use super::structure;
+use super::sexp::{Sexp, SexpResult};
+use super::number::Number;
+use super::package;
+use super::readtable::{self, Macro, ReadtableData};
+use super::stream::Stream;
+use super::symbol::Symbol;
+use super::character::char_from_name;
+use num::{BigInt, BigRational, Zero};
+
+const END_OF_FILE: &str = "End of file";
+
+thread_local! {
+    /// The objects labelled with `#n=` in the object being read, which
+    /// `#n#` refers to, and how deeply nested the calls to `read` are.
+    static LABELS: RefCell<HashMap<i64, Sexp>> = RefCell::new(HashMap::new());
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+
+    /// How many lists the object being read has open; after running out of
+    /// input, how many were left unclosed.
+    static OPEN_LISTS: Cell<usize> = const { Cell::new(0) };
+
+    /// Whether the reader is skipping a form excluded by `#+` or `#-`, and so
+    /// reads tokens as nil instead of interning symbols in packages that might
+    /// not exist.
+    static SUPPRESS: Cell<bool> = const { Cell::new(false) };
+}
+
+fn suppressing() -> bool {
+    SUPPRESS.with(Cell::get)
+}
+
+/// Reads the next object from `stream` with the current readtable, or
+/// returns `None` if there is only whitespace and comments left.
+pub fn read(stream: &Stream) -> Result<Option<Sexp>, String> {
+    let depth = DEPTH.with(|d| d.get());
+    if depth == 0 {
+        LABELS.with(|l| l.borrow_mut().clear());
+        OPEN_LISTS.with(|o| o.set(0));
+    }
+
+    DEPTH.with(|d| d.set(depth + 1));
+    let object = read_next(stream);
+    DEPTH.with(|d| d.set(depth));
+    object
+}
+
+fn read_next(stream: &Stream) -> Result<Option<Sexp>, String> {
+    loop {
+        match skip_whitespace(stream) {
+            Some(c) => {
+                if let Some(object) = read_from(stream, c)? {
+                    return Ok(Some(object));
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Reads an object that has to be there, as inside a list.
+pub fn read_object(stream: &Stream) -> SexpResult {
+    read(stream)?.ok_or_else(|| END_OF_FILE.to_string())
+}
+
+/// How many lists are open at the end of `input`, if reading it with the
+/// current readtable runs out of input and so it needs more lines to be a
+/// complete set of forms; `None` if it is complete, or has an error that
+/// reading it for real will report. Reading is suppressed as for `#+`, so
+/// nothing is interned or evaluated.
+pub fn unfinished_depth(input: &str) -> Option<usize> {
+    let stream = Stream::string_input(input);
+    let suppressed = SUPPRESS.with(|s| s.replace(true));
+    let depth = loop {
+        match read(&stream) {
+            Ok(Some(_)) => {}
+            Err(ref e) if e == END_OF_FILE => break Some(OPEN_LISTS.with(Cell::get)),
+            Ok(None) | Err(_) => break None,
+        }
+    };
+    SUPPRESS.with(|s| s.set(suppressed));
+
+    depth
+}
+
+fn skip_whitespace(stream: &Stream) -> Option<char> {
+    loop {
+        match stream.read_char() {
+            Some(c) if c.is_whitespace() => {}
+            c => return c,
+        }
+    }
+}
+
+fn next_char(stream: &Stream) -> Result<char, String> {
+    stream.read_char().ok_or_else(|| END_OF_FILE.to_string())
+}
+
+/// Reads what starts with `c`: calls its reader macro, which may return no
+/// object, as comments do, or reads a token.
+fn read_from(stream: &Stream, c: char) -> Result<Option<Sexp>, String> {
+    let readtable = readtable::current();
+    let values = match readtable.macro_character(c) {
+        Some((Macro::Function(f), _)) => f.apply(vec![Sexp::Stream(stream.clone()), Sexp::Char(c)])?,
+        Some((Macro::Dispatch(_), _)) => read_dispatch(vec![Sexp::Stream(stream.clone()), Sexp::Char(c)])?,
+        None => {
+            stream.unread_char();
+            return read_token(stream, &readtable).map(Some);
+        }
+    };
+
+    match values {
+        Sexp::Values(ref v) if v.is_empty() => Ok(None),
+        v => Ok(Some(v.primary())),
+    }
+}
+
+/// The reader macro function of a dispatching macro character: reads the
+/// optional decimal argument and the sub-character, and calls the function
+/// the readtable has for them.
+pub fn read_dispatch(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    let c = match args.get(1) {
+        Some(&Sexp::Char(c)) => c,
+        Some(v) => return Err(format!("Argument error: {}", v)),
+        None => return Err(format!("Invalid number of arguments: {}", args.len())),
+    };
+    let mut digits = String::new();
+    let mut sub = next_char(&stream)?;
+    while sub.is_ascii_digit() {
+        digits.push(sub);
+        sub = next_char(&stream)?;
+    }
+    let argument = match digits.parse::<i64>() {
+        Ok(n) => Sexp::Number(Number::Fixnum(n)),
+        Err(_) => Sexp::Nil,
+    };
+
+    let f = readtable::current().dispatch_macro_character(c, sub).ok_or_else(|| {
+        format!("No dispatch function defined for {}", Sexp::Char(sub))
+    })?;
+    f.apply(vec![Sexp::Stream(stream), Sexp::Char(sub), argument])
+}
+
+/// Reads the characters of a token up to whitespace, which is consumed, or a
+/// terminating macro character, keeping any `\` and `|` escapes, and whether
+/// there were any.
+fn read_raw_token(stream: &Stream, readtable: &ReadtableData) -> Result<(String, bool), String> {
+    let mut token = String::new();
+    let mut escaped = false;
+
+    while let Some(c) = stream.read_char() {
+        match c {
+            '\\' => {
+                token.push(c);
+                token.push(next_char(stream)?);
+                escaped = true;
+            }
+            '|' => {
+                token.push(c);
+                escaped = true;
+                loop {
+                    let c = next_char(stream)?;
+                    token.push(c);
+                    match c {
+                        '\\' => token.push(next_char(stream)?),
+                        '|' => break,
+                        _ => {}
+                    }
+                }
+            }
+            c if c.is_whitespace() => break,
+            c if readtable.is_terminating(c) => {
+                stream.unread_char();
+                break;
+            }
+            c => token.push(c),
+        }
+    }
+
+    Ok((token, escaped))
+}
+
+/// Reads a token as a number if it has the syntax of one, and otherwise as a
+/// symbol.
+fn read_token(stream: &Stream, readtable: &ReadtableData) -> SexpResult {
+    let (token, escaped) = read_raw_token(stream, readtable)?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+
+    match parse_decimal(&token) {
+        Some(n) if !escaped => n.map(Sexp::Number),
+        _ => Symbol::read(&token).map(Sexp::from_symbol),
+    }
+}
+
+/// Reads objects up to the character `end`. With `dotted`, a `.` on its own
+/// makes the object after it, which has to be the last, the tail.
+fn read_delimited(stream: &Stream,
+                  end: char,
+                  dotted: bool)
+                  -> Result<(Vec<Sexp>, Option<Sexp>), String> {
+    OPEN_LISTS.with(|o| o.set(o.get() + 1));
+    let mut objects = vec![];
+    let mut tail = None;
+    loop {
+        let c = skip_whitespace(stream).ok_or_else(|| END_OF_FILE.to_string())?;
+        if c == end {
+            OPEN_LISTS.with(|o| o.set(o.get() - 1));
+            return Ok((objects, tail));
+        }
+
+        if tail.is_some() {
+            if read_from(stream, c)?.is_some() {
+                return Err("More than one object follows . in list".to_string());
+            }
+        } else if dotted && c == '.' && is_consing_dot(stream) {
+            if objects.is_empty() {
+                return Err("Nothing appears before . in list".to_string());
+            }
+            tail = Some(read_object(stream)?);
+        } else {
+            objects.extend(read_from(stream, c)?);
+        }
+    }
+}
+
+/// Whether the `.` just read is on its own, as the consing dot, rather than
+/// part of a token such as `.5` or `...`.
+fn is_consing_dot(stream: &Stream) -> bool {
+    let readtable = readtable::current();
+    readtable.macro_character('.').is_none() &&
+    stream.peek_char().is_none_or(|c| c.is_whitespace() || readtable.is_terminating(c))
+}
+
+/// The stream a reader macro function is called with.
+fn stream_arg(args: &[Sexp]) -> Result<Stream, String> {
+    match args.first() {
+        Some(Sexp::Stream(s)) if s.is_input() => Ok(s.clone()),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => Err("Invalid number of arguments: 0".to_string()),
+    }
+}
+
+fn no_values() -> SexpResult {
+    Ok(Sexp::Values(vec![]))
+}
+
+/// Reads the next object and wraps it as `(name object)`, with `name` in
+/// `common-lisp` whatever the current package.
+fn wrap(args: &[Sexp], name: &str) -> SexpResult {
+    let head = Sexp::Symbol(package::cl_symbol(name));
+    Ok(Sexp::list(vec![head, read_object(&stream_arg(args)?)?]))
+}
+
+fn read_list(args: Vec<Sexp>) -> SexpResult {
+    Ok(match read_delimited(&stream_arg(&args)?, ')', true)? {
+        (objects, Some(tail)) => Sexp::dotted_list(objects, tail),
+        (ref objects, None) if objects.is_empty() => Sexp::Nil,
+        (objects, None) => Sexp::list(objects),
+    })
+}
+
+fn read_right_paren(_: Vec<Sexp>) -> SexpResult {
+    Err("Unmatched close parenthesis".to_string())
+}
+
+fn read_quote(args: Vec<Sexp>) -> SexpResult {
+    wrap(&args, "QUOTE")
+}
+
+fn read_comment(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    while let Some(c) = stream.read_char() {
+        if c == '\n' {
+            break;
+        }
+    }
+
+    no_values()
+}
+
+/// Reads a string up to the closing `"`, where `\` escapes the next
+/// character.
+fn read_string(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    let mut s = String::new();
+    loop {
+        match next_char(&stream)? {
+            '"' => return Ok(Sexp::string(s)),
+            '\\' => s.push(next_char(&stream)?),
+            c => s.push(c),
+        }
+    }
+}
+
+fn read_backquote(args: Vec<Sexp>) -> SexpResult {
+    wrap(&args, "QUASIQUOTE")
+}
+
+/// `,form` or `,@form` inside a backquote.
+fn read_comma(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    if stream.peek_char() == Some('@') {
+        stream.read_char();
+        return wrap(&args, "UNQUOTE-SPLICING");
+    }
+
+    wrap(&args, "UNQUOTE")
+}
+
+fn read_function(args: Vec<Sexp>) -> SexpResult {
+    wrap(&args, "FUNCTION")
+}
+
+fn read_vector(args: Vec<Sexp>) -> SexpResult {
+    let (elements, _) = read_delimited(&stream_arg(&args)?, ')', false)?;
+
+    Ok(Sexp::Array(Rc::new(RefCell::new(Array::vector(elements)))))
+}
+
+/// Reads `#\x` character syntax. The character after the backslash is taken
+/// literally, and if more constituent characters follow it the whole token is
+/// treated as a character name such as `Space` or `U+1F600`.
+fn read_character(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    let mut name = next_char(&stream)?.to_string();
+    let readtable = readtable::current();
+    while let Some(c) = stream.read_char() {
+        if readtable.is_terminating(c) {
+            stream.unread_char();
+            break;
+        }
+        name.push(c);
+    }
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+
+    char_from_name(&name)
+        .map(Sexp::Char)
+        .ok_or_else(|| format!("Unrecognized character name: {}", name))
+}
+
+/// Skips a `#| ... |#` comment, which may be nested.
+fn read_block_comment(args: Vec<Sexp>) -> SexpResult {
+    let stream = stream_arg(&args)?;
+    let mut depth = 1;
+    let mut previous = ' ';
+    while depth > 0 {
+        let c = next_char(&stream)?;
+        match (previous, c) {
+            ('|', '#') => {
+                depth -= 1;
+                previous = ' ';
+                continue;
+            }
+            ('#', '|') => {
+                depth += 1;
+                previous = ' ';
+                continue;
+            }
+            _ => previous = c,
+        }
+    }
+
+    no_values()
+}
+
+fn read_complex(args: Vec<Sexp>) -> SexpResult {
+    let parts = read_object(&stream_arg(&args)?)?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+
+    complex_from_parts(&parts).ok_or_else(|| format!("Illegal complex number format: #C{}", parts))
+}
+
+fn read_struct(args: Vec<Sexp>) -> SexpResult {
+    let contents = read_object(&stream_arg(&args)?)?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+    let structure = match contents {
+        Sexp::List(ref v) => structure::read(v),
+        _ => None,
+    };
+
+    structure.ok_or_else(|| format!("Illegal structure syntax: #S{}", contents))
+}
+
+/// The decimal argument of a dispatch macro, such as the rank in `#2A`.
+fn numeric_argument(args: &[Sexp]) -> Option<i64> {
+    match args.get(2) {
+        Some(&Sexp::Number(Number::Fixnum(n))) => Some(n),
+        _ => None,
+    }
+}
+
+fn read_rational(args: &[Sexp], radix: u32) -> SexpResult {
+    let stream = stream_arg(args)?;
+    let (token, _) = read_raw_token(&stream, &readtable::current())?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+
+    parse_rational(&token, radix)
+        .ok_or_else(|| format!("{} is not a number in radix {}", token, radix))?
+        .map(Sexp::Number)
+}
+
+fn read_hexadecimal(args: Vec<Sexp>) -> SexpResult {
+    read_rational(&args, 16)
+}
+
+fn read_octal(args: Vec<Sexp>) -> SexpResult {
+    read_rational(&args, 8)
+}
+
+fn read_binary(args: Vec<Sexp>) -> SexpResult {
+    read_rational(&args, 2)
+}
+
+/// `#nRdigits` reads a rational in radix `n`.
+fn read_radix(args: Vec<Sexp>) -> SexpResult {
+    match numeric_argument(&args) {
+        Some(radix) if (2..=36).contains(&radix) => read_rational(&args, radix as u32),
+        _ => Err("Illegal radix for #R".to_string()),
+    }
+}
+
+/// `#nA(...)` reads an array of rank `n` from nested lists.
+fn read_array(args: Vec<Sexp>) -> SexpResult {
+    let contents = read_object(&stream_arg(&args)?)?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+    let rank = numeric_argument(&args).ok_or_else(|| "Missing rank for #A".to_string())?;
+
+    Array::from_nested(rank as usize, &contents)
+        .map(|a| Sexp::Array(Rc::new(RefCell::new(a))))
+        .ok_or_else(|| format!("Illegal array contents for rank {}: {}", rank, contents))
+}
+
+/// `#:name` reads a new uninterned symbol.
+fn read_uninterned(args: Vec<Sexp>) -> SexpResult {
+    let readtable = readtable::current();
+    let (token, _) = read_raw_token(&stream_arg(&args)?, &readtable)?;
+    let chars = readtable.case().convert(&readtable::token_chars(&token)?);
+
+    Ok(Sexp::Symbol(Symbol::uninterned(&chars.into_iter().map(|(c, _)| c).collect::<String>())))
+}
+
+/// `#.form` reads as the value of `form`, unless `*read-eval*` is nil.
+fn read_eval(args: Vec<Sexp>) -> SexpResult {
+    let form = read_object(&stream_arg(&args)?)?;
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+    let env = env::global().ok_or_else(|| "There is no environment to evaluate #. in".to_string())?;
+
+    match env::env_get(&env, package::cl_symbol("*READ-EVAL*")) {
+        Some(Sexp::Nil) => Err(format!("Can't read #.{} while *read-eval* is nil", form)),
+        _ => form.eval(&env).map(Sexp::primary),
+    }
+}
+
+/// `#n=object` reads the object and labels it for `#n#` to refer to, even
+/// from inside it.
+fn read_label(args: Vec<Sexp>) -> SexpResult {
+    if suppressing() {
+        return read_object(&stream_arg(&args)?);
+    }
+    let n = numeric_argument(&args).ok_or_else(|| "Missing label for #=".to_string())?;
+    if LABELS.with(|l| l.borrow().contains_key(&n)) {
+        return Err(format!("Label #{}= is already defined", n));
+    }
+
+    let placeholder = Sexp::Symbol(Symbol::uninterned(&format!("#{}#", n)));
+    LABELS.with(|l| l.borrow_mut().insert(n, placeholder.clone()));
+    let object = read_object(&stream_arg(&args)?)?;
+    if object == placeholder {
+        return Err(format!("Label #{}= can't label only itself", n));
+    }
+
+    let mut copies = HashMap::new();
+    let object = with_cells(&object, &placeholder, &mut copies)?.unwrap_or(object);
+    fill_placeholder(&object, &placeholder, &object, &mut HashSet::new())?;
+    LABELS.with(|l| {
+        for labelled in l.borrow_mut().values_mut() {
+            if let Some(Some(copy)) = labelled.identity().and_then(|id| copies.get(&id)) {
+                *labelled = copy.clone();
+            }
+        }
+        l.borrow_mut().insert(n, object.clone());
+    });
+    Ok(object)
+}
+
+/// Copies the lists in `s` that hold `placeholder`, directly or through other
+/// lists, as cons cells, whose elements can be replaced; gives the copy `s`
+/// becomes, if any. `copies` records those made of what was already seen, so
+/// that shared lists stay shared and cycles end.
+fn with_cells(s: &Sexp,
+              placeholder: &Sexp,
+              copies: &mut HashMap<usize, Option<Sexp>>)
+              -> Result<Option<Sexp>, String> {
+    if let Some(id) = s.identity() {
+        if let Some(copy) = copies.get(&id) {
+            return Ok(copy.clone());
+        }
+        copies.insert(id, None);
+    }
+
+    let copy = match *s {
+        Sexp::List(ref l) => {
+            let mut elements = l.to_vec();
+            let mut changed = false;
+            for element in &mut elements {
+                if element == placeholder {
+                    changed = true;
+                } else if let Some(e) = with_cells(element, placeholder, copies)? {
+                    *element = e;
+                    changed = true;
+                }
+            }
+            if changed {
+                Some(elements.into_iter().rev().fold(Sexp::Nil, |cdr, car| Sexp::cons(car, cdr)))
+            } else {
+                None
+            }
+        }
+        Sexp::Cons(ref c) => {
+            let (car, cdr) = (c.borrow().car().clone(), c.borrow().cdr().clone());
+            if let Some(car) = with_cells(&car, placeholder, copies)? {
+                c.borrow_mut().set_car(car);
+            }
+            if let Some(cdr) = with_cells(&cdr, placeholder, copies)? {
+                c.borrow_mut().set_cdr(cdr);
+            }
+            None
+        }
+        Sexp::Array(ref a) => {
+            let elements = a.borrow().elements();
+            for (i, element) in elements.iter().enumerate() {
+                if let Some(e) = with_cells(element, placeholder, copies)? {
+                    a.borrow_mut().set(i, e)?;
+                }
+            }
+            None
+        }
+        Sexp::Struct(ref s) => {
+            let slots = s.borrow().slots().to_vec();
+            for (i, slot) in slots.iter().enumerate() {
+                if let Some(e) = with_cells(slot, placeholder, copies)? {
+                    s.borrow_mut().set_slot(i, e);
+                }
+            }
+            None
+        }
+        _ => None,
+    };
+
+    if let Some(id) = s.identity() {
+        copies.insert(id, copy.clone());
+    }
+    Ok(copy)
+}
+
+/// Replaces `placeholder` with `object` in the cons cells, vectors and
+/// structures in `s`, which after `with_cells` are all that can hold it.
+fn fill_placeholder(s: &Sexp,
+                    placeholder: &Sexp,
+                    object: &Sexp,
+                    seen: &mut HashSet<usize>)
+                    -> Result<(), String> {
+    if let Some(id) = s.identity() {
+        if !seen.insert(id) {
+            return Ok(());
+        }
+    }
+
+    match *s {
+        Sexp::List(ref l) => {
+            for element in l.iter() {
+                fill_placeholder(element, placeholder, object, seen)?;
+            }
+        }
+        Sexp::Cons(ref c) => {
+            let (car, cdr) = (c.borrow().car().clone(), c.borrow().cdr().clone());
+            if car == *placeholder {
+                c.borrow_mut().set_car(object.clone());
+            } else {
+                fill_placeholder(&car, placeholder, object, seen)?;
+            }
+            if cdr == *placeholder {
+                c.borrow_mut().set_cdr(object.clone());
+            } else {
+                fill_placeholder(&cdr, placeholder, object, seen)?;
+            }
+        }
+        Sexp::Array(ref a) => {
+            let elements = a.borrow().elements();
+            for (i, element) in elements.iter().enumerate() {
+                if element == placeholder {
+                    a.borrow_mut().set(i, object.clone())?;
+                } else {
+                    fill_placeholder(element, placeholder, object, seen)?;
+                }
+            }
+        }
+        Sexp::Struct(ref s) => {
+            let slots = s.borrow().slots().to_vec();
+            for (i, slot) in slots.iter().enumerate() {
+                if slot == placeholder {
+                    s.borrow_mut().set_slot(i, object.clone());
+                } else {
+                    fill_placeholder(slot, placeholder, object, seen)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `#n#` reads as the object labelled `#n=`.
+fn read_reference(args: Vec<Sexp>) -> SexpResult {
+    if suppressing() {
+        return Ok(Sexp::Nil);
+    }
+    let n = numeric_argument(&args).ok_or_else(|| "Missing label for ##".to_string())?;
+
+    LABELS.with(|l| l.borrow().get(&n).cloned())
+        .ok_or_else(|| format!("Reference to undefined label #{}#", n))
+}
+
+/// The value of `*features*`.
+fn features() -> Vec<Sexp> {
+    match env::global().and_then(|env| env::env_get(&env, package::cl_symbol("*FEATURES*"))) {
+        Some(Sexp::List(features)) => features.to_vec(),
+        _ => vec![],
+    }
+}
+
+/// Whether the feature expression `feature` holds: a keyword in `features`,
+/// or `(and ...)`, `(or ...)` or `(not ...)` of feature expressions.
+fn feature_holds(feature: &Sexp, features: &[Sexp]) -> Result<bool, String> {
+    let (operator, operands) = match *feature {
+        Sexp::Symbol(_) | Sexp::Nil => return Ok(features.contains(feature)),
+        Sexp::List(ref l) if l.is_empty() => return Ok(features.contains(&Sexp::Nil)),
+        Sexp::List(ref l) => (&l[0], &l[1..]),
+        ref v => return Err(format!("Illegal feature expression: {}", v)),
+    };
+
+    match *operator {
+        Sexp::Symbol(ref s) if s.is_keyword() => {
+            match (s.name(), operands) {
+                ("AND", _) => {
+                    for operand in operands {
+                        if !feature_holds(operand, features)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                ("OR", _) => {
+                    for operand in operands {
+                        if feature_holds(operand, features)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                ("NOT", [operand]) => feature_holds(operand, features).map(|holds| !holds),
+                _ => Err(format!("Illegal feature expression: {}", feature)),
+            }
+        }
+        _ => Err(format!("Illegal feature expression: {}", feature)),
+    }
+}
+
+/// `#+feature form` reads as the form if the feature expression, read as
+/// keywords, holds, and `#-feature form` if it doesn't; otherwise the form is
+/// skipped. The feature expression is read normally even inside a skipped
+/// form.
+fn read_feature_conditional(args: &[Sexp], wanted: bool) -> SexpResult {
+    let stream = stream_arg(args)?;
+    let package = package::current();
+    let suppressed = SUPPRESS.with(|s| s.replace(false));
+    package::set_current(package::find_package("KEYWORD").unwrap());
+    let feature = read_object(&stream);
+    package::set_current(package);
+    SUPPRESS.with(|s| s.set(suppressed));
+    let feature = feature?;
+
+    if feature_holds(&feature, &features())? == wanted {
+        return read_object(&stream);
+    }
+
+    SUPPRESS.with(|s| s.set(true));
+    let skipped = read_object(&stream);
+    SUPPRESS.with(|s| s.set(suppressed));
+    skipped?;
+    no_values()
+}
+
+fn read_feature_present(args: Vec<Sexp>) -> SexpResult {
+    read_feature_conditional(&args, true)
+}
+
+fn read_feature_absent(args: Vec<Sexp>) -> SexpResult {
+    read_feature_conditional(&args, false)
+}
+
+/// A reader macro function built into the reader.
+type ReaderMacro = fn(Vec<Sexp>) -> SexpResult;
+
+/// Gives a new readtable the standard syntax: the macro characters below,
+/// with `#` dispatching on the character after it.
+pub fn install_standard_macros(readtable: &ReadtableData) {
+    let macros: &[(char, ReaderMacro)] = &[('(', read_list),
+                                           (')', read_right_paren),
+                                           ('\'', read_quote),
+                                           (';', read_comment),
+                                           ('"', read_string),
+                                           ('`', read_backquote),
+                                           (',', read_comma)];
+    for &(c, f) in macros {
+        readtable.set_macro_character(c, Sexp::BuiltInFunc(f), false);
+    }
+
+    let dispatch: &[(char, ReaderMacro)] = &[('\'', read_function),
+                                             ('(', read_vector),
+                                             ('\\', read_character),
+                                             ('|', read_block_comment),
+                                             ('C', read_complex),
+                                             ('S', read_struct),
+                                             ('X', read_hexadecimal),
+                                             ('O', read_octal),
+                                             ('B', read_binary),
+                                             ('R', read_radix),
+                                             ('A', read_array),
+                                             (':', read_uninterned),
+                                             ('.', read_eval),
+                                             ('=', read_label),
+                                             ('#', read_reference),
+                                             ('+', read_feature_present),
+                                             ('-', read_feature_absent)];
+    readtable.make_dispatch_macro_character('#', true);
+    for &(c, f) in dispatch {
+        readtable.set_dispatch_macro_character('#', c, Sexp::BuiltInFunc(f)).unwrap();
+    }
+}
+
+fn complex_from_parts(parts: &Sexp) -> Option<Sexp> {
+    match *parts {
+        Sexp::List(ref v) if v.len() == 2 => {
+            match (&v[0], &v[1]) {
+                (Sexp::Number(r), Sexp::Number(i)) if !r.is_complex() && !i.is_complex() => {
+                    Some(Sexp::Number(Number::complex(r.clone(), i.clone())))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn split_sign(token: &str) -> (bool, &str) {
+    if let Some(rest) = token.strip_prefix('-') {
+        (true, rest)
+    } else if let Some(rest) = token.strip_prefix('+') {
+        (false, rest)
+    } else {
+        (false, token)
+    }
+}
+
+pub fn parse_unsigned(digits: &str, radix: u32) -> Option<BigInt> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    BigInt::parse_bytes(digits.as_bytes(), radix)
+}
+
+/// Parses `[sign] digits [/ digits]` in the given radix, giving None if the
+/// token isn't one, and an error for a zero denominator.
+fn parse_rational(token: &str, radix: u32) -> Option<Result<Number, String>> {
+    let (negative, unsigned) = split_sign(token);
+    let mut parts = unsigned.splitn(2, '/');
+    let mut numerator = parse_unsigned(parts.next().unwrap(), radix)?;
+    if negative {
+        numerator = -numerator;
+    }
+
+    let denominator = match parts.next() {
+        None => return Some(Ok(Number::from_bigint(numerator))),
+        Some(d) => parse_unsigned(d, radix)?,
+    };
+    if denominator.is_zero() {
+        return Some(Err(format!("Division by zero reading {}", token)));
+    }
+    Some(Ok(Number::from_ratio(BigRational::new(numerator, denominator))))
+}
+
+fn is_exponent_marker(c: char) -> bool {
+    "esfdlESFDL".contains(c)
+}
+
+/// Parses a decimal float such as `1.5`, `.5`, `1e10` or `6.02d23`, where any
+/// of Common Lisp's exponent markers may introduce the exponent. One too big
+/// for a double is an error.
+fn parse_float(token: &str) -> Option<Result<Number, String>> {
+    let (negative, unsigned) = split_sign(token);
+    let (mantissa, exponent) = match unsigned.find(is_exponent_marker) {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if !all_digits(integer) || !fraction.is_none_or(all_digits) {
+        return None;
+    }
+
+    let fraction = fraction.unwrap_or("");
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let exponent = match exponent {
+        None => "0",
+        Some(e) => {
+            let (_, digits) = split_sign(e);
+            if digits.is_empty() || !all_digits(digits) {
+                return None;
+            }
+            e
+        }
+    };
+
+    let n = f64::from_str(&format!("{}.{}e{}", integer, fraction, exponent)).ok()?;
+    if n.is_infinite() {
+        return Some(Err(format!("Floating point overflow reading {}", token)));
+    }
+    Some(Ok(Number::Float(if negative { -n } else { n })))
+}
+
+/// Parses a token using Common Lisp's decimal number syntax: integers (with
+/// an optional trailing decimal point), ratios and floats. A token with that
+/// syntax whose number can't be made is an error.
+pub fn parse_decimal(token: &str) -> Option<Result<Number, String>> {
+    let integer = token.strip_suffix('.').filter(|t| !t.contains('/'));
+
+    integer.and_then(|t| parse_rational(t, 10))
+        .or_else(|| parse_rational(token, 10))
+        .or_else(|| parse_float(token))
+}
+
 
 #[cfg(test)]
 mod tests {
-    use super::super::sexp::Sexp;
-    use super::{sexp, list, string, symbol, number};
-    use nom::IResult::Done;
+    use super::super::sexp::{Sexp, SexpResult};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::super::array::Array;
+    use super::super::env;
+    use super::super::package;
+    use super::super::readtable::{self, Case};
+    use super::super::stream::Stream;
+    use super::super::structure;
+    use super::super::symbol::Symbol;
+    use super::super::number::Number;
+    use super::super::test_util::{int, ratio, float, eval_source};
+
+    /// Reads the first object of `s`.
+    fn read(s: &str) -> SexpResult {
+        super::read_object(&Stream::string_input(s))
+    }
+
+    /// Reads the first object of `s` and returns what is left unread.
+    fn read_rest(s: &str) -> (SexpResult, String) {
+        let stream = Stream::string_input(s);
+        let object = super::read_object(&stream);
+        let mut rest = String::new();
+        while let Some(c) = stream.read_char() {
+            rest.push(c);
+        }
+        (object, rest)
+    }
 
     #[test]
     fn test_number() {
-        assert_eq!(number(b"12"), Done(&b""[..], Sexp::Number(12.)));
-        assert_eq!(number(b"+12"), Done(&b""[..], Sexp::Number(12.)));
-        assert_eq!(number(b"-12"), Done(&b""[..], Sexp::Number(-12.)));
-        assert_eq!(number(b"12."), Done(&b""[..], Sexp::Number(12.)));
-        assert_eq!(number(b"12.34"), Done(&b""[..], Sexp::Number(12.34)));
-        assert_eq!(number(b"-12.34"), Done(&b""[..], Sexp::Number(-12.34)));
-        assert_eq!(number(b".34"), Done(&b""[..], Sexp::Number(0.34)));
-        assert_eq!(number(b"-.34"), Done(&b""[..], Sexp::Number(-0.34)));
+        assert_eq!(read("12"), Ok(int(12)));
+        assert_eq!(read("+12"), Ok(int(12)));
+        assert_eq!(read("-12"), Ok(int(-12)));
+        assert_eq!(read("12."), Ok(int(12)));
+        assert_eq!(read("12.34"), Ok(float(12.34)));
+        assert_eq!(read("-12.34"), Ok(float(-12.34)));
+        assert_eq!(read(".34"), Ok(float(0.34)));
+        assert_eq!(read("-.34"), Ok(float(-0.34)));
+        assert_eq!(read("12.0"), Ok(float(12.)));
+        assert_eq!(read("99999999999999999999"),
+                   Ok(Sexp::Number(Number::Bignum("99999999999999999999".parse().unwrap()))));
+    }
+
+    #[test]
+    fn test_number_with_exponent() {
+        assert_eq!(read("1e10"), Ok(float(1e10)));
+        assert_eq!(read("1E10"), Ok(float(1e10)));
+        assert_eq!(read("6.02d23"), Ok(float(6.02e23)));
+        assert_eq!(read("1.5e400"), Err("Floating point overflow reading 1.5e400".to_string()));
+        assert_eq!(read("-1e999"), Err("Floating point overflow reading -1e999".to_string()));
+        assert_eq!(read("-1.5e-3"), Ok(float(-1.5e-3)));
+        assert_eq!(read("1.e+2"), Ok(float(100.)));
+        assert_eq!(read(".5s0"), Ok(float(0.5)));
+        assert_eq!(read("2f1"), Ok(float(20.)));
+        assert_eq!(read("2l1"), Ok(float(20.)));
+        assert_eq!(read("1e"), Ok(Sexp::symbol("1e")));
+        assert_eq!(read("e10"), Ok(Sexp::symbol("e10")));
+        assert_eq!(read(".e10"), Ok(Sexp::symbol(".e10")));
+    }
+
+    #[test]
+    fn test_number_with_radix() {
+        assert_eq!(read("#x1F"), Ok(int(31)));
+        assert_eq!(read("#X-1f"), Ok(int(-31)));
+        assert_eq!(read("#b1010"), Ok(int(10)));
+        assert_eq!(read("#o17"), Ok(int(15)));
+        assert_eq!(read("#36rZZ"), Ok(int(1295)));
+        assert_eq!(read("#3r-12"), Ok(int(-5)));
+        assert_eq!(read("#x1/2"), Ok(ratio(1, 2)));
+        assert_eq!(read("#2r101"), Ok(int(5)));
+        assert_eq!(read("#b102"), Err("102 is not a number in radix 2".to_string()));
+        assert_eq!(read("#37r1"), Err("Illegal radix for #R".to_string()));
+        assert!(read("#1r1").is_err());
+    }
+
+    #[test]
+    fn test_number_with_ratio() {
+        assert_eq!(read("1/4"), Ok(ratio(1, 4)));
+        assert_eq!(read("-3/4"), Ok(ratio(-3, 4)));
+        assert_eq!(read("+1/2"), Ok(ratio(1, 2)));
+        assert_eq!(read("4/6"), Ok(ratio(2, 3)));
+        assert_eq!(read("4/2"), Ok(int(2)));
+        assert_eq!(read("1/0"), Err("Division by zero reading 1/0".to_string()));
+        assert_eq!(read("#x-1/0"), Err("Division by zero reading -1/0".to_string()));
+        assert_eq!(read("|1/0|"), Ok(Sexp::symbol("|1/0|")));
+        assert_eq!(read("1/"), Ok(Sexp::symbol("1/")));
+        assert_eq!(read("/2"), Ok(Sexp::symbol("/2")));
+        assert_eq!(read("1/2."), Ok(Sexp::symbol("1/2.")));
+    }
+
+    #[test]
+    fn test_complex() {
+        assert_eq!(read("#C(1 2)"),
+                   Ok(Sexp::Number(Number::complex(Number::Fixnum(1), Number::Fixnum(2)))));
+        assert_eq!(read("#c(1/2 -1.5)"),
+                   Ok(Sexp::Number(Number::complex(Number::Float(0.5), Number::Float(-1.5)))));
+        assert_eq!(read("#C(1 0)"), Ok(int(1)));
+        assert_eq!(read("#C(1)"), Err("Illegal complex number format: #C(1)".to_string()));
+        assert!(read("#C(1 a)").is_err());
+        assert!(read("#C(1 #C(1 2))").is_err());
+    }
+
+    #[test]
+    fn test_character() {
+        assert_eq!(read("#\\a"), Ok(Sexp::Char('a')));
+        assert_eq!(read_rest("#\\A)"), (Ok(Sexp::Char('A')), ")".to_string()));
+        assert_eq!(read("#\\("), Ok(Sexp::Char('(')));
+        assert_eq!(read("#\\ "), Ok(Sexp::Char(' ')));
+        assert_eq!(read("#\\Space"), Ok(Sexp::Char(' ')));
+        assert_eq!(read("#\\newline"), Ok(Sexp::Char('\n')));
+        assert_eq!(read("#\\U+1F600"), Ok(Sexp::Char('\u{1F600}')));
+        assert_eq!(read("#\\é"), Ok(Sexp::Char('é')));
+        assert_eq!(read("#\\bogus"), Err("Unrecognized character name: bogus".to_string()));
+        assert_eq!(read("#\\"), Err("End of file".to_string()));
     }
 
     #[test]
     fn test_string() {
-        assert_eq!(string(b"\"\""), Done(&b""[..], Sexp::String("".to_string())));
-        assert_eq!(string(b"\"string\""), Done(&b""[..], Sexp::String("string".to_string())));
-        assert_eq!(
-            string(b"\"str\\\"ing\""),
-            Done(&b""[..], Sexp::String("str\\\"ing".to_string()))
-        );
+        assert_eq!(read("\"\""), Ok(Sexp::string("")));
+        assert_eq!(read("\"string\""), Ok(Sexp::string("string")));
+        assert_eq!(read("\"str\\\"ing\""), Ok(Sexp::string("str\"ing")));
+        assert_eq!(read("\"a\\\\b\""), Ok(Sexp::string("a\\b")));
+        assert_eq!(read("\"open"), Err("End of file".to_string()));
     }
 
     #[test]
     fn test_symbol() {
-        assert_eq!(symbol(b"sym"), Done(&b""[..], Sexp::Symbol("sym".to_string())));
-        assert_eq!(symbol(b"sym12"), Done(&b""[..], Sexp::Symbol("sym12".to_string())));
-        assert_eq!(symbol(b"12sym"), Done(&b""[..], Sexp::Symbol("12sym".to_string())));
-        assert_eq!(symbol(b"sym!"), Done(&b""[..], Sexp::Symbol("sym!".to_string())));
-        assert_eq!(symbol(b"!sym"), Done(&b""[..], Sexp::Symbol("!sym".to_string())));
+        assert_eq!(read("sym"), Ok(Sexp::symbol("sym")));
+        assert_eq!(read("sym12"), Ok(Sexp::symbol("sym12")));
+        assert_eq!(read("12sym"), Ok(Sexp::symbol("12sym")));
+        assert_eq!(read("sym!"), Ok(Sexp::symbol("sym!")));
+        assert_eq!(read("!sym"), Ok(Sexp::symbol("!sym")));
+        assert_eq!(read("a#b"), Ok(Sexp::symbol("a#b")));
+        assert_eq!(read_rest("sym)"), (Ok(Sexp::symbol("sym")), ")".to_string()));
+        assert_eq!(read_rest("sym  x"), (Ok(Sexp::symbol("sym")), " x".to_string()));
+    }
+
+    #[test]
+    fn test_qualified_symbol() {
+        assert_eq!(read(":key"), Ok(Sexp::symbol(":key")));
+        assert_eq!(read("cl-user::sym"), Ok(Sexp::symbol("sym")));
+        assert!(read("cl-user:sym").is_err());
+        assert!(read("no-such-package:sym").is_err());
+    }
+
+    #[test]
+    fn test_escaped_symbol() {
+        let mixed = Sexp::Symbol(Symbol::intern("Mixed Case"));
+
+        assert_eq!(read("|Mixed Case|"), Ok(mixed.clone()));
+        assert_eq!(read("Mixed\\ Case"), Ok(Sexp::Symbol(Symbol::intern("MIXED CASE"))));
+        assert_eq!(read("(|a) b|)"), Ok(Sexp::list(vec![Sexp::Symbol(Symbol::intern("a) b"))])));
+        assert_eq!(read("|x:y|"), Ok(Sexp::Symbol(Symbol::intern("x:y"))));
+        assert_eq!(read("|12|"), Ok(Sexp::Symbol(Symbol::intern("12"))));
+        assert_eq!(read("|open"), Err("End of file".to_string()));
+    }
+
+    #[test]
+    fn test_uninterned_symbol() {
+        match read("#:foo") {
+            Ok(Sexp::Symbol(s)) => {
+                assert_eq!(s.name(), "FOO");
+                assert!(s.home().is_none());
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_readtable_case() {
+        readtable::current().set_case(Case::Preserve);
+        let preserved = read("Foo");
+        readtable::current().set_case(Case::Downcase);
+        let downcased = read("Foo");
+        readtable::current().set_case(Case::Upcase);
+
+        assert_eq!(preserved, Ok(Sexp::Symbol(Symbol::intern("Foo"))));
+        assert_eq!(downcased, Ok(Sexp::Symbol(Symbol::intern("foo"))));
     }
 
     #[test]
     fn test_list() {
-        assert_eq!(list(b"()"), Done(&b""[..], Sexp::List(vec![])));
-        assert_eq!(list(b"(())"), Done(&b""[..], Sexp::List(vec![Sexp::List(vec![])])));
-        assert_eq!(list(b"(a)"), Done(&b""[..], Sexp::List(vec![Sexp::Symbol("a".to_string())])));
-        assert_eq!(
-            list(b"(\ta\t)"),
-            Done(&b""[..], Sexp::List(vec![Sexp::Symbol("a".to_string())]))
-        );
-        assert_eq!(
-            list(b"(a b)"),
-            Done(
-                &b""[..],
-                Sexp::List(vec![Sexp::Symbol("a".to_string()), Sexp::Symbol("b".to_string())])
-            )
-        );
-        assert_eq!(
-            list(b"(a\t\tb)"),
-            Done(
-                &b""[..],
-                Sexp::List(vec![Sexp::Symbol("a".to_string()), Sexp::Symbol("b".to_string())])
-            )
-        );
-    }
-
-    #[test]
-    fn test_sexp() {
-        assert_eq!(sexp(b"12"), Done(&b""[..], Sexp::Number(12.)));
-        assert_eq!(sexp(b"\"\""), Done(&b""[..], Sexp::String("".to_string())));
-        assert_eq!(sexp(b"sym"), Done(&b""[..], Sexp::Symbol("sym".to_string())));
-        assert_eq!(sexp(b"()"), Done(&b""[..], Sexp::List(vec![])));
+        assert_eq!(read("()"), Ok(Sexp::Nil));
+        assert_eq!(read("(())"), Ok(Sexp::list(vec![Sexp::Nil])));
+        assert_eq!(read("(a)"), Ok(Sexp::list(vec![Sexp::symbol("a")])));
+        assert_eq!(read("(\ta\t)"), Ok(Sexp::list(vec![Sexp::symbol("a")])));
+        assert_eq!(read("(a b)"), Ok(Sexp::list(vec![Sexp::symbol("a"), Sexp::symbol("b")])));
+        assert_eq!(read("(a\t\tb)"), Ok(Sexp::list(vec![Sexp::symbol("a"), Sexp::symbol("b")])));
+        assert_eq!(read("(a(b)\"c\")"),
+                   Ok(Sexp::list(vec![Sexp::symbol("a"),
+                                      Sexp::list(vec![Sexp::symbol("b")]),
+                                      Sexp::string("c")])));
+        assert_eq!(read("(a"), Err("End of file".to_string()));
+        assert_eq!(read(")"), Err("Unmatched close parenthesis".to_string()));
+    }
+
+    #[test]
+    fn test_nil_and_t() {
+        assert_eq!(eval_source("(null 'nil)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(eq nil 'nil)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(eq 't t)"), Ok("T".to_string()));
+        assert_eq!(eval_source("(if (elt '(nil) 0) 1 2)"), Ok("2".to_string()));
+        assert_eq!(eval_source("(if '() 1 2)"), Ok("2".to_string()));
+        assert_eq!(eval_source("(equal '(nil) (list nil))"), Ok("T".to_string()));
+        assert_eq!(eval_source("(mapcar #'null '(nil () a))"), Ok("(T T NIL)".to_string()));
+        assert_eq!(eval_source("'(1 . (2 . nil))"), Ok("(1 2)".to_string()));
+        assert_eq!(eval_source("(length '(1 . (2 . nil)))"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn test_standard_macros_outside_common_lisp() {
+        let bare = "(defpackage :bare (:use)) (in-package :bare) ";
+        assert_eq!(eval_source(&format!("{}'x", bare)), Ok("X".to_string()));
+        assert_eq!(eval_source(&format!("{}(cl:funcall #'cl:+ 1 2)", bare)), Ok("3".to_string()));
+        assert_eq!(eval_source(&format!("{}`(a ,(cl:+ 1 2) ,@(cl:list 4))", bare)),
+                   Ok("(A 3 4)".to_string()));
+    }
+
+    #[test]
+    fn test_dotted_list() {
+        assert_eq!(read("(a . b)"), Ok(Sexp::cons(Sexp::symbol("a"), Sexp::symbol("b"))));
+        assert_eq!(read("(a . (b))"), Ok(Sexp::list(vec![Sexp::symbol("a"), Sexp::symbol("b")])));
+        assert_eq!(read("(a . ())"), Ok(Sexp::list(vec![Sexp::symbol("a")])));
+        assert_eq!(format!("{}", read("(a b . c)").unwrap()), "(A B . C)");
+        assert_eq!(read("(a .b)"), Ok(Sexp::list(vec![Sexp::symbol("a"), Sexp::symbol(".b")])));
+        assert_eq!(read("( . a)"), Err("Nothing appears before . in list".to_string()));
+        assert_eq!(read("(a . b c)"), Err("More than one object follows . in list".to_string()));
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(read("; comment\n1"), Ok(int(1)));
+        assert_eq!(read("(1 ; two\n 3)"), Ok(Sexp::list(vec![int(1), int(3)])));
+        assert_eq!(read("#| a #| nested |# comment |# 2"), Ok(int(2)));
+        assert_eq!(super::read(&Stream::string_input("  ; only a comment")), Ok(None));
+    }
+
+    #[test]
+    fn test_vector() {
+        let vector_of = |v| Sexp::Array(Rc::new(RefCell::new(Array::vector(v))));
+
+        assert_eq!(read("#()"), Ok(vector_of(vec![])));
+        assert_eq!(read("#(1 \"a\")"), Ok(vector_of(vec![int(1), Sexp::string("a")])));
+        assert_eq!(read("#(1 #(2))"), Ok(vector_of(vec![int(1), vector_of(vec![int(2)])])));
+    }
+
+    #[test]
+    fn test_array() {
+        let nested = Sexp::list(vec![Sexp::list(vec![int(1), int(2)]),
+                                     Sexp::list(vec![int(3), int(4)])]);
+        let matrix = Array::from_nested(2, &nested).unwrap();
+
+        assert_eq!(read("#2A((1 2) (3 4))"), Ok(Sexp::Array(Rc::new(RefCell::new(matrix)))));
+        assert!(read("#2A((1 2) (3))").is_err());
+        assert_eq!(read("#A(1)"), Err("Missing rank for #A".to_string()));
+    }
+
+    #[test]
+    fn test_struct_literal() {
+        let env = env::env_new(None);
+        structure::defstruct(&[Sexp::symbol("defstruct"),
+                               Sexp::symbol("pt"),
+                               Sexp::symbol("x")],
+                             &env)
+            .unwrap();
+
+        assert_eq!(read("#S(pt :x 1)").map(|s| format!("{}", s)), Ok("#S(PT :X 1)".to_string()));
+        assert!(read("#S(undefined :x 1)").is_err());
+    }
+
+    #[test]
+    fn test_function() {
+        assert_eq!(read("#'car"),
+                   Ok(Sexp::list(vec![Sexp::symbol("function"), Sexp::symbol("car")])));
+        assert_eq!(read("#%"), Err("No dispatch function defined for #\\%".to_string()));
+    }
+
+    #[test]
+    fn test_quoted() {
+        let quote = |s| Sexp::list(vec![Sexp::symbol("quote"), s]);
+
+        assert_eq!(read("'a"), Ok(quote(Sexp::symbol("a"))));
+        assert_eq!(read("'(1 'b)"), Ok(quote(Sexp::list(vec![int(1), quote(Sexp::symbol("b"))]))));
+    }
+
+    #[test]
+    fn test_backquote() {
+        let wrap = |name, s| Sexp::list(vec![Sexp::symbol(name), s]);
+
+        assert_eq!(read("`(a ,b ,@c)"),
+                   Ok(wrap("quasiquote",
+                           Sexp::list(vec![Sexp::symbol("a"),
+                                           wrap("unquote", Sexp::symbol("b")),
+                                           wrap("unquote-splicing", Sexp::symbol("c"))]))));
+    }
+
+    #[test]
+    fn test_user_macro_characters() {
+        let standard = readtable::current();
+        let readtable = standard.copy();
+        readtable.set_macro_character('!', Sexp::BuiltInFunc(super::read_quote), false);
+        readtable.set_dispatch_macro_character('#', '?', Sexp::BuiltInFunc(super::read_comment))
+            .unwrap();
+        readtable::set_current(readtable);
+        let quoted = read("(a!b)");
+        let skipped = read("#?skipped\n3");
+        readtable::set_current(standard);
+
+        assert_eq!(quoted,
+                   Ok(Sexp::list(vec![Sexp::symbol("a"),
+                                      Sexp::list(vec![Sexp::symbol("quote"), Sexp::symbol("b")])])));
+        assert_eq!(skipped, Ok(int(3)));
+        assert_eq!(read("a!b"), Ok(Sexp::symbol("a!b")));
+    }
+
+    #[test]
+    fn test_read_eval() {
+        let env = env::env_new(None);
+        env::env_set(&env, "x", int(2));
+        readtable::use_env(&env);
+
+        assert_eq!(read("(a #.x)"), Ok(Sexp::list(vec![Sexp::symbol("a"), int(2)])));
+        env::env_set(&env, package::cl_symbol("*READ-EVAL*"), Sexp::Nil);
+        assert_eq!(read("#.x"), Err("Can't read #.X while *read-eval* is nil".to_string()));
+
+        assert_eq!(eval_source("(setf *read-eval* nil)
+                                (defpackage :bare (:use))
+                                (in-package :bare)
+                                (cl:read-from-string \"#.(cl:+ 1 2)\")"),
+                   Err("Can't read #.(COMMON-LISP:+ 1 2) while *read-eval* is nil".to_string()));
+    }
+
+    #[test]
+    fn test_labels() {
+        let shared = read("(#1=(a) #1# #2=b #2#)").unwrap();
+        let elements = match shared {
+            Sexp::List(ref l) => l.to_vec(),
+            ref v => panic!("unexpected {}", v),
+        };
+        assert_eq!(elements[0].identity(), elements[1].identity());
+        assert_eq!(elements[2], elements[3]);
+
+        let cyclic = read("#1=#(1 (x #1#))").unwrap();
+        let inner = match cyclic {
+            Sexp::Array(ref a) => a.borrow().get(1),
+            ref v => panic!("unexpected {}", v),
+        };
+        let rest = match inner {
+            Sexp::Cons(ref c) => c.borrow().cdr().clone(),
+            ref v => panic!("unexpected {}", v),
+        };
+        match rest {
+            Sexp::Cons(ref c) => assert_eq!(c.borrow().car().identity(), cyclic.identity()),
+            ref v => panic!("unexpected {}", v),
+        }
+
+        let circular = read("#1=(a . #1#)").unwrap();
+        match circular {
+            Sexp::Cons(ref c) => assert_eq!(c.borrow().cdr().identity(), circular.identity()),
+            ref v => panic!("unexpected {}", v),
+        }
+        assert_eq!(read("#1=#1#"), Err("Label #1= can't label only itself".to_string()));
+        assert_eq!(read("(#1=a #1=b)"), Err("Label #1= is already defined".to_string()));
+        assert_eq!(read("#3#"), Err("Reference to undefined label #3#".to_string()));
+        assert_eq!(read("#=a"), Err("Missing label for #=".to_string()));
+    }
+
+    #[test]
+    fn test_print_circle_round_trip() {
+        let env = env::env_new(None);
+        env::env_set(&env, package::cl_symbol("*PRINT-CIRCLE*"), Sexp::True);
+        env::set_global(&env);
+        let round_trip = |s: &str| format!("{}", read(s).unwrap());
+
+        for printed in &["(#1=(A) #1#)",
+                         "#1=#(1 #1#)",
+                         "#1=#(#2=(X #1#) #2#)",
+                         "(A (B) (B))",
+                         "#1=(A . #1#)",
+                         "#1=(A #1#)",
+                         "(#1=(A . B) #1#)",
+                         "(QUOTE #1=(A #1#))"] {
+            assert_eq!(round_trip(printed), *printed);
+            assert_eq!(round_trip(&round_trip(printed)), *printed);
+        }
+        assert_eq!(round_trip("'#1=(a #1#)"), "(QUOTE #1=(A #1#))");
+    }
+
+    #[test]
+    fn test_feature_expressions() {
+        let env = env::env_new(None);
+        env::env_set(&env,
+                     package::cl_symbol("*FEATURES*"),
+                     Sexp::list(vec![Sexp::symbol(":rust-lisp"), Sexp::symbol(":linux")]));
+        env::set_global(&env);
+
+        assert_eq!(read("(#+rust-lisp 1 #-rust-lisp 2 #+sbcl (sb-ext:quit) 3)"),
+                   Ok(Sexp::list(vec![int(1), int(3)])));
+        assert_eq!(read("#+(and linux (not sbcl)) a"), Ok(Sexp::symbol("a")));
+        assert_eq!(read("#+(or sbcl ccl) a b"), Ok(Sexp::symbol("b")));
+        assert_eq!(read("#-(or sbcl (and rust-lisp windows)) a"), Ok(Sexp::symbol("a")));
+        assert_eq!(read("#+sbcl #+rust-lisp a b"), Ok(Sexp::symbol("b")));
+        assert_eq!(read("(#+() a b)"), Ok(Sexp::list(vec![Sexp::symbol("b")])));
+        assert_eq!(read("(#-() a)"), Ok(Sexp::list(vec![Sexp::symbol("a")])));
+        assert_eq!(read("#+sbcl (#.(error) #\\no-such-char #x2g) c"), Ok(Sexp::symbol("c")));
+        assert_eq!(read("#+(xor a) b"), Err("Illegal feature expression: (:XOR :A)".to_string()));
+        assert_eq!(read("#+(not a b) c"),
+                   Err("Illegal feature expression: (:NOT :A :B)".to_string()));
+    }
+
+    #[test]
+    fn test_unfinished_depth() {
+        assert_eq!(super::unfinished_depth("(+ 1 2)"), None);
+        assert_eq!(super::unfinished_depth("(defun f (x)\n"), Some(1));
+        assert_eq!(super::unfinished_depth("(a (b"), Some(2));
+        assert_eq!(super::unfinished_depth("(a) (b"), Some(1));
+        assert_eq!(super::unfinished_depth("\"open ("), Some(0));
+        assert_eq!(super::unfinished_depth("(f \"a\\\" (\")"), None);
+        assert_eq!(super::unfinished_depth("(f #\\( ; (\n)"), None);
+        assert_eq!(super::unfinished_depth("#| ( #| |# |# x"), None);
+        assert_eq!(super::unfinished_depth("#| #| |# ("), Some(0));
+        assert_eq!(super::unfinished_depth("(|a (| b"), Some(1));
+        assert_eq!(super::unfinished_depth("a)"), None);
+        assert_eq!(super::unfinished_depth("#.(no-such-function)"), None);
+        assert_eq!(super::unfinished_depth("#(1 #2A((1"), Some(3));
+
+        let standard = readtable::current();
+        let readtable = standard.copy();
+        readtable.set_macro_character('%', Sexp::BuiltInFunc(super::read_comment), false);
+        readtable::set_current(readtable);
+        let commented = super::unfinished_depth("(a % )\n");
+        readtable::set_current(standard);
+        assert_eq!(commented, Some(1));
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(read("12"), Ok(int(12)));
+        assert_eq!(read("#x10"), Ok(int(16)));
+        assert_eq!(read("#C(0 1)"),
+                   Ok(Sexp::Number(Number::complex(Number::Fixnum(0), Number::Fixnum(1)))));
+        assert_eq!(read("1+"), Ok(Sexp::symbol("1+")));
+        assert_eq!(read("12sym"), Ok(Sexp::symbol("12sym")));
+        assert_eq!(read("+"), Ok(Sexp::symbol("+")));
+        assert_eq!(read("\"\""), Ok(Sexp::string("")));
+        assert_eq!(read("()"), Ok(Sexp::Nil));
+        assert_eq!(read("nil"), Ok(Sexp::Nil));
+        assert_eq!(read("t"), Ok(Sexp::True));
+        assert_eq!(read("#\\x"), Ok(Sexp::Char('x')));
+        assert_eq!(read("(#\\a #\\))"), Ok(Sexp::list(vec![Sexp::Char('a'), Sexp::Char(')')])));
+        assert_eq!(read(""), Err("End of file".to_string()));
     }
 }
@@ -0,0 +1,56 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The state of a splitmix64 generator, which `random` advances.
+pub struct RandomStateData {
+    state: Cell<u64>,
+}
+
+pub type RandomState = Rc<RandomStateData>;
+
+impl RandomStateData {
+    fn with_seed(seed: u64) -> RandomState {
+        Rc::new(RandomStateData { state: Cell::new(seed) })
+    }
+
+    /// A state seeded from the clock.
+    pub fn fresh() -> RandomState {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64);
+        RandomStateData::with_seed(seed.unwrap_or(0))
+    }
+
+    /// A state that will generate the same numbers as this one.
+    pub fn copy(&self) -> RandomState {
+        RandomStateData::with_seed(self.state.get())
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let next = self.state.get().wrapping_add(0x9e3779b97f4a7c15);
+        self.state.set(next);
+
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl PartialEq for RandomStateData {
+    fn eq(&self, other: &RandomStateData) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for RandomStateData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RandomState({})", self.state.get())
+    }
+}
+
+impl fmt::Display for RandomStateData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<RANDOM-STATE>")
+    }
+}
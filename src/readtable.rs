@@ -0,0 +1,331 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use super::env::{self, Env};
+use super::package;
+use super::parser;
+use super::sexp::Sexp;
+
+/// How the reader treats the case of unescaped characters in symbol names:
+/// `:upcase` and `:downcase` convert them, `:preserve` leaves them alone and
+/// `:invert` flips the case of names written all in one case.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Case {
+    Upcase,
+    Downcase,
+    Preserve,
+    Invert,
+}
+
+impl Case {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Case::Upcase => "UPCASE",
+            Case::Downcase => "DOWNCASE",
+            Case::Preserve => "PRESERVE",
+            Case::Invert => "INVERT",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Case> {
+        match name {
+            "UPCASE" => Some(Case::Upcase),
+            "DOWNCASE" => Some(Case::Downcase),
+            "PRESERVE" => Some(Case::Preserve),
+            "INVERT" => Some(Case::Invert),
+            _ => None,
+        }
+    }
+
+    /// Converts the unescaped characters of a token read with this case.
+    pub fn convert(&self, chars: &[(char, bool)]) -> Vec<(char, bool)> {
+        let unescaped = chars.iter().filter(|&&(_, escaped)| !escaped).map(|&(c, _)| c);
+        let convert: fn(char) -> char = match *self {
+            Case::Upcase => upcase,
+            Case::Downcase => downcase,
+            Case::Preserve => |c| c,
+            Case::Invert if single_case(unescaped) => invert,
+            Case::Invert => |c| c,
+        };
+
+        chars.iter()
+            .map(|&(c, escaped)| if escaped { (c, true) } else { (convert(c), false) })
+            .collect()
+    }
+}
+
+fn upcase(c: char) -> char {
+    c.to_uppercase().next().unwrap_or(c)
+}
+
+fn downcase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn invert(c: char) -> char {
+    if c.is_uppercase() { downcase(c) } else { upcase(c) }
+}
+
+/// Whether the letters among `chars` are all uppercase or all lowercase.
+fn single_case<I: Iterator<Item = char>>(chars: I) -> bool {
+    let (mut upper, mut lower) = (false, false);
+    for c in chars {
+        upper |= c.is_uppercase();
+        lower |= c.is_lowercase();
+    }
+
+    !(upper && lower)
+}
+
+/// What the reader does on seeing a macro character: call a function of the
+/// stream and the character, or read another character and call the function
+/// dispatched on for it with the stream, that character and the decimal
+/// argument written between them, if any.
+#[derive(Clone)]
+pub enum Macro {
+    Function(Sexp),
+    Dispatch(HashMap<char, Sexp>),
+}
+
+/// The reader's syntax: how it converts case, and its macro characters, each
+/// with whether it is non-terminating, that is, can appear inside a token.
+pub struct ReadtableData {
+    case: Cell<Case>,
+    macros: RefCell<HashMap<char, (Macro, bool)>>,
+}
+
+pub type Readtable = Rc<ReadtableData>;
+
+thread_local! {
+    static CURRENT: RefCell<Readtable> = RefCell::new(standard());
+}
+
+impl ReadtableData {
+    fn new() -> Readtable {
+        Rc::new(ReadtableData {
+            case: Cell::new(Case::Upcase),
+            macros: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn case(&self) -> Case {
+        self.case.get()
+    }
+
+    pub fn set_case(&self, case: Case) {
+        self.case.set(case);
+    }
+
+    pub fn copy(&self) -> Readtable {
+        let copy = ReadtableData::new();
+        self.copy_into(&copy);
+        copy
+    }
+
+    /// Replaces the syntax of `to` with a copy of this readtable's.
+    pub fn copy_into(&self, to: &ReadtableData) {
+        let macros = self.macros.borrow().clone();
+        to.case.set(self.case());
+        *to.macros.borrow_mut() = macros;
+    }
+
+    pub fn macro_character(&self, c: char) -> Option<(Macro, bool)> {
+        self.macros.borrow().get(&c).cloned()
+    }
+
+    /// Whether `c` ends a token, as whitespace and terminating macro
+    /// characters do.
+    pub fn is_terminating(&self, c: char) -> bool {
+        c.is_whitespace() || self.macros.borrow().get(&c).is_some_and(|&(_, non)| !non)
+    }
+
+    pub fn set_macro_character(&self, c: char, function: Sexp, non_terminating: bool) {
+        self.macros.borrow_mut().insert(c, (Macro::Function(function), non_terminating));
+    }
+
+    pub fn make_dispatch_macro_character(&self, c: char, non_terminating: bool) {
+        self.macros.borrow_mut().insert(c, (Macro::Dispatch(HashMap::new()), non_terminating));
+    }
+
+    /// Sets the function `c` followed by `sub` calls; `sub` is
+    /// case-insensitive.
+    pub fn set_dispatch_macro_character(&self, c: char, sub: char, function: Sexp) -> Result<(), String> {
+        match self.macros.borrow_mut().get_mut(&c) {
+            Some(&mut (Macro::Dispatch(ref mut functions), _)) => {
+                functions.insert(upcase(sub), function);
+                Ok(())
+            }
+            _ => Err(format!("{} is not a dispatching macro character", Sexp::Char(c))),
+        }
+    }
+
+    pub fn dispatch_macro_character(&self, c: char, sub: char) -> Option<Sexp> {
+        match self.macros.borrow().get(&c) {
+            Some(&(Macro::Dispatch(ref functions), _)) => functions.get(&upcase(sub)).cloned(),
+            _ => None,
+        }
+    }
+}
+
+/// A new readtable with the standard syntax.
+pub fn standard() -> Readtable {
+    let readtable = ReadtableData::new();
+    parser::install_standard_macros(&readtable);
+    readtable
+}
+
+impl PartialEq for ReadtableData {
+    fn eq(&self, other: &ReadtableData) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for ReadtableData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Readtable({:?})", self.case())
+    }
+}
+
+impl fmt::Display for ReadtableData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<READTABLE>")
+    }
+}
+
+/// The readtable the reader and printer use.
+pub fn current() -> Readtable {
+    CURRENT.with(|r| r.borrow().clone())
+}
+
+pub fn set_current(readtable: Readtable) {
+    CURRENT.with(|r| *r.borrow_mut() = readtable);
+}
+
+/// Makes the value of `*readtable*` in `env` the current readtable, so that
+/// setting the variable takes effect from the next read, and `env` the
+/// global environment `#.` evaluates in.
+pub fn use_env(env: &Env) {
+    if let Some(Sexp::Readtable(readtable)) =
+        env::env_get(env, package::cl_symbol("*READTABLE*")) {
+        set_current(readtable);
+    }
+    env::set_global(env);
+}
+
+/// Splits a symbol token into its characters, each marked with whether it
+/// was escaped by `\` or by being between `|`s.
+pub fn token_chars(token: &str) -> Result<Vec<(char, bool)>, String> {
+    let mut chars = vec![];
+    let mut in_bars = false;
+    let mut iter = token.chars();
+
+    while let Some(c) = iter.next() {
+        match c {
+            '\\' => {
+                let escaped = iter.next().ok_or_else(|| format!("Unterminated escape in {}", token))?;
+                chars.push((escaped, true));
+            }
+            '|' => in_bars = !in_bars,
+            c => chars.push((c, in_bars)),
+        }
+    }
+
+    if in_bars {
+        return Err(format!("Unterminated | in {}", token));
+    }
+    Ok(chars)
+}
+
+/// Whether `c` can appear unescaped in a symbol name.
+pub fn is_constituent(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!$%&*+-./<=>?@^_~".contains(c)
+}
+
+/// How `name` prints so that reading it with the current readtable gives the
+/// same name back: converted for `:invert`, and between `|`s if it has
+/// characters the reader would convert or not take as part of a symbol. With
+/// `escape` false, as for `princ`, the `|`s are left out.
+pub fn print_name(name: &str, escape: bool) -> String {
+    let case = current().case();
+    let printed: String = match case {
+        Case::Invert if single_case(name.chars()) => name.chars().map(invert).collect(),
+        _ => name.to_string(),
+    };
+    if !escape {
+        return printed;
+    }
+
+    let converts = |c: char| {
+        match case {
+            Case::Upcase => c.is_lowercase(),
+            Case::Downcase => c.is_uppercase(),
+            Case::Preserve | Case::Invert => false,
+        }
+    };
+    let needs_bars = name.is_empty() || name.chars().all(|c| c == '.') ||
+                     name.chars().any(|c| !is_constituent(c) || converts(c)) ||
+                     parser::parse_decimal(name).is_some();
+    if !needs_bars {
+        return printed;
+    }
+
+    let mut escaped = "|".to_string();
+    for c in name.chars() {
+        if c == '|' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('|');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{current, print_name, token_chars, Case};
+    use super::super::sexp::Sexp;
+
+    #[test]
+    fn test_convert() {
+        let chars = token_chars("ab|Cd|").unwrap();
+        let name = |case: Case| case.convert(&chars).into_iter().map(|(c, _)| c).collect::<String>();
+
+        assert_eq!(name(Case::Upcase), "ABCd");
+        assert_eq!(name(Case::Downcase), "abCd");
+        assert_eq!(name(Case::Preserve), "abCd");
+        assert_eq!(name(Case::Invert), "ABCd");
+        assert_eq!(token_chars("a\\b"), Ok(vec![('a', false), ('b', true)]));
+        assert_eq!(token_chars("|ab"), Err("Unterminated | in |ab".to_string()));
+    }
+
+    #[test]
+    fn test_print_name() {
+        assert_eq!(print_name("FOO", true), "FOO");
+        assert_eq!(print_name("Mixed Case", true), "|Mixed Case|");
+        assert_eq!(print_name("Mixed Case", false), "Mixed Case");
+        assert_eq!(print_name("12", true), "|12|");
+        assert_eq!(print_name("A|B", true), "|A\\|B|");
+
+        current().set_case(Case::Invert);
+        assert_eq!(print_name("FOO", true), "foo");
+        assert_eq!(print_name("Foo", true), "Foo");
+        current().set_case(Case::Upcase);
+    }
+
+    #[test]
+    fn test_copy_and_dispatch() {
+        let standard = super::standard();
+        let copy = standard.copy();
+        copy.set_macro_character('!', Sexp::Nil, false);
+        copy.make_dispatch_macro_character('$', true);
+
+        assert!(standard.macro_character('!').is_none());
+        assert!(copy.is_terminating('!'));
+        assert!(!copy.is_terminating('$'));
+        assert_eq!(copy.set_dispatch_macro_character('$', 'a', Sexp::True), Ok(()));
+        assert_eq!(copy.dispatch_macro_character('$', 'A'), Some(Sexp::True));
+        assert_eq!(copy.set_dispatch_macro_character('!', 'a', Sexp::True),
+                   Err("#\\! is not a dispatching macro character".to_string()));
+    }
+}
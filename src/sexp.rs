@@ -1,5 +1,20 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::rc::Rc;
 use super::env::{self, Env};
+use super::number::Number;
+use super::character::char_name;
+use super::stream::Stream;
+use super::hash_table::HashTable;
+use super::array::Array;
+use super::structure::{self, Structure};
+use super::clos::{self, Instance};
+use super::package::{self, Package};
+use super::random::RandomState;
+use super::readtable::Readtable;
+use super::symbol::Symbol;
+use super::types;
 
 macro_rules! extract_value {
     ($src:expr, $t:path) => {
@@ -20,36 +35,295 @@ macro_rules! extract_values {
             Sexp::List(ref v) => {
                 v.iter().map(|i| { extract_value!(*i, $t) }).collect()
             },
+            Sexp::Nil => Ok(vec![]),
             ref v => Err(format!("Argument error: {}", v))
         }
     }
 }
 
+/// A parameter after `&optional` or `&key`: `name`, `(name default)` or
+/// `(name default supplied-p)`, where `supplied-p` is bound to whether an
+/// argument was given.
+#[derive(PartialEq, Debug, Clone)]
+struct Parameter {
+    name: Symbol,
+    default: Sexp,
+    supplied: Option<Symbol>,
+}
+
+impl Parameter {
+    fn parse(param: &Sexp) -> Result<Parameter, String> {
+        match *param {
+            Sexp::Symbol(ref name) => {
+                Ok(Parameter { name: name.clone(), default: Sexp::Nil, supplied: None })
+            }
+            Sexp::List(ref v) if !v.is_empty() && v.len() <= 3 => {
+                Ok(Parameter {
+                    name: extract_value!(v[0], Sexp::Symbol)?,
+                    default: v.get(1).cloned().unwrap_or(Sexp::Nil),
+                    supplied: match v.get(2) {
+                        Some(s) => Some(extract_value!(*s, Sexp::Symbol)?),
+                        None => None,
+                    },
+                })
+            }
+            ref p => Err(format!("Argument error: {}", p)),
+        }
+    }
+
+    /// Binds the parameter to `value`, or to its default if there is none.
+    fn bind(&self, value: Option<&Sexp>, env: &Env) -> Result<(), String> {
+        let (value, supplied) = match value {
+            Some(v) => (v.clone(), Sexp::True),
+            None => (self.default.eval(env)?.primary(), Sexp::Nil),
+        };
+        env::env_set(env, &self.name, value);
+        if let Some(ref name) = self.supplied {
+            env::env_set(env, name, supplied);
+        }
+
+        Ok(())
+    }
+}
+
+/// The lambda list keywords of `common-lisp`; `&aux`, `&body`, `&whole`
+/// and `&environment` aren't supported.
+pub const LAMBDA_LIST_KEYWORDS: &[&str] = &["&OPTIONAL", "&REST", "&KEY", "&ALLOW-OTHER-KEYS",
+                                            "&AUX", "&BODY", "&WHOLE", "&ENVIRONMENT"];
+
+/// The name of the lambda list keyword `param` is, if it is one.
+pub fn lambda_list_keyword(param: &Sexp) -> Option<&str> {
+    match *param {
+        Sexp::Symbol(ref s) => package::cl_name(s).filter(|n| LAMBDA_LIST_KEYWORDS.contains(n)),
+        _ => None,
+    }
+}
+
+/// The parameters of a user function: required ones, then any after
+/// `&optional`, a `&rest` one, and any after `&key`, which may be followed
+/// by `&allow-other-keys`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LambdaList {
+    required: Vec<Symbol>,
+    optional: Vec<Parameter>,
+    rest: Option<Symbol>,
+    keys: Option<Vec<Parameter>>,
+    allow_other_keys: bool,
+}
+
+impl LambdaList {
+    fn parse(params: &Sexp) -> Result<LambdaList, String> {
+        let mut list = LambdaList {
+            required: vec![],
+            optional: vec![],
+            rest: None,
+            keys: None,
+            allow_other_keys: false,
+        };
+        // The lambda list keyword of the section being read, if any.
+        let mut section = None;
+        for param in list_elements(params)? {
+            let keyword = lambda_list_keyword(param);
+            match (keyword, section) {
+                (Some("&OPTIONAL"), None) |
+                (Some("&REST"), None) |
+                (Some("&REST"), Some("&OPTIONAL")) => section = keyword,
+                (Some("&KEY"), None | Some("&OPTIONAL")) => {
+                    section = keyword;
+                    list.keys = Some(vec![]);
+                }
+                (Some("&KEY"), Some("&REST")) if list.rest.is_some() => {
+                    section = keyword;
+                    list.keys = Some(vec![]);
+                }
+                (Some("&ALLOW-OTHER-KEYS"), Some("&KEY")) => {
+                    section = keyword;
+                    list.allow_other_keys = true;
+                }
+                (Some(k), _) => {
+                    return Err(format!("Misplaced or unsupported lambda list keyword {}", k));
+                }
+                (None, None) => list.required.push(extract_value!(*param, Sexp::Symbol)?),
+                (None, Some("&OPTIONAL")) => list.optional.push(Parameter::parse(param)?),
+                (None, Some("&REST")) if list.rest.is_none() => {
+                    list.rest = Some(extract_value!(*param, Sexp::Symbol)?);
+                }
+                (None, Some("&KEY")) => {
+                    list.keys.as_mut().unwrap().push(Parameter::parse(param)?);
+                }
+                (None, _) => return Err(format!("Argument error: {}", param)),
+            }
+        }
+        if section == Some("&REST") && list.rest.is_none() {
+            return Err(format!("Argument error: {}", params));
+        }
+
+        Ok(list)
+    }
+
+    /// Binds the arguments in `env`; defaults are evaluated there too, so
+    /// they can refer to earlier parameters.
+    fn bind(&self, args: Vec<Sexp>, env: &Env) -> Result<(), String> {
+        let positional = self.required.len() + self.optional.len();
+        if args.len() < self.required.len() ||
+           args.len() > positional && self.rest.is_none() && self.keys.is_none() {
+            return Err(format!("Invalid number of arguments: {}", args.len()));
+        }
+
+        for (k, v) in self.required.iter().zip(args.iter()) {
+            env::env_set(env, k, v.clone());
+        }
+        for (i, param) in self.optional.iter().enumerate() {
+            param.bind(args.get(self.required.len() + i), env)?;
+        }
+        let rest = args.get(positional..).unwrap_or(&[]);
+        if let Some(ref name) = self.rest {
+            let list = if rest.is_empty() { Sexp::Nil } else { Sexp::list(rest.to_vec()) };
+            env::env_set(env, name, list);
+        }
+
+        match self.keys {
+            Some(ref keys) => self.bind_keys(keys, rest, env),
+            None => Ok(()),
+        }
+    }
+
+    fn bind_keys(&self, keys: &[Parameter], rest: &[Sexp], env: &Env) -> Result<(), String> {
+        if !rest.len().is_multiple_of(2) {
+            return Err("Odd number of &key arguments".to_string());
+        }
+        let keyword = |name: &str| Sexp::Symbol(Symbol::keyword(name));
+        let value = |key: &Sexp| rest.chunks(2).find(|pair| pair[0] == *key).map(|pair| &pair[1]);
+
+        let allow_other_keys = keyword("ALLOW-OTHER-KEYS");
+        if !self.allow_other_keys && value(&allow_other_keys).is_none_or(|v| *v == Sexp::Nil) {
+            for pair in rest.chunks(2) {
+                let known = pair[0] == allow_other_keys ||
+                            keys.iter().any(|p| pair[0] == keyword(p.name.name()));
+                if !known {
+                    return Err(format!("Unknown &key argument: {}", pair[0]));
+                }
+            }
+        }
+
+        for param in keys {
+            param.bind(value(&keyword(param.name.name())), env)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct FuncData {
-    params: Vec<String>,
-    body: Box<Sexp>,
+    params: LambdaList,
+    body: Vec<Sexp>,
     env: Env,
 }
 
 impl FuncData {
-    fn new(params: Vec<String>, body: Sexp, env: Env) -> FuncData {
-        FuncData {
-            params: params,
-            body: Box::new(body),
-            env: env,
+    fn new(params: LambdaList, body: Vec<Sexp>, env: Env) -> FuncData {
+        FuncData { params, body, env }
+    }
+
+    /// `(name lambda-list body...)`, as in `flet`, `labels` and `macrolet`,
+    /// closing over `env`.
+    fn parse_definition(definition: &Sexp, env: &Env) -> Result<(Symbol, FuncData), String> {
+        match *definition {
+            Sexp::List(ref v) if v.len() >= 2 => {
+                let name = extract_value!(v[0], Sexp::Symbol)?;
+                let params = LambdaList::parse(&v[1])?;
+                Ok((name, FuncData::new(params, v[2..].to_vec(), env.clone())))
+            }
+            ref d => Err(format!("Argument error: {}", d)),
         }
     }
+
+    fn call(&self, args: Vec<Sexp>) -> SexpResult {
+        let env = env::env_new(Some(self.env.clone()));
+        self.params.bind(args, &env)?;
+        eval_body(&self.body, &env)
+    }
 }
 
+/// A function implemented in Rust that closes over some state, such as the
+/// accessors `defstruct` defines.
+#[derive(Clone)]
+pub struct Closure(Rc<dyn Fn(Vec<Sexp>) -> SexpResult>);
+
+impl Closure {
+    pub fn new<F>(f: F) -> Closure
+        where F: Fn(Vec<Sexp>) -> SexpResult + 'static
+    {
+        Closure(Rc::new(f))
+    }
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Closure")
+    }
+}
+
+/// A cons cell, for the lists a vector can't hold: dotted lists such as
+/// `(a . b)`, and lists that contain themselves through `#n#`, whose cells
+/// the reader fills in after making them.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Cons {
+    car: Sexp,
+    cdr: Sexp,
+}
+
+impl Cons {
+    pub fn car(&self) -> &Sexp {
+        &self.car
+    }
+
+    pub fn cdr(&self) -> &Sexp {
+        &self.cdr
+    }
+
+    pub fn set_car(&mut self, car: Sexp) {
+        self.car = car;
+    }
+
+    pub fn set_cdr(&mut self, cdr: Sexp) {
+        self.cdr = cdr;
+    }
+}
+
+#[allow(unknown_lints, unpredictable_function_pointer_comparisons)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum Sexp {
-    Number(f64),
-    String(String),
-    Symbol(String),
-    List(Vec<Sexp>),
+    Number(Number),
+    Char(char),
+    String(Rc<str>),
+    Symbol(Symbol),
+    List(Rc<Vec<Sexp>>),
+    Cons(Rc<RefCell<Cons>>),
+    Stream(Stream),
+    HashTable(Rc<RefCell<HashTable>>),
+    Array(Rc<RefCell<Array>>),
+    Struct(Rc<RefCell<Structure>>),
+    Instance(Rc<RefCell<Instance>>),
+    Package(Package),
+    Readtable(Readtable),
+    RandomState(RandomState),
+    /// The result of a form returning other than exactly one value. Anything
+    /// but the multiple-value forms sees only the primary value.
+    Values(Vec<Sexp>),
     BuiltInFunc(fn(Vec<Sexp>) -> SexpResult),
     UserFunc(FuncData),
+    Closure(Closure),
+    /// A local macro from `macrolet`; it lives only in the function
+    /// namespace and is expanded where it appears in operator position.
+    Macro(FuncData),
     Nil,
     True,
 }
@@ -60,13 +334,26 @@ impl Sexp {
     pub fn eval(&self, env: &Env) -> SexpResult {
         match *self {
             ref s @ Sexp::Number(_) |
+            ref s @ Sexp::Char(_) |
             ref s @ Sexp::String(_) |
+            ref s @ Sexp::Stream(_) |
+            ref s @ Sexp::HashTable(_) |
+            ref s @ Sexp::Array(_) |
+            ref s @ Sexp::Struct(_) |
+            ref s @ Sexp::Instance(_) |
+            ref s @ Sexp::Package(_) |
+            ref s @ Sexp::Readtable(_) |
+            ref s @ Sexp::RandomState(_) |
+            ref s @ Sexp::Values(_) |
             ref s @ Sexp::BuiltInFunc(_) |
             ref s @ Sexp::UserFunc(_) |
+            ref s @ Sexp::Closure(_) |
+            ref s @ Sexp::Macro(_) |
             ref s @ Sexp::Nil |
             ref s @ Sexp::True => Ok(s.clone()),
+            Sexp::Symbol(ref s) if s.is_keyword() => Ok(self.clone()),
             Sexp::Symbol(ref s) => {
-                match env::env_get(env, s) {
+                match env::env_get(env, s.clone()) {
                     Some(v) => Ok(v.clone()),
                     None => Err(format!("The variable {} is unbound", &s)),
                 }
@@ -76,44 +363,354 @@ impl Sexp {
                     return Ok(Sexp::Nil);
                 }
 
-                process_special_form(v, env).unwrap_or_else(|| {
-                    let evaled: Result<Vec<Sexp>, String> = v.iter().map(|s| s.eval(env)).collect();
-                    evaled.and_then(|v| v[0].apply(v[1..].to_vec()))
-                })
+                process_special_form(v, env).unwrap_or_else(|| call(v, env))
             }
+            Sexp::Cons(_) => Err("Illegal function call".to_string()),
         }
     }
 
-    fn apply(&self, args: Vec<Sexp>) -> SexpResult {
-        match *self {
-            Sexp::BuiltInFunc(f) => f(args),
-            Sexp::UserFunc(ref d) => {
-                let env = env::env_new(Some(d.env.clone()));
-                for (k, v) in d.params.iter().zip(args.iter()) {
-                    env::env_set(&env, k.clone(), v.clone());
+    pub fn string<S: Into<Rc<str>>>(s: S) -> Sexp {
+        Sexp::String(s.into())
+    }
+
+    /// The symbol that reading `name` produces.
+    pub fn symbol(name: &str) -> Sexp {
+        Sexp::from_symbol(Symbol::from(name))
+    }
+
+    /// The object the symbol `s` stands for: nil and t in `common-lisp` are
+    /// their own objects.
+    pub fn from_symbol(s: Symbol) -> Sexp {
+        if s.home().is_some_and(|p| p.name() == "COMMON-LISP") {
+            match s.name() {
+                "NIL" => return Sexp::Nil,
+                "T" => return Sexp::True,
+                _ => (),
+            }
+        }
+        Sexp::Symbol(s)
+    }
+
+    pub fn list(elements: Vec<Sexp>) -> Sexp {
+        Sexp::List(Rc::new(elements))
+    }
+
+    pub fn cons(car: Sexp, cdr: Sexp) -> Sexp {
+        Sexp::Cons(Rc::new(RefCell::new(Cons { car, cdr })))
+    }
+
+    /// The list of `elements` ending in `tail` instead of nil, as read from
+    /// `(elements... . tail)`: a plain list if `tail` is a list too.
+    pub fn dotted_list(mut elements: Vec<Sexp>, tail: Sexp) -> Sexp {
+        match tail {
+            Sexp::Nil => Sexp::list(elements),
+            Sexp::List(ref l) => {
+                elements.extend(l.iter().cloned());
+                Sexp::list(elements)
+            }
+            tail => elements.into_iter().rev().fold(tail, |cdr, car| Sexp::cons(car, cdr)),
+        }
+    }
+
+    /// The elements of a list and what it ends in: nil for a proper list, or
+    /// the tail after the dot of a dotted one.
+    pub fn list_parts(&self) -> Result<(Vec<Sexp>, Sexp), String> {
+        let mut elements = vec![];
+        let mut seen = HashSet::new();
+        let mut rest = self.clone();
+        loop {
+            rest = match rest {
+                Sexp::Nil => return Ok((elements, Sexp::Nil)),
+                Sexp::List(ref v) => {
+                    elements.extend(v.iter().cloned());
+                    return Ok((elements, Sexp::Nil));
+                }
+                Sexp::Cons(ref c) => {
+                    if !seen.insert(Rc::as_ptr(c)) {
+                        return Err(format!("The list {} is circular", self));
+                    }
+                    elements.push(c.borrow().car.clone());
+                    c.borrow().cdr.clone()
                 }
-                d.body.eval(&env)
+                _ if elements.is_empty() => return Err(format!("Argument error: {}", self)),
+                tail => return Ok((elements, tail)),
+            };
+        }
+    }
+
+    /// Tells apart the objects that can be shared, lists, vectors, hash
+    /// tables, structures and instances, by their address.
+    pub fn identity(&self) -> Option<usize> {
+        match *self {
+            Sexp::List(ref l) if !l.is_empty() => Some(Rc::as_ptr(l) as usize),
+            Sexp::Cons(ref c) => Some(Rc::as_ptr(c) as usize),
+            Sexp::Array(ref a) => Some(Rc::as_ptr(a) as usize),
+            Sexp::Struct(ref s) => Some(Rc::as_ptr(s) as usize),
+            Sexp::HashTable(ref t) => Some(Rc::as_ptr(t) as usize),
+            Sexp::Instance(ref i) => Some(Rc::as_ptr(i) as usize),
+            _ => None,
+        }
+    }
+
+    /// The objects `self` contains that the printer prints, or that a
+    /// `print-object` method may.
+    fn components(&self) -> Vec<Sexp> {
+        match *self {
+            Sexp::List(ref l) => l.to_vec(),
+            Sexp::Cons(ref c) => vec![c.borrow().car.clone(), c.borrow().cdr.clone()],
+            Sexp::Array(ref a) => a.borrow().elements(),
+            Sexp::Values(ref v) => v.clone(),
+            Sexp::Struct(ref s) => s.borrow().slots().to_vec(),
+            Sexp::HashTable(ref t) => {
+                t.borrow().entries().into_iter().flat_map(|(k, v)| [k, v]).collect()
             }
+            Sexp::Instance(ref i) if clos::has_print_object(self) => i.borrow().slot_values(),
+            _ => vec![],
+        }
+    }
+
+    /// Reduces multiple values to the first, or nil if there are none.
+    pub fn primary(self) -> Sexp {
+        match self {
+            Sexp::Values(v) => v.into_iter().next().unwrap_or(Sexp::Nil),
+            s => s,
+        }
+    }
+
+    pub fn apply(&self, args: Vec<Sexp>) -> SexpResult {
+        match *self {
+            Sexp::BuiltInFunc(f) => f(args),
+            Sexp::UserFunc(ref d) => d.call(args),
+            Sexp::Closure(ref c) => (c.0)(args),
             _ => Err("Illegal function call".to_string()),
         }
     }
 }
 
+/// The labels of what is being printed with `*print-circle*`: the objects
+/// that appear more than once, each with the number it was labelled with when
+/// first printed.
+struct Circle {
+    labels: HashMap<usize, Option<usize>>,
+    next: usize,
+}
+
+thread_local! {
+    static CIRCLE: RefCell<Option<Circle>> = const { RefCell::new(None) };
+}
+
+fn print_circle() -> bool {
+    env::global()
+        .and_then(|env| env::env_get(&env, package::cl_symbol("*PRINT-CIRCLE*")))
+        .is_some_and(|v| v != Sexp::Nil)
+}
+
+/// Counts how many times each shareable object appears in `s`, without
+/// going into an object a second time.
+fn count_references(s: &Sexp, counts: &mut HashMap<usize, usize>) {
+    if let Some(id) = s.identity() {
+        let count = counts.entry(id).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            return;
+        }
+    }
+
+    for component in s.components() {
+        count_references(&component, counts);
+    }
+}
+
+/// Whether `s` contains itself, so that printing it without labels would
+/// never end. `open` has the objects being looked into that `s` is part of,
+/// and `done` those already looked into.
+fn contains_itself(s: &Sexp, open: &mut HashSet<usize>, done: &mut HashSet<usize>) -> bool {
+    let id = s.identity();
+    if let Some(id) = id {
+        if open.contains(&id) {
+            return true;
+        }
+        if !done.insert(id) {
+            return false;
+        }
+        open.insert(id);
+    }
+
+    let circular = s.components().iter().any(|c| contains_itself(c, open, done));
+    if let Some(id) = id {
+        open.remove(&id);
+    }
+    circular
+}
+
+fn is_circular(s: &Sexp) -> bool {
+    contains_itself(s, &mut HashSet::new(), &mut HashSet::new())
+}
+
+/// Errors if `object` contains itself and `*print-circle*` is nil, as
+/// printing it then would never end.
+pub fn check_printable(object: &Sexp) -> Result<(), String> {
+    let printing_circle = CIRCLE.with(|c| c.borrow().is_some());
+    if !print_circle() && !printing_circle && is_circular(object) {
+        return Err("Can't print a circular object while *print-circle* is nil".to_string());
+    }
+    Ok(())
+}
+
+/// Whether `s` is labelled in what is being printed with `*print-circle*`,
+/// so that as the tail of a list it has to follow a dot.
+fn is_labelled(s: &Sexp) -> bool {
+    s.identity().is_some_and(|id| {
+        CIRCLE.with(|c| c.borrow().as_ref().is_some_and(|c| c.labels.contains_key(&id)))
+    })
+}
+
+/// How an object is printed with `*print-circle*`: `Some((n, false))` for
+/// the first time, to print as `#n=object`, and `Some((n, true))` after
+/// that, to print as `#n#`.
+fn circle_label(s: &Sexp) -> Option<(usize, bool)> {
+    let id = s.identity()?;
+
+    CIRCLE.with(|c| {
+        let mut c = c.borrow_mut();
+        let circle = c.as_mut()?;
+        let next = circle.next + 1;
+        let label = circle.labels.get_mut(&id)?;
+        match *label {
+            Some(n) => Some((n, true)),
+            None => {
+                *label = Some(next);
+                circle.next = next;
+                Some((next, false))
+            }
+        }
+    })
+}
+
+/// Prints readably by default; the alternate flag (`{:#}`) prints strings and
+/// characters without their syntax, like `princ`. With `*print-circle*`,
+/// objects that appear more than once are labelled, and so they are in an
+/// object that contains itself anyway, for its printing to end.
 impl fmt::Display for Sexp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printing_circle = CIRCLE.with(|c| c.borrow().is_some());
+        if !printing_circle && self.identity().is_some() && (print_circle() || is_circular(self)) {
+            let mut counts = HashMap::new();
+            count_references(self, &mut counts);
+            let labels = counts.into_iter().filter(|&(_, n)| n > 1).map(|(id, _)| (id, None)).collect();
+
+            CIRCLE.with(|c| *c.borrow_mut() = Some(Circle { labels, next: 0 }));
+            let result = self.fmt(f);
+            CIRCLE.with(|c| *c.borrow_mut() = None);
+            return result;
+        }
+
+        match circle_label(self) {
+            Some((n, true)) => return write!(f, "#{}#", n),
+            Some((n, false)) => write!(f, "#{}=", n)?,
+            None => {}
+        }
+
+        if let Sexp::Struct(_) | Sexp::Instance(_) = *self {
+            if let Some(printed) = clos::print_object(self) {
+                return write!(f, "{}", printed);
+            }
+        }
+
         match *self {
             Sexp::Number(ref n) => write!(f, "{}", n),
-            Sexp::String(ref s) => write!(f, "\"{}\"", s.to_uppercase()),
+            Sexp::Char(c) if f.alternate() => write!(f, "{}", c),
+            Sexp::Char(c) => {
+                match char_name(c) {
+                    Some(name) => write!(f, "#\\{}", name),
+                    None => write!(f, "#\\{}", c),
+                }
+            }
+            Sexp::String(ref s) if f.alternate() => write!(f, "{}", s),
+            Sexp::String(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+                write!(f, "\"")
+            }
+            Sexp::Stream(ref s) => write!(f, "{}", s),
+            Sexp::HashTable(ref t) => write!(f, "{}", t.borrow()),
+            Sexp::Array(ref a) if f.alternate() => write!(f, "{:#}", a.borrow()),
+            Sexp::Array(ref a) => write!(f, "{}", a.borrow()),
+            Sexp::Struct(ref s) if f.alternate() => write!(f, "{:#}", s.borrow()),
+            Sexp::Struct(ref s) => write!(f, "{}", s.borrow()),
+            Sexp::Instance(ref i) => write!(f, "{}", i.borrow()),
+            Sexp::Package(ref p) => write!(f, "{}", p),
+            Sexp::Readtable(ref r) => write!(f, "{}", r),
+            Sexp::RandomState(ref r) => write!(f, "{}", r),
+            Sexp::Values(ref v) => {
+                for (i, s) in v.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", s)?;
+                }
+                Ok(())
+            }
+            Sexp::Symbol(ref s) if f.alternate() => write!(f, "{:#}", s),
             Sexp::Symbol(ref s) => write!(f, "{}", s),
             Sexp::BuiltInFunc(_) |
-            Sexp::UserFunc(_) => write!(f, "<fn>"),
+            Sexp::UserFunc(_) |
+            Sexp::Closure(_) => write!(f, "<fn>"),
+            Sexp::Macro(_) => write!(f, "<macro>"),
             Sexp::List(ref v) => {
-                try!(write!(f, "("));
+                write!(f, "(")?;
                 for (i, s) in v.iter().enumerate() {
                     if i > 0 {
-                        try!(write!(f, " "));
+                        write!(f, " ")?;
+                    }
+                    if f.alternate() {
+                        write!(f, "{:#}", s)?;
+                    } else {
+                        write!(f, "{}", s)?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Sexp::Cons(ref c) => {
+                let element = |f: &mut fmt::Formatter, s: &Sexp| {
+                    if f.alternate() { write!(f, "{:#}", s) } else { write!(f, "{}", s) }
+                };
+
+                write!(f, "(")?;
+                let mut cell = c.clone();
+                loop {
+                    let (car, cdr) = {
+                        let cell = cell.borrow();
+                        (cell.car.clone(), cell.cdr.clone())
+                    };
+                    element(f, &car)?;
+                    match cdr {
+                        Sexp::Nil => break,
+                        ref tail if is_labelled(tail) => {
+                            write!(f, " . ")?;
+                            element(f, tail)?;
+                            break;
+                        }
+                        Sexp::Cons(ref next) => cell = next.clone(),
+                        Sexp::List(ref l) => {
+                            for s in l.iter() {
+                                write!(f, " ")?;
+                                element(f, s)?;
+                            }
+                            break;
+                        }
+                        ref tail => {
+                            write!(f, " . ")?;
+                            element(f, tail)?;
+                            break;
+                        }
                     }
-                    try!(write!(f, "{}", s));
+                    write!(f, " ")?;
                 }
                 write!(f, ")")
             }
@@ -123,14 +720,44 @@ impl fmt::Display for Sexp {
     }
 }
 
+/// The names of the special forms, which are symbols of the `common-lisp`
+/// package.
+pub const SPECIAL_FORMS: &[&str] = &["CHECK-TYPE", "DEFCLASS", "DEFGENERIC", "DEFMETHOD",
+                                     "DEFPACKAGE", "DEFPARAMETER", "DEFSTRUCT", "DEFUN",
+                                     "DO-SYMBOLS", "FLET", "FUNCTION", "IF", "IN-PACKAGE",
+                                     "LABELS", "LAMBDA", "MACROLET", "MULTIPLE-VALUE-BIND",
+                                     "MULTIPLE-VALUE-LIST", "PROGN", "QUASIQUOTE", "QUOTE",
+                                     "SETF", "UNQUOTE", "UNQUOTE-SPLICING"];
+
+/// Evaluates `v` if its head is one of the special forms; other symbols with
+/// the same names, such as ones read with a different readtable case, aren't.
 fn process_special_form(v: &[Sexp], env: &Env) -> Option<SexpResult> {
     match v[0] {
-        Sexp::Symbol(ref s) => {
-            match &s[..] {
-                "defparameter" => Some(defparameter(v, env)),
-                "defun" => Some(defun(v, env)),
-                "if" => Some(if_special_form(v, env)),
-                "quote" => Some(Ok(v[1].clone())),
+        Sexp::Symbol(ref s) if s.home().is_some_and(|p| p.name() == "COMMON-LISP") => {
+            match s.name() {
+                "CHECK-TYPE" => Some(check_type(v, env)),
+                "DEFCLASS" => Some(clos::defclass(v, env)),
+                "DEFGENERIC" => Some(clos::defgeneric(v, env)),
+                "DEFMETHOD" => Some(clos::defmethod(v, env)),
+                "DEFPACKAGE" => Some(package::defpackage(v, env)),
+                "DEFPARAMETER" => Some(defparameter(v, env)),
+                "DEFSTRUCT" => Some(structure::defstruct(v, env)),
+                "DEFUN" => Some(defun(v, env)),
+                "DO-SYMBOLS" => Some(package::do_symbols(v, env)),
+                "FLET" => Some(flet(v, env, false)),
+                "FUNCTION" => Some(function(v, env)),
+                "IF" => Some(if_special_form(v, env)),
+                "IN-PACKAGE" => Some(package::in_package(v, env)),
+                "LABELS" => Some(flet(v, env, true)),
+                "LAMBDA" => Some(lambda(v, env)),
+                "MACROLET" => Some(macrolet(v, env)),
+                "MULTIPLE-VALUE-BIND" => Some(multiple_value_bind(v, env)),
+                "MULTIPLE-VALUE-LIST" => Some(multiple_value_list(v, env)),
+                "PROGN" => Some(eval_body(&v[1..], env)),
+                "QUASIQUOTE" => Some(quasiquote(v, env)),
+                "QUOTE" => Some(Ok(v[1].clone())),
+                "SETF" => Some(setf(v, env)),
+                "UNQUOTE" | "UNQUOTE-SPLICING" => Some(Err("Comma not inside a backquote".to_string())),
                 _ => None,
             }
         }
@@ -138,26 +765,236 @@ fn process_special_form(v: &[Sexp], env: &Env) -> Option<SexpResult> {
     }
 }
 
+/// `(check-type place type)` signals an error unless the value of `place` is of
+/// the (unevaluated) type specifier.
+fn check_type(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() != 3 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let value = v[1].eval(env)?.primary();
+    if types::typep(&value, &v[2])? {
+        Ok(Sexp::Nil)
+    } else {
+        Err(format!("The value of {} is {}, which is not of type {}",
+                    v[1].to_string().to_uppercase(),
+                    value,
+                    v[2].to_string().to_uppercase()))
+    }
+}
+
+/// Errors if `s` is a constant, nil, t or a keyword, which can't be made a
+/// variable or assigned.
+fn check_constant(s: &Sexp) -> Result<(), String> {
+    match *s {
+        Sexp::Symbol(ref name) if !name.is_keyword() => Ok(()),
+        Sexp::Symbol(_) | Sexp::Nil | Sexp::True => {
+            Err(format!("{} is a constant and thus can't be set", s))
+        }
+        _ => Ok(()),
+    }
+}
+
 fn defparameter(v: &[Sexp], env: &Env) -> SexpResult {
-    let name = try!(extract_value!(v[1], Sexp::Symbol, "{} is not a legal info name"));
-    let value = try!(v[2].eval(&env));
+    check_constant(&v[1])?;
+    let name = extract_value!(v[1], Sexp::Symbol, "{} is not a legal info name")?;
+    package::check_lock(&name)?;
+    let value = v[2].eval(env)?.primary();
 
     env::env_set(env, name.clone(), value);
     Ok(Sexp::Symbol(name))
 }
 
+/// Calls the function named by the head of the form `v` with its evaluated
+/// arguments, or expands and evaluates it if the head names a macro.
+fn call(v: &[Sexp], env: &Env) -> SexpResult {
+    let function = match v[0] {
+        Sexp::Symbol(ref name) => {
+            match env::env_get_function(env, name) {
+                Some(Sexp::Macro(ref m)) => return m.call(v[1..].to_vec())?.primary().eval(env),
+                Some(f) => f,
+                None => return Err(format!("The function {} is undefined", name)),
+            }
+        }
+        Sexp::List(ref l) if is_lambda_expression(l) => lambda(l, env)?,
+        _ => return Err("Illegal function call".to_string()),
+    };
+
+    let mut args = vec![];
+    for arg in &v[1..] {
+        args.push(arg.eval(env)?.primary());
+    }
+    function.apply(args)
+}
+
 fn defun(v: &[Sexp], env: &Env) -> SexpResult {
-    let name = try!(extract_value!(v[1], Sexp::Symbol));
-    let params = try!(extract_values!(v[2], Sexp::Symbol));
+    if v.len() < 3 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+    let name = extract_value!(v[1], Sexp::Symbol)?;
+    package::check_lock(&name)?;
+    let params = LambdaList::parse(&v[2])?;
 
-    env::env_set(env,
-                 name.clone(),
-                 Sexp::UserFunc(FuncData::new(params, v[3].clone(), env.clone())));
+    env::env_set_function(env,
+                          name.clone(),
+                          Sexp::UserFunc(FuncData::new(params, v[3..].to_vec(), env.clone())));
     Ok(Sexp::Symbol(name))
 }
 
+/// `(flet ((name lambda-list body...)...) body...)` binds local functions
+/// that see the enclosing functions; `labels` ones also see each other.
+fn flet(v: &[Sexp], env: &Env, recursive: bool) -> SexpResult {
+    if v.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+    let body_env = env::env_new(Some(env.clone()));
+    let closure_env = if recursive { &body_env } else { env };
+
+    for definition in list_elements(&v[1])? {
+        let (name, f) = FuncData::parse_definition(definition, closure_env)?;
+        env::env_set_function(&body_env, name, Sexp::UserFunc(f));
+    }
+
+    eval_body(&v[2..], &body_env)
+}
+
+/// `(macrolet ((name lambda-list body...)...) body...)` binds local macros.
+fn macrolet(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+    let body_env = env::env_new(Some(env.clone()));
+
+    for definition in list_elements(&v[1])? {
+        let (name, m) = FuncData::parse_definition(definition, env)?;
+        env::env_set_function(&body_env, name, Sexp::Macro(m));
+    }
+
+    eval_body(&v[2..], &body_env)
+}
+
+fn list_elements(s: &Sexp) -> Result<&[Sexp], String> {
+    match *s {
+        Sexp::List(ref v) => Ok(v),
+        Sexp::Nil => Ok(&[]),
+        ref v => Err(format!("Argument error: {}", v)),
+    }
+}
+
+/// `(function name)`, read from `#'name`, looks up a function; a lambda
+/// expression is evaluated to a closure.
+fn function(v: &[Sexp], env: &Env) -> SexpResult {
+    match v.get(1) {
+        Some(Sexp::Symbol(name)) => {
+            match env::env_get_function(env, name) {
+                Some(f @ Sexp::BuiltInFunc(_)) |
+                Some(f @ Sexp::UserFunc(_)) |
+                Some(f @ Sexp::Closure(_)) => Ok(f),
+                _ => Err(format!("The function {} is undefined", name)),
+            }
+        }
+        Some(Sexp::List(l)) if is_lambda_expression(l) => {
+            lambda(l, env)
+        }
+        Some(f) => Err(format!("Argument error: {}", f)),
+        None => Err("Invalid number of arguments: 0".to_string()),
+    }
+}
+
+/// `(quasiquote template)`, read from a backquote, copies the template with
+/// the forms marked by commas at the same nesting level replaced by their
+/// values, or for `,@` spliced in.
+fn quasiquote(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() != 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    backquote(&v[1], 1, env)
+}
+
+/// The name of the backquote operator `l` is a use of, if any.
+fn backquote_operator(l: &[Sexp]) -> Option<&str> {
+    match l {
+        [Sexp::Symbol(s), _] if s.home().is_some_and(|p| p.name() == "COMMON-LISP") => {
+            match s.name() {
+                name @ ("QUASIQUOTE" | "UNQUOTE" | "UNQUOTE-SPLICING") => Some(name),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn backquote(template: &Sexp, depth: usize, env: &Env) -> SexpResult {
+    match *template {
+        Sexp::List(ref l) => {
+            match backquote_operator(l) {
+                Some("UNQUOTE") if depth == 1 => return Ok(l[1].eval(env)?.primary()),
+                Some("UNQUOTE-SPLICING") if depth == 1 => {
+                    return Err(format!(",@ after backquote in {}", template));
+                }
+                Some(name) => {
+                    let depth = if name == "QUASIQUOTE" { depth + 1 } else { depth - 1 };
+                    return Ok(Sexp::list(vec![l[0].clone(), backquote(&l[1], depth, env)?]));
+                }
+                None => (),
+            }
+            let (copy, tail) = backquote_elements(l, true, depth, env)?;
+            Ok(if copy.is_empty() { tail } else { Sexp::dotted_list(copy, tail) })
+        }
+        Sexp::Cons(_) => {
+            let (elements, tail) = template.list_parts()?;
+            let (copy, _) = backquote_elements(&elements, false, depth, env)?;
+            Ok(Sexp::dotted_list(copy, backquote(&tail, depth, env)?))
+        }
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 && !a.borrow().is_float() => {
+            let elements = a.borrow().elements();
+            let (copy, _) = backquote_elements(&elements, false, depth, env)?;
+            Ok(Sexp::Array(Rc::new(RefCell::new(Array::vector(copy)))))
+        }
+        ref s => Ok(s.clone()),
+    }
+}
+
+/// Copies the elements of a backquoted list or vector, splicing in `,@`
+/// forms. A list's `(... . ,form)`, which reads as a comma operator among
+/// the last two elements, gives the tail the copy ends in.
+fn backquote_elements(elements: &[Sexp], dotted: bool, depth: usize, env: &Env)
+                      -> Result<(Vec<Sexp>, Sexp), String> {
+    let mut copy = vec![];
+    for (i, element) in elements.iter().enumerate() {
+        if dotted && i > 0 && i + 2 == elements.len() &&
+           backquote_operator(&elements[i..]).is_some() {
+            let tail = backquote(&Sexp::list(elements[i..].to_vec()), depth, env)?;
+            return Ok((copy, tail));
+        }
+        match *element {
+            Sexp::List(ref e) if depth == 1 && backquote_operator(e) == Some("UNQUOTE-SPLICING") => {
+                copy.extend_from_slice(list_elements(&e[1].eval(env)?.primary())?);
+            }
+            ref e => copy.push(backquote(e, depth, env)?),
+        }
+    }
+
+    Ok((copy, Sexp::Nil))
+}
+
+/// Whether `l` is `(lambda lambda-list body...)`.
+fn is_lambda_expression(l: &[Sexp]) -> bool {
+    l.first() == Some(&Sexp::Symbol(package::cl_symbol("LAMBDA")))
+}
+
+fn lambda(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+    let params = LambdaList::parse(&v[1])?;
+
+    Ok(Sexp::UserFunc(FuncData::new(params, v[2..].to_vec(), env.clone())))
+}
+
 fn if_special_form(v: &[Sexp], env: &Env) -> SexpResult {
-    let conditional = try!(v[1].eval(&env));
+    let conditional = v[1].eval(env)?.primary();
 
     match conditional {
         Sexp::Nil => v[3].eval(env),
@@ -165,18 +1002,104 @@ fn if_special_form(v: &[Sexp], env: &Env) -> SexpResult {
     }
 }
 
+pub fn eval_body(body: &[Sexp], env: &Env) -> SexpResult {
+    let mut result = Sexp::Nil;
+    for form in body {
+        result = form.eval(env)?;
+    }
+
+    Ok(result)
+}
+
+fn values_of(s: Sexp) -> Vec<Sexp> {
+    match s {
+        Sexp::Values(v) => v,
+        s => vec![s],
+    }
+}
+
+/// `(setf place value ...)` assigns variables, and for a place `(name args...)`
+/// calls the function named `(setf name)` with the value followed by the args.
+fn setf(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len().is_multiple_of(2) {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let mut result = Sexp::Nil;
+    for pair in v[1..].chunks(2) {
+        check_constant(&pair[0])?;
+        result = match pair[0] {
+            Sexp::Symbol(ref name) => {
+                let value = pair[1].eval(env)?.primary();
+                env::env_assign(env, name.clone(), value.clone());
+                value
+            }
+            Sexp::List(ref place) if !place.is_empty() => {
+                let name = extract_value!(place[0], Sexp::Symbol)?;
+                let function = env::env_get_setf_function(env, &name)
+                    .ok_or_else(|| format!("The function (SETF {:#}) is undefined", name))?;
+
+                let mut args = vec![];
+                for arg in &place[1..] {
+                    args.push(arg.eval(env)?.primary());
+                }
+                let value = pair[1].eval(env)?.primary();
+                args.insert(0, value.clone());
+
+                function.apply(args)?;
+                value
+            }
+            ref place => return Err(format!("Argument error: {}", place)),
+        };
+    }
+
+    Ok(result)
+}
+
+/// `(multiple-value-bind (vars...) form body...)`
+fn multiple_value_bind(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 3 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let names: Vec<Symbol> = extract_values!(v[1], Sexp::Symbol)?;
+    let values = values_of(v[2].eval(env)?);
+    let body_env = env::env_new(Some(env.clone()));
+
+    for (i, name) in names.into_iter().enumerate() {
+        env::env_set(&body_env, name, values.get(i).cloned().unwrap_or(Sexp::Nil));
+    }
+
+    eval_body(&v[3..], &body_env)
+}
+
+fn multiple_value_list(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() != 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    Ok(Sexp::list(values_of(v[1].eval(env)?)))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Sexp, SexpResult, FuncData};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::{Sexp, SexpResult, FuncData, LambdaList, check_printable};
+    use super::super::array::Array;
     use super::super::env;
+    use super::super::package;
+    use super::super::symbol::Symbol;
+    use super::super::test_util::{int, symbol, eval_source};
 
     #[test]
     fn test_eval_with_self_evaluating_sexps() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::Number(5.).eval(&env), Ok(Sexp::Number(5.)));
-        assert_eq!(Sexp::String("str".to_string()).eval(&env),
-                   Ok(Sexp::String("str".to_string())));
+        assert_eq!(int(5).eval(&env), Ok(int(5)));
+        assert_eq!(Sexp::string("str").eval(&env),
+                   Ok(Sexp::string("str")));
+        assert_eq!(Sexp::Char('a').eval(&env), Ok(Sexp::Char('a')));
         assert_eq!(Sexp::Nil.eval(&env), Ok(Sexp::Nil));
         assert_eq!(Sexp::True.eval(&env), Ok(Sexp::True));
     }
@@ -185,56 +1108,56 @@ mod tests {
     fn test_eval_with_symbol() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::Symbol("sym".to_string()).eval(&env),
-                   Err("The variable sym is unbound".to_string()));
+        assert_eq!(Sexp::symbol("sym").eval(&env),
+                   Err("The variable SYM is unbound".to_string()));
 
-        env::env_set(&env, "sym".to_string(), Sexp::Number(5.));
-        assert_eq!(Sexp::Symbol("sym".to_string()).eval(&env),
-                   Ok(Sexp::Number(5.)));
+        env::env_set(&env, "sym".to_string(), int(5));
+        assert_eq!(Sexp::symbol("sym").eval(&env),
+                   Ok(int(5)));
     }
 
     #[test]
     fn test_eval_with_empty_list() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![]).eval(&env), Ok(Sexp::Nil));
+        assert_eq!(Sexp::list(vec![]).eval(&env), Ok(Sexp::Nil));
     }
 
     #[test]
     fn test_eval_with_list_with_func_in_front() {
         let env = env::env_new(None);
-        env::env_set(&env, "func".to_string(), Sexp::BuiltInFunc(ok));
+        env::env_set_function(&env, "func".to_string(), Sexp::BuiltInFunc(ok));
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("func".to_string()), Sexp::Number(5.)]).eval(&env),
+        assert_eq!(Sexp::list(vec![Sexp::symbol("func"), int(5)]).eval(&env),
                    Ok(Sexp::Nil));
     }
 
     #[test]
     fn test_eval_with_list_with_func_in_front_that_errors() {
         let env = env::env_new(None);
-        env::env_set(&env, "func".to_string(), Sexp::BuiltInFunc(err));
+        env::env_set_function(&env, "func".to_string(), Sexp::BuiltInFunc(err));
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("func".to_string()), Sexp::Number(5.)]).eval(&env),
+        assert_eq!(Sexp::list(vec![Sexp::symbol("func"), int(5)]).eval(&env),
                    Err("BOOM".to_string()));
     }
 
     #[test]
     fn test_eval_with_user_func_in_front() {
         let env = env::env_new(None);
-        let func_data = FuncData::new(vec!["n".to_string()],
-                                      Sexp::Symbol("n".to_string()),
+        let func_data = FuncData::new(LambdaList::parse(&Sexp::list(vec![Sexp::symbol("n")])).unwrap(),
+                                      vec![Sexp::symbol("n")],
                                       env.clone());
-        env::env_set(&env, "func".to_string(), Sexp::UserFunc(func_data));
+        env::env_set_function(&env, "func".to_string(), Sexp::UserFunc(func_data));
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("func".to_string()), Sexp::Number(5.)]).eval(&env),
-                   Ok(Sexp::Number(5.)));
+        assert_eq!(Sexp::list(vec![Sexp::symbol("func"), int(5)]).eval(&env),
+                   Ok(int(5)));
     }
 
     #[test]
     fn test_eval_with_list_non_func() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![Sexp::Number(5.)]).eval(&env),
+        assert_eq!(Sexp::list(vec![int(5)]).eval(&env),
                    Err("Illegal function call".to_string()));
     }
 
@@ -242,65 +1165,394 @@ mod tests {
     fn test_eval_with_defparameter() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("defparameter".to_string()),
-                                   Sexp::Symbol("a".to_string()),
-                                   Sexp::Number(5.)])
+        assert_eq!(Sexp::list(vec![Sexp::symbol("defparameter"),
+                                   Sexp::symbol("a"),
+                                   int(5)])
                        .eval(&env),
-                   Ok(Sexp::Symbol("a".to_string())));
-        assert_eq!(env::env_get(&env, &"a".to_string()), Some(Sexp::Number(5.)));
+                   Ok(Sexp::symbol("a")));
+        assert_eq!(env::env_get(&env, "a"), Some(int(5)));
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("defparameter".to_string()),
-                                   Sexp::Number(5.),
-                                   Sexp::Number(5.)])
+        assert_eq!(Sexp::list(vec![Sexp::symbol("defparameter"),
+                                   int(5),
+                                   int(5)])
                        .eval(&env),
                    Err("5 is not a legal info name".to_string()));
+        assert_eq!(Sexp::list(vec![Sexp::symbol("defparameter"), Sexp::symbol(":foo"), int(5)])
+                       .eval(&env),
+                   Err(":FOO is a constant and thus can't be set".to_string()));
     }
 
     #[test]
     fn test_eval_with_if() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("if".to_string()),
+        assert_eq!(Sexp::list(vec![Sexp::symbol("if"),
                                    Sexp::True,
-                                   Sexp::Number(1.),
-                                   Sexp::Number(2.)])
+                                   int(1),
+                                   int(2)])
                        .eval(&env),
-                   Ok(Sexp::Number(1.)));
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("if".to_string()),
+                   Ok(int(1)));
+        assert_eq!(Sexp::list(vec![Sexp::symbol("if"),
                                    Sexp::True,
-                                   Sexp::Number(1.),
-                                   Sexp::Number(2.)])
+                                   int(1),
+                                   int(2)])
                        .eval(&env),
-                   Ok(Sexp::Number(1.)));
+                   Ok(int(1)));
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("if".to_string()),
+        assert_eq!(Sexp::list(vec![Sexp::symbol("if"),
                                    Sexp::Nil,
-                                   Sexp::Number(1.),
-                                   Sexp::Number(2.)])
+                                   int(1),
+                                   int(2)])
                        .eval(&env),
-                   Ok(Sexp::Number(2.)));
+                   Ok(int(2)));
     }
 
     #[test]
     fn test_eval_with_quote() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("quote".to_string()),
-                                   Sexp::List(vec![Sexp::Number(5.)])])
+        assert_eq!(Sexp::list(vec![Sexp::symbol("quote"),
+                                   Sexp::list(vec![int(5)])])
+                       .eval(&env),
+                   Ok(Sexp::list(vec![int(5)])));
+        assert_eq!(Sexp::list(vec![Sexp::Symbol(Symbol::uninterned("QUOTE")), int(5)])
                        .eval(&env),
-                   Ok(Sexp::List(vec![Sexp::Number(5.)])));
+                   Err("The function #:QUOTE is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_display_string() {
+        assert_eq!(format!("{}", Sexp::string("Mixed Case")), "\"Mixed Case\"");
+        assert_eq!(format!("{}", Sexp::string("straße")), "\"straße\"");
+        assert_eq!(format!("{}", Sexp::string("a\"b\\c")), "\"a\\\"b\\\\c\"");
+        assert_eq!(format!("{:#}", Sexp::string("a\"b")), "a\"b");
+    }
+
+    #[test]
+    fn test_display_with_print_circle() {
+        let env = env::env_new(None);
+        let a = Sexp::list(vec![symbol("a")]);
+        let shared = Sexp::list(vec![a.clone(), a.clone(), Sexp::list(vec![symbol("a")])]);
+        let vector = Rc::new(RefCell::new(Array::vector(vec![int(1)])));
+        vector.borrow_mut().set(0, Sexp::Array(vector.clone())).unwrap();
+        env::set_global(&env);
+
+        assert_eq!(format!("{}", shared), "((A) (A) (A))");
+        assert_eq!(format!("{}", Sexp::Array(vector.clone())), "#1=#(#1#)");
+        assert_eq!(check_printable(&Sexp::Array(vector.clone())),
+                   Err("Can't print a circular object while *print-circle* is nil".to_string()));
+        assert_eq!(check_printable(&shared), Ok(()));
+        env::env_set(&env, package::cl_symbol("*PRINT-CIRCLE*"), Sexp::True);
+        assert_eq!(format!("{}", shared), "(#1=(A) #1# (A))");
+        assert_eq!(format!("{}", Sexp::Array(vector.clone())), "#1=#(#1#)");
+        assert_eq!(check_printable(&Sexp::Array(vector)), Ok(()));
+    }
+
+    #[test]
+    fn test_display_circular_hash_table_and_instance() {
+        let table = "(defparameter h (make-hash-table)) (setf (gethash 1 h) h)";
+        let node = "(defclass node () ((next :accessor node-next)))
+                    (defmethod print-object ((n node) s) (format s \"#<NODE ~a>\" (node-next n)))
+                    (defparameter n (make-instance 'node))
+                    (setf (node-next n) n)";
+        let error = "Can't print a circular object while *print-circle* is nil";
+
+        assert_eq!(eval_source(&format!("{} (format nil \"~a\" h)", table)),
+                   Err(error.to_string()));
+        assert_eq!(eval_source(&format!("{} (setf *print-circle* t) (format nil \"~a\" h)", table)),
+                   Ok("\"#1=#<HASH-TABLE :TEST EQL :COUNT 1 (1 . #1#)>\"".to_string()));
+        assert_eq!(eval_source(&format!("{} (format nil \"~a\" n)", node)), Err(error.to_string()));
+        assert_eq!(eval_source(&format!("{} (setf *print-circle* t) (format nil \"~a\" n)", node)),
+                   Ok("\"#1=#<NODE #1#>\"".to_string()));
+    }
+
+    #[test]
+    fn test_display_alternate() {
+        let list = Sexp::list(vec![Sexp::string("a"), Sexp::Char('b')]);
+
+        assert_eq!(format!("{}", list), "(\"a\" #\\b)");
+        assert_eq!(format!("{:#}", list), "(a b)");
+    }
+
+    #[test]
+    fn test_display_char() {
+        assert_eq!(format!("{}", Sexp::Char('a')), "#\\a");
+        assert_eq!(format!("{}", Sexp::Char(' ')), "#\\Space");
+        assert_eq!(format!("{}", Sexp::Char('\n')), "#\\Newline");
+        assert_eq!(format!("{}", Sexp::Char('\u{1F600}')), "#\\\u{1F600}");
     }
 
     #[test]
     fn test_eval_with_defun() {
         let env = env::env_new(None);
 
-        assert_eq!(Sexp::List(vec![Sexp::Symbol("defun".to_string()),
-                                   Sexp::Symbol("identity".to_string()),
-                                   Sexp::List(vec![Sexp::Symbol("n".to_string())]),
-                                   Sexp::Symbol("n".to_string())])
+        assert_eq!(Sexp::list(vec![Sexp::symbol("defun"),
+                                   Sexp::symbol("identity"),
+                                   Sexp::list(vec![Sexp::symbol("n")]),
+                                   Sexp::symbol("n")])
+                       .eval(&env),
+                   Ok(Sexp::symbol("identity")));
+    }
+
+    #[test]
+    fn test_eval_with_key_parameters() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+        Sexp::list(vec![symbol("defun"),
+                        symbol("f"),
+                        Sexp::list(vec![symbol("a"),
+                                        symbol("&key"),
+                                        symbol("b"),
+                                        Sexp::list(vec![symbol("c"), symbol("a")])]),
+                        Sexp::list(vec![symbol("list"), symbol("a"), symbol("b"), symbol("c")])])
+            .eval(&env)
+            .unwrap();
+
+        assert_eq!(Sexp::list(vec![symbol("f"), int(1)]).eval(&env),
+                   Ok(Sexp::list(vec![int(1), Sexp::Nil, int(1)])));
+        assert_eq!(Sexp::list(vec![symbol("f"), int(1), symbol(":c"), int(3), symbol(":b"), int(2)])
                        .eval(&env),
-                   Ok(Sexp::Symbol("identity".to_string())));
+                   Ok(Sexp::list(vec![int(1), int(2), int(3)])));
+        assert_eq!(Sexp::list(vec![symbol("f"), int(1), symbol(":d"), int(3)]).eval(&env),
+                   Err("Unknown &key argument: :D".to_string()));
+        assert_eq!(Sexp::list(vec![symbol("f"), int(1), symbol(":b")]).eval(&env),
+                   Err("Odd number of &key arguments".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_optional_rest_and_other_keys() {
+        let cases = [("(defun f (&rest r) r) (f 1 2 3)", "(1 2 3)"),
+                     ("(defun f (&rest r) r) (f)", "NIL"),
+                     ("(defun f (a &optional (b 2 b-p) c) (list a b b-p c)) (f 1)",
+                      "(1 2 NIL NIL)"),
+                     ("(defun f (a &optional (b 2 b-p) c) (list a b b-p c)) (f 1 5 6)",
+                      "(1 5 T 6)"),
+                     ("(defun f (a &rest r &key b) (list a r b)) (f 1 :b 2)", "(1 (:B 2) 2)"),
+                     ("(defun f (&key a &allow-other-keys) a) (f :b 1 :a 2)", "2"),
+                     ("(defun f (&key a) a) (f :allow-other-keys t :b 1 :a 2)", "2")];
+        for (source, value) in cases {
+            assert_eq!(eval_source(source), Ok(value.to_string()));
+        }
+
+        assert_eq!(eval_source("(defun f (a &optional b) a) (f)"),
+                   Err("Invalid number of arguments: 0".to_string()));
+        assert_eq!(eval_source("(defun f (a &optional b) a) (f 1 2 3)"),
+                   Err("Invalid number of arguments: 3".to_string()));
+        assert_eq!(eval_source("(defun f (&key a) a) (f :allow-other-keys nil :b 1)"),
+                   Err("Unknown &key argument: :B".to_string()));
+        for lambda_list in ["(&aux x)", "(&rest)", "(&rest a b)", "(&key a &optional b)",
+                            "(a &allow-other-keys)"] {
+            assert!(eval_source(&format!("(defun f {} nil)", lambda_list)).is_err(),
+                    "{}",
+                    lambda_list);
+        }
+        assert_eq!(eval_source("(defmethod m ((a integer) &rest r) r)"),
+                   Err("Misplaced or unsupported lambda list keyword &REST".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_function_and_lambda() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+        let lambda = Sexp::list(vec![symbol("lambda"),
+                                     Sexp::list(vec![symbol("x")]),
+                                     Sexp::list(vec![symbol("list"), symbol("x"), symbol("x")])]);
+
+        assert_eq!(Sexp::list(vec![symbol("function"), symbol("list")]).eval(&env),
+                   Ok(Sexp::BuiltInFunc(list)));
+        assert_eq!(Sexp::list(vec![symbol("function"), symbol("nope")]).eval(&env),
+                   Err("The function NOPE is undefined".to_string()));
+        assert_eq!(Sexp::list(vec![Sexp::list(vec![symbol("function"), lambda.clone()]), int(1)])
+                       .eval(&env),
+                   Err("Illegal function call".to_string()));
+        assert_eq!(Sexp::list(vec![lambda, int(2)]).eval(&env),
+                   Ok(Sexp::list(vec![int(2), int(2)])));
+    }
+
+    #[test]
+    fn test_eval_with_separate_namespaces() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "items".to_string(), Sexp::BuiltInFunc(list));
+        Sexp::list(vec![symbol("defparameter"), symbol("items"), int(5)]).eval(&env).unwrap();
+
+        assert_eq!(Sexp::list(vec![symbol("items"), symbol("items")]).eval(&env),
+                   Ok(Sexp::list(vec![int(5)])));
+        assert_eq!(Sexp::list(vec![symbol("nope")]).eval(&env),
+                   Err("The function NOPE is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_flet_and_labels() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+        let doubled = Sexp::list(vec![symbol("flet"),
+                                      Sexp::list(vec![Sexp::list(vec![symbol("list"),
+                                                                      Sexp::list(vec![symbol("x")]),
+                                                                      Sexp::list(vec![symbol("list"),
+                                                                                      symbol("x"),
+                                                                                      symbol("x")])])]),
+                                      Sexp::list(vec![symbol("list"), int(1)])]);
+        let mutual = |operator: &str| {
+            Sexp::list(vec![symbol(operator),
+                            Sexp::list(vec![Sexp::list(vec![symbol("a"),
+                                                            Sexp::Nil,
+                                                            Sexp::list(vec![symbol("b")])]),
+                                            Sexp::list(vec![symbol("b"),
+                                                            Sexp::Nil,
+                                                            Sexp::list(vec![symbol("list"),
+                                                                            int(2)])])]),
+                            Sexp::list(vec![symbol("a")])])
+        };
+
+        assert_eq!(doubled.eval(&env), Ok(Sexp::list(vec![int(1), int(1)])));
+        assert_eq!(Sexp::list(vec![symbol("list"), int(1)]).eval(&env),
+                   Ok(Sexp::list(vec![int(1)])));
+        assert_eq!(mutual("flet").eval(&env),
+                   Err("The function B is undefined".to_string()));
+        assert_eq!(mutual("labels").eval(&env), Ok(Sexp::list(vec![int(2)])));
+    }
+
+    #[test]
+    fn test_eval_with_macrolet() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+        let expansion = Sexp::list(vec![symbol("list"),
+                                        Sexp::list(vec![symbol("quote"), symbol("list")]),
+                                        symbol("x"),
+                                        symbol("x")]);
+        let macrolet = |form: Sexp| {
+            Sexp::list(vec![symbol("macrolet"),
+                            Sexp::list(vec![Sexp::list(vec![symbol("twice"),
+                                                            Sexp::list(vec![symbol("x")]),
+                                                            expansion.clone()])]),
+                            form])
+        };
+
+        assert_eq!(macrolet(Sexp::list(vec![symbol("twice"), int(1)])).eval(&env),
+                   Ok(Sexp::list(vec![int(1), int(1)])));
+        assert_eq!(macrolet(Sexp::list(vec![symbol("function"), symbol("twice")])).eval(&env),
+                   Err("The function TWICE is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_quasiquote() {
+        let env = env::env_new(None);
+        env::env_set(&env, "x".to_string(), int(1));
+        env::env_set(&env, "xs".to_string(), Sexp::list(vec![int(2), int(3)]));
+        let wrap = |name, s| Sexp::list(vec![symbol(name), s]);
+        let quasiquote = |v| wrap("quasiquote", Sexp::list(v));
+
+        assert_eq!(quasiquote(vec![symbol("a"),
+                                   wrap("unquote", symbol("x")),
+                                   wrap("unquote-splicing", symbol("xs"))])
+                       .eval(&env),
+                   Ok(Sexp::list(vec![symbol("a"), int(1), int(2), int(3)])));
+        assert_eq!(quasiquote(vec![wrap("unquote-splicing", Sexp::Nil)]).eval(&env),
+                   Ok(Sexp::Nil));
+        assert_eq!(quasiquote(vec![quasiquote(vec![wrap("unquote", symbol("x"))])]).eval(&env),
+                   Ok(Sexp::list(vec![quasiquote(vec![wrap("unquote", symbol("x"))])])));
+        assert_eq!(wrap("unquote", symbol("x")).eval(&env),
+                   Err("Comma not inside a backquote".to_string()));
+    }
+
+    #[test]
+    fn test_quasiquote_dotted_and_vector() {
+        assert_eq!(eval_source("`(a . ,(+ 1 2))"), Ok("(A . 3)".to_string()));
+        assert_eq!(eval_source("`(a b . ,(list 1 2))"), Ok("(A B 1 2)".to_string()));
+        assert_eq!(eval_source("`(,(+ 1 1) . c)"), Ok("(2 . C)".to_string()));
+        assert_eq!(eval_source("`(a unquote)"), Ok("(A UNQUOTE)".to_string()));
+        assert_eq!(eval_source("`#(1 ,(+ 1 1) ,@(list 3 4))"), Ok("#(1 2 3 4)".to_string()));
+        assert_eq!(eval_source("`#(a (b ,(+ 1 1)))"), Ok("#(A (B 2))".to_string()));
+    }
+
+    #[test]
+    fn test_eval_with_keyword() {
+        let env = env::env_new(None);
+
+        assert_eq!(symbol(":test").eval(&env), Ok(symbol(":test")));
+    }
+
+    #[test]
+    fn test_eval_with_setf() {
+        let global = env::env_new(None);
+        let env = env::env_new(Some(global.clone()));
+        env::env_set(&env, "a".to_string(), int(1));
+
+        assert_eq!(Sexp::list(vec![symbol("setf"), symbol("a"), int(2), symbol("b"), int(3)])
+                       .eval(&env),
+                   Ok(int(3)));
+        assert_eq!(env::env_get(&env, "a"), Some(int(2)));
+        assert_eq!(env::env_get(&global, "b"), Some(int(3)));
+
+        env::env_set_setf_function(&env, "first", Sexp::BuiltInFunc(list));
+        assert_eq!(Sexp::list(vec![symbol("setf"),
+                                   Sexp::list(vec![symbol("first"), symbol("a")]),
+                                   int(5)])
+                       .eval(&env),
+                   Ok(int(5)));
+        assert_eq!(Sexp::list(vec![symbol("setf"),
+                                   Sexp::list(vec![symbol("second"), symbol("a")]),
+                                   int(5)])
+                       .eval(&env),
+                   Err("The function (SETF SECOND) is undefined".to_string()));
+        assert_eq!(Sexp::list(vec![symbol("setf"), symbol("a")]).eval(&env),
+                   Err("Invalid number of arguments: 1".to_string()));
+        assert_eq!(eval_source("(defparameter x nil) (setf (getf x :a) 1)"),
+                   Err("The function (SETF GETF) is undefined".to_string()));
+        assert_eq!(eval_source("(defstruct pt x) (defparameter p (make-pt)) \
+                                (setf (pt-x p) 3) \
+                                (list (pt-x p) (find-symbol \"(SETF GET)\" :cl))"),
+                   Ok("(3 NIL)".to_string()));
+
+        for constant in &[Sexp::True, Sexp::Nil, symbol(":foo")] {
+            assert_eq!(Sexp::list(vec![symbol("setf"), constant.clone(), int(1)]).eval(&env),
+                       Err(format!("{} is a constant and thus can't be set", constant)));
+        }
+        assert_eq!(Sexp::list(vec![symbol("if"), Sexp::True, int(1), int(2)]).eval(&env),
+                   Ok(int(1)));
+    }
+
+    #[test]
+    fn test_eval_with_multiple_values() {
+        let env = env::env_new(None);
+        env::env_set_function(&env, "values".to_string(), Sexp::BuiltInFunc(values));
+        env::env_set_function(&env, "list".to_string(), Sexp::BuiltInFunc(list));
+        let two_values = Sexp::list(vec![symbol("values"), int(1), int(2)]);
+
+        assert_eq!(Sexp::list(vec![symbol("multiple-value-bind"),
+                                   Sexp::list(vec![symbol("a"), symbol("b"), symbol("c")]),
+                                   two_values.clone(),
+                                   Sexp::list(vec![symbol("list"),
+                                                   symbol("a"),
+                                                   symbol("b"),
+                                                   symbol("c")])])
+                       .eval(&env),
+                   Ok(Sexp::list(vec![int(1), int(2), Sexp::Nil])));
+        assert_eq!(Sexp::list(vec![symbol("multiple-value-list"), two_values.clone()]).eval(&env),
+                   Ok(Sexp::list(vec![int(1), int(2)])));
+        assert_eq!(Sexp::list(vec![symbol("list"), two_values]).eval(&env),
+                   Ok(Sexp::list(vec![int(1)])));
+    }
+
+    #[test]
+    fn test_eval_with_check_type() {
+        let env = env::env_new(None);
+        env::env_set(&env, "x".to_string(), int(1));
+
+        assert_eq!(Sexp::list(vec![symbol("check-type"), symbol("x"), symbol("integer")])
+                       .eval(&env),
+                   Ok(Sexp::Nil));
+        assert_eq!(Sexp::list(vec![symbol("check-type"), symbol("x"), symbol("string")])
+                       .eval(&env),
+                   Err("The value of X is 1, which is not of type STRING".to_string()));
+    }
+
+    fn list(args: Vec<Sexp>) -> SexpResult {
+        Ok(Sexp::list(args))
+    }
+
+    fn values(args: Vec<Sexp>) -> SexpResult {
+        Ok(Sexp::Values(args))
     }
 
     fn ok(_: Vec<Sexp>) -> SexpResult {
@@ -0,0 +1,211 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+thread_local! {
+    static STDOUT_AT_LINE_START: Cell<bool> = const { Cell::new(true) };
+    static STDIN: RefCell<StdinBuffer> = const {
+        RefCell::new(StdinBuffer { chars: Vec::new(), position: 0 })
+    };
+}
+
+/// The line of standard input being read, and how much of it has been read.
+struct StdinBuffer {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl StdinBuffer {
+    /// Reads the next line once the current one is used up, keeping the last
+    /// character read so that it can still be unread.
+    fn fill(&mut self) {
+        if self.position < self.chars.len() {
+            return;
+        }
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).is_ok_and(|n| n > 0) {
+            let last = self.chars.last().cloned();
+            self.chars = last.into_iter().chain(line.chars()).collect();
+            self.position = self.chars.len() - line.chars().count();
+        }
+    }
+}
+
+/// The characters of a string input stream and how many have been read.
+#[derive(PartialEq, Debug)]
+pub struct StringInput {
+    chars: Vec<char>,
+    position: Cell<usize>,
+}
+
+/// A stream: standard output, standard input, a string output stream whose
+/// buffer is shared by every copy of the stream, or a string input stream.
+/// The reader reads from the input streams.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Stream {
+    Stdout,
+    Stdin,
+    StringOutput(Rc<RefCell<String>>),
+    StringInput(Rc<StringInput>),
+}
+
+impl Stream {
+    pub fn string_output() -> Stream {
+        Stream::StringOutput(Rc::new(RefCell::new(String::new())))
+    }
+
+    pub fn string_input(s: &str) -> Stream {
+        Stream::StringInput(Rc::new(StringInput {
+            chars: s.chars().collect(),
+            position: Cell::new(0),
+        }))
+    }
+
+    pub fn is_input(&self) -> bool {
+        matches!(*self, Stream::StringInput(_) | Stream::Stdin)
+    }
+
+    pub fn peek_char(&self) -> Option<char> {
+        match *self {
+            Stream::StringInput(ref input) => input.chars.get(input.position.get()).cloned(),
+            Stream::Stdin => {
+                STDIN.with(|b| {
+                    let mut buffer = b.borrow_mut();
+                    buffer.fill();
+                    buffer.chars.get(buffer.position).cloned()
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn read_char(&self) -> Option<char> {
+        let c = self.peek_char();
+        match (c, self) {
+            (Some(_), Stream::StringInput(input)) => input.position.set(input.position.get() + 1),
+            (Some(_), Stream::Stdin) => STDIN.with(|b| b.borrow_mut().position += 1),
+            _ => (),
+        }
+        c
+    }
+
+    /// How many characters have been read from an input stream.
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            Stream::StringInput(ref input) => Some(input.position.get()),
+            _ => None,
+        }
+    }
+
+    /// Puts back the last character read.
+    pub fn unread_char(&self) {
+        match *self {
+            Stream::StringInput(ref input) => {
+                input.position.set(input.position.get().saturating_sub(1))
+            }
+            Stream::Stdin => {
+                STDIN.with(|b| {
+                    let mut buffer = b.borrow_mut();
+                    buffer.position = buffer.position.saturating_sub(1);
+                })
+            }
+            _ => (),
+        }
+    }
+
+    pub fn write_str(&self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        match *self {
+            Stream::Stdout => {
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                let _ = handle.write_all(s.as_bytes());
+                let _ = handle.flush();
+                STDOUT_AT_LINE_START.with(|c| c.set(s.ends_with('\n')));
+            }
+            Stream::StringOutput(ref buffer) => buffer.borrow_mut().push_str(s),
+            Stream::Stdin | Stream::StringInput(_) => {}
+        }
+    }
+
+    /// Whether nothing has been written since the last newline.
+    pub fn at_line_start(&self) -> bool {
+        match *self {
+            Stream::Stdout => STDOUT_AT_LINE_START.with(|c| c.get()),
+            Stream::StringOutput(ref buffer) => {
+                let buffer = buffer.borrow();
+                buffer.is_empty() || buffer.ends_with('\n')
+            }
+            Stream::Stdin | Stream::StringInput(_) => true,
+        }
+    }
+
+    /// Writes a newline unless the stream is already at the start of a line.
+    pub fn fresh_line(&self) {
+        if !self.at_line_start() {
+            self.write_str("\n");
+        }
+    }
+
+    /// Returns and clears the contents of a string output stream.
+    pub fn take_string(&self) -> Option<String> {
+        match *self {
+            Stream::StringOutput(ref buffer) => Some(buffer.replace(String::new())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stream::Stdout => write!(f, "#<STANDARD-OUTPUT-STREAM>"),
+            Stream::Stdin => write!(f, "#<STANDARD-INPUT-STREAM>"),
+            Stream::StringOutput(_) => write!(f, "#<STRING-OUTPUT-STREAM>"),
+            Stream::StringInput(_) => write!(f, "#<STRING-INPUT-STREAM>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stream;
+
+    #[test]
+    fn test_string_output() {
+        let stream = Stream::string_output();
+        let copy = stream.clone();
+
+        assert!(stream.at_line_start());
+        copy.write_str("abc");
+        assert!(!stream.at_line_start());
+        stream.fresh_line();
+        stream.fresh_line();
+        assert_eq!(stream.take_string(), Some("abc\n".to_string()));
+        assert_eq!(copy.take_string(), Some("".to_string()));
+    }
+
+    #[test]
+    fn test_string_input() {
+        let stream = Stream::string_input("ab");
+
+        assert_eq!(stream.read_char(), Some('a'));
+        stream.unread_char();
+        assert_eq!(stream.peek_char(), Some('a'));
+        assert_eq!(stream.read_char(), Some('a'));
+        assert_eq!(stream.read_char(), Some('b'));
+        assert_eq!(stream.read_char(), None);
+    }
+
+    #[test]
+    fn test_stdout_is_not_a_string_stream() {
+        assert_eq!(Stream::Stdout.take_string(), None);
+        assert!(!Stream::Stdout.is_input());
+        assert!(Stream::Stdin.is_input());
+        assert_eq!(Stream::Stdin.take_string(), None);
+    }
+}
@@ -0,0 +1,396 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use super::env::{self, Env};
+use super::sexp::{Closure, Sexp, SexpResult};
+use super::symbol::Symbol;
+
+thread_local! {
+    static TYPES: RefCell<HashMap<Symbol, Rc<StructType>>> = RefCell::new(HashMap::new());
+}
+
+pub struct Slot {
+    name: Symbol,
+    initform: Sexp,
+}
+
+/// A structure type defined by `defstruct`. The slots include those of the
+/// included type first, so its accessors work on instances of this type too.
+pub struct StructType {
+    name: Symbol,
+    slots: Vec<Slot>,
+    include: Option<Rc<StructType>>,
+    env: Env,
+}
+
+impl StructType {
+    pub fn name(&self) -> &Symbol {
+        &self.name
+    }
+
+    pub fn include(&self) -> Option<&Rc<StructType>> {
+        self.include.as_ref()
+    }
+
+    /// Whether this type is `name` or includes it, directly or indirectly.
+    pub fn is_subtype_of(&self, name: &Symbol) -> bool {
+        self.name == *name ||
+        self.include.as_ref().is_some_and(|t| t.is_subtype_of(name))
+    }
+
+    /// Makes an instance from `:slot value` pairs; missing slots get their
+    /// initforms, evaluated in the environment of the `defstruct`.
+    pub fn construct(ty: &Rc<StructType>, args: &[Sexp]) -> SexpResult {
+        if !args.len().is_multiple_of(2) {
+            return Err(format!("Invalid number of arguments: {}", args.len()));
+        }
+
+        let mut values = vec![None; ty.slots.len()];
+        for pair in args.chunks(2) {
+            let slot = match pair[0] {
+                Sexp::Symbol(ref k) if k.is_keyword() => {
+                    ty.slots.iter().position(|s| s.name.name() == k.name())
+                }
+                _ => None,
+            };
+
+            match slot {
+                Some(i) => values[i] = values[i].take().or_else(|| Some(pair[1].clone())),
+                None => return Err(format!("Argument error: {}", pair[0])),
+            }
+        }
+
+        let mut slots = vec![];
+        for (value, slot) in values.into_iter().zip(ty.slots.iter()) {
+            slots.push(match value {
+                Some(v) => v,
+                None => slot.initform.eval(&ty.env)?.primary(),
+            });
+        }
+
+        Ok(Sexp::Struct(Rc::new(RefCell::new(Structure {
+            struct_type: ty.clone(),
+            slots,
+        }))))
+    }
+}
+
+impl PartialEq for StructType {
+    fn eq(&self, other: &StructType) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StructType({})", self.name)
+    }
+}
+
+/// An instance of a structure type.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Structure {
+    struct_type: Rc<StructType>,
+    slots: Vec<Sexp>,
+}
+
+impl Structure {
+    pub fn struct_type(&self) -> &Rc<StructType> {
+        &self.struct_type
+    }
+
+    pub fn slots(&self) -> &[Sexp] {
+        &self.slots
+    }
+
+    pub fn set_slot(&mut self, index: usize, value: Sexp) {
+        self.slots[index] = value;
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "#S({:#}", self.struct_type.name)?;
+        } else {
+            write!(f, "#S({}", self.struct_type.name)?;
+        }
+        for (slot, value) in self.struct_type.slots.iter().zip(self.slots.iter()) {
+            let key = Symbol::keyword(slot.name.name());
+            if f.alternate() {
+                write!(f, " {:#} {:#}", key, value)?;
+            } else {
+                write!(f, " {} {}", key, value)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+pub fn find_type(name: &Symbol) -> Option<Rc<StructType>> {
+    TYPES.with(|t| t.borrow().get(name).cloned())
+}
+
+/// Builds the structure read as `#S(name :slot value ...)`; the type must
+/// already be defined.
+pub fn read(contents: &[Sexp]) -> Option<Sexp> {
+    match contents.first() {
+        Some(Sexp::Symbol(name)) => {
+            find_type(name).and_then(|ty| StructType::construct(&ty, &contents[1..]).ok())
+        }
+        _ => None,
+    }
+}
+
+/// The instance in `arg` if it is of the structure type `name`.
+fn instance(name: &Symbol, arg: Option<&Sexp>) -> Result<Rc<RefCell<Structure>>, String> {
+    match arg {
+        Some(Sexp::Struct(s)) if s.borrow().struct_type.is_subtype_of(name) => Ok(s.clone()),
+        Some(v) => Err(format!("Argument error: {}", v)),
+        None => Err("Invalid number of arguments: 0".to_string()),
+    }
+}
+
+fn parse_slot(spec: &Sexp) -> Result<Slot, String> {
+    match *spec {
+        Sexp::Symbol(ref name) => Ok(Slot { name: name.clone(), initform: Sexp::Nil }),
+        Sexp::List(ref v) if !v.is_empty() => {
+            match v[0] {
+                Sexp::Symbol(ref name) => {
+                    Ok(Slot {
+                        name: name.clone(),
+                        initform: v.get(1).cloned().unwrap_or(Sexp::Nil),
+                    })
+                }
+                ref n => Err(format!("Argument error: {}", n)),
+            }
+        }
+        ref s => Err(format!("Argument error: {}", s)),
+    }
+}
+
+/// `(defstruct name-and-options [doc] slots...)`, where the name may be
+/// `(name (:include parent))` and a slot is `name` or `(name initform)`.
+pub fn defstruct(v: &[Sexp], env: &Env) -> SexpResult {
+    if v.len() < 2 {
+        return Err(format!("Invalid number of arguments: {}", v.len() - 1));
+    }
+
+    let (name, options) = match v[1] {
+        Sexp::Symbol(ref name) => (name.clone(), &[][..]),
+        Sexp::List(ref o) if !o.is_empty() => {
+            match o[0] {
+                Sexp::Symbol(ref name) => (name.clone(), &o[1..]),
+                ref n => return Err(format!("Argument error: {}", n)),
+            }
+        }
+        ref n => return Err(format!("Argument error: {}", n)),
+    };
+
+    let mut include = None;
+    for option in options {
+        match *option {
+            Sexp::List(ref o) if o.len() == 2 && o[0] == Sexp::symbol(":include") => {
+                let parent = match o[1] {
+                    Sexp::Symbol(ref p) => p,
+                    ref p => return Err(format!("Argument error: {}", p)),
+                };
+                include = Some(find_type(parent)
+                    .ok_or_else(|| format!("The structure {} is undefined", parent))?);
+            }
+            ref o => return Err(format!("Argument error: {}", o)),
+        }
+    }
+
+    let mut slots: Vec<Slot> = match include {
+        Some(ref parent) => {
+            parent.slots
+                .iter()
+                .map(|s| Slot { name: s.name.clone(), initform: s.initform.clone() })
+                .collect()
+        }
+        None => vec![],
+    };
+    let specs = match v.get(2) {
+        Some(Sexp::String(_)) => &v[3..],
+        _ => &v[2..],
+    };
+    for spec in specs {
+        slots.push(parse_slot(spec)?);
+    }
+
+    let ty = Rc::new(StructType {
+        name: name.clone(),
+        slots,
+        include,
+        env: env.clone(),
+    });
+    TYPES.with(|t| t.borrow_mut().insert(name.clone(), ty.clone()));
+    define_functions(&ty, env);
+
+    Ok(Sexp::Symbol(name))
+}
+
+fn define_function<F>(env: &Env, name: Symbol, f: F)
+    where F: Fn(Vec<Sexp>) -> SexpResult + 'static
+{
+    env::env_set_function(env, name, Sexp::Closure(Closure::new(f)));
+}
+
+fn define_functions(ty: &Rc<StructType>, env: &Env) {
+    let name = ty.name.name();
+
+    let constructor_type = ty.clone();
+    define_function(env,
+                    Symbol::intern(&format!("MAKE-{}", name)),
+                    move |args| StructType::construct(&constructor_type, &args));
+
+    let type_name = ty.name.clone();
+    define_function(env, Symbol::intern(&format!("COPY-{}", name)), move |args| {
+        let copy = instance(&type_name, args.first())?.borrow().clone();
+        Ok(Sexp::Struct(Rc::new(RefCell::new(copy))))
+    });
+
+    let type_name = ty.name.clone();
+    define_function(env, Symbol::intern(&format!("{}-P", name)), move |args| {
+        match args.first() {
+            Some(Sexp::Struct(s)) if s.borrow().struct_type.is_subtype_of(&type_name) => {
+                Ok(Sexp::True)
+            }
+            _ => Ok(Sexp::Nil),
+        }
+    });
+
+    for (i, slot) in ty.slots.iter().enumerate() {
+        let accessor = Symbol::intern(&format!("{}-{}", name, slot.name.name()));
+
+        let type_name = ty.name.clone();
+        define_function(env, accessor.clone(), move |args| {
+            Ok(instance(&type_name, args.first())?.borrow().slots[i].clone())
+        });
+
+        let type_name = ty.name.clone();
+        let writer = Closure::new(move |args| {
+            let value = args.first().cloned().unwrap_or(Sexp::Nil);
+            instance(&type_name, args.get(1))?.borrow_mut().slots[i] = value.clone();
+            Ok(value)
+        });
+        env::env_set_setf_function(env, accessor, Sexp::Closure(writer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::env::{self, Env};
+    use super::super::package;
+    use super::super::readtable::{self, Case};
+    use super::super::sexp::Sexp;
+    use super::super::symbol::Symbol;
+    use super::super::test_util::{int, symbol};
+
+    fn call(env: &Env, name: &str, args: Vec<Sexp>) -> Result<Sexp, String> {
+        env::env_get_function(env, name).unwrap().apply(args)
+    }
+
+    fn define(env: &Env, name: Sexp, slots: Vec<Sexp>) {
+        let mut form = vec![symbol("defstruct"), name];
+        form.extend(slots);
+        super::defstruct(&form, env).unwrap();
+    }
+
+    #[test]
+    fn test_defstruct() {
+        let env = env::env_new(None);
+        define(&env,
+               symbol("point"),
+               vec![Sexp::list(vec![symbol("x"), int(0)]), symbol("y")]);
+
+        let p = call(&env, "make-point", vec![symbol(":y"), int(2)]).unwrap();
+        assert_eq!(format!("{}", p), "#S(POINT :X 0 :Y 2)");
+        assert_eq!(call(&env, "point-y", vec![p.clone()]), Ok(int(2)));
+        assert_eq!(call(&env, "point-p", vec![p.clone()]), Ok(Sexp::True));
+        assert_eq!(call(&env, "point-p", vec![int(1)]), Ok(Sexp::Nil));
+        assert_eq!(call(&env, "point-x", vec![int(1)]), Err("Argument error: 1".to_string()));
+        assert_eq!(call(&env, "make-point", vec![symbol(":z"), int(1)]),
+                   Err("Argument error: :Z".to_string()));
+
+        let q = call(&env, "copy-point", vec![p.clone()]).unwrap();
+        let setf_x = env::env_get_setf_function(&env, "point-x").unwrap();
+        assert_eq!(setf_x.apply(vec![int(5), p.clone()]), Ok(int(5)));
+        assert_eq!(format!("{}", p), "#S(POINT :X 5 :Y 2)");
+        assert_eq!(format!("{}", q), "#S(POINT :X 0 :Y 2)");
+    }
+
+    #[test]
+    fn test_include() {
+        let env = env::env_new(None);
+        define(&env, symbol("shape"), vec![symbol("name")]);
+        define(&env,
+               Sexp::list(vec![symbol("circle"),
+                               Sexp::list(vec![symbol(":include"), symbol("shape")])]),
+               vec![Sexp::list(vec![symbol("radius"), int(1)])]);
+
+        let c = call(&env, "make-circle", vec![symbol(":name"), Sexp::string("c")])
+            .unwrap();
+        assert_eq!(format!("{}", c), "#S(CIRCLE :NAME \"c\" :RADIUS 1)");
+        assert_eq!(format!("{:#}", c), "#S(CIRCLE :NAME c :RADIUS 1)");
+        assert_eq!(call(&env, "shape-p", vec![c.clone()]), Ok(Sexp::True));
+        assert_eq!(call(&env, "shape-name", vec![c.clone()]),
+                   Ok(Sexp::string("c")));
+        assert_eq!(call(&env, "circle-name", vec![c]), Ok(Sexp::string("c")));
+
+        let s = call(&env, "make-shape", vec![]).unwrap();
+        assert_eq!(call(&env, "circle-p", vec![s]), Ok(Sexp::Nil));
+        assert_eq!(super::defstruct(&[symbol("defstruct"),
+                                      Sexp::list(vec![symbol("oval"),
+                                                      Sexp::list(vec![symbol(":include"),
+                                                                      symbol("blob")])])],
+                                    &env),
+                   Err("The structure BLOB is undefined".to_string()));
+    }
+
+    #[test]
+    fn test_read() {
+        let env = env::env_new(None);
+        define(&env, symbol("pair"), vec![symbol("left"), symbol("right")]);
+
+        let p = super::read(&[symbol("pair"), symbol(":left"), int(1)]).unwrap();
+        assert_eq!(format!("{}", p), "#S(PAIR :LEFT 1 :RIGHT NIL)");
+        assert_eq!(super::read(&[symbol("nothing")]), None);
+    }
+
+    #[test]
+    fn test_types_in_packages() {
+        let env = env::env_new(None);
+        for name in ["A", "B"] {
+            package::defpackage(&[symbol("defpackage"), Sexp::string(name)], &env).unwrap();
+            package::set_current(package::find_package(name).unwrap());
+            define(&env, symbol("point"), vec![symbol("x")]);
+        }
+
+        let p = call(&env, "b::make-point", vec![]).unwrap();
+        assert_eq!(call(&env, "a::point-p", vec![p.clone()]), Ok(Sexp::Nil));
+        assert_eq!(call(&env, "b::point-p", vec![p.clone()]), Ok(Sexp::True));
+        assert_eq!(call(&env, "a::point-x", vec![p]),
+                   Err("Argument error: #S(POINT :X NIL)".to_string()));
+    }
+
+    #[test]
+    fn test_preserved_case() {
+        let env = env::env_new(None);
+        readtable::current().set_case(Case::Preserve);
+        let name = |s: &str| Sexp::Symbol(Symbol::intern(s));
+        let key = |s: &str| Sexp::Symbol(Symbol::keyword(s));
+        let call = |name: &str, args| {
+            env::env_get_function(&env, Symbol::intern(name)).unwrap().apply(args)
+        };
+        define(&env, name("Pt"), vec![name("x"), name("X")]);
+
+        let p = call("MAKE-Pt", vec![key("x"), int(1), key("X"), int(2)]).unwrap();
+        assert_eq!(format!("{}", p), "#S(Pt :x 1 :X 2)");
+        assert_eq!(call("Pt-x", vec![p.clone()]), Ok(int(1)));
+        assert_eq!(call("Pt-X", vec![p]), Ok(int(2)));
+        readtable::current().set_case(Case::Upcase);
+    }
+}
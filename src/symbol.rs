@@ -0,0 +1,273 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use super::hash_table::HashTest;
+use super::package::{self, Package, Status};
+use super::readtable::{self, Case};
+use super::sexp::Sexp;
+
+#[derive(Debug)]
+struct SymbolData {
+    name: String,
+    home: Option<String>,
+    plist: RefCell<Vec<Sexp>>,
+}
+
+/// A symbol. Interning a name in a package always gives the same object, so
+/// symbols compare and hash by identity. Keywords are the symbols of the
+/// `keyword` package and evaluate to themselves.
+#[derive(Clone)]
+pub struct Symbol(Rc<SymbolData>);
+
+thread_local! {
+    static GENSYM_COUNTER: Cell<u64> = const { Cell::new(1) };
+}
+
+impl Symbol {
+    /// The symbol named exactly `name` in the current package, creating it
+    /// if it doesn't exist yet.
+    pub fn intern(name: &str) -> Symbol {
+        package::current().intern(name)
+    }
+
+    /// The keyword named exactly `name`, without the leading colon.
+    pub fn keyword(name: &str) -> Symbol {
+        package::find_package("KEYWORD").unwrap().intern(name)
+    }
+
+    /// Reads `token` as the reader does, converting its case as the current
+    /// readtable says.
+    pub fn read(token: &str) -> Result<Symbol, String> {
+        Symbol::read_with_case(token, readtable::current().case())
+    }
+
+    /// Reads `token` with the readtable case `case`: as a keyword if it starts
+    /// with a colon, and in another package if qualified with `pkg:` for an
+    /// external symbol or `pkg::` for any. Characters escaped with `\` or
+    /// `|...|` are taken as they are, and never as package markers.
+    pub fn read_with_case(token: &str, case: Case) -> Result<Symbol, String> {
+        let chars = case.convert(&readtable::token_chars(token)?);
+        let text = |chars: &[(char, bool)]| chars.iter().map(|&(c, _)| c).collect::<String>();
+        let marker = chars.iter().position(|&(c, escaped)| c == ':' && !escaped);
+
+        let (package, name, internal) = match marker {
+            Some(0) => return Ok(Symbol::keyword(&text(&chars[1..]))),
+            Some(i) if chars.get(i + 1) == Some(&(':', false)) => {
+                (text(&chars[..i]), text(&chars[i + 2..]), true)
+            }
+            Some(i) => (text(&chars[..i]), text(&chars[i + 1..]), false),
+            None => return Ok(Symbol::intern(&text(&chars))),
+        };
+        let package = package::find_package(&package)
+            .ok_or_else(|| format!("Package {} does not exist", package))?;
+        let name = &name[..];
+
+        match package.find_symbol(name) {
+            _ if package.name() == "KEYWORD" => Ok(Symbol::keyword(name)),
+            Some((s, Status::External)) => Ok(s),
+            _ if internal => Ok(package.intern(name)),
+            _ => Err(format!("The symbol {} is not external in the {} package", name, package.name())),
+        }
+    }
+
+    /// A fresh symbol that isn't in the symbol table.
+    pub fn uninterned(name: &str) -> Symbol {
+        Symbol::new_in(name, None)
+    }
+
+    /// A fresh uninterned symbol named with `prefix` and a counter.
+    pub fn gensym(prefix: &str) -> Symbol {
+        let n = GENSYM_COUNTER.with(|c| c.replace(c.get() + 1));
+
+        Symbol::uninterned(&format!("{}{}", prefix, n))
+    }
+
+    /// A new symbol homed in the package named `home`; only packages make
+    /// these, when interning.
+    pub fn new_in(name: &str, home: Option<&str>) -> Symbol {
+        Symbol(Rc::new(SymbolData {
+            name: name.to_string(),
+            home: home.map(str::to_string),
+            plist: RefCell::new(vec![]),
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    pub fn home(&self) -> Option<Package> {
+        self.0.home.as_ref().and_then(|h| package::find_package(h))
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        self.0.home.as_ref().is_some_and(|h| h == "KEYWORD")
+    }
+
+    pub fn plist(&self) -> Vec<Sexp> {
+        self.0.plist.borrow().clone()
+    }
+
+    fn position(&self, indicator: &Sexp) -> Option<usize> {
+        self.0
+            .plist
+            .borrow()
+            .chunks(2)
+            .position(|pair| HashTest::Eq.test(&pair[0], indicator))
+            .map(|i| i * 2)
+    }
+
+    pub fn get(&self, indicator: &Sexp) -> Option<Sexp> {
+        self.position(indicator).map(|i| self.0.plist.borrow()[i + 1].clone())
+    }
+
+    pub fn put(&self, indicator: Sexp, value: Sexp) {
+        match self.position(&indicator) {
+            Some(i) => self.0.plist.borrow_mut()[i + 1] = value,
+            None => self.0.plist.borrow_mut().extend(vec![indicator, value]),
+        }
+    }
+
+    /// Removes `indicator` from the property list, returning whether it was
+    /// there.
+    pub fn remprop(&self, indicator: &Sexp) -> bool {
+        match self.position(indicator) {
+            Some(i) => {
+                self.0.plist.borrow_mut().drain(i..i + 2);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Reads `name` as the reader does with the standard readtable, whatever the
+/// current one is, or interns it upcased in the current package if it
+/// doesn't read as a symbol.
+impl<'a> From<&'a str> for Symbol {
+    fn from(name: &'a str) -> Symbol {
+        Symbol::read_with_case(name, Case::Upcase)
+            .unwrap_or_else(|_| Symbol::intern(&name.to_uppercase()))
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::from(&name[..])
+    }
+}
+
+impl<'a> From<&'a Symbol> for Symbol {
+    fn from(symbol: &'a Symbol) -> Symbol {
+        symbol.clone()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.0 as *const SymbolData).hash(state);
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.name)
+    }
+}
+
+/// Prints the name, prefixed with a colon for keywords, with the home
+/// package for symbols not accessible in the current package, and with `#:`
+/// for uninterned symbols, escaped as needed to read back with the current
+/// readtable. The alternate flag leaves out the escapes and the `#:`.
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let escape = !f.alternate();
+
+        match self.home() {
+            Some(_) if self.is_keyword() => write!(f, ":")?,
+            Some(home) => {
+                let accessible = package::current()
+                    .find_symbol(&self.0.name)
+                    .is_some_and(|(s, _)| s == *self);
+                if !accessible {
+                    let marker = if home.is_external(&self.0.name) { ":" } else { "::" };
+                    write!(f, "{}{}", readtable::print_name(home.name(), escape), marker)?;
+                }
+            }
+            None if escape => write!(f, "#:")?,
+            None => {}
+        }
+        write!(f, "{}", readtable::print_name(&self.0.name, escape))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbol;
+    use super::super::readtable::Case;
+    use super::super::number::Number;
+    use super::super::sexp::Sexp;
+
+    #[test]
+    fn test_intern() {
+        assert_eq!(Symbol::intern("FOO"), Symbol::from("foo"));
+        assert!(Symbol::intern("foo") != Symbol::intern("FOO"));
+        assert!(Symbol::uninterned("FOO") != Symbol::intern("FOO"));
+        assert_eq!(Symbol::read("cl-user::foo"), Ok(Symbol::intern("FOO")));
+        assert_eq!(Symbol::read("nope:foo"), Err("Package NOPE does not exist".to_string()));
+        assert_eq!(Symbol::read("cl:foo"),
+                   Err("The symbol FOO is not external in the COMMON-LISP package".to_string()));
+        assert_eq!(format!("{}", Symbol::uninterned("G1")), "#:G1");
+        assert_eq!(format!("{:#}", Symbol::uninterned("G1")), "G1");
+    }
+
+    #[test]
+    fn test_escapes_and_case() {
+        let mixed = Symbol::read("|Mixed Case|").unwrap();
+
+        assert_eq!(mixed.name(), "Mixed Case");
+        assert_eq!(Symbol::read("ab\\c|d:e|"), Ok(Symbol::intern("ABcd:e")));
+        assert_eq!(Symbol::read_with_case("Foo", Case::Preserve), Ok(Symbol::intern("Foo")));
+        assert_eq!(Symbol::read_with_case("foo", Case::Invert), Ok(Symbol::intern("FOO")));
+        assert_eq!(Symbol::read_with_case("|x|:y", Case::Upcase),
+                   Err("Package x does not exist".to_string()));
+        assert_eq!(format!("{}", mixed), "|Mixed Case|");
+        assert_eq!(format!("{:#}", mixed), "Mixed Case");
+    }
+
+    #[test]
+    fn test_keyword() {
+        let k = Symbol::from(":test");
+
+        assert_eq!(k, Symbol::keyword("TEST"));
+        assert!(k != Symbol::from("test"));
+        assert!(k.is_keyword());
+        assert_eq!(k.name(), "TEST");
+        assert_eq!(format!("{}", k), ":TEST");
+    }
+
+    #[test]
+    fn test_plist() {
+        let s = Symbol::uninterned("S");
+        let color = Sexp::symbol("color");
+        let red = Sexp::symbol("red");
+
+        assert_eq!(s.get(&color), None);
+        s.put(color.clone(), Sexp::Number(Number::Fixnum(1)));
+        s.put(color.clone(), red.clone());
+        assert_eq!(s.get(&color), Some(red.clone()));
+        assert_eq!(s.plist(), vec![color.clone(), red]);
+        assert!(s.remprop(&color));
+        assert!(!s.remprop(&color));
+        assert_eq!(s.plist(), vec![]);
+    }
+}
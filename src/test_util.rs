@@ -0,0 +1,53 @@
+//! Constructors for the objects unit tests build over and over.
+
+use num::{BigInt, BigRational};
+use super::built_in;
+use super::number::Number;
+use super::package;
+use super::parser;
+use super::readtable;
+use super::sexp::Sexp;
+use super::stream::Stream;
+
+pub fn int(n: i64) -> Sexp {
+    Sexp::Number(Number::Fixnum(n))
+}
+
+pub fn ratio(n: i64, d: i64) -> Sexp {
+    Sexp::Number(Number::from_ratio(BigRational::new(BigInt::from(n), BigInt::from(d))))
+}
+
+pub fn float(n: f64) -> Sexp {
+    Sexp::Number(Number::Float(n))
+}
+
+pub fn string(s: &str) -> Sexp {
+    Sexp::string(s)
+}
+
+/// Reads `s` as a symbol name in the current package.
+pub fn symbol(s: &str) -> Sexp {
+    Sexp::symbol(s)
+}
+
+pub fn list(elements: Vec<Sexp>) -> Sexp {
+    Sexp::list(elements)
+}
+
+/// Reads and evaluates the forms of `source` in a fresh global environment,
+/// starting in `common-lisp-user` with the standard readtable, giving the
+/// printed value of the last one.
+pub fn eval_source(source: &str) -> Result<String, String> {
+    package::set_current(package::find_package("COMMON-LISP-USER").unwrap());
+    readtable::set_current(readtable::standard());
+    let env = built_in::default_env();
+    let stream = Stream::string_input(source);
+    let mut value = Sexp::Nil;
+    loop {
+        readtable::use_env(&env);
+        match parser::read(&stream)? {
+            Some(form) => value = form.eval(&env)?.primary(),
+            None => return Ok(value.to_string()),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::cmp::Ordering;
+use super::clos;
+use super::hash_table::HashTest;
+use super::number::Number;
+use super::package;
+use super::sexp::Sexp;
+use super::structure;
+use super::symbol::Symbol;
+
+/// Type names that aren't classes of any object but are still recognized.
+pub const OTHER_TYPES: &[&str] = &["ATOM", "BOOLEAN", "FIXNUM", "BIGNUM",
+                                   "SINGLE-FLOAT", "DOUBLE-FLOAT", "SHORT-FLOAT", "LONG-FLOAT",
+                                   "SIMPLE-STRING", "NIL", "STANDARD-OBJECT", "STRUCTURE-OBJECT"];
+
+/// The heads of compound type specifiers, such as `(or string null)`, that
+/// aren't type names themselves.
+pub const COMPOUND_TYPES: &[&str] = &["AND", "OR", "NOT", "MEMBER", "EQL"];
+
+/// Classes that built-in values can have.
+pub const BUILT_IN_CLASSES: &[&str] = &["T", "NUMBER", "REAL", "RATIONAL", "INTEGER",
+                                        "RATIO", "FLOAT", "COMPLEX", "CHARACTER", "STRING",
+                                        "VECTOR", "ARRAY", "SEQUENCE", "SYMBOL", "KEYWORD",
+                                        "LIST", "CONS", "NULL", "STREAM", "HASH-TABLE", "PACKAGE",
+                                        "READTABLE", "RANDOM-STATE", "FUNCTION"];
+
+fn symbol(name: &str) -> Sexp {
+    Sexp::Symbol(package::cl_symbol(name))
+}
+
+pub fn type_of(object: &Sexp) -> Sexp {
+    match *object {
+        Sexp::Number(Number::Fixnum(_)) => symbol("FIXNUM"),
+        Sexp::Number(Number::Bignum(_)) => symbol("BIGNUM"),
+        Sexp::Number(Number::Float(_)) => symbol("DOUBLE-FLOAT"),
+        Sexp::True => symbol("BOOLEAN"),
+        Sexp::List(ref v) if v.is_empty() => symbol("NULL"),
+        _ => Sexp::Symbol(clos::class_precedence(object).remove(0)),
+    }
+}
+
+/// Whether `name` is one of the type names of `common-lisp`, which may be
+/// the names of built-in classes or only recognized as types.
+fn is_standard_type(name: &Symbol) -> bool {
+    let n = name.name();
+    (BUILT_IN_CLASSES.contains(&n) || OTHER_TYPES.contains(&n)) && *name == package::cl_symbol(n)
+}
+
+fn atomic_typep(object: &Sexp, name: &Symbol) -> Result<bool, String> {
+    let standard = is_standard_type(name);
+    if !standard && clos::find_class(name).is_none() && structure::find_type(name).is_none() {
+        return Err(format!("Unknown type specifier: {}", name));
+    }
+
+    let standard_name = if standard { name.name() } else { "" };
+    Ok(match standard_name {
+        "NIL" => false,
+        "ATOM" => !is_cons(object),
+        "BOOLEAN" => *object == Sexp::True || is_null(object),
+        "FIXNUM" => matches!(*object, Sexp::Number(Number::Fixnum(_))),
+        "BIGNUM" => matches!(*object, Sexp::Number(Number::Bignum(_))),
+        "SINGLE-FLOAT" | "DOUBLE-FLOAT" | "SHORT-FLOAT" | "LONG-FLOAT" => {
+            matches!(*object, Sexp::Number(Number::Float(_)))
+        }
+        "SIMPLE-STRING" => matches!(*object, Sexp::String(_)),
+        _ => clos::class_precedence(object).contains(name),
+    })
+}
+
+pub fn is_null(object: &Sexp) -> bool {
+    match *object {
+        Sexp::Nil => true,
+        Sexp::List(ref v) => v.is_empty(),
+        _ => false,
+    }
+}
+
+pub fn is_cons(object: &Sexp) -> bool {
+    match *object {
+        Sexp::List(ref v) => !v.is_empty(),
+        Sexp::Cons(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether `n` is within the bounds of a `(type low high)` specifier, where
+/// `*` is unbounded and a bound in a list is exclusive.
+fn in_range(n: &Number, bounds: &[Sexp]) -> Result<bool, String> {
+    for (i, bound) in bounds.iter().enumerate() {
+        let (limit, exclusive) = match *bound {
+            Sexp::Symbol(ref s) if s.name() == "*" => continue,
+            Sexp::Number(ref b) => (b, false),
+            Sexp::List(ref v) if v.len() == 1 => {
+                match v[0] {
+                    Sexp::Number(ref b) => (b, true),
+                    ref b => return Err(format!("Unknown type specifier: {}", b)),
+                }
+            }
+            ref b => return Err(format!("Unknown type specifier: {}", b)),
+        };
+
+        let ordering = n.num_cmp(limit);
+        let ok = match (i, exclusive) {
+            (0, false) => ordering != Some(Ordering::Less),
+            (0, true) => ordering == Some(Ordering::Greater),
+            (_, false) => ordering != Some(Ordering::Greater),
+            (_, true) => ordering == Some(Ordering::Less),
+        };
+        if !ok {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn length_of(object: &Sexp) -> Option<usize> {
+    match *object {
+        Sexp::String(ref s) => Some(s.chars().count()),
+        Sexp::Array(ref a) if a.borrow().dimensions().len() == 1 => Some(a.borrow().len()),
+        _ => None,
+    }
+}
+
+/// Whether `object` is of the type `spec`: a type name, or a compound
+/// specifier such as `(or string null)`, `(integer 0 10)` or `(member a b)`.
+pub fn typep(object: &Sexp, spec: &Sexp) -> Result<bool, String> {
+    let v = match *spec {
+        Sexp::Symbol(ref name) => return atomic_typep(object, name),
+        Sexp::True => return Ok(true),
+        Sexp::Nil => return Ok(false),
+        Sexp::List(ref v) if !v.is_empty() => v,
+        ref s => return Err(format!("Unknown type specifier: {}", s)),
+    };
+
+    let (name, head) = match v[0] {
+        Sexp::Symbol(ref head) => (head, package::cl_name(head).unwrap_or("")),
+        ref h => return Err(format!("Unknown type specifier: {}", h)),
+    };
+    let args = &v[1..];
+
+    match head {
+        "OR" => {
+            for s in args {
+                if typep(object, s)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        "AND" => {
+            for s in args {
+                if !typep(object, s)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        "NOT" if args.len() == 1 => Ok(!typep(object, &args[0])?),
+        "MEMBER" => Ok(args.iter().any(|a| HashTest::Eql.test(object, a))),
+        "EQL" if args.len() == 1 => Ok(HashTest::Eql.test(object, &args[0])),
+        "INTEGER" | "RATIONAL" | "REAL" | "FLOAT" | "DOUBLE-FLOAT" | "SINGLE-FLOAT" => {
+            match *object {
+                Sexp::Number(ref n) if atomic_typep(object, name)? => in_range(n, args),
+                _ => Ok(false),
+            }
+        }
+        "STRING" | "SIMPLE-STRING" | "VECTOR" => {
+            let size = match head {
+                "VECTOR" => args.get(1),
+                _ => args.first(),
+            };
+            Ok(atomic_typep(object, name)? &&
+               match size {
+                Some(Sexp::Number(Number::Fixnum(n))) => length_of(object) == Some(*n as usize),
+                _ => true,
+            })
+        }
+        _ => atomic_typep(object, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::number::Number;
+    use super::super::sexp::Sexp;
+    use super::super::test_util::{int, symbol};
+
+    fn spec(names: Vec<Sexp>) -> Sexp {
+        Sexp::list(names)
+    }
+
+    #[test]
+    fn test_type_of() {
+        assert_eq!(super::type_of(&int(1)), symbol("FIXNUM"));
+        assert_eq!(super::type_of(&Sexp::string("a")), symbol("STRING"));
+        assert_eq!(super::type_of(&Sexp::Nil), symbol("NULL"));
+        assert_eq!(super::type_of(&Sexp::list(vec![int(1)])), symbol("CONS"));
+        assert_eq!(super::type_of(&Sexp::Char('a')), symbol("CHARACTER"));
+        assert_eq!(super::type_of(&symbol(":k")), symbol("KEYWORD"));
+    }
+
+    #[test]
+    fn test_typep_atomic() {
+        assert_eq!(super::typep(&int(1), &symbol("integer")), Ok(true));
+        assert_eq!(super::typep(&int(1), &symbol("number")), Ok(true));
+        assert_eq!(super::typep(&int(1), &symbol("float")), Ok(false));
+        assert_eq!(super::typep(&Sexp::Nil, &symbol("list")), Ok(true));
+        assert_eq!(super::typep(&Sexp::Nil, &symbol("symbol")), Ok(true));
+        assert_eq!(super::typep(&Sexp::list(vec![]), &symbol("atom")), Ok(true));
+        assert_eq!(super::typep(&Sexp::string("a"), &symbol("sequence")), Ok(true));
+        assert_eq!(super::typep(&int(1), &Sexp::True), Ok(true));
+        assert_eq!(super::typep(&int(1), &symbol("widget")),
+                   Err("Unknown type specifier: WIDGET".to_string()));
+    }
+
+    #[test]
+    fn test_typep_compound() {
+        let string_or_null = spec(vec![symbol("or"), symbol("string"), symbol("null")]);
+        let small = spec(vec![symbol("integer"), int(0), int(10)]);
+        let positive = spec(vec![symbol("real"), spec(vec![int(0)]), symbol("*")]);
+
+        assert_eq!(super::typep(&Sexp::Nil, &string_or_null), Ok(true));
+        assert_eq!(super::typep(&int(1), &string_or_null), Ok(false));
+        assert_eq!(super::typep(&int(10), &small), Ok(true));
+        assert_eq!(super::typep(&int(11), &small), Ok(false));
+        assert_eq!(super::typep(&Sexp::Number(Number::Float(1.0)), &small), Ok(false));
+        assert_eq!(super::typep(&int(0), &positive), Ok(false));
+        assert_eq!(super::typep(&int(1), &positive), Ok(true));
+        assert_eq!(super::typep(&int(2),
+                                &spec(vec![symbol("and"),
+                                           symbol("integer"),
+                                           spec(vec![symbol("not"), spec(vec![symbol("eql"), int(2)])])])),
+                   Ok(false));
+        assert_eq!(super::typep(&symbol("b"), &spec(vec![symbol("member"), symbol("a"), symbol("b")])),
+                   Ok(true));
+        assert_eq!(super::typep(&Sexp::string("ab"), &spec(vec![symbol("string"), int(2)])),
+                   Ok(true));
+        assert_eq!(super::typep(&Sexp::string("ab"), &spec(vec![symbol("string"), int(3)])),
+                   Ok(false));
+        assert_eq!(super::typep(&Sexp::Nil, &spec(vec![symbol("|or|"), symbol("null")])),
+                   Err("Unknown type specifier: |or|".to_string()));
+    }
+}